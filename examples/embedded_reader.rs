@@ -0,0 +1,45 @@
+//! How to drive the decoder from firmware with its own USB host stack, e.g. on an RP2040 or an ESP32-S3.
+//!
+//! This runs on a PC, with `FakeUsbHost` standing in for whatever your HAL offers. In firmware the shape is the same:
+//! enumerate the device, send the 9 zero byte feature report as a `SET_REPORT` control transfer, then poll the
+//! interrupt IN endpoint through the closure passed to `ReportReader::new`.
+use co2_monitor::{MonitorError, embedded::ReportReader};
+
+/// Stand-in for a USB host driver. Replays a single measurement cycle of a ZGm053U.
+struct FakeUsbHost {
+    reports: [[u8; 8]; 3],
+    next: usize,
+}
+
+impl FakeUsbHost {
+    fn interrupt_in(&mut self, buffer: &mut [u8; 8]) -> Result<usize, ()> {
+        *buffer = self.reports[self.next % self.reports.len()];
+        self.next += 1;
+        Ok(buffer.len())
+    }
+}
+
+fn main() {
+    let mut usb = FakeUsbHost {
+        reports: [
+            // Temperature 0x1283 / 16 K, co2 1000 ppm, sanity check 11811.
+            [0x42, 0x12, 0x83, 0xd7, 0x0d, 0, 0, 0],
+            [0x50, 0x03, 0xe8, 0x3b, 0x0d, 0, 0, 0],
+            [0x6e, 0x2e, 0x23, 0xbf, 0x0d, 0, 0, 0],
+        ],
+        next: 0,
+    };
+
+    let mut reader = ReportReader::new(|buffer: &mut [u8; 8]| {
+        usb.interrupt_in(buffer)
+            .map_err(|_| MonitorError::ReadFailed)
+    });
+
+    for _ in 0..6 {
+        match reader.read_reading() {
+            Ok(Some(reading)) => println!("{reading:.1}"),
+            Ok(None) => (),
+            Err(e) => println!("Bad report: {e:?}"),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+#ifndef CO2_MONITOR_H
+#define CO2_MONITOR_H
+
+/* Generated by cbindgen from src/ffi.rs, don't edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * What the co2 value of a [`Co2Reading`] means, see [`Co2Value`].
+ */
+typedef enum Co2State {
+  /**
+   * Within spec.
+   */
+  Co2State_Valid = 0,
+  /**
+   * Above the range of the sensor.
+   */
+  Co2State_TooHigh = 1,
+  /**
+   * The sensor is still warming up.
+   */
+  Co2State_WarmingUp = 2,
+  /**
+   * Implausibly low.
+   */
+  Co2State_TooLow = 3,
+  /**
+   * The sensor isn't measuring.
+   */
+  Co2State_SensorFault = 4,
+} Co2State;
+
+/**
+ * Result of the C functions.
+ */
+typedef enum Co2Status {
+  /**
+   * Success.
+   */
+  Co2Status_Ok = 0,
+  /**
+   * Reading from the device failed, it's probably gone.
+   */
+  Co2Status_ReadFailed = -1,
+  /**
+   * The report doesn't have the terminator byte.
+   */
+  Co2Status_MissingTerminatorByte = -2,
+  /**
+   * The checksum of the report is wrong.
+   */
+  Co2Status_ChecksumInvalid = -3,
+  /**
+   * No complete reading within the timeout.
+   */
+  Co2Status_Timeout = -4,
+  /**
+   * The read was cancelled.
+   */
+  Co2Status_Cancelled = -5,
+  /**
+   * A pointer argument was NULL.
+   */
+  Co2Status_NullPointer = -6,
+} Co2Status;
+
+/**
+ * An open monitor, opaque to C.
+ */
+typedef struct Co2Monitor Co2Monitor;
+
+/**
+ * A complete reading.
+ */
+typedef struct Co2Reading {
+  /**
+   * Co2 in ppm, only meaningful if `co2_state` is `Valid`.
+   */
+  uint16_t co2_ppm;
+  /**
+   * Whether co2 is valid, and if not, why.
+   */
+  enum Co2State co2_state;
+  /**
+   * Temperature in degrees Celsius.
+   */
+  float temperature;
+  /**
+   * Relative humidity in percent, NaN if the device has no humidity sensor.
+   */
+  float humidity;
+} Co2Reading;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Validate a single 8-byte report and extract its opcode and value.
+ *
+ * # Safety
+ *
+ * `report` must point to 8 readable bytes, `opcode` and `value` must be valid for writes.
+ */
+enum Co2Status co2_parse_report(const uint8_t (*report)[8], uint8_t *opcode, uint16_t *value);
+
+/**
+ * Open the first connected monitor of any known model. Returns NULL if there is none or it can't be opened. Free it
+ * with [`co2_close`].
+ */
+struct Co2Monitor *co2_open(void);
+
+/**
+ * Wait up to `timeout_ms` for the next complete reading and write it to `reading`. A complete reading takes about 5
+ * seconds.
+ *
+ * # Safety
+ *
+ * `monitor` must come from [`co2_open`] and not be closed yet, and not be used from another thread at the same time.
+ * `reading` must be valid for writes.
+ */
+enum Co2Status co2_read_blocking(struct Co2Monitor *monitor,
+                                 uint32_t timeout_ms,
+                                 struct Co2Reading *reading);
+
+/**
+ * Close a monitor from [`co2_open`]. NULL is ignored.
+ *
+ * # Safety
+ *
+ * `monitor` must come from [`co2_open`] and not be closed already.
+ */
+void co2_close(struct Co2Monitor *monitor);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CO2_MONITOR_H */
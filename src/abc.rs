@@ -0,0 +1,131 @@
+//! Automatic baseline calibration (ABC) in software, for sensors whose own ABC is off or gets it wrong.
+//!
+//! ABC assumes that a room gets close to the co2 level outside at some point every few days, when it's empty or aired
+//! out. [`AutoBaseline`] keeps the lowest raw co2 of each day (see [`DailyMinima`]), and once it has a week of them (by
+//! default) it takes the lowest as [`OUTDOOR_PPM`] and adds the difference to every reading from then on. The offset is
+//! updated once a day. Corrected readings are flagged [`Provenance::CALIBRATED`], so the csv log shows which values
+//! were corrected, and every change of the offset is logged with what it was based on.
+//!
+//! It's the same assumption the sensor's own ABC makes, with the same weakness: in a room that's never aired out,
+//! like a bedroom with the window always closed, the lowest value is above the outdoor level and the correction pulls
+//! all readings down. The offset is capped (200 ppm by default) so that can't get too far, but don't turn this on for
+//! such rooms. A sensor that needs more than the cap should be recalibrated by hand.
+//!
+//! It takes a week to learn the baseline, so on a PC keep it across restarts with a
+//! [`StateFile`](crate::state::StateFile), and pass readings with wall clock timestamps so the days line up.
+use crate::{Co2Value, Provenance, TimestampedReading, stats::DailyMinima};
+
+/// Co2 outside, what the lowest value of the last days is taken as.
+pub const OUTDOOR_PPM: f32 = 420.0;
+
+/// The lowest value of this many days is the baseline, unless configured otherwise.
+pub const DEFAULT_DAYS: u8 = 7;
+
+/// The offset is at most this many ppm either way, unless configured otherwise.
+pub const DEFAULT_MAX_OFFSET_PPM: u16 = 200;
+
+/// Corrects co2 with an offset from the lowest values of the last days, see the [module documentation](self).
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AutoBaseline {
+    days: u8,
+    max_offset: i16,
+    minima: DailyMinima,
+    offset: i16,
+}
+
+impl AutoBaseline {
+    /// A baseline over [`DEFAULT_DAYS`] days, with offsets of at most [`DEFAULT_MAX_OFFSET_PPM`].
+    pub const fn new() -> Self {
+        Self {
+            days: DEFAULT_DAYS,
+            max_offset: DEFAULT_MAX_OFFSET_PPM as i16,
+            minima: DailyMinima::new(),
+            offset: 0,
+        }
+    }
+
+    /// Take the lowest value of `days` days (1 to [`DailyMinima::MAX_DAYS`]) as the baseline.
+    pub const fn with_days(mut self, days: u8) -> Self {
+        self.days = if days == 0 {
+            1
+        } else if days as usize > DailyMinima::MAX_DAYS {
+            DailyMinima::MAX_DAYS as u8
+        } else {
+            days
+        };
+        self
+    }
+
+    /// Cap the offset at `ppm` either way.
+    pub const fn with_max_offset(mut self, ppm: u16) -> Self {
+        self.max_offset = if ppm > i16::MAX as u16 {
+            i16::MAX
+        } else {
+            ppm as i16
+        };
+        self
+    }
+
+    /// The offset added to co2, 0 until there are enough days.
+    pub fn offset(&self) -> i16 {
+        self.offset
+    }
+
+    /// The lowest raw co2 of the last days the offset is based on, once there are that many complete days.
+    pub fn floor_ppm(&self) -> Option<f32> {
+        self.minima.minimum(usize::from(self.days))
+    }
+
+    /// Take over what `saved` learned, e.g. before a restart, but keep the own settings.
+    pub fn restore(&mut self, saved: &Self) {
+        self.minima = saved.minima;
+        self.offset = saved.offset;
+        // The settings may have changed since.
+        self.update_offset();
+    }
+
+    /// Learn from `reading` and return it corrected with the current offset. Only valid co2 values are corrected and
+    /// learned from. The readings have to be in chronological order.
+    pub fn correct(&mut self, reading: &TimestampedReading) -> TimestampedReading {
+        let mut corrected = *reading;
+        let Co2Value::Valid(ppm) = reading.reading.co2_value else {
+            return corrected;
+        };
+        if self.minima.push(reading.timestamp_ms, f32::from(ppm)) {
+            self.update_offset();
+        }
+        if self.offset != 0 {
+            let ppm = (i32::from(ppm) + i32::from(self.offset)).clamp(0, i32::from(u16::MAX));
+            corrected.reading.co2_value = Co2Value::Valid(ppm as u16);
+            corrected.reading.provenance.insert(Provenance::CALIBRATED);
+        }
+        corrected
+    }
+
+    fn update_offset(&mut self) {
+        let Some(floor) = self.floor_ppm() else {
+            return;
+        };
+        // Both are whole ppm, there's nothing to round.
+        let max_offset = f32::from(self.max_offset);
+        let offset = (OUTDOOR_PPM - floor).clamp(-max_offset, max_offset) as i16;
+        if offset != self.offset {
+            event!(
+                info,
+                "software ABC: co2 offset {:+} ppm (was {:+}), the lowest of the last {} days was {} ppm",
+                offset,
+                self.offset,
+                self.days,
+                floor
+            );
+            self.offset = offset;
+        }
+    }
+}
+
+impl Default for AutoBaseline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,110 @@
+//! Switch something on and off by co2, usually a ventilation fan.
+//!
+//! [`FanController`] decides: it switches on when co2 reaches one level and off once it's back below a lower one, so a
+//! value hovering around a single limit doesn't toggle the fan all the time. On top of that, the fan stays on (and
+//! off) for a minimum time, which relays and motors are happier with. An [`Actuator`] is whatever is switched, see
+//! [`crate::switch`] for smart plugs and MQTT, and [`crate::gpio`] for a GPIO pin with a relay.
+//!
+//! The controller starts off, and the first decision always goes to the actuator, so a fan that was left on before a
+//! restart is switched off if it's not needed. A failed switch is retried with the next reading.
+use crate::{Co2Value, TimestampedReading};
+
+/// Minimum time on and off, in seconds, unless configured otherwise.
+pub const DEFAULT_MIN_SECONDS: u32 = 60;
+
+/// Something that can be switched on and off.
+pub trait Actuator {
+    /// Why switching failed.
+    type Error;
+
+    /// Switch on or off. Switching to the current state again has to be fine.
+    fn set(&mut self, on: bool) -> Result<(), Self::Error>;
+}
+
+/// Decides when to switch, see the [module documentation](self).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FanController {
+    on_ppm: u16,
+    off_ppm: u16,
+    min_on_ms: u64,
+    min_off_ms: u64,
+    on: bool,
+    // When it was last switched.
+    since_ms: Option<u64>,
+    synced: bool,
+}
+
+impl FanController {
+    /// Switch on at `on_ppm` and off below `off_ppm`, which should be lower, with [`DEFAULT_MIN_SECONDS`].
+    pub const fn new(on_ppm: u16, off_ppm: u16) -> Self {
+        Self {
+            on_ppm,
+            off_ppm,
+            min_on_ms: DEFAULT_MIN_SECONDS as u64 * 1000,
+            min_off_ms: DEFAULT_MIN_SECONDS as u64 * 1000,
+            on: false,
+            since_ms: None,
+            synced: false,
+        }
+    }
+
+    /// Stay on for at least `on_seconds` and off for at least `off_seconds` after switching.
+    pub const fn with_min_times(mut self, on_seconds: u32, off_seconds: u32) -> Self {
+        self.min_on_ms = on_seconds as u64 * 1000;
+        self.min_off_ms = off_seconds as u64 * 1000;
+        self
+    }
+
+    /// Whether the fan is (supposed to be) on.
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+
+    /// Look at a reading and return the state to switch to, if the actuator needs to be told. The readings have to be
+    /// in chronological order. Co2 above the sensor range counts, other out of spec values are skipped.
+    pub fn push(&mut self, reading: &TimestampedReading) -> Option<bool> {
+        let (Co2Value::Valid(ppm) | Co2Value::TooHigh(ppm)) = reading.reading.co2_value else {
+            return None;
+        };
+        let timestamp_ms = reading.timestamp_ms;
+        let wanted = if self.on {
+            ppm >= self.off_ppm
+        } else {
+            ppm >= self.on_ppm
+        };
+        let min_ms = if self.on {
+            self.min_on_ms
+        } else {
+            self.min_off_ms
+        };
+        let held = self
+            .since_ms
+            .is_some_and(|since_ms| timestamp_ms.saturating_sub(since_ms) < min_ms);
+        let changed = wanted != self.on && !held;
+        if changed {
+            self.on = wanted;
+            self.since_ms = Some(timestamp_ms);
+        }
+        if !changed && self.synced {
+            return None;
+        }
+        self.synced = true;
+        Some(self.on)
+    }
+
+    /// [`push`](Self::push) the reading and switch `actuator` if needed. Returns the new state, if it was switched.
+    pub fn drive<A: Actuator + ?Sized>(
+        &mut self,
+        reading: &TimestampedReading,
+        actuator: &mut A,
+    ) -> Result<Option<bool>, A::Error> {
+        let Some(on) = self.push(reading) else {
+            return Ok(None);
+        };
+        if let Err(e) = actuator.set(on) {
+            self.synced = false;
+            return Err(e);
+        }
+        Ok(Some(on))
+    }
+}
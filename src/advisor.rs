@@ -0,0 +1,168 @@
+//! When to ventilate, and for how long, from the size of the room, the number of people in it and the co2 trend.
+//!
+//! The model is the usual single-zone mass balance: people add co2 at a fixed rate, and air exchange with the outside
+//! pulls it towards the outdoor level. Given the current slope (see [`crate::trend::Trend`]), that balance tells how
+//! much air is exchanged right now ([`Advisor::air_exchange_rate`]). With a window wide open the exchange rate is much
+//! higher, which gives the time to get down to the target ([`Advisor::ventilation_minutes`]).
+//!
+//! All of this is a rough estimate. Occupancy is a guess, and how much a window exchanges depends on wind and the
+//! temperature difference, so the defaults are on the conservative side.
+
+/// Co2 exhaled by a sitting adult in m³ per hour, about 18 liters.
+pub const CO2_PER_PERSON_M3_PER_H: f32 = 0.018;
+
+/// Air changes per hour with a window wide open (shock ventilation). Somewhere between 5 and 30 in practice.
+pub const OPEN_WINDOW_EXCHANGE_PER_H: f32 = 10.0;
+
+/// What to do about the air, see [`Advisor::advise`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Advice {
+    /// Co2 is below the limit, and isn't rising towards it (or not within the next hour).
+    Fine,
+    /// Co2 will reach the limit in about `minutes`.
+    VentilateIn {
+        /// Minutes until the limit is reached at the current rate.
+        minutes: f32,
+    },
+    /// Co2 is at or above the limit, open a window for about `minutes` to get down to `target_ppm`.
+    VentilateNow {
+        /// How long to keep the window open, `None` if even an open window can't get co2 down to the target.
+        minutes: Option<f32>,
+        /// Co2 after that.
+        target_ppm: u16,
+    },
+}
+
+impl core::fmt::Display for Advice {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Advice::Fine => f.write_str("air is fine"),
+            Advice::VentilateIn { minutes } => write!(f, "ventilate in ~{:.0} min", minutes),
+            Advice::VentilateNow {
+                minutes: Some(minutes),
+                target_ppm,
+            } => write!(
+                f,
+                "open window ~{:.0} min to reach {} ppm",
+                minutes.max(1.0),
+                target_ppm
+            ),
+            Advice::VentilateNow {
+                minutes: None,
+                target_ppm,
+            } => write!(f, "open window, {} ppm is out of reach though", target_ppm),
+        }
+    }
+}
+
+/// Estimates air exchange and ventilation times for a single room.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Advisor {
+    volume_m3: f32,
+    occupants: f32,
+    outdoor_ppm: f32,
+    limit_ppm: u16,
+    target_ppm: u16,
+    open_window_exchange_per_h: f32,
+}
+
+impl Advisor {
+    /// A room of `volume_m3` cubic meters with `occupants` people in it. Ventilate at 1000 ppm down to 700 ppm, with
+    /// 420 ppm outside.
+    pub const fn new(volume_m3: f32, occupants: f32) -> Self {
+        Self {
+            volume_m3,
+            occupants,
+            outdoor_ppm: 420.0,
+            limit_ppm: 1000,
+            target_ppm: 700,
+            open_window_exchange_per_h: OPEN_WINDOW_EXCHANGE_PER_H,
+        }
+    }
+
+    /// Co2 at which to ventilate, and to get down to by ventilating.
+    pub const fn with_limits(mut self, limit_ppm: u16, target_ppm: u16) -> Self {
+        self.limit_ppm = limit_ppm;
+        self.target_ppm = target_ppm;
+        self
+    }
+
+    /// Co2 outside, 420 ppm by default.
+    pub const fn with_outdoor_ppm(mut self, outdoor_ppm: f32) -> Self {
+        self.outdoor_ppm = outdoor_ppm;
+        self
+    }
+
+    /// Air changes per hour with the window open, [`OPEN_WINDOW_EXCHANGE_PER_H`] by default.
+    pub const fn with_open_window_exchange_rate(mut self, per_hour: f32) -> Self {
+        self.open_window_exchange_per_h = per_hour;
+        self
+    }
+
+    /// The people change.
+    pub fn set_occupants(&mut self, occupants: f32) {
+        self.occupants = occupants;
+    }
+
+    /// How fast the occupants raise co2 in a closed room, in ppm per hour.
+    pub fn generation_ppm_per_h(&self) -> f32 {
+        if self.volume_m3 <= 0.0 {
+            return 0.0;
+        }
+        self.occupants * CO2_PER_PERSON_M3_PER_H / self.volume_m3 * 1e6
+    }
+
+    /// Air changes per hour right now, from the co2 level and its slope. `None` when co2 is too close to the outdoor
+    /// level to tell. Negative results (more rise than the occupants explain) are clamped to 0, it usually means there
+    /// are more people than assumed.
+    pub fn air_exchange_rate(&self, co2_ppm: f32, ppm_per_minute: f32) -> Option<f32> {
+        let excess = co2_ppm - self.outdoor_ppm;
+        if excess < 50.0 {
+            return None;
+        }
+        Some(((self.generation_ppm_per_h() - ppm_per_minute * 60.0) / excess).max(0.0))
+    }
+
+    /// Minutes of open window to get from `co2_ppm` down to the target. `None` if the target can't be reached, i.e.
+    /// when the occupants produce more than an open window gets rid of.
+    pub fn ventilation_minutes(&self, co2_ppm: f32) -> Option<f32> {
+        let target = f32::from(self.target_ppm);
+        if co2_ppm <= target {
+            return Some(0.0);
+        }
+        let rate = self.open_window_exchange_per_h / 60.0;
+        let generation = self.generation_ppm_per_h() / 60.0;
+        // Step through the exponential decay towards the steady state, in 6 second steps for at most 2 hours.
+        let step = 0.1;
+        let mut co2 = co2_ppm;
+        for i in 0..1200 {
+            if co2 <= target {
+                return Some(i as f32 * step);
+            }
+            co2 += (generation - rate * (co2 - self.outdoor_ppm)) * step;
+        }
+        None
+    }
+
+    /// Whether and when to ventilate, given the current co2 and (if known) its slope in ppm per minute.
+    pub fn advise(&self, co2_ppm: f32, ppm_per_minute: Option<f32>) -> Advice {
+        let limit = f32::from(self.limit_ppm);
+        if co2_ppm >= limit {
+            return Advice::VentilateNow {
+                minutes: self.ventilation_minutes(co2_ppm),
+                target_ppm: self.target_ppm,
+            };
+        }
+        match ppm_per_minute {
+            Some(slope) if slope > 0.0 && (limit - co2_ppm) / slope <= 60.0 => {
+                Advice::VentilateIn {
+                    minutes: (limit - co2_ppm) / slope,
+                }
+            }
+            _ => Advice::Fine,
+        }
+    }
+}
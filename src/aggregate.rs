@@ -0,0 +1,148 @@
+//! Combine several readings into one, for logs that don't need a reading every 5 seconds.
+//!
+//! [`Aggregator`] combines a fixed number of readings, [`IntervalAggregator`] all readings within a time interval (e.g.
+//! one reading per minute). The combined reading has the mean co2, temperature and humidity, and its co2 is only valid
+//! if all combined readings were valid. Otherwise it's invalid like the first invalid one, or warming up if any of
+//! them was. It is marked as [`Provenance::SMOOTHED`].
+use crate::{Co2Value, MonitorReading, Provenance, TimestampedReading};
+
+/// Combines every `n` readings into one.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Aggregator {
+    n: u32,
+    count: u32,
+    co2_sum: u64,
+    /// The first invalid co2 value, or the first warming up one.
+    invalid: Option<Co2Value>,
+    temperature_sum: f32,
+    temperature_centi_sum: i64,
+    humidity_sum: f32,
+    humidity_count: u32,
+    provenance: Provenance,
+}
+
+impl Aggregator {
+    /// Combine every `n` readings. `n` of 0 is treated like 1.
+    pub const fn new(n: u32) -> Self {
+        Self {
+            n: if n == 0 { 1 } else { n },
+            count: 0,
+            co2_sum: 0,
+            invalid: None,
+            temperature_sum: 0.0,
+            temperature_centi_sum: 0,
+            humidity_sum: 0.0,
+            humidity_count: 0,
+            provenance: Provenance::SMOOTHED,
+        }
+    }
+
+    /// Add a reading. Returns the combined reading whenever this was the `n`-th one.
+    pub fn push(&mut self, reading: &MonitorReading) -> Option<MonitorReading> {
+        self.add(reading);
+        if self.count >= self.n {
+            self.take()
+        } else {
+            None
+        }
+    }
+
+    /// Add a reading without ever completing the combined reading, see [`Aggregator::take`].
+    pub fn add(&mut self, reading: &MonitorReading) {
+        let (co2, valid) = reading.co2_value.as_num_and_bool();
+        self.count += 1;
+        self.co2_sum += u64::from(co2);
+        if matches!(reading.co2_value, Co2Value::WarmingUp(_)) || !valid && self.invalid.is_none() {
+            self.invalid = Some(reading.co2_value);
+        }
+        self.temperature_sum += reading.temperature;
+        self.temperature_centi_sum += i64::from(reading.temperature_centi);
+        if let Some(humidity) = reading.humidity {
+            self.humidity_sum += humidity;
+            self.humidity_count += 1;
+        }
+        self.provenance |= reading.provenance;
+    }
+
+    /// Combine the readings added so far (even if there are less than `n`) and start over. `None` if there are none.
+    pub fn take(&mut self) -> Option<MonitorReading> {
+        if self.count == 0 {
+            return None;
+        }
+        let count = self.count;
+        let co2 = ((self.co2_sum + u64::from(count / 2)) / u64::from(count)) as u16;
+        let reading = MonitorReading {
+            temperature: self.temperature_sum / count as f32,
+            temperature_centi: (self.temperature_centi_sum / i64::from(count)) as i32,
+            co2_value: self
+                .invalid
+                .map_or(Co2Value::Valid(co2), |invalid| invalid.with_ppm(co2)),
+            humidity: (self.humidity_count > 0)
+                .then(|| self.humidity_sum / self.humidity_count as f32),
+            provenance: self.provenance,
+        };
+        *self = Self::new(self.n);
+        Some(reading)
+    }
+
+    /// How many readings were added since the last combined reading.
+    pub fn pending(&self) -> u32 {
+        self.count
+    }
+}
+
+/// Combines all readings within consecutive intervals of a fixed length into one.
+///
+/// Intervals start with the first reading, and are back to back from then on. An interval is complete when the first
+/// reading after it arrives, so the combined reading comes (at most) one reading late.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct IntervalAggregator {
+    interval_ms: u64,
+    end_ms: Option<u64>,
+    last_timestamp_ms: u64,
+    last_sequence: u32,
+    inner: Aggregator,
+}
+
+impl IntervalAggregator {
+    /// Combine the readings of every `interval_ms` milliseconds. An interval of 0 is treated like 1 ms.
+    pub const fn new(interval_ms: u64) -> Self {
+        Self {
+            interval_ms: if interval_ms == 0 { 1 } else { interval_ms },
+            end_ms: None,
+            last_timestamp_ms: 0,
+            last_sequence: 0,
+            inner: Aggregator::new(u32::MAX),
+        }
+    }
+
+    /// Add a reading. Returns the combined reading of the previous interval when `reading` is beyond it, with the
+    /// timestamp and sequence number of its last reading.
+    pub fn push(&mut self, reading: &TimestampedReading) -> Option<TimestampedReading> {
+        let end_ms = *self
+            .end_ms
+            .get_or_insert(reading.timestamp_ms + self.interval_ms);
+        let mut complete = None;
+        if reading.timestamp_ms >= end_ms {
+            complete = self.take();
+            // Skip intervals without readings, but stay aligned to the first one.
+            let skipped = (reading.timestamp_ms - end_ms) / self.interval_ms;
+            self.end_ms = Some(end_ms + (skipped + 1) * self.interval_ms);
+        }
+        self.inner.add(&reading.reading);
+        self.last_timestamp_ms = reading.timestamp_ms;
+        self.last_sequence = reading.sequence;
+        complete
+    }
+
+    /// Combine the readings of the current, incomplete interval, e.g. on shutdown. `None` if there are none.
+    pub fn take(&mut self) -> Option<TimestampedReading> {
+        self.inner.take().map(|reading| TimestampedReading {
+            reading,
+            timestamp_ms: self.last_timestamp_ms,
+            sequence: self.last_sequence,
+        })
+    }
+}
@@ -0,0 +1,411 @@
+//! Threshold alerts on readings, with hysteresis and optional quiet hours.
+//!
+//! Each [`AlertRule`] watches a single threshold. It is raised when the value crosses the limit, and only cleared once
+//! the value is back by more than the hysteresis, so a value hovering around the limit doesn't produce a flood of
+//! alerts. During quiet hours the state is still tracked, but raising is only reported once the quiet hours are over
+//! (if the condition still holds by then).
+//!
+//! The alerts about the sensor itself, stuck or drifted, come from [`AnomalyDetector`](crate::anomaly::AnomalyDetector)
+//! instead of a rule.
+//!
+//! The caller passes the local hour of the day, since a [`Clock`](crate::Clock) has no time zone.
+use crate::{Co2Value, MonitorReading, TemperatureUnit};
+
+/// What an alert is about.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum AlertKind {
+    /// Co2 is above the limit.
+    Co2High,
+    /// Temperature is below the limit.
+    TemperatureLow,
+    /// Temperature is above the limit.
+    TemperatureHigh,
+    /// Co2 is the same value for too long, see [`crate::anomaly`].
+    SensorStuck,
+    /// The lowest co2 of the last days is far from the outdoor level, see [`crate::anomaly`].
+    SensorDrift,
+}
+
+impl AlertKind {
+    /// The name in JSON and environment variables, e.g. `co2_high`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            AlertKind::Co2High => "co2_high",
+            AlertKind::TemperatureLow => "temperature_low",
+            AlertKind::TemperatureHigh => "temperature_high",
+            AlertKind::SensorStuck => "sensor_stuck",
+            AlertKind::SensorDrift => "sensor_drift",
+        }
+    }
+}
+
+impl core::fmt::Display for AlertKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Whether an alert started or ended.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Transition {
+    /// The condition started to hold (or quiet hours ended while it held).
+    Raised,
+    /// The condition no longer holds.
+    Cleared,
+}
+
+impl Transition {
+    /// `raised` or `cleared`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Transition::Raised => "raised",
+            Transition::Cleared => "cleared",
+        }
+    }
+}
+
+impl core::fmt::Display for Transition {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Reported whenever a rule changes its (reported) state.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AlertEvent {
+    /// Which rule fired.
+    pub kind: AlertKind,
+    /// Whether it was raised or cleared.
+    pub transition: Transition,
+    /// The value that caused the transition, in ppm or degrees Celsius.
+    pub value: f32,
+    /// The limit of the rule. For [`AlertKind::SensorStuck`] it's in minutes, for [`AlertKind::SensorDrift`] it's the
+    /// outdoor level.
+    pub limit: f32,
+}
+
+impl AlertEvent {
+    /// Display the event with temperatures in `unit`. The plain `Display` impl uses Celsius.
+    pub fn display_in(&self, unit: TemperatureUnit) -> AlertDisplay<'_> {
+        AlertDisplay { event: self, unit }
+    }
+
+    /// [`value`](Self::value) and [`limit`](Self::limit), with temperatures in `unit` instead of degrees Celsius.
+    pub fn value_and_limit_in(&self, unit: TemperatureUnit) -> (f32, f32) {
+        match self.kind {
+            AlertKind::TemperatureLow | AlertKind::TemperatureHigh => {
+                (unit.from_celsius(self.value), unit.from_celsius(self.limit))
+            }
+            AlertKind::Co2High | AlertKind::SensorStuck | AlertKind::SensorDrift => {
+                (self.value, self.limit)
+            }
+        }
+    }
+}
+
+impl core::fmt::Display for AlertEvent {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.display_in(TemperatureUnit::Celsius).fmt(f)
+    }
+}
+
+/// Displays an alert event in a specific temperature unit, see [`AlertEvent::display_in`].
+#[derive(Debug)]
+pub struct AlertDisplay<'a> {
+    event: &'a AlertEvent,
+    unit: TemperatureUnit,
+}
+
+impl core::fmt::Display for AlertDisplay<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let event = self.event;
+        let temperature = |celsius| self.unit.from_celsius(celsius);
+        let (what, limit, value, unit) = match (event.kind, event.transition) {
+            (AlertKind::Co2High, _) => ("co2 above", event.limit, event.value, "ppm"),
+            (AlertKind::TemperatureLow, _) => (
+                "temperature below",
+                temperature(event.limit),
+                temperature(event.value),
+                self.unit.symbol(),
+            ),
+            (AlertKind::TemperatureHigh, _) => (
+                "temperature above",
+                temperature(event.limit),
+                temperature(event.value),
+                self.unit.symbol(),
+            ),
+            (AlertKind::SensorStuck, Transition::Raised) => {
+                return write!(
+                    f,
+                    "co2 stuck at {:.0}ppm for {:.0} min, replug the sensor",
+                    event.value, event.limit
+                );
+            }
+            (AlertKind::SensorStuck, Transition::Cleared) => {
+                return write!(f, "co2 no longer stuck at {:.0}ppm", event.value);
+            }
+            (AlertKind::SensorDrift, Transition::Raised) => {
+                return write!(
+                    f,
+                    "lowest co2 of the last days is {:.0}ppm instead of ~{:.0}ppm, recalibrate the sensor",
+                    event.value, event.limit
+                );
+            }
+            (AlertKind::SensorDrift, Transition::Cleared) => {
+                return write!(
+                    f,
+                    "lowest co2 of the last days back at {:.0}ppm",
+                    event.value
+                );
+            }
+        };
+        write!(
+            f,
+            "{what} {limit:.1}{unit} {} at {value:.1}{unit}",
+            event.transition
+        )
+    }
+}
+
+/// A range of hours of the day, during which alerts are not raised. Wraps around midnight if `start > end`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct QuietHours {
+    /// First quiet hour, 0..24.
+    pub start: u8,
+    /// First hour that isn't quiet anymore, 0..24.
+    pub end: u8,
+}
+
+impl QuietHours {
+    /// Quiet from `start` (inclusive) to `end` (exclusive), e.g. `QuietHours::new(22, 7)`.
+    pub const fn new(start: u8, end: u8) -> Self {
+        Self { start, end }
+    }
+
+    /// Whether `hour` is within the quiet hours.
+    pub fn contains(&self, hour: u8) -> bool {
+        let hour = hour % 24;
+        if self.start <= self.end {
+            (self.start..self.end).contains(&hour)
+        } else {
+            hour >= self.start || hour < self.end
+        }
+    }
+}
+
+impl core::fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+/// Returned when parsing [`QuietHours`] from a string that isn't e.g. `22-7`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseQuietHoursError;
+
+impl core::fmt::Display for ParseQuietHoursError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("expected two hours between 0 and 23 separated by '-', e.g. 22-7")
+    }
+}
+
+impl core::error::Error for ParseQuietHoursError {}
+
+impl core::str::FromStr for QuietHours {
+    type Err = ParseQuietHoursError;
+
+    /// Accepts `start-end`, e.g. `22-7`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s.split_once('-').ok_or(ParseQuietHoursError)?;
+        let parse_hour = |h: &str| match h.trim().parse::<u8>() {
+            Ok(h) if h < 24 => Ok(h),
+            _ => Err(ParseQuietHoursError),
+        };
+        Ok(Self::new(parse_hour(start)?, parse_hour(end)?))
+    }
+}
+
+/// A single threshold with hysteresis.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AlertRule {
+    kind: AlertKind,
+    limit: f32,
+    hysteresis: f32,
+    quiet_hours: Option<QuietHours>,
+    active: bool,
+    notified: bool,
+}
+
+impl AlertRule {
+    /// The device can't tell how far above 3000 ppm an out of spec reading is, so it is treated as exactly that.
+    pub const OUT_OF_SPEC_CO2_PPM: f32 = 3000.0;
+
+    fn new(kind: AlertKind, limit: f32, hysteresis: f32) -> Self {
+        Self {
+            kind,
+            limit,
+            hysteresis: hysteresis.max(0.0),
+            quiet_hours: None,
+            active: false,
+            notified: false,
+        }
+    }
+
+    /// Raised when co2 goes above `limit_ppm`, cleared when it drops below `limit_ppm - hysteresis_ppm`.
+    pub fn co2_above(limit_ppm: u16, hysteresis_ppm: u16) -> Self {
+        Self::new(
+            AlertKind::Co2High,
+            f32::from(limit_ppm),
+            f32::from(hysteresis_ppm),
+        )
+    }
+
+    /// Raised when the temperature goes above `limit`, cleared when it drops below `limit - hysteresis`.
+    pub fn temperature_above(limit: f32, hysteresis: f32) -> Self {
+        Self::new(AlertKind::TemperatureHigh, limit, hysteresis)
+    }
+
+    /// Raised when the temperature goes below `limit`, cleared when it rises above `limit + hysteresis`.
+    pub fn temperature_below(limit: f32, hysteresis: f32) -> Self {
+        Self::new(AlertKind::TemperatureLow, limit, hysteresis)
+    }
+
+    /// Don't raise this alert during `quiet_hours`.
+    pub fn with_quiet_hours(mut self, quiet_hours: QuietHours) -> Self {
+        self.quiet_hours = Some(quiet_hours);
+        self
+    }
+
+    /// What this rule is about.
+    pub fn kind(&self) -> AlertKind {
+        self.kind
+    }
+
+    /// Whether the condition currently holds, regardless of quiet hours.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Whether the rule was raised and not cleared since.
+    pub fn is_raised(&self) -> bool {
+        self.notified
+    }
+
+    /// Mark the rule as raised, e.g. because it was before a restart, so it isn't raised again. If the condition
+    /// doesn't hold anymore, the next evaluation clears it.
+    pub fn set_raised(&mut self) {
+        self.active = true;
+        self.notified = true;
+    }
+
+    fn value_of(&self, reading: &MonitorReading) -> f32 {
+        match self.kind {
+            AlertKind::Co2High => match reading.co2_value {
+                Co2Value::Valid(ppm) => f32::from(ppm),
+                _ => Self::OUT_OF_SPEC_CO2_PPM,
+            },
+            AlertKind::TemperatureLow | AlertKind::TemperatureHigh => reading.temperature,
+            // There are no rules for these.
+            AlertKind::SensorStuck | AlertKind::SensorDrift => f32::NAN,
+        }
+    }
+
+    /// Update the rule with a raw value (ppm or degrees Celsius) measured in the local `hour`.
+    pub fn evaluate_value(&mut self, hour: u8, value: f32) -> Option<AlertEvent> {
+        let (beyond_limit, back_to_normal) = match self.kind {
+            AlertKind::TemperatureLow => (value < self.limit, value > self.limit + self.hysteresis),
+            AlertKind::Co2High
+            | AlertKind::TemperatureHigh
+            | AlertKind::SensorStuck
+            | AlertKind::SensorDrift => (value > self.limit, value < self.limit - self.hysteresis),
+        };
+        if beyond_limit {
+            self.active = true;
+        } else if back_to_normal {
+            self.active = false;
+        }
+
+        let quiet = self.quiet_hours.is_some_and(|q| q.contains(hour));
+        let transition = if self.active && !self.notified && !quiet {
+            self.notified = true;
+            Transition::Raised
+        } else if !self.active && self.notified {
+            self.notified = false;
+            Transition::Cleared
+        } else {
+            return None;
+        };
+        Some(AlertEvent {
+            kind: self.kind,
+            transition,
+            value,
+            limit: self.limit,
+        })
+    }
+
+    /// Update the rule with a reading measured in the local `hour`. Co2 that is neither valid nor too high (e.g. of a
+    /// sensor that is still warming up) is ignored.
+    pub fn evaluate(&mut self, hour: u8, reading: &MonitorReading) -> Option<AlertEvent> {
+        if self.kind == AlertKind::Co2High
+            && !matches!(reading.co2_value, Co2Value::Valid(_) | Co2Value::TooHigh(_))
+        {
+            return None;
+        }
+        let value = self.value_of(reading);
+        self.evaluate_value(hour, value)
+    }
+}
+
+/// Too cold / too hot alerts, e.g. for a server closet.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TemperatureAlert {
+    /// Raised when it's too cold.
+    pub too_cold: AlertRule,
+    /// Raised when it's too hot.
+    pub too_hot: AlertRule,
+}
+
+impl TemperatureAlert {
+    /// Alert outside of `min..=max` degrees Celsius, with the same hysteresis on both ends.
+    pub fn new(min: f32, max: f32, hysteresis: f32) -> Self {
+        Self {
+            too_cold: AlertRule::temperature_below(min, hysteresis),
+            too_hot: AlertRule::temperature_above(max, hysteresis),
+        }
+    }
+
+    /// Don't raise either alert during `quiet_hours`.
+    pub fn with_quiet_hours(self, quiet_hours: QuietHours) -> Self {
+        Self {
+            too_cold: self.too_cold.with_quiet_hours(quiet_hours),
+            too_hot: self.too_hot.with_quiet_hours(quiet_hours),
+        }
+    }
+
+    /// Both rules, too cold first.
+    pub fn rules(&self) -> impl Iterator<Item = &AlertRule> {
+        [&self.too_cold, &self.too_hot].into_iter()
+    }
+
+    /// Both rules, too cold first.
+    pub fn rules_mut(&mut self) -> impl Iterator<Item = &mut AlertRule> {
+        [&mut self.too_cold, &mut self.too_hot].into_iter()
+    }
+
+    /// Update both rules with a reading measured in the local `hour`, passing any resulting events to `emit`.
+    pub fn evaluate(
+        &mut self,
+        hour: u8,
+        reading: &MonitorReading,
+        emit: &mut impl FnMut(AlertEvent),
+    ) {
+        for rule in self.rules_mut() {
+            if let Some(event) = rule.evaluate(hour, reading) {
+                emit(event);
+            }
+        }
+    }
+}
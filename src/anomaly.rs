@@ -0,0 +1,173 @@
+//! Notice when the sensor itself goes wrong: stuck on one value, or drifted away from the outdoor level.
+//!
+//! A stuck sensor repeats the same co2 value reading after reading. Real air never holds still to the ppm for long, so
+//! the same value for an hour (by default) raises an [`AlertKind::SensorStuck`] alert. Replugging usually helps.
+//!
+//! Drift is slower. Rooms are aired out or empty at some point, nights and weekends, and then co2 gets close to the
+//! level outside, about [`OUTDOOR_PPM`]. [`AnomalyDetector`] keeps the lowest value of each day, and once the lowest of
+//! the last week (by default) is further than a tolerance from the outdoor level, it raises an
+//! [`AlertKind::SensorDrift`] alert. Below the outdoor level is impossible indoors, so that's certainly the sensor.
+//! Well above it may also be a room that's never aired out, so the tolerance is generous. Recalibrate the sensor
+//! outside (or with a window wide open for a while) to fix it.
+//!
+//! A day is 24 hours of readings since the first one, not a calendar day, see [`DailyMinima`]. Like with
+//! [`AutoBaseline`](crate::abc::AutoBaseline), keep the detector across restarts to not start over every time.
+use crate::{
+    Co2Value, TimestampedReading,
+    alert::{AlertEvent, AlertKind, Transition},
+    stats::DailyMinima,
+};
+
+/// Co2 outside, what the lowest values of a room should get close to.
+pub const OUTDOOR_PPM: f32 = 420.0;
+
+/// The same co2 value for this many minutes is a stuck sensor, unless configured otherwise.
+pub const DEFAULT_STUCK_MINUTES: u32 = 60;
+
+/// The lowest value may be this far from [`OUTDOOR_PPM`], unless configured otherwise.
+pub const DEFAULT_DRIFT_TOLERANCE_PPM: u16 = 150;
+
+/// The lowest value of this many days is compared to [`OUTDOOR_PPM`], unless configured otherwise.
+pub const DEFAULT_DRIFT_DAYS: u8 = 7;
+
+/// The most days the drift check can look back.
+pub const MAX_DRIFT_DAYS: u8 = DailyMinima::MAX_DAYS as u8;
+
+/// Watches the readings for a stuck or drifting sensor, see the [module documentation](self).
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnomalyDetector {
+    stuck_ms: u64,
+    drift_tolerance: f32,
+    drift_days: u8,
+    // The repeated value and since when.
+    same: Option<(u64, u16)>,
+    stuck: bool,
+    minima: DailyMinima,
+    drifted: bool,
+}
+
+impl AnomalyDetector {
+    /// A detector with [`DEFAULT_STUCK_MINUTES`], [`DEFAULT_DRIFT_TOLERANCE_PPM`] and [`DEFAULT_DRIFT_DAYS`].
+    pub const fn new() -> Self {
+        Self {
+            stuck_ms: DEFAULT_STUCK_MINUTES as u64 * 60 * 1000,
+            drift_tolerance: DEFAULT_DRIFT_TOLERANCE_PPM as f32,
+            drift_days: DEFAULT_DRIFT_DAYS,
+            same: None,
+            stuck: false,
+            minima: DailyMinima::new(),
+            drifted: false,
+        }
+    }
+
+    /// Raise the stuck alert after the same value for `minutes`, 0 turns the check off.
+    pub const fn with_stuck_minutes(mut self, minutes: u32) -> Self {
+        self.stuck_ms = minutes as u64 * 60 * 1000;
+        self
+    }
+
+    /// Raise the drift alert when the lowest value of `days` days is more than `tolerance_ppm` from [`OUTDOOR_PPM`].
+    /// `days` is at most [`MAX_DRIFT_DAYS`], 0 turns the check off.
+    pub const fn with_drift(mut self, tolerance_ppm: u16, days: u8) -> Self {
+        self.drift_tolerance = tolerance_ppm as f32;
+        self.drift_days = if days > MAX_DRIFT_DAYS {
+            MAX_DRIFT_DAYS
+        } else {
+            days
+        };
+        self
+    }
+
+    /// The lowest co2 of the last days the drift check looks at, once there are that many complete days.
+    pub fn floor_ppm(&self) -> Option<f32> {
+        self.minima.minimum(usize::from(self.drift_days))
+    }
+
+    /// Take over what `saved` learned and which alerts it raised, e.g. before a restart, but keep the own settings.
+    pub fn restore(&mut self, saved: &Self) {
+        self.same = saved.same;
+        self.stuck = saved.stuck;
+        self.minima = saved.minima;
+        self.drifted = saved.drifted;
+    }
+
+    /// Whether the stuck alert is raised.
+    pub fn is_stuck(&self) -> bool {
+        self.stuck
+    }
+
+    /// Whether the drift alert is raised.
+    pub fn is_drifted(&self) -> bool {
+        self.drifted
+    }
+
+    /// Add a reading, passing any resulting alerts to `emit`. The readings have to be in chronological order, out of
+    /// spec values are skipped. For stuck values, [`AlertEvent::limit`] is the minutes after which it counts as stuck,
+    /// for drift it's [`OUTDOOR_PPM`].
+    pub fn observe(&mut self, reading: &TimestampedReading, emit: &mut impl FnMut(AlertEvent)) {
+        let Co2Value::Valid(ppm) = reading.reading.co2_value else {
+            return;
+        };
+        let timestamp_ms = reading.timestamp_ms;
+        if let Some(event) = self.observe_stuck(timestamp_ms, ppm) {
+            emit(event);
+        }
+        if let Some(event) = self.observe_drift(timestamp_ms, f32::from(ppm)) {
+            emit(event);
+        }
+    }
+
+    fn observe_stuck(&mut self, timestamp_ms: u64, ppm: u16) -> Option<AlertEvent> {
+        let since_ms = match self.same {
+            Some((since_ms, same_ppm)) if same_ppm == ppm && timestamp_ms >= since_ms => since_ms,
+            _ => {
+                self.same = Some((timestamp_ms, ppm));
+                timestamp_ms
+            }
+        };
+        let stuck = self.stuck_ms > 0 && timestamp_ms - since_ms >= self.stuck_ms;
+        if stuck == self.stuck {
+            return None;
+        }
+        self.stuck = stuck;
+        Some(AlertEvent {
+            kind: AlertKind::SensorStuck,
+            transition: if stuck {
+                Transition::Raised
+            } else {
+                Transition::Cleared
+            },
+            value: f32::from(ppm),
+            limit: (self.stuck_ms / 60_000) as f32,
+        })
+    }
+
+    fn observe_drift(&mut self, timestamp_ms: u64, ppm: f32) -> Option<AlertEvent> {
+        if !self.minima.push(timestamp_ms, ppm) {
+            return None;
+        }
+        let floor = self.floor_ppm()?;
+        let drifted = (floor - OUTDOOR_PPM).abs() > self.drift_tolerance;
+        if drifted == self.drifted {
+            return None;
+        }
+        self.drifted = drifted;
+        Some(AlertEvent {
+            kind: AlertKind::SensorDrift,
+            transition: if drifted {
+                Transition::Raised
+            } else {
+                Transition::Cleared
+            },
+            value: floor,
+            limit: OUTDOOR_PPM,
+        })
+    }
+}
+
+impl Default for AnomalyDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
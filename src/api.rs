@@ -0,0 +1,417 @@
+//! A small HTTP API, so other services and apps on the LAN can ask for the latest readings and the history.
+//!
+//! [`ApiServer`] listens in the background and answers `GET` requests with JSON:
+//!
+//! | Endpoint                                    | Answer                                                       |
+//! |---------------------------------------------|--------------------------------------------------------------|
+//! | `/api/devices`                              | The devices with a reading, `[{"id": …, "timestamp_ms": …}]` |
+//! | `/api/devices/{id}/current`                 | The latest reading of the device, with the derived values    |
+//! | `/api/devices/{id}/history?from=&to=&step=` | [`HistoryPoint`]s of the device from the csv log             |
+//!
+//! The id is the serial number of the device (or its path, percent-encoded), or the room of a
+//! [`Fleet`](crate::fleet::Fleet). For the history, `from` and `to` are local times like `2025-01-31` or
+//! `2025-01-31T08:00:00` (both optional), and `step` is the bucket size in seconds, an hour by default and a year at
+//! most. Rows of the log without a device or room column count for every device. Timestamps in answers are
+//! milliseconds since the Unix epoch, temperatures are in degrees Celsius. The derived values are the ones the logger
+//! passes to [`ApiServer::publish_with`], e.g. `co2_deviation`, as more fields of the current reading.
+//!
+//! Every connection gets a thread, up to [`MAX_CONNECTIONS`] at a time, further ones are answered with 503 right away.
+//!
+//! With a token in [`Access`], every request needs an `Authorization: Bearer <token>` header, or is answered with 401.
+//! With a CORS origin, answers carry `Access-Control-Allow-Origin`, and preflight `OPTIONS` requests are answered, so a
+//! web app from that origin can use the API. It's plain HTTP, like the Grafana sink: the token is sent in the clear,
+//! so keep it on a network you trust, or put a TLS proxy in front of it.
+use crate::{
+    Co2Quality, MonitorReading, TimestampedReading,
+    export::{in_range, parse_timestamp},
+    history::{HistoryPoint, Resampler},
+    log_format::{Co2State, LogReader},
+};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    format,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    string::{String, ToString},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+    vec::Vec,
+};
+
+/// The port, when none is given.
+pub const DEFAULT_PORT: u16 = 8080;
+
+/// Bucket size of the history when the request has no `step`, in seconds.
+pub const DEFAULT_STEP_SECONDS: u64 = 3600;
+
+/// Largest `step` of the history, in seconds: a year.
+pub const MAX_STEP_SECONDS: u64 = 366 * 24 * 3600;
+
+/// Connections that are answered at the same time, every one has its own thread. More are answered with 503.
+pub const MAX_CONNECTIONS: usize = 16;
+
+/// Requests with a larger head than this are refused, nothing this API takes needs more.
+const MAX_HEAD_BYTES: u64 = 16 * 1024;
+
+/// Who may use the API, see the [module documentation](self).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Access {
+    /// Token clients have to send as `Authorization: Bearer <token>`. Without it, anyone who can connect may ask.
+    pub token: Option<String>,
+    /// Origin of web apps that may use the API, e.g. `https://dashboard.example.org`, or `*` for any.
+    pub cors_origin: Option<String>,
+}
+
+/// The latest reading of a device.
+#[derive(Clone, Debug)]
+struct Latest {
+    reading: MonitorReading,
+    derived: Vec<(String, f32)>,
+    timestamp_ms: u64,
+}
+
+#[derive(Serialize)]
+struct DeviceJson<'a> {
+    id: &'a str,
+    timestamp_ms: u64,
+}
+
+#[derive(Serialize)]
+struct CurrentJson<'a> {
+    id: &'a str,
+    timestamp_ms: u64,
+    co2_ppm: u16,
+    co2_state: Co2State,
+    temperature: f32,
+    humidity: Option<f32>,
+    quality: Co2Quality,
+    #[serde(flatten)]
+    derived: BTreeMap<&'a str, f32>,
+}
+
+/// State shared with the connections.
+#[derive(Debug)]
+struct Shared {
+    access: Access,
+    log: Option<PathBuf>,
+    devices: Mutex<BTreeMap<String, Latest>>,
+}
+
+/// Serves the API in the background, see the [module documentation](self).
+#[derive(Debug)]
+pub struct ApiServer {
+    shared: Arc<Shared>,
+    local_addr: SocketAddr,
+}
+
+impl ApiServer {
+    /// Listen on `address` (`address[:port]`, the port defaults to [`DEFAULT_PORT`]), with the history from the csv
+    /// log at `log`. Without a log, the history is answered with 404.
+    pub fn bind(address: &str, access: Access, log: Option<PathBuf>) -> io::Result<Self> {
+        let address = if address.contains(':') {
+            String::from(address)
+        } else {
+            format!("{address}:{DEFAULT_PORT}")
+        };
+        let listener = TcpListener::bind(address)?;
+        let local_addr = listener.local_addr()?;
+        let shared = Arc::new(Shared {
+            access,
+            log,
+            devices: Mutex::new(BTreeMap::new()),
+        });
+        let accepting = Arc::clone(&shared);
+        let open = Arc::new(AtomicUsize::new(0));
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        event!(warn, "could not accept an api connection: {e}");
+                        continue;
+                    }
+                };
+                if open.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+                    open.fetch_sub(1, Ordering::Relaxed);
+                    event!(debug, "too many api connections, refusing one");
+                    let busy = error(503, "Service Unavailable", "too many connections");
+                    let _ = stream
+                        .set_write_timeout(Some(Duration::from_secs(1)))
+                        .and_then(|()| write_response(&stream, &accepting.access, busy));
+                    continue;
+                }
+                let (shared, open) = (Arc::clone(&accepting), Arc::clone(&open));
+                std::thread::spawn(move || {
+                    if let Err(e) = serve(stream, &shared) {
+                        event!(debug, "api connection failed: {e}");
+                    }
+                    open.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+        });
+        event!(info, "serving the api on {local_addr}");
+        Ok(Self { shared, local_addr })
+    }
+
+    /// Where the server listens, e.g. to find the port after binding to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Make `reading`, taken at `timestamp_ms` since the Unix epoch, the latest one of `device`.
+    pub fn publish(&self, device: &str, reading: &MonitorReading, timestamp_ms: u64) {
+        self.publish_with(device, reading, &[], timestamp_ms);
+    }
+
+    /// Like [`publish`](Self::publish), with more values that are answered along with the reading, e.g. the deviation
+    /// from the typical co2 as `co2_deviation`.
+    pub fn publish_with(
+        &self,
+        device: &str,
+        reading: &MonitorReading,
+        fields: &[(&str, f32)],
+        timestamp_ms: u64,
+    ) {
+        let latest = Latest {
+            reading: *reading,
+            derived: fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), *value))
+                .collect(),
+            timestamp_ms,
+        };
+        self.shared
+            .devices
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(device.to_string(), latest);
+    }
+}
+
+/// An answer: status code, reason and JSON body.
+type Response = (u16, &'static str, String);
+
+fn error(status: u16, reason: &'static str, message: &str) -> Response {
+    let body = serde_json::json!({ "error": message }).to_string();
+    (status, reason, body)
+}
+
+fn json(value: &impl Serialize) -> Response {
+    match serde_json::to_string(value) {
+        Ok(body) => (200, "OK", body),
+        Err(e) => error(500, "Internal Server Error", &e.to_string()),
+    }
+}
+
+/// Answer the single request on `stream`, then close it.
+fn serve(stream: TcpStream, shared: &Shared) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut reader = BufReader::new((&stream).take(MAX_HEAD_BYTES));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut authorization = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "incomplete request",
+            ));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("authorization")
+        {
+            authorization = Some(value.trim().to_string());
+        }
+    }
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let response = if method == "OPTIONS" {
+        // Preflight requests of browsers come without the token.
+        (204, "No Content", String::new())
+    } else if !authorized(&shared.access, authorization.as_deref()) {
+        error(401, "Unauthorized", "missing or wrong token")
+    } else if method != "GET" {
+        error(405, "Method Not Allowed", "only GET is supported")
+    } else {
+        respond(shared, target)
+    };
+    write_response(&stream, &shared.access, response)
+}
+
+/// Whether `authorization` (the header value) passes `access`.
+fn authorized(access: &Access, authorization: Option<&str>) -> bool {
+    let Some(token) = &access.token else {
+        return true;
+    };
+    let Some(sent) = authorization.and_then(|value| value.strip_prefix("Bearer ")) else {
+        return false;
+    };
+    // Compare all bytes, so the time doesn't tell how much of the token was right.
+    sent.len() == token.len()
+        && sent
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+fn write_response(mut stream: &TcpStream, access: &Access, response: Response) -> io::Result<()> {
+    let (status, reason, body) = response;
+    let mut head = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        body.len()
+    );
+    if let Some(origin) = &access.cors_origin {
+        head += &format!(
+            "Access-Control-Allow-Origin: {origin}\r\nAccess-Control-Allow-Methods: GET, OPTIONS\r\n\
+             Access-Control-Allow-Headers: Authorization\r\nVary: Origin\r\n"
+        );
+    }
+    if status == 401 {
+        head += "WWW-Authenticate: Bearer\r\n";
+    }
+    head += "\r\n";
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body.as_bytes())?;
+    stream.flush()
+}
+
+/// Answer a `GET` of `target`, the path and query.
+fn respond(shared: &Shared, target: &str) -> Response {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let segments: Vec<String> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(percent_decode)
+        .collect();
+    let devices = shared.devices.lock().unwrap_or_else(|e| e.into_inner());
+    match segments.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["api", "devices"] => json(
+            &devices
+                .iter()
+                .map(|(id, latest)| DeviceJson {
+                    id,
+                    timestamp_ms: latest.timestamp_ms,
+                })
+                .collect::<Vec<_>>(),
+        ),
+        ["api", "devices", id, "current"] => match devices.get(id) {
+            Some(latest) => json(&current(id, latest)),
+            None => error(404, "Not Found", "no such device"),
+        },
+        ["api", "devices", id, "history"] => {
+            if !devices.contains_key(id) {
+                return error(404, "Not Found", "no such device");
+            }
+            // Reading the log takes a while, don't hold up publishing.
+            drop(devices);
+            history(shared, id, query)
+        }
+        _ => error(404, "Not Found", "no such endpoint"),
+    }
+}
+
+fn current<'a>(id: &'a str, latest: &'a Latest) -> CurrentJson<'a> {
+    let reading = &latest.reading;
+    CurrentJson {
+        id,
+        timestamp_ms: latest.timestamp_ms,
+        co2_ppm: reading.co2_value.as_num_and_bool().0,
+        co2_state: Co2State::from(reading.co2_value),
+        temperature: reading.temperature,
+        humidity: reading.humidity,
+        quality: reading.quality(),
+        derived: latest
+            .derived
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+            .collect(),
+    }
+}
+
+/// The history of `id` from the log, with `from`, `to` and `step` in `query`.
+fn history(shared: &Shared, id: &str, query: &str) -> Response {
+    let Some(log) = &shared.log else {
+        return error(404, "Not Found", "there's no log to take the history from");
+    };
+    let (mut from, mut to, mut step) = (None, None, DEFAULT_STEP_SECONDS);
+    for (name, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+        let value = percent_decode(value);
+        let valid = match name {
+            "from" => parse_timestamp(&value).map(|t| from = Some(t)).is_ok(),
+            "to" => parse_timestamp(&value).map(|t| to = Some(t)).is_ok(),
+            "step" => value
+                .parse()
+                .ok()
+                .filter(|&seconds: &u64| seconds <= MAX_STEP_SECONDS)
+                .map(|seconds| step = seconds.max(1))
+                .is_some(),
+            _ => true,
+        };
+        if !valid {
+            return error(400, "Bad Request", &format!("invalid {name}: {value}"));
+        }
+    }
+    let reader = match LogReader::open(log) {
+        Ok(reader) => reader,
+        Err(e) => return error(500, "Internal Server Error", &e.to_string()),
+    };
+    let mut resampler = Resampler::new(step * 1000);
+    let mut points = Vec::new();
+    // The logger may be writing the last row right now, skip what can't be read.
+    for row in reader.flatten() {
+        let device = row.device.as_deref().or(row.room.as_deref());
+        if !in_range(&row, from, to) || device.is_some_and(|device| device != id) {
+            continue;
+        }
+        let Some(timestamp) = row.timestamp.and_local_timezone(chrono::Local).earliest() else {
+            continue;
+        };
+        let timestamped = TimestampedReading {
+            reading: row.to_reading(),
+            timestamp_ms: timestamp.timestamp_millis().max(0) as u64,
+            sequence: 0,
+        };
+        resampler.push(&timestamped, &mut |point: HistoryPoint| points.push(point));
+    }
+    resampler.finish(&mut |point| points.push(point));
+    json(&points)
+}
+
+/// Decode `%xx` escapes (and `+` as space), leaving malformed ones as they are.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| core::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,131 @@
+//! Choose how to talk to the monitor at runtime, e.g. from a command line flag or the config.
+//!
+//! [`Backend::connect`] returns a `Box<dyn Co2MonitorCommunication>`, so the code reading from it doesn't have to be
+//! generic over the transport.
+use crate::{
+    capture::ReplayMonitor, device::Co2MonitorCommunication, pc::PcCo2Monitor,
+    profiles::DeviceProfile, simulate::SimulatedCo2Monitor,
+};
+use std::{boxed::Box, io, path::PathBuf};
+
+/// A way to get readings.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Backend {
+    /// The device, through hidapi.
+    #[default]
+    Hidapi,
+    /// The device, through libusb, see [`UsbCo2Monitor`](crate::libusb::UsbCo2Monitor).
+    #[cfg(feature = "libusb")]
+    Libusb,
+    /// The device, through its `/dev/hidraw*` node, see [`HidrawCo2Monitor`](crate::hidraw::HidrawCo2Monitor).
+    #[cfg(all(target_os = "linux", feature = "hidraw"))]
+    Hidraw,
+    /// [`SimulatedCo2Monitor`], no device needed.
+    Simulate,
+    /// Play back a capture file in its original timing, see [`ReplayMonitor`].
+    Replay(PathBuf),
+}
+
+impl Backend {
+    /// Whether readings come from an actual device, as opposed to being simulated or replayed.
+    pub fn is_device(&self) -> bool {
+        match self {
+            Backend::Hidapi => true,
+            #[cfg(feature = "libusb")]
+            Backend::Libusb => true,
+            #[cfg(all(target_os = "linux", feature = "hidraw"))]
+            Backend::Hidraw => true,
+            Backend::Simulate | Backend::Replay(_) => false,
+        }
+    }
+
+    /// Connect to the backend. Like [`PcCo2Monitor::init_and_connect`], hidapi panics if the device can't be opened.
+    pub fn connect(&self) -> io::Result<Box<dyn Co2MonitorCommunication>> {
+        Ok(match self {
+            Backend::Hidapi => Box::new(PcCo2Monitor::init_and_connect()),
+            #[cfg(feature = "libusb")]
+            Backend::Libusb => Box::new(
+                crate::libusb::UsbCo2Monitor::open()
+                    .map_err(|e| io::Error::other(std::format!("libusb: {e}")))?,
+            ),
+            #[cfg(all(target_os = "linux", feature = "hidraw"))]
+            Backend::Hidraw => Box::new(crate::hidraw::HidrawCo2Monitor::open()?),
+            Backend::Simulate => Box::new(SimulatedCo2Monitor::init_and_connect()),
+            Backend::Replay(path) => Box::new(ReplayMonitor::open(path, 1.0)?),
+        })
+    }
+
+    /// Like `connect`, but a device is opened with the ids and the key of `profile`, and hidapi fails instead of
+    /// panicking.
+    pub fn connect_with_profile(
+        &self,
+        profile: DeviceProfile,
+    ) -> io::Result<Box<dyn Co2MonitorCommunication>> {
+        match self {
+            Backend::Hidapi => PcCo2Monitor::connect_with_profile(profile)
+                .map(|monitor| Box::new(monitor) as Box<dyn Co2MonitorCommunication>)
+                .map_err(|e| io::Error::other(std::format!("hidapi: {e:?}"))),
+            #[cfg(feature = "libusb")]
+            Backend::Libusb => Ok(Box::new(
+                crate::libusb::UsbCo2Monitor::open_with_profile(profile)
+                    .map_err(|e| io::Error::other(std::format!("libusb: {e}")))?,
+            )),
+            #[cfg(all(target_os = "linux", feature = "hidraw"))]
+            Backend::Hidraw => Ok(Box::new(
+                crate::hidraw::HidrawCo2Monitor::open_with_profile(profile)?,
+            )),
+            Backend::Simulate | Backend::Replay(_) => self.connect(),
+        }
+    }
+}
+
+impl core::fmt::Display for Backend {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Backend::Hidapi => f.write_str("hidapi"),
+            #[cfg(feature = "libusb")]
+            Backend::Libusb => f.write_str("libusb"),
+            #[cfg(all(target_os = "linux", feature = "hidraw"))]
+            Backend::Hidraw => f.write_str("hidraw"),
+            Backend::Simulate => f.write_str("simulate"),
+            Backend::Replay(path) => write!(f, "replay:{}", path.display()),
+        }
+    }
+}
+
+/// Returned when parsing [`Backend`] from an unknown string.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseBackendError;
+
+impl core::fmt::Display for ParseBackendError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(
+            "expected hidapi, libusb (with the `libusb` feature), hidraw (linux with the `hidraw` feature), simulate \
+             or replay:<file>",
+        )
+    }
+}
+
+impl core::error::Error for ParseBackendError {}
+
+impl core::str::FromStr for Backend {
+    type Err = ParseBackendError;
+
+    /// Accepts `hidapi`, `libusb` (with the `libusb` feature), `hidraw` (linux with the `hidraw` feature), `simulate`
+    /// and `replay:<file>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hidapi" => Ok(Backend::Hidapi),
+            #[cfg(feature = "libusb")]
+            "libusb" => Ok(Backend::Libusb),
+            #[cfg(all(target_os = "linux", feature = "hidraw"))]
+            "hidraw" => Ok(Backend::Hidraw),
+            "simulate" => Ok(Backend::Simulate),
+            _ => match s.strip_prefix("replay:") {
+                Some(path) if !path.is_empty() => Ok(Backend::Replay(path.into())),
+                _ => Err(ParseBackendError),
+            },
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Learns which co2 levels are typical for each hour of the day, so unusual readings can be spotted even when they are
+//! below any absolute threshold. 900 ppm at 7 a.m. might be perfectly normal in an office, but not in a bedroom that
+//! usually sits at 450 ppm by then.
+//!
+//! The caller passes the local hour of the day along with each value, a [`Clock`](crate::Clock) only counts
+//! milliseconds. It takes weeks to learn, so the logger keeps it in its [state file](crate::state) and hands the
+//! deviation to the sinks as `co2_deviation`.
+use crate::{Co2Value, MonitorReading};
+
+/// Per hour of the day, an exponentially weighted mean of co2 and of the absolute deviation from that mean.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HourlyBaseline {
+    mean: [f32; 24],
+    spread: [f32; 24],
+    count: [u32; 24],
+    alpha: f32,
+    min_samples: u32,
+}
+
+/// How a value compares to the learned baseline of its hour.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Deviation {
+    /// The typical co2 ppm for this hour.
+    pub expected: f32,
+    /// How many ppm the value is above (positive) or below (negative) `expected`.
+    pub delta: f32,
+    /// `delta` in units of the typical deviation for this hour. Values beyond ~3 are unusual.
+    pub score: f32,
+}
+
+impl HourlyBaseline {
+    /// With one reading every ~5 seconds, this averages over roughly the last two weeks of each hour.
+    pub const DEFAULT_ALPHA: f32 = 1.0 / 10_000.0;
+    /// Don't report deviations before an hour has seen about one hour of readings.
+    pub const DEFAULT_MIN_SAMPLES: u32 = 720;
+
+    /// Create an empty baseline with the default smoothing.
+    pub fn new() -> Self {
+        Self::with_alpha(Self::DEFAULT_ALPHA, Self::DEFAULT_MIN_SAMPLES)
+    }
+
+    /// Create an empty baseline. Smaller `alpha` values remember longer. No deviation is reported for an hour until it
+    /// has seen `min_samples` values.
+    pub fn with_alpha(alpha: f32, min_samples: u32) -> Self {
+        Self {
+            mean: [0.0; 24],
+            spread: [0.0; 24],
+            count: [0; 24],
+            alpha,
+            min_samples,
+        }
+    }
+
+    /// Learn from a co2 value measured in the given local `hour` (0..24, larger values wrap).
+    pub fn observe(&mut self, hour: u8, co2_ppm: u16) {
+        let h = usize::from(hour % 24);
+        let value = f32::from(co2_ppm);
+        self.count[h] = self.count[h].saturating_add(1);
+        // Plain average while there are few samples, so the first values don't get drowned by the initial zero.
+        let alpha = (1.0 / self.count[h] as f32).max(self.alpha);
+        let delta = value - self.mean[h];
+        self.mean[h] += alpha * delta;
+        self.spread[h] += alpha * (delta.abs() - self.spread[h]);
+    }
+
+    /// Learn from a reading, ignoring it if its co2 value is out of spec.
+    pub fn observe_reading(&mut self, hour: u8, reading: &MonitorReading) {
+        if let Co2Value::Valid(ppm) = reading.co2_value {
+            self.observe(hour, ppm);
+        }
+    }
+
+    /// Take over what `saved` learned, e.g. before a restart, but keep the own settings.
+    pub fn restore(&mut self, saved: &Self) {
+        self.mean = saved.mean;
+        self.spread = saved.spread;
+        self.count = saved.count;
+    }
+
+    /// The typical co2 ppm for `hour`, if enough values were seen.
+    pub fn expected(&self, hour: u8) -> Option<f32> {
+        let h = usize::from(hour % 24);
+        (self.count[h] >= self.min_samples).then_some(self.mean[h])
+    }
+
+    /// Compare `co2_ppm` to the baseline of `hour`. `None` until enough values were seen for that hour.
+    pub fn deviation(&self, hour: u8, co2_ppm: u16) -> Option<Deviation> {
+        let expected = self.expected(hour)?;
+        let delta = f32::from(co2_ppm) - expected;
+        // Very stable hours would otherwise turn every tiny change into a huge score.
+        const MIN_SPREAD_PPM: f32 = 10.0;
+        let spread = self.spread[usize::from(hour % 24)].max(MIN_SPREAD_PPM);
+        Some(Deviation {
+            expected,
+            delta,
+            score: delta / spread,
+        })
+    }
+}
+
+impl Default for HourlyBaseline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
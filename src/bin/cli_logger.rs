@@ -1,76 +1,2376 @@
 //! Whenever a new complete reading is available, logs it *as a whole*. When the sensor reading didn't change, a
 //! simple '.' is appended to the line. In addition writes readings to a csv file.
-use chrono::{Local, NaiveDateTime};
+//!
+//! With `--dump-raw`, every raw HID report (opcode and value) is printed instead, which helps to figure out what the
+//! undocumented opcodes mean.
+//!
+//! With `--min-temperature` and/or `--max-temperature`, a warning is printed whenever the temperature leaves that
+//! range, likewise with `--max-co2`. `--sample-interval` averages the readings over a longer interval before they're
+//! logged, for less chatty logs. With `--zabbix-server` and `--zabbix-host`, logged readings are also sent to Zabbix,
+//! and with the `[grafana]` section of the config to Grafana Live (see `co2_monitor::grafana`).
+//! `--modbus-listen` serves the latest reading as Modbus TCP input registers (see `co2_monitor::modbus`), and
+//! `--api-listen` an HTTP API with the latest readings and the history (see `co2_monitor::api`). With the
+//! `dbus` feature, `--dbus` publishes every reading on the session bus (see `co2_monitor::dbus`). With the `tts`
+//! feature, `--announce` also speaks these warnings. With the `knx` feature, logged readings go to the group addresses
+//! in the `[knx]` section of the config (see `co2_monitor::knx`). `--stdio-json` doesn't log, but talks newline
+//! delimited JSON on stdin and stdout, for other programs like Node-RED (see `co2_monitor::stdio_json`). With the
+//! `esphome` feature, `--esphome-listen` serves readings like an ESPHome device (see `co2_monitor::esphome`).
+//!
+//! Printed readings include the co2 trend over the last 10 minutes, and with `--max-co2` also when co2 will reach that
+//! limit at the current rate. With `--room-volume` (and `--occupants`), they also say when to ventilate and for how
+//! long, and with the `occupancy` feature about how many people are in the room.
+//!
+//! `--state-file` keeps the software ABC, the anomaly detector and raised alerts across restarts, per device (see
+//! `co2_monitor::state`). It's saved every few minutes and when something changed.
+//!
+//! Which warnings are printed and spoken, and when, is up to the `[notifications]` section of the config (see
+//! `co2_monitor::notification`): at most one every so often, only critical ones at night, and an escalation.
+//! `--hook` runs a shell command on them (or on readings), see `co2_monitor::hook`.
+//!
+//! Defaults for most options can be set in a config file, see `co2_monitor::config`. Flags take precedence.
+//!
+//! With `--simulate`, readings come from a simulated monitor instead of the device, and go to a separate csv file.
+//! `--backend` picks the source more generally, e.g. `--backend replay:capture.txt` logs a capture as if it came from
+//! the device.
+//!
+//! The `analyze` subcommand summarizes an existing log instead of reading from the device, `export` converts it, `info` describes the
+//! connected device, `bench` measures the connection to it and `doctor` explains why it can't be opened. `capture`
+//! records the raw reads of the device to a file, and `replay` plays such a file back. `status` prints a single
+//! reading for status bars, and `fleet` logs all connected monitors with the room they're in.
+//!
+//! On macOS, `install-agent` runs the logger at login with launchd. On windows with the `windows-service` feature, `service` installs the logger as a windows service.
+use chrono::{Local, NaiveDateTime, Timelike};
+use clap::{Args, Parser, Subcommand};
+#[cfg(feature = "esphome")]
+use co2_monitor::esphome::{EsphomeDevice, EsphomeServer};
+#[cfg(target_os = "macos")]
+use co2_monitor::launchd;
+#[cfg(feature = "mdns")]
+use co2_monitor::mdns::{self, MdnsAdvertiser};
+#[cfg(feature = "tts")]
+use co2_monitor::speech::{Announcer, notification_text, summary_text};
 use co2_monitor::{
-    MonitorReading, MonitorReadingParts, device::Co2MonitorCommunication, pc::PcCo2Monitor,
+    Co2MonitorExt, Co2Value, Conversions, MonitorError, MonitorHealth, MonitorReading,
+    MonitorReadingParts, MonitorReportRaw, OPCODE_CO2_SANITY_CHECK, OPCODE_CO2_VALUE,
+    OPCODE_HUMIDITY, OPCODE_TEMPERATURE, TemperatureUnit, TimestampedReading,
+    advisor::Advice,
+    aggregate::IntervalAggregator,
+    alert::QuietHours,
+    api::ApiServer,
+    backend::Backend,
+    baseline::HourlyBaseline,
+    cadence::CadenceTracker,
+    capture::{CaptureWriter, CapturedRead, ReplayMonitor},
+    chart::Chart,
+    config::{
+        ApiConfig, CalibrationConfig, Config, DeviceConfig, HookConfig, IntervalConfig,
+        ModbusConfig, PipelineConfig, RoomConfig, SinkConfig, ThresholdConfig, UnitConfig,
+        ZabbixConfig,
+    },
+    cross_check::ChannelCrossCheck,
+    device::Co2MonitorCommunication,
+    diagnostics::{self, Diagnosis},
+    export::{self, ClockCorrection, ExportFormat, ExportWriter},
+    fleet::{Fleet, worst_room},
+    forecast::Forecast,
+    history::{HistoryPoint, Resampler},
+    hook::HookTrigger,
+    log_format::{DEFAULT_LOG_NAME, LogReader, LogWriter, Row},
+    modbus::ModbusServer,
+    pc::{self, DeviceEvent, DeviceInfo, DeviceWatcher, PcCo2Monitor},
+    pipeline::{Pipeline, Stage},
+    policy::{ReadOutcome, ReadPolicy},
+    profiles,
+    report::{Report, ReportBuilder, ReportFormat},
+    sink::SinkQueue,
+    spool::{self, Spool},
+    state::{DeviceState, StateFile},
+    stats::{READINGS_PER_DAY, RollingStats},
+    status::{StatusFormat, format_status},
+    stdio_json::{Message, Session},
+    trend::Trend,
+    ventilation::VentilationDetector,
+    warmup::{self, WarmUp},
+    zabbix::ZabbixSender,
 };
 use serde::Serialize;
-use std::{io::Write, time::Duration};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-#[derive(Serialize)]
-struct Row {
-    timestamp: NaiveDateTime,
-    temperature: f32,
-    co2_ppm: usize,
-    co2_is_valid: bool,
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Log readings of a ZGm053U co2 monitor to a csv file."
+)]
+struct Cli {
+    /// Diagnostic output on stderr: error, warn, info, debug or trace.
+    #[arg(long, global = true, default_value = "warn")]
+    log_level: tracing::Level,
+
+    /// Config file to use instead of `~/.config/co2-monitor/config.toml`.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    #[command(flatten)]
+    log: LogArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Options for logging, i.e. when no subcommand is given.
+#[derive(Args)]
+struct LogArgs {
+    /// Read from a simulated monitor instead of the device, and log to `simulated-log.csv` by default. Short for
+    /// `--backend simulate`.
+    #[arg(long)]
+    simulate: bool,
+
+    /// How to read from the monitor: hidapi, libusb (with the `libusb` feature), hidraw (linux with the `hidraw`
+    /// feature), simulate or replay:<file>. Simulated and replayed readings go to `simulated-log.csv` by default
+    /// [default: hidapi].
+    #[arg(long)]
+    backend: Option<Backend>,
+
+    /// The csv file to log to [default: log.csv].
+    #[arg(long)]
+    csv: Option<PathBuf>,
+
+    /// Add humidity, device, quality and the raw sanity check value to the csv log. Only applies to new logs, an
+    /// existing log keeps its columns.
+    #[arg(long)]
+    extended_columns: bool,
+
+    /// Print every raw HID report instead of the readings.
+    #[arg(long)]
+    dump_raw: bool,
+
+    /// Don't log, take JSON commands on stdin and write readings and alerts as JSON lines to stdout instead, for
+    /// wrapping the logger in other programs like Node-RED. See `co2_monitor::stdio_json`.
+    #[arg(long)]
+    stdio_json: bool,
+
+    /// Unit for printed temperatures and the temperature options: C, F or K. The csv log is always in Celsius
+    /// [default: C].
+    #[arg(long)]
+    unit: Option<TemperatureUnit>,
+
+    /// Conversions of the raw reports: legacy or v2. Changing this on an existing log can make its series jump a bit
+    /// [default: legacy, or v2 with the `v2-conversions` feature].
+    #[arg(long)]
+    conversions: Option<Conversions>,
+
+    /// Warn when the temperature drops below this (in `--unit`).
+    #[arg(long, allow_hyphen_values = true)]
+    min_temperature: Option<f32>,
+
+    /// Warn when the temperature rises above this (in `--unit`).
+    #[arg(long, allow_hyphen_values = true)]
+    max_temperature: Option<f32>,
+
+    /// How far the temperature has to be back within range before a warning is cleared [default: 0.5].
+    #[arg(long)]
+    temperature_hysteresis: Option<f32>,
+
+    /// Warn when co2 rises above this many ppm.
+    #[arg(long)]
+    max_co2: Option<u16>,
+
+    /// Hours in which no warnings are raised, e.g. `22-7`.
+    #[arg(long)]
+    quiet_hours: Option<QuietHours>,
+
+    /// Log one averaged reading every this many seconds instead of every reading.
+    #[arg(long)]
+    sample_interval: Option<u64>,
+
+    /// Only log (and send) readings that differ from the last logged one.
+    #[arg(long)]
+    on_change: bool,
+
+    /// With `--on-change`, log an unchanged reading anyway when nothing was logged for this many seconds.
+    #[arg(long)]
+    heartbeat: Option<u64>,
+
+    /// Volume of the room in m³, to advise when and how long to ventilate.
+    #[arg(long)]
+    room_volume: Option<f32>,
+
+    /// How many people are in the room, for the ventilation advice [default: 1].
+    #[arg(long)]
+    occupants: Option<f32>,
+
+    /// Also send logged readings to this Zabbix server or proxy, `address[:port]`.
+    #[arg(long)]
+    zabbix_server: Option<String>,
+
+    /// Host name in Zabbix to send the readings for.
+    #[arg(long)]
+    zabbix_host: Option<String>,
+
+    /// Keep readings that couldn't be sent to Zabbix in this directory, and send them once the server is back.
+    #[arg(long)]
+    spool_dir: Option<PathBuf>,
+
+    /// Keep the software ABC, the anomaly detector and raised alerts in this file, so a restart doesn't lose them.
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+
+    /// Serve the latest reading as Modbus TCP input registers on this address, `address[:port]`.
+    #[arg(long)]
+    modbus_listen: Option<String>,
+
+    /// Serve an HTTP API with the latest readings and the history of the log on this address, `address[:port]`. Set
+    /// a token in the `[api]` section of the config to require it.
+    #[arg(long)]
+    api_listen: Option<String>,
+
+    /// Run this shell command on alerts, with the reading and the alert in environment variables (see
+    /// `co2_monitor::hook`).
+    #[arg(long)]
+    hook: Option<String>,
+
+    /// When to run `--hook`: `reading`, `change` or `alert`.
+    #[arg(long)]
+    hook_on: Option<HookTrigger>,
+
+    /// Serve readings with the ESPHome native API on this address, `address[:port]`, so Home Assistant can add the
+    /// monitor as an ESPHome device.
+    #[cfg(feature = "esphome")]
+    #[arg(long)]
+    esphome_listen: Option<String>,
+
+    /// With `--esphome-listen`, the node name of the device in Home Assistant.
+    #[cfg(feature = "esphome")]
+    #[arg(long, requires = "esphome_listen", default_value = "co2-monitor")]
+    esphome_name: String,
+
+    /// Publish every reading on the session bus as `org.co2monitor`, for desktop widgets.
+    #[cfg(all(target_os = "linux", feature = "dbus"))]
+    #[arg(long)]
+    dbus: bool,
+
+    /// Speak warnings through the text-to-speech engine of the OS.
+    #[cfg(feature = "tts")]
+    #[arg(long)]
+    announce: bool,
+
+    /// With `--announce`, also speak the current reading every this many minutes.
+    #[cfg(feature = "tts")]
+    #[arg(long, requires = "announce")]
+    announce_every: Option<u64>,
+}
+
+impl LogArgs {
+    /// The flags that were given, to take precedence over the config file.
+    fn to_config(&self) -> Config {
+        Config {
+            device: DeviceConfig {
+                backend: self.backend.clone(),
+                simulate: self.simulate.then_some(true),
+                conversions: self.conversions,
+                profile: None,
+            },
+            sinks: SinkConfig {
+                csv: self.csv.clone(),
+                extended_columns: self.extended_columns.then_some(true),
+                on_change: self.on_change.then_some(true),
+                spool_dir: self.spool_dir.clone(),
+                state_file: self.state_file.clone(),
+                ..SinkConfig::default()
+            },
+            units: UnitConfig {
+                temperature: self.unit,
+            },
+            thresholds: ThresholdConfig {
+                min_temperature: self.min_temperature,
+                max_temperature: self.max_temperature,
+                temperature_hysteresis: self.temperature_hysteresis,
+                max_co2: self.max_co2,
+                quiet_hours: self.quiet_hours,
+            },
+            calibration: CalibrationConfig::default(),
+            intervals: IntervalConfig {
+                sample_seconds: self.sample_interval,
+                heartbeat_seconds: self.heartbeat,
+                ..IntervalConfig::default()
+            },
+            fleet: Default::default(),
+            profiles: Default::default(),
+            room: RoomConfig {
+                volume: self.room_volume,
+                occupants: self.occupants,
+                #[cfg(feature = "occupancy")]
+                air_changes: None,
+            },
+            ventilation: Default::default(),
+            anomaly: Default::default(),
+            notifications: Default::default(),
+            pipeline: PipelineConfig::default(),
+            zabbix: ZabbixConfig {
+                server: self.zabbix_server.clone(),
+                host: self.zabbix_host.clone(),
+                ..ZabbixConfig::default()
+            },
+            grafana: Default::default(),
+            modbus: ModbusConfig {
+                listen: self.modbus_listen.clone(),
+                ..ModbusConfig::default()
+            },
+            api: ApiConfig {
+                listen: self.api_listen.clone(),
+                ..ApiConfig::default()
+            },
+            fan: Default::default(),
+            hook: HookConfig {
+                command: self.hook.clone(),
+                on: self.hook_on,
+                ..HookConfig::default()
+            },
+            #[cfg(feature = "knx")]
+            knx: Default::default(),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Summarize an existing csv log. The log is streamed, so its size doesn't matter.
+    Analyze {
+        /// The log file to analyze [default: the csv log of the config, or log.csv].
+        log: Option<PathBuf>,
+    },
+    /// Downsample a csv log into buckets with min, mean and max, marking the times the device was offline.
+    History {
+        /// The log file to read [default: the csv log of the config, or log.csv].
+        log: Option<PathBuf>,
+        /// Bucket size in seconds.
+        #[arg(long, default_value_t = 3600)]
+        bucket: u64,
+        /// Only rows at or after this local time, e.g. `2025-01-31` or `2025-01-31T08:00:00`.
+        #[arg(long, value_parser = export::parse_timestamp)]
+        from: Option<NaiveDateTime>,
+        /// Only rows before this local time.
+        #[arg(long, value_parser = export::parse_timestamp)]
+        to: Option<NaiveDateTime>,
+        /// Only rows of this room, in logs of `fleet`.
+        #[arg(long)]
+        room: Option<String>,
+        /// Print one JSON object per bucket or gap instead of a table, timestamps are in milliseconds.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Convert a csv log to another format, e.g. to import it into a dashboard. Streams like `analyze`.
+    Export {
+        /// The log file to export [default: the csv log of the config, or log.csv].
+        log: Option<PathBuf>,
+        /// Output format: jsonl (one JSON object per line), influx (InfluxDB line protocol) or parquet (with the
+        /// `parquet` feature).
+        #[arg(long, default_value = "jsonl")]
+        format: ExportFormat,
+        /// Where to write the export [default: stdout].
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Only export rows at or after this local time, e.g. `2025-01-31` or `2025-01-31T08:00:00`.
+        #[arg(long, value_parser = export::parse_timestamp)]
+        from: Option<NaiveDateTime>,
+        /// Only export rows before this local time.
+        #[arg(long, value_parser = export::parse_timestamp)]
+        to: Option<NaiveDateTime>,
+        /// Average the rows over intervals of this many seconds.
+        #[arg(long)]
+        sample_interval: Option<u64>,
+        /// Measurement name for the influx format.
+        #[arg(long, default_value = ExportWriter::<std::io::Stdout>::DEFAULT_MEASUREMENT)]
+        measurement: String,
+        /// Fix the timestamps of readings logged before the clock was set, e.g. by NTP on a Raspberry Pi. Reads the log
+        /// twice.
+        #[arg(long)]
+        fix_clock: bool,
+    },
+    /// Draw a chart of a csv log as SVG, with co2, temperature, the air quality bands and the thresholds of the config.
+    Plot {
+        /// The log file to draw [default: the csv log of the config, or log.csv].
+        log: Option<PathBuf>,
+        /// Where to write the SVG [default: stdout]. There's no PNG output, convert it for chat apps, e.g. with
+        /// `rsvg-convert`.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Only rows at or after this local time, e.g. `2025-01-31` or `2025-01-31T08:00:00`.
+        #[arg(long, value_parser = export::parse_timestamp)]
+        from: Option<NaiveDateTime>,
+        /// Only rows before this local time.
+        #[arg(long, value_parser = export::parse_timestamp)]
+        to: Option<NaiveDateTime>,
+        /// Only rows of this room, in logs of `fleet`.
+        #[arg(long)]
+        room: Option<String>,
+        /// Combine the readings of this many seconds into one point.
+        #[arg(long, default_value_t = 300)]
+        bucket: u64,
+        /// Width in pixels.
+        #[arg(long, default_value_t = 960)]
+        width: u32,
+        /// Height in pixels.
+        #[arg(long, default_value_t = 480)]
+        height: u32,
+        /// Title above the chart.
+        #[arg(long)]
+        title: Option<String>,
+    },
+    /// Summarize a period of a csv log day by day: mean and peak co2, time above 1000 and 1400 ppm, co2 during working
+    /// hours and ventilations (sharp drops of co2).
+    Report {
+        /// The log file to summarize [default: the csv log of the config, or log.csv].
+        log: Option<PathBuf>,
+        /// Output format: text, markdown or html.
+        #[arg(long, default_value = "text")]
+        format: ReportFormat,
+        /// Where to write the report [default: stdout].
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Only rows at or after this local time, e.g. `2025-01-31` or `2025-01-31T08:00:00`.
+        #[arg(long, value_parser = export::parse_timestamp)]
+        from: Option<NaiveDateTime>,
+        /// Only rows before this local time.
+        #[arg(long, value_parser = export::parse_timestamp)]
+        to: Option<NaiveDateTime>,
+        /// Only rows of this room, in logs of `fleet`.
+        #[arg(long)]
+        room: Option<String>,
+        /// Working hours on weekdays, from the first hour to the second (exclusive).
+        #[arg(long, default_value = "9-17")]
+        working_hours: QuietHours,
+    },
+    /// List the connected monitors of every known model, with serial numbers and firmware versions.
+    Devices {
+        /// Print a JSON array instead of text.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print information about the connected device.
+    Info {
+        /// Print a JSON document with a stable schema instead of text.
+        #[arg(long)]
+        json: bool,
+        /// How long to listen to the device to find out which values it reports.
+        #[arg(long, default_value_t = 10)]
+        probe_seconds: u64,
+    },
+    /// Measure how well the connection to the device works, to tell bad cables and hubs apart from firmware oddities.
+    Bench {
+        /// How long to measure.
+        #[arg(long, default_value_t = 60)]
+        seconds: u64,
+    },
+    /// Record the raw reads of the device to a file, e.g. to attach it to a bug report.
+    Capture {
+        /// Where to write the capture.
+        file: PathBuf,
+        /// How long to record.
+        #[arg(long, default_value_t = 300)]
+        seconds: u64,
+    },
+    /// Play a capture back and print the readings in it.
+    Replay {
+        /// The capture to play.
+        file: PathBuf,
+        /// Playback speed, 1 is the original timing and 0 as fast as possible.
+        #[arg(long, default_value_t = 0.0)]
+        speed: f64,
+        /// Also print every valid raw report.
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Log every connected monitor to the csv log, with a column for its room (see `[fleet]` in the config). Readings
+    /// of monitors in the same room are averaged.
+    Fleet {
+        /// Log one reading per room every this many seconds.
+        #[arg(long, default_value_t = 60)]
+        sample_interval: u64,
+    },
+    /// Print a single reading for a status bar (waybar, i3blocks, polybar) or script, and exit.
+    Status {
+        /// Output format: text, json, waybar, i3blocks or polybar.
+        #[arg(long, default_value = "text")]
+        format: StatusFormat,
+        /// Give up after this many seconds without a complete reading.
+        #[arg(long, default_value_t = 15)]
+        timeout: u64,
+    },
+    /// Run the logger at login with a launchd agent. Options after a `--` are passed to the logger.
+    #[cfg(target_os = "macos")]
+    InstallAgent {
+        /// Only print the plist.
+        #[arg(long)]
+        print: bool,
+        /// Options for the logger after a `--`, e.g. `-- --config ~/co2.toml`.
+        #[arg(last = true)]
+        options: Vec<String>,
+    },
+    /// Remove the launchd agent again.
+    #[cfg(target_os = "macos")]
+    UninstallAgent,
+    /// Install, remove or run the logger as a windows service.
+    #[cfg(all(windows, feature = "windows-service"))]
+    Service {
+        #[command(subcommand)]
+        action: service::ServiceAction,
+    },
+    /// Find out why the device can't be opened, and fix permissions with a udev rule.
+    Doctor {
+        /// Print the udev rule.
+        #[arg(long)]
+        print_rule: bool,
+        /// Install the udev rule (needs root).
+        #[arg(long)]
+        install_rule: bool,
+        /// After installing the rule, reload udev so re-plugging the device isn't necessary.
+        #[arg(long, requires = "install_rule")]
+        reload: bool,
+    },
 }
 
 fn main() {
-    let program_start = std::time::Instant::now();
+    let cli = Cli::parse();
+    #[cfg(all(windows, feature = "windows-service"))]
+    if let Some(Command::Service {
+        action: service::ServiceAction::Run,
+    }) = cli.command
+    {
+        // Logging goes to the event log there.
+        return service::run();
+    }
+    tracing_subscriber::fmt()
+        .with_max_level(cli.log_level)
+        .with_writer(std::io::stderr)
+        .init();
+    let config = load_config(&cli).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(2);
+    });
+    match cli.command {
+        None if cli.log.stdio_json => stdio_json(&config),
+        None => log(&cli.log, &config),
+        Some(Command::Analyze { log }) => analyze(
+            &log.or(config.sinks.csv)
+                .unwrap_or_else(|| DEFAULT_LOG_NAME.into()),
+        ),
+        Some(Command::History {
+            log,
+            bucket,
+            from,
+            to,
+            room,
+            json,
+        }) => history(
+            &log.or(config.sinks.csv)
+                .unwrap_or_else(|| DEFAULT_LOG_NAME.into()),
+            Resampler::new(bucket.max(1) * 1000),
+            config.ventilation.detector(),
+            (from, to),
+            room.as_deref(),
+            json,
+        ),
+        Some(Command::Export {
+            log,
+            format,
+            output,
+            from,
+            to,
+            sample_interval,
+            measurement,
+            fix_clock,
+        }) => {
+            let log = log
+                .or(config.sinks.csv)
+                .unwrap_or_else(|| DEFAULT_LOG_NAME.into());
+            let output: Box<dyn Write> = match output {
+                Some(path) => Box::new(std::io::BufWriter::new(
+                    std::fs::File::create(path).expect("Could not create the output file."),
+                )),
+                None => Box::new(std::io::stdout().lock()),
+            };
+            let writer = ExportWriter::new(output, format).with_measurement(&measurement);
+            export(&log, writer, from, to, sample_interval, fix_clock)
+        }
+        Some(Command::Plot {
+            log,
+            output,
+            from,
+            to,
+            room,
+            bucket,
+            width,
+            height,
+            title,
+        }) => {
+            if let Some(extension) = output.as_ref().and_then(|path| path.extension())
+                && !extension.eq_ignore_ascii_case("svg")
+            {
+                eprintln!(
+                    "Only SVG can be written, convert it afterwards, e.g. with rsvg-convert."
+                );
+                std::process::exit(2);
+            }
+            let unit = config.units.temperature.unwrap_or_default();
+            let mut chart = Chart::new(width, height)
+                .with_unit(unit)
+                .with_max_co2(config.thresholds.max_co2)
+                .with_temperature_limits(
+                    config.thresholds.min_temperature,
+                    config.thresholds.max_temperature,
+                );
+            if let Some(title) = &title {
+                chart = chart.with_title(title);
+            }
+            let log = log
+                .or(config.sinks.csv)
+                .unwrap_or_else(|| DEFAULT_LOG_NAME.into());
+            plot(
+                &log,
+                chart,
+                Resampler::new(bucket.max(1) * 1000),
+                config.ventilation.detector(),
+                (from, to),
+                room.as_deref(),
+                output.as_deref(),
+            )
+        }
+        Some(Command::Report {
+            log,
+            format,
+            output,
+            from,
+            to,
+            room,
+            working_hours,
+        }) => {
+            let log = log
+                .or(config.sinks.csv)
+                .unwrap_or_else(|| DEFAULT_LOG_NAME.into());
+            let builder = ReportBuilder::new()
+                .with_working_hours(working_hours.start, working_hours.end)
+                .with_detector(config.ventilation.detector());
+            let report = report(&log, builder, (from, to), room.as_deref()).render(format);
+            match output {
+                Some(path) => std::fs::write(path, report).expect("Could not write the report."),
+                None => print!("{report}"),
+            }
+        }
+        Some(Command::Devices { json }) => devices(&config, json),
+        Some(Command::Info {
+            json,
+            probe_seconds,
+        }) => info(json, Duration::from_secs(probe_seconds)),
+        Some(Command::Bench { seconds }) => bench(Duration::from_secs(seconds)),
+        Some(Command::Capture { file, seconds }) => capture(&file, Duration::from_secs(seconds)),
+        Some(Command::Replay { file, speed, raw }) => replay(&file, speed, raw),
+        Some(Command::Fleet { sample_interval }) => {
+            fleet(&config, Duration::from_secs(sample_interval))
+        }
+        Some(Command::Status { format, timeout }) => {
+            status(&config, format, Duration::from_secs(timeout))
+        }
+        Some(Command::Doctor {
+            print_rule,
+            install_rule,
+            reload,
+        }) => doctor(print_rule, install_rule, reload),
+        #[cfg(target_os = "macos")]
+        Some(Command::InstallAgent { print, options }) => install_agent(print, options),
+        #[cfg(target_os = "macos")]
+        Some(Command::UninstallAgent) => match launchd::uninstall_agent(launchd::DEFAULT_LABEL) {
+            Ok(path) => println!("Removed {}.", path.display()),
+            Err(e) => {
+                eprintln!("Could not remove the agent: {e}");
+                std::process::exit(1);
+            }
+        },
+        #[cfg(all(windows, feature = "windows-service"))]
+        Some(Command::Service { action }) => service::manage(action),
+    }
+}
 
-    use std::fs::OpenOptions;
-    const LOG_NAME: &str = "log.csv";
+#[cfg(target_os = "macos")]
+fn install_agent(print: bool, options: Vec<String>) {
+    let exe = std::env::current_exe()
+        .and_then(|exe| exe.canonicalize())
+        .expect("Could not find the path of this binary.");
+    let working_directory = std::env::current_dir().expect("Could not get the working directory.");
+    let log_file = launchd::default_log_file().expect("HOME is not set.");
+    let mut arguments = vec![exe.to_string_lossy().into_owned()];
+    arguments.extend(options);
+    let plist = launchd::agent_plist(
+        launchd::DEFAULT_LABEL,
+        &arguments,
+        &working_directory,
+        &log_file,
+    );
+    if print {
+        print!("{plist}");
+        return;
+    }
 
-    let log_exists = std::path::Path::new(LOG_NAME).exists();
-    if log_exists {
-        println!("Appending to existing log file.");
+    // macOS ties the Input Monitoring permission for HID devices to the binary, and can't ask for it from a background
+    // agent. Open the device once in the foreground, so the dialog shows up now if it's needed.
+    match diagnostics::diagnose() {
+        Diagnosis::Ok(_) | Diagnosis::NoDevice => (),
+        diagnosis => {
+            println!("{diagnosis}");
+            println!(
+                "If macOS asked for it, allow Input Monitoring for {} in System Settings > Privacy & Security.",
+                exe.display()
+            );
+        }
+    }
+    if exe.components().any(|c| c.as_os_str() == "target") {
+        println!(
+            "This binary is in a cargo target directory. Rebuilding it makes macOS forget the permission, consider \
+             `cargo install --features pc --path .` and installing the agent from there."
+        );
     }
+    match launchd::install_agent(launchd::DEFAULT_LABEL, &plist) {
+        Ok(path) => println!(
+            "Installed {}. The logger runs in {}, its output goes to {}.",
+            path.display(),
+            working_directory.display(),
+            log_file.display()
+        ),
+        Err(e) => {
+            eprintln!("Could not install the agent: {e}");
+            std::process::exit(1);
+        }
+    }
+}
 
-    let file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(LOG_NAME)
-        .unwrap();
+/// The config file, overridden by the flags.
+fn load_config(cli: &Cli) -> Result<Config, co2_monitor::config::ConfigError> {
+    let file_config = match &cli.config {
+        Some(path) => Config::load(path)?,
+        None => Config::load_default()?,
+    };
+    cli.log.to_config().or(file_config).check()
+}
+
+/// Where `--simulate` (and replays) log to, so simulated readings never end up in a real log.
+const SIMULATED_LOG_NAME: &str = "simulated-log.csv";
+
+/// Save the state file at least this often.
+const STATE_SAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+fn log(args: &LogArgs, config: &Config) {
+    let program_start = std::time::Instant::now();
+    let dump_raw = args.dump_raw;
+    let backend = config.device.backend_or_default();
+    let simulate = backend == Backend::Simulate;
+    let conversions = config.device.conversions.unwrap_or_default();
+    let unit = config.units.temperature.unwrap_or(TemperatureUnit::Celsius);
+    let thresholds = &config.thresholds;
+    let (mut temperature_alert, mut co2_alert) = config.alerts(unit);
+    let mut notification_policy = config.notification_policy();
+    let mut hook = config.hook.hook(unit);
+    let mut fan = config.fan.controller().map(|controller| {
+        let switch = match config.fan.switch() {
+            Some(Ok(switch)) => switch,
+            Some(Err(e)) => {
+                eprintln!("Could not set up the fan: {e}");
+                std::process::exit(1);
+            }
+            None => {
+                eprintln!("The fan needs a switch in the [fan] section of the config.");
+                std::process::exit(1);
+            }
+        };
+        (controller, switch)
+    });
+    #[cfg(feature = "tts")]
+    let mut announcer = args.announce.then(Announcer::new);
+    #[cfg(feature = "tts")]
+    let mut last_summary: Option<std::time::Instant> = None;
 
-    let mut csv_writer = csv::WriterBuilder::new()
-        .has_headers(!log_exists)
-        .from_writer(file);
+    // Simulated readings only go to a real log when explicitly asked for on the command line.
+    let log_name = match (&args.csv, &config.sinks.csv) {
+        (Some(path), _) => path.clone(),
+        (None, _) if !backend.is_device() => SIMULATED_LOG_NAME.into(),
+        (None, Some(path)) => path.clone(),
+        (None, None) => DEFAULT_LOG_NAME.into(),
+    };
+    let extended_columns = config.sinks.extended_columns.unwrap_or(false);
+    if log_name.exists() {
+        println!("Appending to existing log file, using {conversions} conversions.");
+    }
+    let mut csv_writer =
+        LogWriter::append(&log_name, extended_columns).expect("Could not open the log file.");
+    if csv_writer.extended() != extended_columns {
+        println!(
+            "The existing log {} the extended columns, keeping it that way.",
+            if csv_writer.extended() {
+                "has"
+            } else {
+                "doesn't have"
+            }
+        );
+    }
 
+    // The simulated monitor is always there, and so is a capture. libusb detaches the kernel driver, which hidapi
+    // would see as the device being gone.
+    let mut watcher = (backend == Backend::Hidapi).then(|| {
+        DeviceWatcher::with_registry(&config.profile_registry())
+            .expect("Could not initialize Hid Api.")
+    });
+    if let Some(watcher) = &mut watcher
+        && (watcher.poll(&mut print_device_event).is_err() || watcher.present().is_empty())
+    {
+        println!("Waiting for the monitor to be connected.");
+    }
+    // The simulated monitor answers immediately, pace it like the device (7 reports every ~5 seconds).
+    let read_interval =
+        Duration::from_millis(
+            config
+                .intervals
+                .read_ms
+                .unwrap_or(if simulate { 700 } else { 200 }),
+        );
+    let reconnect_after = config.intervals.reconnect_after_seconds.unwrap_or(60);
+    let zabbix = match (&config.zabbix.server, &config.zabbix.host) {
+        (Some(server), Some(host)) => {
+            Some(ZabbixSender::new(server, host).with_keys(config.zabbix.keys()))
+        }
+        (Some(_), None) => {
+            eprintln!("Sending to Zabbix needs the host name, pass --zabbix-host.");
+            std::process::exit(2);
+        }
+        _ => None,
+    };
+    // Sending happens in the background, a slow server must not hold up reading the device.
+    let zabbix_queue = zabbix.clone().map(|sender| {
+        let spool = open_spool(&config.sinks, "zabbix.jsonl");
+        SinkQueue::spawn(sender, config.sinks.batching(), spool)
+    });
+    let grafana = config.grafana.sink();
+    let grafana_queue = grafana.clone().map(|sink| {
+        let spool = open_spool(&config.sinks, "grafana.jsonl");
+        SinkQueue::spawn(sink, config.sinks.batching(), spool)
+    });
+    #[cfg(all(target_os = "linux", feature = "dbus"))]
+    let dbus = args.dbus.then(|| {
+        co2_monitor::dbus::DbusService::session().unwrap_or_else(|e| {
+            eprintln!("Could not publish on the session bus: {e}");
+            std::process::exit(1);
+        })
+    });
+    let modbus = config.modbus.listen.as_ref().map(|address| {
+        ModbusServer::bind(
+            address,
+            config.modbus.unit_id.unwrap_or(1),
+            config.modbus.register_map(),
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("Could not serve modbus on {address}: {e}");
+            std::process::exit(1);
+        })
+    });
+    let api = serve_api(config, &log_name);
+    #[cfg(feature = "mdns")]
+    let mdns = api.as_ref().and_then(|api| advertise_api(config, api));
+    #[cfg(feature = "knx")]
+    let knx = config.knx.sender().map(|sender| {
+        sender.unwrap_or_else(|e| {
+            eprintln!("Could not open a socket for KNX: {e}");
+            std::process::exit(1);
+        })
+    });
+    #[cfg(feature = "esphome")]
+    let esphome = args.esphome_listen.as_ref().map(|address| {
+        let profile = config.device_profile();
+        let device = EsphomeDevice {
+            model: profiles::name_of(profile.vendor_id, profile.product_id)
+                .unwrap_or("ZGm053U")
+                .to_string(),
+            has_humidity: profile.has_humidity,
+            ..EsphomeDevice::new(&args.esphome_name)
+        };
+        EsphomeServer::bind(address, device).unwrap_or_else(|e| {
+            eprintln!("Could not serve the ESPHome API on {address}: {e}");
+            std::process::exit(1);
+        })
+    });
+    // Smoothing and dedup before logging.
+    let mut pipeline = Pipeline::from_config(&config.pipeline_stages());
+    // Numbers the complete readings, across reconnects.
+    let mut sequence = 0u32;
+    let advisor = config.room.advisor(thresholds.max_co2);
+    let mut cadence = CadenceTracker::new();
+    // 10 minutes of readings every 5 seconds.
+    let mut trend = Trend::<120>::new();
+    let mut ventilation = config.ventilation.detector();
+    let mut anomaly = config.anomaly.detector();
+    // What's typical for every hour of the day, for the deviation from it.
+    let mut baseline = HourlyBaseline::new();
+    let mut abc = config.calibration.auto_baseline();
+    if abc.is_some() {
+        println!("Software ABC is on, corrected co2 is flagged as calibrated in the log.");
+    }
+    #[cfg(feature = "occupancy")]
+    let mut occupancy = config.room.occupancy();
+    let mut state = config.sinks.state_file.as_ref().map(|path| {
+        StateFile::open(path).unwrap_or_else(|e| {
+            eprintln!("Could not read the state file {}: {e}", path.display());
+            std::process::exit(1);
+        })
+    });
+    // The device the state is about, and when it was last saved.
+    let mut state_device: Option<String> = None;
+    let mut state_saved = std::time::Instant::now();
     loop {
+        // Any supported clone, with its profile.
+        let found = watcher.as_mut().map(|watcher| {
+            watcher.wait_until_present(Duration::from_secs(2), &mut |event| {
+                // An unplugged monitor didn't silently stop, the time until it's back isn't missed readings.
+                if matches!(event, DeviceEvent::Disconnected(_)) {
+                    cadence.reset_gap();
+                }
+                print_device_event(event);
+            })
+        });
+        let profile = match &found {
+            Some(device) => config.device_profile_for(device.vendor_id, device.product_id),
+            None => config.device_profile(),
+        };
         let mut heartbeat = std::time::Instant::now();
-        let monitor = PcCo2Monitor::init_and_connect();
+        let connected = std::time::Instant::now();
+        let mut policy = ReadPolicy::new();
+        let mut warm_up = WarmUp::new(
+            config
+                .intervals
+                .warm_up_seconds
+                .map_or(warmup::DEFAULT_GRACE_MS, |seconds| seconds * 1000),
+        );
+        let connection = match &found {
+            // The one the watcher found, there may be several with the same ids.
+            Some(device) => PcCo2Monitor::open_with_profile(device, profile)
+                .map(|monitor| Box::new(monitor) as Box<dyn Co2MonitorCommunication>)
+                .map_err(|e| std::io::Error::other(format!("hidapi: {e:?}"))),
+            None => backend.connect_with_profile(profile),
+        };
+        let monitor = match connection {
+            Ok(monitor) => monitor,
+            // Right after it's plugged in, udev may not have given access to it yet.
+            Err(e) if found.is_some() => {
+                println!("Could not connect to {backend}: {e}. Trying again in 10 seconds.");
+                std::thread::sleep(Duration::from_secs(10));
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Could not connect to {backend}: {e}");
+                std::process::exit(1);
+            }
+        };
         let mut prev_reading = MonitorReading::default();
         let mut partial_reading = MonitorReadingParts::default();
+        partial_reading.conversions = conversions;
+        partial_reading.sanity_model = config.calibration.sanity_model();
+        partial_reading.validity = config.calibration.validity_model(&profile, conversions);
+        let mut cross_check = ChannelCrossCheck::new(partial_reading.sanity_model);
+        // For the extended columns of the log.
+        let device = found.as_ref().map_or_else(
+            || backend.to_string(),
+            |device| device.identifier().to_string(),
+        );
+        let mut last_sanity_raw = None;
+        #[cfg(feature = "mdns")]
+        if let Some(mdns) = &mdns {
+            let model =
+                profiles::name_of(profile.vendor_id, profile.product_id).unwrap_or("custom");
+            let mut txt = Vec::from(API_TXT);
+            txt.extend([("model", model), ("device", &device)]);
+            if let Err(e) = mdns.set_txt(&txt) {
+                tracing::warn!("could not announce the device with mdns: {e}");
+            }
+        }
+        // Another device learns from scratch, or from where it was.
+        if let Some(state) = &mut state
+            && state_device.as_ref() != Some(&device)
+        {
+            if let Some(previous) = state_device.replace(device.clone()) {
+                let rules = temperature_alert.rules().chain(co2_alert.as_ref());
+                state.set(
+                    &previous,
+                    DeviceState::new(abc.as_ref(), &anomaly, &baseline, rules),
+                );
+                abc = config.calibration.auto_baseline();
+                anomaly = config.anomaly.detector();
+                baseline = HourlyBaseline::new();
+                (temperature_alert, co2_alert) = config.alerts(unit);
+            }
+            if let Some(saved) = state.get(&device) {
+                let rules = temperature_alert.rules_mut().chain(co2_alert.as_mut());
+                saved.restore(abc.as_mut(), &mut anomaly, &mut baseline, rules);
+                println!(
+                    "Restored the state of {device} from {}.",
+                    state.path().display()
+                );
+            }
+        }
         loop {
-            if heartbeat.elapsed() > Duration::from_secs(60) {
+            if heartbeat.elapsed() > Duration::from_secs(reconnect_after) {
+                tracing::warn!("no readings for {reconnect_after} seconds, reconnecting");
                 println!(
-                    "WARNING, there were no readings since at least 60 seconds. Re-starting loop in 10 seconds."
+                    "WARNING, there were no readings since at least {reconnect_after} seconds. Re-starting loop in 10 \
+                     seconds."
                 );
+                print_health(policy.health(), connected);
                 std::thread::sleep(Duration::from_secs(10));
                 break;
             };
-            std::thread::sleep(Duration::from_millis(200));
-            if let Ok(Some(reading)) = monitor.read_to_part(&mut partial_reading) {
-                let (ppm, valid) = reading.co2_value.as_num_and_bool();
-                let now = Local::now().naive_local();
-                let row = Row {
-                    temperature: reading.temperature,
-                    co2_ppm: ppm as usize,
-                    co2_is_valid: valid,
-                    timestamp: now,
+            std::thread::sleep(read_interval);
+            let mut observe_raw = |raw: MonitorReportRaw| {
+                if let MonitorReportRaw::Co2SanityCheck(value) = raw {
+                    last_sanity_raw = Some(value);
+                }
+                if dump_raw {
+                    println!("{:>10.1?} -- {:?}", program_start.elapsed(), raw);
+                }
+                if let Some(disagreement) = cross_check.observe(raw) {
+                    println!();
+                    println!("WARNING, the co2 channels disagree: {}", disagreement);
+                }
+            };
+            let reading =
+                match monitor.read_with_policy(&mut partial_reading, &mut policy, &mut observe_raw)
+                {
+                    ReadOutcome::Reading(reading) => reading,
+                    ReadOutcome::TransientError { retry_after_ms, .. } => {
+                        std::thread::sleep(Duration::from_millis(u64::from(retry_after_ms)));
+                        continue;
+                    }
+                    ReadOutcome::Unhealthy {
+                        consecutive_failures,
+                    } => {
+                        println!();
+                        println!(
+                            "WARNING, {} reads in a row failed. Re-starting loop in 10 seconds.",
+                            consecutive_failures
+                        );
+                        print_health(policy.health(), connected);
+                        std::thread::sleep(Duration::from_secs(10));
+                        break;
+                    }
+                    _ => continue,
                 };
-                csv_writer.serialize(&row).unwrap();
-                if reading != prev_reading {
+            let elapsed_ms = program_start.elapsed().as_millis() as u64;
+            let mut timestamped = warm_up.observe(&TimestampedReading {
+                reading,
+                timestamp_ms: elapsed_ms,
+                sequence,
+            });
+            let now = Local::now();
+            // What is kept across restarts needs the wall clock, the time since the start begins at 0 again.
+            let unix_ms = now.timestamp_millis() as u64;
+            let mut state_changed = false;
+            // Before anything looks at co2, so the log, the alerts and the trend agree.
+            if let Some(abc) = &mut abc {
+                let offset = abc.offset();
+                timestamped.reading = abc
+                    .correct(&TimestampedReading {
+                        timestamp_ms: unix_ms,
+                        ..timestamped
+                    })
+                    .reading;
+                if abc.offset() != offset {
+                    state_changed = true;
                     println!();
-                    print!("{:>10.1?} -- {:.1}", program_start.elapsed(), reading);
-                    prev_reading = reading;
-                } else {
-                    print!(".");
+                    print!(
+                        "Software ABC: co2 offset is now {:+} ppm, the lowest of the last days was {:.0} ppm.",
+                        abc.offset(),
+                        abc.floor_ppm().unwrap_or_default()
+                    );
+                }
+            }
+            let reading = timestamped.reading;
+            sequence = sequence.wrapping_add(1);
+            let missed = cadence.observe(elapsed_ms);
+            if missed > 0 {
+                println!();
+                print!(
+                    "WARNING, missed about {} readings ({:.1}% of all so far).",
+                    missed,
+                    100.0 * cadence.missed_ratio()
+                );
+            }
+            let hour = now.hour() as u8;
+            // Compared to what was typical before this reading.
+            let deviation = match reading.co2_value {
+                Co2Value::Valid(ppm) => baseline.deviation(hour, ppm).map(|d| d.delta.round()),
+                _ => None,
+            };
+            baseline.observe_reading(hour, &reading);
+            let mut alerts = Vec::new();
+            temperature_alert.evaluate(hour, &reading, &mut |event| alerts.push(event));
+            alerts.extend(
+                co2_alert
+                    .as_mut()
+                    .and_then(|rule| rule.evaluate(hour, &reading)),
+            );
+            anomaly.observe(
+                &TimestampedReading {
+                    timestamp_ms: unix_ms,
+                    ..timestamped
+                },
+                &mut |event| alerts.push(event),
+            );
+            state_changed |= !alerts.is_empty();
+            if let Some(state) = &mut state
+                && (state_changed || state_saved.elapsed() >= STATE_SAVE_INTERVAL)
+            {
+                let rules = temperature_alert.rules().chain(co2_alert.as_ref());
+                state.set(
+                    &device,
+                    DeviceState::new(abc.as_ref(), &anomaly, &baseline, rules),
+                );
+                if let Err(e) = state.save() {
+                    tracing::warn!(
+                        "could not save the state to {}: {e}",
+                        state.path().display()
+                    );
+                }
+                state_saved = std::time::Instant::now();
+            }
+            let mut notifications = Vec::new();
+            for event in &alerts {
+                notification_policy.push(event, elapsed_ms, hour, &mut |n| notifications.push(n));
+            }
+            notification_policy.observe(&reading, elapsed_ms, hour, &mut |n| notifications.push(n));
+            if let Some(hook) = &mut hook {
+                hook.reading(&reading, now.timestamp());
+            }
+            for notification in notifications {
+                if let Some(hook) = &mut hook {
+                    hook.notification(&notification, &reading, now.timestamp());
+                }
+                println!();
+                print!("WARNING, {}", notification.display_in(unit));
+                #[cfg(feature = "tts")]
+                announce(&mut announcer, &notification_text(&notification, unit));
+            }
+            #[cfg(feature = "tts")]
+            if let Some(minutes) = args.announce_every
+                && last_summary.is_none_or(|t| t.elapsed() >= Duration::from_secs(minutes * 60))
+            {
+                announce(&mut announcer, &summary_text(&reading, unit));
+                last_summary = Some(std::time::Instant::now());
+            }
+            trend.observe_reading(&timestamped);
+            #[cfg(feature = "occupancy")]
+            let people = occupancy
+                .as_mut()
+                .and_then(|estimator| estimator.push(&timestamped));
+            let forecast = thresholds
+                .max_co2
+                .map_or(Forecast::Steady, |limit| Forecast::new(&trend, limit));
+            // Values derived from the readings, for the sinks that take them.
+            let mut derived = vec![
+                ("missed_cycles", cadence.missed() as f32),
+                ("missed_percent", 100.0 * cadence.missed_ratio()),
+            ];
+            derived.extend(
+                forecast
+                    .minutes_to_limit()
+                    .map(|minutes| ("minutes_to_limit", minutes)),
+            );
+            derived.extend(
+                forecast
+                    .minutes_to_below()
+                    .map(|minutes| ("minutes_to_below", minutes)),
+            );
+            derived.extend(deviation.map(|delta| ("co2_deviation", delta)));
+            #[cfg(feature = "occupancy")]
+            derived.extend(people.map(|people| ("occupancy", people)));
+            if let Some(event) = ventilation.push(&timestamped) {
+                println!();
+                print!(
+                    "Ventilation: co2 fell from {} to {} ppm in {:.0} min.",
+                    event.start_ppm,
+                    event.end_ppm,
+                    event.minutes()
+                );
+            }
+            if let Some((controller, switch)) = &mut fan {
+                match controller.drive(&timestamped, switch.as_mut()) {
+                    Ok(Some(on)) => {
+                        println!();
+                        print!("Fan switched {}.", if on { "on" } else { "off" });
+                    }
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("could not switch the fan: {e}"),
+                }
+            }
+            if let Some(modbus) = &modbus {
+                modbus.publish(&reading);
+            }
+            if let Some(api) = &api {
+                api.publish_with(&device, &reading, &derived, unix_ms);
+            }
+            #[cfg(feature = "esphome")]
+            if let Some(esphome) = &esphome {
+                esphome.publish(&reading);
+            }
+            #[cfg(all(target_os = "linux", feature = "dbus"))]
+            if let Some(dbus) = &dbus
+                && let Err(e) = dbus.publish(&reading, now.timestamp())
+            {
+                tracing::warn!("could not publish the reading on dbus: {e}");
+            }
+            let mut samples = Vec::new();
+            pipeline.process(timestamped, &mut |sample| samples.push(sample));
+            for TimestampedReading {
+                reading: sample,
+                timestamp_ms,
+                sequence: sample_sequence,
+            } in samples
+            {
+                // Stages like `sample` hand a reading on later, e.g. the previous interval once the next one started.
+                let taken = now
+                    - chrono::TimeDelta::milliseconds(
+                        elapsed_ms.saturating_sub(timestamp_ms) as i64
+                    );
+                let row = Row::from_reading(&sample, taken.naive_local())
+                    .with_extended(&sample, &device, last_sanity_raw)
+                    .with_monotonic_ms(timestamp_ms)
+                    .with_deviation(deviation)
+                    .with_sequence(sample_sequence);
+                csv_writer.write(&row).unwrap();
+                tracing::trace!("wrote {:?} to {}", row, log_name.display());
+                #[cfg(feature = "knx")]
+                if let Some(knx) = &knx
+                    && let Err(e) = knx.send(&sample)
+                {
+                    tracing::warn!("could not send the reading to KNX: {e}");
+                }
+                if let Some((zabbix, queue)) = zabbix.as_ref().zip(zabbix_queue.as_ref())
+                    && !queue.push(zabbix.items_with(&sample, &derived, taken.timestamp()))
+                {
+                    tracing::warn!(
+                        "zabbix can't keep up, dropped {} readings so far",
+                        queue.dropped()
+                    );
+                }
+                if let Some((grafana, queue)) = grafana.as_ref().zip(grafana_queue.as_ref())
+                    && !queue.push(vec![grafana.line_with(
+                        &sample,
+                        &derived,
+                        taken.timestamp(),
+                    )])
+                {
+                    tracing::warn!(
+                        "grafana can't keep up, dropped {} readings so far",
+                        queue.dropped()
+                    );
+                }
+            }
+            if dump_raw {
+                // The raw reports were already printed.
+            } else if reading != prev_reading {
+                println!();
+                print!(
+                    "{:>10.1?} -- {}",
+                    program_start.elapsed(),
+                    reading.display_in(unit)
+                );
+                if let Some(slope) = trend.ppm_per_minute() {
+                    print!(" ({:+.0} ppm/min", slope);
+                    // The advice already says when the limit is reached.
+                    if advisor.is_none() && forecast != Forecast::Steady {
+                        print!(", {}", forecast);
+                    }
+                    print!(")");
+                }
+                if let Some(advisor) = &advisor
+                    && let Co2Value::Valid(ppm) = reading.co2_value
+                {
+                    let advice = advisor.advise(f32::from(ppm), trend.ppm_per_minute());
+                    if advice != Advice::Fine {
+                        print!(" -- {}", advice);
+                    }
+                }
+                #[cfg(feature = "occupancy")]
+                if let Some(people) = people {
+                    print!(" -- about {:.0} people", people);
+                }
+                prev_reading = reading;
+            } else {
+                print!(".");
+            }
+            csv_writer.flush().unwrap();
+            std::io::stdout().flush().unwrap();
+            heartbeat = std::time::Instant::now();
+        }
+    }
+}
+
+/// The API server of the config, with the history from `log`, if there's an address to listen on. Exits if it can't
+/// listen there.
+fn serve_api(config: &Config, log: &Path) -> Option<ApiServer> {
+    let address = config.api.listen.as_ref()?;
+    let api = ApiServer::bind(address, config.api.access(), Some(log.to_path_buf()))
+        .unwrap_or_else(|e| {
+            eprintln!("Could not serve the api on {address}: {e}");
+            std::process::exit(1);
+        });
+    Some(api)
+}
+
+/// What the mDNS advertisement of the api says about it, see `co2_monitor::mdns`.
+#[cfg(feature = "mdns")]
+const API_TXT: [(&str, &str); 2] = [("path", "/api"), ("version", env!("CARGO_PKG_VERSION"))];
+
+/// Advertise `api` with mDNS, unless the config says not to. If that doesn't work, the api is still there, so it's
+/// only a warning.
+#[cfg(feature = "mdns")]
+fn advertise_api(config: &Config, api: &ApiServer) -> Option<MdnsAdvertiser> {
+    if config.api.mdns == Some(false) {
+        return None;
+    }
+    let name = config
+        .api
+        .mdns_name
+        .clone()
+        .unwrap_or_else(|| format!("co2-monitor on {}", mdns::host_name()));
+    MdnsAdvertiser::start(&name, api.local_addr(), &API_TXT)
+        .inspect_err(|e| println!("WARNING, could not advertise the api with mDNS: {e}"))
+        .ok()
+}
+
+/// The spool `file_name` in the spool directory, if there is one. Exits if it can't be opened.
+fn open_spool<T: Serialize + serde::de::DeserializeOwned>(
+    sinks: &SinkConfig,
+    file_name: &str,
+) -> Option<Spool<T>> {
+    let dir = sinks.spool_dir.as_ref()?;
+    let spool = std::fs::create_dir_all(dir)
+        .and_then(|()| {
+            Spool::open(
+                dir.join(file_name),
+                sinks.spool_max_items.unwrap_or(spool::DEFAULT_MAX_ITEMS),
+                sinks.spool_drop.unwrap_or_default(),
+            )
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("Could not open the spool in {}: {e}", dir.display());
+            std::process::exit(1);
+        });
+    Some(spool)
+}
+
+fn print_health(health: &MonitorHealth, connected: std::time::Instant) {
+    let rates = health.rates(connected.elapsed().as_millis() as u64);
+    println!(
+        "Since connecting: {} ({:.1} failures and {:.1} timeouts per minute).",
+        health, rates.failures, rates.timeouts
+    );
+}
+
+fn print_device_event(event: DeviceEvent) {
+    match event {
+        DeviceEvent::Connected(device) => println!("Monitor connected at {}.", device.path),
+        DeviceEvent::Disconnected(device) => {
+            println!();
+            println!(
+                "Monitor at {} disconnected, waiting for it to come back.",
+                device.path
+            );
+        }
+        _ => (),
+    }
+}
+
+/// Failing to speak shouldn't stop the logging, so errors are only printed.
+#[cfg(feature = "tts")]
+fn announce(announcer: &mut Option<Announcer>, text: &str) {
+    if let Some(announcer) = announcer
+        && let Err(e) = announcer.say(text)
+    {
+        println!();
+        print!("WARNING, could not announce: {}", e);
+    }
+}
+
+/// Rows `analyze` has the statistics of, a week of them.
+const ANALYZE_READINGS: usize = 7 * READINGS_PER_DAY;
+
+fn analyze(log: &Path) {
+    let reader = LogReader::open(log).expect("Could not open log file.");
+
+    let mut rows = 0usize;
+    let mut first = None;
+    let mut last = None;
+    let mut invalid = 0usize;
+    // Too large for the stack.
+    let mut stats = Box::new(RollingStats::<ANALYZE_READINGS>::new());
+    for row in reader {
+        let row = row.expect("Malformed row in log file.");
+        rows += 1;
+        first.get_or_insert(row.timestamp);
+        last = Some(row.timestamp);
+        if !row.co2_is_valid {
+            invalid += 1;
+        }
+        stats.push(&row.to_reading());
+    }
+
+    let (Some(first), Some(last)) = (first, last) else {
+        println!("The log is empty.");
+        return;
+    };
+    println!("Rows:        {rows}");
+    println!("From:        {first}");
+    println!("To:          {last}");
+    if rows > ANALYZE_READINGS {
+        println!("Statistics of the last {ANALYZE_READINGS} rows, about a week:");
+    }
+    if let Some(t) = stats.temperature() {
+        println!(
+            "Temperature: {:.1} min, {:.1} mean, {:.1} p95, {:.1} max",
+            t.min, t.mean, t.p95, t.max
+        );
+    }
+    if let Some(co2) = stats.co2() {
+        println!(
+            "Co2:         {:.0} min, {:.0} mean, {:.0} p95, {:.0} max",
+            co2.min, co2.mean, co2.p95, co2.max
+        );
+    }
+    println!("Invalid:     {invalid} rows");
+}
+
+fn history(
+    log: &Path,
+    mut resampler: Resampler,
+    mut detector: VentilationDetector,
+    (from, to): (Option<NaiveDateTime>, Option<NaiveDateTime>),
+    room: Option<&str>,
+    json: bool,
+) {
+    let reader = LogReader::open(log).expect("Could not open log file.");
+    // The log has local times, keep them as they are so buckets start at full local hours and days.
+    let local = |ms: u64| {
+        chrono::DateTime::from_timestamp_millis(ms as i64)
+            .unwrap_or_default()
+            .naive_utc()
+            .format("%Y-%m-%d %H:%M:%S")
+    };
+    let mut print = |point: HistoryPoint| {
+        if json {
+            println!(
+                "{}",
+                serde_json::to_string(&point).expect("Could not serialize.")
+            );
+            return;
+        }
+        match point {
+            HistoryPoint::Bucket(bucket) => {
+                let co2 = bucket.co2.map_or_else(
+                    || String::from("too high"),
+                    |co2| format!("{:.0} / {:.0} / {:.0} ppm", co2.min, co2.mean, co2.max),
+                );
+                println!(
+                    "{}  co2 {}  temperature {:.1} / {:.1} / {:.1} °C  ({} readings{})",
+                    local(bucket.start_ms),
+                    co2,
+                    bucket.temperature.min,
+                    bucket.temperature.mean,
+                    bucket.temperature.max,
+                    bucket.count,
+                    if bucket.too_high > 0 {
+                        format!(", {} too high", bucket.too_high)
+                    } else {
+                        String::new()
+                    }
+                );
+            }
+            HistoryPoint::Gap { start_ms, end_ms } => {
+                println!(
+                    "-- offline from {} to {} --",
+                    local(start_ms),
+                    local(end_ms)
+                )
+            }
+            HistoryPoint::Ventilation(event) => {
+                println!(
+                    "-- ventilation from {} to {}, {} to {} ppm --",
+                    local(event.start_ms),
+                    local(event.end_ms),
+                    event.start_ppm,
+                    event.end_ppm
+                )
+            }
+            _ => (),
+        }
+    };
+    if !json {
+        println!("Buckets show min / mean / max.");
+    }
+    for row in reader {
+        let row = row.expect("Malformed row in log file.");
+        if !export::in_range(&row, from, to)
+            || room.is_some_and(|room| row.room.as_deref() != Some(room))
+        {
+            continue;
+        }
+        let timestamped = TimestampedReading {
+            reading: row.to_reading(),
+            timestamp_ms: row.timestamp.and_utc().timestamp_millis() as u64,
+            sequence: 0,
+        };
+        resampler.push(&timestamped, &mut print);
+        if let Some(event) = detector.push(&timestamped) {
+            print(HistoryPoint::Ventilation(event));
+        }
+    }
+    resampler.finish(&mut print);
+    if let Some(event) = detector.finish() {
+        print(HistoryPoint::Ventilation(event));
+    }
+}
+
+fn export(
+    log: &Path,
+    mut writer: ExportWriter<impl Write>,
+    from: Option<NaiveDateTime>,
+    to: Option<NaiveDateTime>,
+    sample_interval: Option<u64>,
+    fix_clock: bool,
+) {
+    let mut clock = ClockCorrection::new();
+    if fix_clock {
+        for row in LogReader::open(log).expect("Could not open log file.") {
+            clock.observe(&row.expect("Malformed row in log file."));
+        }
+        eprintln!(
+            "Fixing the clock of {} run(s) of the logger.",
+            clock.jumps()
+        );
+    }
+    let reader = LogReader::open(log).expect("Could not open log file.");
+    // Logs of `fleet` have several rooms, which are sampled separately.
+    let mut samplers = BTreeMap::<Option<String>, IntervalAggregator>::new();
+    let mut write = |row: &Row| writer.write(row).expect("Could not write the export.");
+    let sample_to_row = |sample: TimestampedReading, room: &Option<String>| {
+        let timestamp = chrono::DateTime::from_timestamp_millis(sample.timestamp_ms as i64)
+            .unwrap_or_default()
+            .naive_utc();
+        Row {
+            room: room.clone(),
+            ..Row::from_reading(&sample.reading, timestamp)
+        }
+    };
+    for row in reader {
+        let mut row = row.expect("Malformed row in log file.");
+        if fix_clock {
+            clock.apply(&mut row);
+        }
+        if !export::in_range(&row, from, to) {
+            continue;
+        }
+        match sample_interval {
+            Some(seconds) => {
+                let timestamped = TimestampedReading {
+                    reading: row.to_reading(),
+                    timestamp_ms: row.timestamp.and_utc().timestamp_millis() as u64,
+                    sequence: 0,
+                };
+                let sampler = samplers
+                    .entry(row.room.clone())
+                    .or_insert_with(|| IntervalAggregator::new(seconds * 1000));
+                if let Some(sample) = sampler.push(&timestamped) {
+                    write(&sample_to_row(sample, &row.room));
+                }
+            }
+            None => write(&row),
+        }
+    }
+    for (room, sampler) in &mut samplers {
+        if let Some(sample) = sampler.take() {
+            write(&sample_to_row(sample, room));
+        }
+    }
+    writer.into_inner().expect("Could not write the export.");
+}
+
+fn plot(
+    log: &Path,
+    mut chart: Chart,
+    mut resampler: Resampler,
+    mut detector: VentilationDetector,
+    (from, to): (Option<NaiveDateTime>, Option<NaiveDateTime>),
+    room: Option<&str>,
+    output: Option<&Path>,
+) {
+    let reader = LogReader::open(log).expect("Could not open log file.");
+    let mut push = |point| chart.push(point);
+    for row in reader {
+        let row = row.expect("Malformed row in log file.");
+        if !export::in_range(&row, from, to)
+            || room.is_some_and(|room| row.room.as_deref() != Some(room))
+        {
+            continue;
+        }
+        let timestamped = TimestampedReading {
+            reading: row.to_reading(),
+            timestamp_ms: row.timestamp.and_utc().timestamp_millis() as u64,
+            sequence: 0,
+        };
+        resampler.push(&timestamped, &mut push);
+        if let Some(event) = detector.push(&timestamped) {
+            push(HistoryPoint::Ventilation(event));
+        }
+    }
+    resampler.finish(&mut push);
+    if let Some(event) = detector.finish() {
+        push(HistoryPoint::Ventilation(event));
+    }
+    if chart.is_empty() {
+        eprintln!("No readings in that range, the chart is empty.");
+    }
+    let svg = chart.to_svg();
+    match output {
+        Some(path) => std::fs::write(path, svg).expect("Could not write the chart."),
+        None => print!("{svg}"),
+    }
+}
+
+fn report(
+    log: &Path,
+    mut builder: ReportBuilder,
+    (from, to): (Option<NaiveDateTime>, Option<NaiveDateTime>),
+    room: Option<&str>,
+) -> Report {
+    let reader = LogReader::open(log).expect("Could not open log file.");
+    for row in reader {
+        let row = row.expect("Malformed row in log file.");
+        if !export::in_range(&row, from, to)
+            || room.is_some_and(|room| row.room.as_deref() != Some(room))
+        {
+            continue;
+        }
+        builder.push(row.timestamp, &row.to_reading());
+    }
+    builder.finish()
+}
+
+/// Bump `schema_version` whenever fields are removed or change their meaning. Adding fields is fine.
+#[derive(Serialize)]
+struct Info {
+    schema_version: u32,
+    library_version: &'static str,
+    device: DeviceInfo,
+    model: Option<&'static str>,
+    capabilities: Capabilities,
+    opcodes_seen: Vec<u8>,
+    firmware_fingerprint: String,
+}
+
+#[derive(Serialize)]
+struct Capabilities {
+    temperature: bool,
+    co2: bool,
+    co2_sanity_check: bool,
+    humidity: bool,
+}
+
+/// A line of `devices`.
+#[derive(Serialize)]
+struct Listing {
+    device: DeviceInfo,
+    firmware_version: String,
+    profile: Option<String>,
+}
+
+fn devices(config: &Config, json: bool) {
+    let registry = config.profile_registry();
+    let devices = pc::enumerate(&registry).unwrap_or_else(|e| {
+        eprintln!("Could not enumerate the devices: {e:?}");
+        std::process::exit(1);
+    });
+    let listings: Vec<Listing> = devices
+        .into_iter()
+        .map(|device| Listing {
+            firmware_version: device.firmware_version(),
+            profile: registry
+                .find(device.vendor_id, device.product_id)
+                .map(|(name, _)| name.to_string()),
+            device,
+        })
+        .collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&listings).unwrap());
+        return;
+    }
+    if listings.is_empty() {
+        println!("No monitor connected.");
+    }
+    for listing in &listings {
+        let device = &listing.device;
+        println!(
+            "{:04x}:{:04x}  {}  serial {}  firmware {}  {} {}  {}",
+            device.vendor_id,
+            device.product_id,
+            listing.profile.as_deref().unwrap_or("unknown"),
+            device.serial_number.as_deref().unwrap_or("-"),
+            listing.firmware_version,
+            device.manufacturer.as_deref().unwrap_or("-"),
+            device.product.as_deref().unwrap_or("-"),
+            device.path
+        );
+    }
+}
+
+fn info(json: bool, probe_duration: Duration) {
+    let monitor = PcCo2Monitor::init_and_connect();
+    let device = monitor
+        .device_info()
+        .expect("Could not read the device information.");
+
+    if !json {
+        println!("Listening to the device for {probe_duration:?}...");
+    }
+    let mut opcodes_seen = BTreeSet::new();
+    let mut partial_reading = MonitorReadingParts::default();
+    let probe_start = std::time::Instant::now();
+    while probe_start.elapsed() < probe_duration {
+        let _ = monitor.read_to_part_with(&mut partial_reading, &mut |raw: MonitorReportRaw| {
+            opcodes_seen.insert(raw.opcode());
+        });
+    }
+
+    let capabilities = Capabilities {
+        temperature: opcodes_seen.contains(&OPCODE_TEMPERATURE),
+        co2: opcodes_seen.contains(&OPCODE_CO2_VALUE),
+        co2_sanity_check: opcodes_seen.contains(&OPCODE_CO2_SANITY_CHECK),
+        humidity: opcodes_seen.contains(&OPCODE_HUMIDITY),
+    };
+    let opcodes_seen: Vec<u8> = opcodes_seen.into_iter().collect();
+    let firmware_fingerprint = opcodes_seen.iter().fold(
+        format!("{:04x}", device.release_number),
+        |fingerprint, op| format!("{fingerprint}-{op:02x}"),
+    );
+    let model = profiles::name_of(device.vendor_id, device.product_id);
+    let info = Info {
+        schema_version: 1,
+        library_version: env!("CARGO_PKG_VERSION"),
+        device,
+        model,
+        capabilities,
+        opcodes_seen,
+        firmware_fingerprint,
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info).unwrap());
+        return;
+    }
+    let unknown = || "unknown".to_string();
+    println!(
+        "Device:       {:04x}:{:04x}",
+        info.device.vendor_id, info.device.product_id
+    );
+    println!("Model:        {}", info.model.unwrap_or("unknown"));
+    println!(
+        "Manufacturer: {}",
+        info.device.manufacturer.clone().unwrap_or_else(unknown)
+    );
+    println!(
+        "Product:      {}",
+        info.device.product.clone().unwrap_or_else(unknown)
+    );
+    println!(
+        "Serial:       {}",
+        info.device.serial_number.clone().unwrap_or_else(unknown)
+    );
+    println!("Path:         {}", info.device.path);
+    println!("Firmware:     {}", info.device.firmware_version());
+    println!("Fingerprint:  {}", info.firmware_fingerprint);
+    println!(
+        "Humidity:     {}",
+        if info.capabilities.humidity {
+            "yes"
+        } else {
+            "no"
+        }
+    );
+    println!("Library:      {}", info.library_version);
+}
+
+/// Reads `device` on its own thread and sends its readings to `readings`, until it's gone.
+fn spawn_reader(
+    device: DeviceInfo,
+    config: &Config,
+    readings: std::sync::mpsc::Sender<(DeviceInfo, MonitorReading)>,
+) {
+    let conversions = config.device.conversions.unwrap_or_default();
+    let sanity_model = config.calibration.sanity_model();
+    let profile = config.device_profile_for(device.vendor_id, device.product_id);
+    let validity = config.calibration.validity_model(&profile, conversions);
+    std::thread::spawn(move || {
+        let monitor = match PcCo2Monitor::open_with_profile(&device, profile) {
+            Ok(monitor) => monitor,
+            Err(e) => {
+                tracing::warn!("could not open {}: {e:?}", device.path);
+                return;
+            }
+        };
+        let start = std::time::Instant::now();
+        let clock = || start.elapsed().as_millis() as u64;
+        let mut partial_reading = MonitorReadingParts::default();
+        partial_reading.conversions = conversions;
+        partial_reading.sanity_model = sanity_model;
+        partial_reading.validity = validity;
+        loop {
+            match monitor.read_complete(&mut partial_reading, &clock, 60_000) {
+                Ok(reading) => {
+                    if readings.send((device.clone(), reading)).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("stopped reading {}: {e:?}", device.path);
+                    return;
                 }
-                csv_writer.flush().unwrap();
-                std::io::stdout().flush().unwrap();
-                heartbeat = std::time::Instant::now();
             }
         }
+    });
+}
+
+fn fleet(config: &Config, sample_interval: Duration) {
+    let mut fleet = Fleet::with_rooms(config.fleet.clone());
+    let log_name = config
+        .sinks
+        .csv
+        .clone()
+        .unwrap_or_else(|| DEFAULT_LOG_NAME.into());
+    let mut csv_writer = LogWriter::append(&log_name, false).expect("Could not open the log file.");
+    let api = serve_api(config, &log_name);
+    #[cfg(feature = "mdns")]
+    let _mdns = api.as_ref().and_then(|api| advertise_api(config, api));
+
+    let mut watcher = DeviceWatcher::with_registry(&config.profile_registry())
+        .expect("Could not initialize Hid Api.");
+    let (sender, readings) = std::sync::mpsc::channel();
+    let start = std::time::Instant::now();
+    let mut last_sample = start;
+    println!("Logging all monitors to {}.", log_name.display());
+    loop {
+        let _ = watcher.poll(&mut |event| match event {
+            DeviceEvent::Connected(device) => {
+                println!("{} connected at {}.", fleet.label(&device), device.path);
+                spawn_reader(device, config, sender.clone());
+            }
+            DeviceEvent::Disconnected(device) => {
+                println!("{} at {} disconnected.", fleet.label(&device), device.path)
+            }
+            _ => (),
+        });
+        // Poll for devices every 2 seconds, collecting readings in between.
+        let poll_at = std::time::Instant::now() + Duration::from_secs(2);
+        while let Some(left) = poll_at.checked_duration_since(std::time::Instant::now())
+            && let Ok((device, reading)) = readings.recv_timeout(left)
+        {
+            fleet.push(&device, &reading);
+        }
+        if last_sample.elapsed() >= sample_interval {
+            last_sample = std::time::Instant::now();
+            let now = Local::now();
+            let readings = fleet.take();
+            let worst = worst_room(&readings);
+            for (room, reading) in &readings {
+                if let Some(api) = &api {
+                    api.publish(room, reading, now.timestamp_millis() as u64);
+                }
+                println!(
+                    "{room}: {}{}",
+                    reading.display_in(TemperatureUnit::Celsius),
+                    if worst == Some(room) {
+                        " <- most co2"
+                    } else {
+                        ""
+                    }
+                );
+                csv_writer
+                    .write(
+                        &Row::from_reading(reading, now.naive_local())
+                            .with_room(room)
+                            .with_monotonic_ms(start.elapsed().as_millis() as u64),
+                    )
+                    .unwrap();
+            }
+            csv_writer.flush().unwrap();
+        }
+    }
+}
+
+fn status(config: &Config, format: StatusFormat, timeout: Duration) {
+    let backend = config.device.backend_or_default();
+    let profile = config.device_profile();
+    let monitor = backend.connect_with_profile(profile).unwrap_or_else(|e| {
+        eprintln!("Could not connect to {backend}: {e}");
+        std::process::exit(1);
+    });
+    let mut partial_reading = MonitorReadingParts::default();
+    partial_reading.conversions = config.device.conversions.unwrap_or_default();
+    partial_reading.sanity_model = config.calibration.sanity_model();
+    partial_reading.validity = config
+        .calibration
+        .validity_model(&profile, partial_reading.conversions);
+    let start = std::time::Instant::now();
+    let clock = || start.elapsed().as_millis() as u64;
+    match monitor.read_complete(&mut partial_reading, &clock, timeout.as_millis() as u64) {
+        Ok(reading) => println!(
+            "{}",
+            format_status(
+                &reading,
+                format,
+                config.units.temperature.unwrap_or_default()
+            )
+        ),
+        Err(e) => {
+            eprintln!("No complete reading within {timeout:?}: {e:?}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn stdio_json(config: &Config) {
+    let backend = config.device.backend_or_default();
+    let profile = config.device_profile();
+    let unit = config.units.temperature.unwrap_or_default();
+    let mut session = Session::new(&config.thresholds, unit)
+        .with_ventilation(config.ventilation.detector())
+        .with_anomaly(config.anomaly.detector());
+    #[cfg(feature = "occupancy")]
+    if let Some(estimator) = config.room.occupancy() {
+        session = session.with_occupancy(estimator);
+    }
+    let mut stdout = std::io::stdout().lock();
+    let mut emit = |message: Message| {
+        // Nobody is listening anymore once stdout is closed.
+        if writeln!(stdout, "{}", message.to_line())
+            .and_then(|()| stdout.flush())
+            .is_err()
+        {
+            std::process::exit(0);
+        }
+    };
+    let monitor = match backend.connect_with_profile(profile) {
+        Ok(monitor) => monitor,
+        Err(e) => {
+            emit(Message::Error {
+                message: format!("could not connect to {backend}: {e}"),
+            });
+            std::process::exit(1);
+        }
+    };
+    // Reading the device blocks, so stdin is read on its own thread.
+    let (sender, lines) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            let Ok(line) = line else { break };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let mut partial_reading = MonitorReadingParts::default();
+    partial_reading.conversions = config.device.conversions.unwrap_or_default();
+    partial_reading.sanity_model = config.calibration.sanity_model();
+    partial_reading.validity = config
+        .calibration
+        .validity_model(&profile, partial_reading.conversions);
+    let start = std::time::Instant::now();
+    let clock = || start.elapsed().as_millis() as u64;
+    // The simulated monitor answers immediately, pace it like the device.
+    let pause = if backend.is_device() {
+        Duration::ZERO
+    } else {
+        Duration::from_millis(CadenceTracker::DEFAULT_PERIOD_MS)
+    };
+    let mut wait = Duration::ZERO;
+    loop {
+        let until = std::time::Instant::now() + wait;
+        loop {
+            match lines.recv_timeout(until.saturating_duration_since(std::time::Instant::now())) {
+                Ok(line) => session.request(&line, &mut emit),
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => break,
+                // The other end is done.
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        // Short reads, so requests are answered quickly. The reading is completed over several of them.
+        wait = match monitor.read_complete(&mut partial_reading, &clock, 500) {
+            Ok(reading) => {
+                let now = Local::now();
+                session.reading(&reading, now.timestamp(), now.hour() as u8, &mut emit);
+                pause
+            }
+            Err(MonitorError::Timeout) => Duration::ZERO,
+            Err(e) => {
+                emit(Message::Error {
+                    message: format!("could not read from {backend}: {e:?}"),
+                });
+                std::process::exit(1);
+            }
+        };
+    }
+}
+
+/// A healthy ZGm053U sends at least one report every 2 seconds and completes a reading every ~5 seconds, see the
+/// README.
+const REFERENCE_REPORTS_PER_SECOND: f64 = 0.5;
+const REFERENCE_CYCLE: Duration = Duration::from_millis(CadenceTracker::DEFAULT_PERIOD_MS);
+
+fn bench(duration: Duration) {
+    let monitor = PcCo2Monitor::init_and_connect();
+    println!("Measuring for {duration:?}...");
+
+    let mut partial_reading = MonitorReadingParts::default();
+    let mut health = MonitorHealth::new();
+    let mut latencies = Vec::new();
+    let mut completed = Vec::new();
+    let start = std::time::Instant::now();
+    while start.elapsed() < duration {
+        let before = std::time::Instant::now();
+        let result =
+            monitor.read_to_part_with_health(&mut partial_reading, &mut health, &mut |_| ());
+        latencies.push(before.elapsed());
+        if let Ok(Some(_)) = result {
+            completed.push(std::time::Instant::now());
+        }
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    let frames = health.reports + health.checksum_failures + health.framing_failures;
+    let percent = |n: u64| 100.0 * n as f64 / frames.max(1) as f64;
+    let ok = |good: bool| if good { "ok" } else { "CHECK" };
+    let reports_per_second = health.reports as f64 / elapsed;
+    println!(
+        "Reports:          {} ({reports_per_second:.2}/s, reference >= {REFERENCE_REPORTS_PER_SECOND}/s) {}",
+        health.reports,
+        ok(reports_per_second >= REFERENCE_REPORTS_PER_SECOND)
+    );
+    println!(
+        "Checksum errors:  {} ({:.1}%, reference 0%) {}",
+        health.checksum_failures,
+        percent(health.checksum_failures),
+        ok(health.checksum_failures == 0)
+    );
+    println!(
+        "Framing errors:   {} ({:.1}%, reference 0%) {}",
+        health.framing_failures,
+        percent(health.framing_failures),
+        ok(health.framing_failures == 0)
+    );
+    println!("Short reads:      {}", health.short_reads);
+    println!("Read errors:      {}", health.read_errors);
+    println!("Timeouts:         {}", health.timeouts);
+    println!("Unknown opcodes:  {}", health.unknown_opcodes);
+    let cycle = (completed.len() > 1)
+        .then(|| (*completed.last().unwrap() - completed[0]) / (completed.len() - 1) as u32);
+    match cycle {
+        Some(cycle) => println!(
+            "Cycle period:     {cycle:.1?} (reference {REFERENCE_CYCLE:.1?}) {}",
+            ok(cycle < REFERENCE_CYCLE * 2)
+        ),
+        None => println!("Cycle period:     not enough complete readings"),
+    }
+    latencies.sort_unstable();
+    if let Some(max) = latencies.last() {
+        let mean = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+        let median = latencies[latencies.len() / 2];
+        println!("Read latency:     {mean:.1?} mean, {median:.1?} median, {max:.1?} max");
+    }
+
+    println!();
+    if health.failures() > 0 {
+        println!(
+            "Corrupted or failed reads usually mean a bad cable, hub or port. Try another one."
+        );
+    } else if reports_per_second < REFERENCE_REPORTS_PER_SECOND
+        || cycle.is_none_or(|c| c >= REFERENCE_CYCLE * 2)
+    {
+        println!(
+            "The connection looks clean, but the device reports less than expected. Probably a firmware oddity."
+        );
+    } else {
+        println!("Everything looks fine.");
+    }
+}
+
+fn capture(path: &Path, duration: Duration) {
+    let monitor = PcCo2Monitor::init_and_connect();
+    let device = monitor
+        .device_info()
+        .expect("Could not read the device information.");
+    let file = std::fs::File::create(path).expect("Could not create the capture file.");
+    let mut writer = CaptureWriter::new(std::io::BufWriter::new(file)).unwrap();
+    writer
+        .comment(&format!(
+            "device {:04x}:{:04x} release {:04x} {:?} {:?}\nco2-monitor {}",
+            device.vendor_id,
+            device.product_id,
+            device.release_number,
+            device.manufacturer.unwrap_or_default(),
+            device.product.unwrap_or_default(),
+            env!("CARGO_PKG_VERSION"),
+        ))
+        .unwrap();
+    println!("Recording for {duration:?} to {}...", path.display());
+
+    let start = std::time::Instant::now();
+    let mut reads = 0;
+    while start.elapsed() < duration {
+        let mut report = [0u8; 8];
+        let result = monitor.read(&mut report);
+        let read = CapturedRead {
+            elapsed_ms: start.elapsed().as_millis() as u64,
+            result,
+            report,
+        };
+        writer.record(&read).unwrap();
+        reads += 1;
+    }
+    writer.into_inner().unwrap();
+    println!("Recorded {reads} reads.");
+}
+
+fn replay(path: &Path, speed: f64, raw: bool) {
+    let monitor = ReplayMonitor::open(path, speed).expect("Could not read the capture.");
+    let start = std::time::Instant::now();
+    let mut partial_reading = MonitorReadingParts::default();
+    let mut health = MonitorHealth::new();
+    while !monitor.is_finished() {
+        let result = monitor.read_to_part_with_health(
+            &mut partial_reading,
+            &mut health,
+            &mut |report: MonitorReportRaw| {
+                if raw {
+                    println!("{:>10.1?} -- {:?}", start.elapsed(), report);
+                }
+            },
+        );
+        match result {
+            Ok(Some(reading)) => println!("{:>10.1?} -- {}", start.elapsed(), reading),
+            Ok(None) => (),
+            Err(e) => println!("{:>10.1?} -- {:?}", start.elapsed(), e),
+        }
+    }
+    println!();
+    println!("{health}");
+}
+
+fn doctor(print_rule: bool, install_rule: bool, reload: bool) {
+    if print_rule {
+        print!("{}", diagnostics::udev_rule());
+        return;
+    }
+    if install_rule {
+        match diagnostics::install_udev_rule(reload) {
+            Ok(()) => println!(
+                "Installed the udev rule at {}.",
+                diagnostics::UDEV_RULE_PATH
+            ),
+            Err(e) => {
+                println!("Could not install the udev rule: {e}. Are you root?");
+                std::process::exit(1);
+            }
+        }
+        if !reload {
+            println!("Re-plug the device for the rule to take effect.");
+            return;
+        }
+    }
+
+    let diagnosis = diagnostics::diagnose();
+    println!("{diagnosis}");
+    if matches!(diagnosis, Diagnosis::PermissionDenied(_)) && diagnostics::udev_rule_installed() {
+        println!(
+            "A udev rule is already installed at {}, maybe the device wasn't re-plugged since?",
+            diagnostics::UDEV_RULE_PATH
+        );
+    }
+    if !matches!(diagnosis, Diagnosis::Ok(_)) {
+        std::process::exit(1);
+    }
+}
+
+/// Running the logger as a windows service. The service runs `co2_cli [options] service run`, where the options are the
+/// ones given to `service install`.
+#[cfg(all(windows, feature = "windows-service"))]
+mod service {
+    use super::{Cli, load_config, log};
+    use clap::{Parser, Subcommand};
+    use co2_monitor::event_log;
+    use std::{ffi::OsString, sync::mpsc, time::Duration};
+    use windows_service::{
+        define_windows_service,
+        service::{
+            ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl,
+            ServiceExitCode, ServiceInfo, ServiceStartType, ServiceState, ServiceStatus,
+            ServiceType,
+        },
+        service_control_handler::{self, ServiceControlHandlerResult},
+        service_dispatcher,
+        service_manager::{ServiceManager, ServiceManagerAccess},
+    };
+
+    const SERVICE_NAME: &str = "co2-monitor";
+
+    #[derive(Subcommand)]
+    pub enum ServiceAction {
+        /// Install the service (as administrator), it starts automatically with windows.
+        Install {
+            /// Options for the logger after a `--`, e.g. `-- --config C:\co2\config.toml`. Use absolute paths, services
+            /// start in System32.
+            #[arg(last = true)]
+            options: Vec<OsString>,
+        },
+        /// Stop and remove the service (as administrator).
+        Uninstall,
+        /// What the service manager runs.
+        #[command(hide = true)]
+        Run,
+    }
+
+    pub fn manage(action: ServiceAction) {
+        let result = match action {
+            ServiceAction::Install { options } => install(options),
+            ServiceAction::Uninstall => uninstall(),
+            ServiceAction::Run => unreachable!("handled before logging is set up"),
+        };
+        if let Err(e) = result {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+
+    fn install(options: Vec<OsString>) -> Result<(), Box<dyn std::error::Error>> {
+        let manager = ServiceManager::local_computer(
+            None::<&str>,
+            ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+        )?;
+        let mut launch_arguments = options;
+        launch_arguments.extend(["service".into(), "run".into()]);
+        let info = ServiceInfo {
+            name: SERVICE_NAME.into(),
+            display_name: "CO2 monitor logger".into(),
+            service_type: ServiceType::OWN_PROCESS,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: std::env::current_exe()?,
+            launch_arguments,
+            dependencies: Vec::new(),
+            account_name: None,
+            account_password: None,
+        };
+        let service = manager.create_service(&info, ServiceAccess::CHANGE_CONFIG)?;
+        service.set_description("Logs the readings of a ZGm053U co2 monitor.")?;
+        event_log::install_source(SERVICE_NAME)?;
+        println!("Installed the {SERVICE_NAME} service, start it with `sc start {SERVICE_NAME}`.");
+        Ok(())
+    }
+
+    fn uninstall() -> Result<(), Box<dyn std::error::Error>> {
+        let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)?;
+        let service = manager.open_service(
+            SERVICE_NAME,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+        )?;
+        if service.query_status()?.current_state != ServiceState::Stopped {
+            service.stop()?;
+        }
+        service.delete()?;
+        // Not being able to remove the event source doesn't matter much.
+        let _ = event_log::uninstall_source(SERVICE_NAME);
+        println!("Removed the {SERVICE_NAME} service.");
+        Ok(())
+    }
+
+    pub fn run() {
+        if let Err(e) = service_dispatcher::start(SERVICE_NAME, ffi_service_main) {
+            eprintln!(
+                "Could not start the service, it has to be started by the service manager: {e}"
+            );
+            std::process::exit(1);
+        }
+    }
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    fn service_main(_arguments: Vec<OsString>) {
+        let (stop, stopped) = mpsc::channel();
+        let handler = move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                let _ = stop.send(());
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        };
+        let Ok(status_handle) = service_control_handler::register(SERVICE_NAME, handler) else {
+            return;
+        };
+        let set_state = |state, exit_code| {
+            let _ = status_handle.set_service_status(ServiceStatus {
+                service_type: ServiceType::OWN_PROCESS,
+                current_state: state,
+                controls_accepted: if state == ServiceState::Running {
+                    ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+                } else {
+                    ServiceControlAccept::empty()
+                },
+                exit_code: ServiceExitCode::Win32(exit_code),
+                checkpoint: 0,
+                wait_hint: Duration::default(),
+                process_id: None,
+            });
+        };
+
+        // The arguments of the service binary, not the ones `sc start` passes to service_main.
+        let cli = Cli::parse();
+        if let Ok(event_log) = event_log::EventLog::register(SERVICE_NAME) {
+            tracing_subscriber::fmt()
+                .with_max_level(cli.log_level)
+                .with_ansi(false)
+                .without_time()
+                .with_writer(event_log)
+                .init();
+        }
+        let config = match load_config(&cli) {
+            Ok(config) => config,
+            Err(e) => {
+                tracing::error!("{e}");
+                set_state(ServiceState::Stopped, 1);
+                return;
+            }
+        };
+
+        set_state(ServiceState::Running, 0);
+        let logger = std::thread::spawn(move || log(&cli.log, &config));
+        // The logger never returns, unless it panics. Every row is flushed right away, so just stop when asked to.
+        let exit_code = loop {
+            match stopped.recv_timeout(Duration::from_secs(1)) {
+                Ok(()) | Err(mpsc::RecvTimeoutError::Disconnected) => break 0,
+                Err(mpsc::RecvTimeoutError::Timeout) if logger.is_finished() => {
+                    tracing::error!("the logger stopped unexpectedly");
+                    break 1;
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => (),
+            }
+        };
+        set_state(ServiceState::Stopped, exit_code);
+        std::process::exit(exit_code as i32);
     }
 }
@@ -0,0 +1,78 @@
+//! Counts missed measurement cycles, to tell "the room is fine" apart from "the sensor silently stopped".
+//!
+//! The device completes a reading roughly every 5 seconds. Whenever the time between two readings is much longer than
+//! that, the readings in between are counted as missed.
+
+/// Tracks the cadence of complete readings against the expected period.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CadenceTracker {
+    expected_period_ms: u64,
+    last_ms: Option<u64>,
+    observed: u64,
+    missed: u64,
+}
+
+impl CadenceTracker {
+    /// The ZGm053U completes a reading about every 5 seconds.
+    pub const DEFAULT_PERIOD_MS: u64 = 5000;
+
+    /// Track against the default period.
+    pub fn new() -> Self {
+        Self::with_period(Self::DEFAULT_PERIOD_MS)
+    }
+
+    /// Track against a custom expected period between readings.
+    pub fn with_period(expected_period_ms: u64) -> Self {
+        Self {
+            expected_period_ms: expected_period_ms.max(1),
+            last_ms: None,
+            observed: 0,
+            missed: 0,
+        }
+    }
+
+    /// Record a complete reading at `timestamp_ms` (of any [`crate::Clock`], as long as it's always the same). Returns
+    /// how many readings were missed since the previous one.
+    pub fn observe(&mut self, timestamp_ms: u64) -> u64 {
+        self.observed += 1;
+        let Some(last_ms) = self.last_ms.replace(timestamp_ms) else {
+            return 0;
+        };
+        let interval = timestamp_ms.saturating_sub(last_ms);
+        // Readings jitter a bit, so only count a cycle as missed once the gap is 1.5 periods long.
+        let missed = (interval + self.expected_period_ms / 2) / self.expected_period_ms;
+        let missed = missed.saturating_sub(1);
+        self.missed += missed;
+        missed
+    }
+
+    /// Number of readings seen.
+    pub fn observed(&self) -> u64 {
+        self.observed
+    }
+
+    /// Number of readings that should have been there, but weren't.
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+
+    /// Fraction of expected readings that were missed, between 0 and 1.
+    pub fn missed_ratio(&self) -> f32 {
+        let expected = self.observed + self.missed;
+        if expected == 0 {
+            return 0.0;
+        }
+        self.missed as f32 / expected as f32
+    }
+
+    /// Forget the previous reading, e.g. after a deliberate reconnect, so the pause isn't counted as missed cycles.
+    pub fn reset_gap(&mut self) {
+        self.last_ms = None;
+    }
+}
+
+impl Default for CadenceTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
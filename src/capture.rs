@@ -0,0 +1,210 @@
+//! Record raw HID reads to a file and play them back later.
+//!
+//! This is mostly for debugging protocol issues on devices I don't own: the owner records a capture with
+//! `co2_cli capture`, sends me the file, and I replay it through the same code with [`ReplayMonitor`].
+//!
+//! The format is plain text, one read per line, so captures can be pasted into issues and edited by hand:
+//!
+//! ```text
+//! # co2-monitor capture v1
+//! 1207 42 12 8b df 0d 00 00 00
+//! 2210
+//! 3214 error ReadFailed
+//! ```
+//!
+//! Every line starts with the milliseconds since the capture started, followed by the bytes that were read (none for a
+//! timeout without data) or `error` and the [`MonitorError`] variant. Lines starting with `#` are comments.
+use crate::device::{Co2MonitorCommunication, MonitorError};
+use std::{
+    cell::Cell,
+    format,
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    string::String,
+    time::{Duration, Instant},
+    vec::Vec,
+};
+
+/// First line of every capture.
+pub const CAPTURE_HEADER: &str = "# co2-monitor capture v1";
+
+/// A single read of a capture.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CapturedRead {
+    /// Milliseconds since the capture started.
+    pub elapsed_ms: u64,
+    /// What [`Co2MonitorCommunication::read`] returned.
+    pub result: Result<usize, MonitorError>,
+    /// The read buffer, of which the first `result` bytes are valid.
+    pub report: [u8; 8],
+}
+
+/// Writes reads in the capture format.
+#[derive(Debug)]
+pub struct CaptureWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Start a capture, this writes the header right away.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writeln!(writer, "{CAPTURE_HEADER}")?;
+        Ok(Self { writer })
+    }
+
+    /// Add a comment, e.g. which device this is. Every line of `text` becomes its own comment line.
+    pub fn comment(&mut self, text: &str) -> io::Result<()> {
+        for line in text.lines() {
+            writeln!(self.writer, "# {line}")?;
+        }
+        Ok(())
+    }
+
+    /// Add a read.
+    pub fn record(&mut self, read: &CapturedRead) -> io::Result<()> {
+        write!(self.writer, "{}", read.elapsed_ms)?;
+        match read.result {
+            Ok(len) => {
+                for byte in &read.report[..len.min(8)] {
+                    write!(self.writer, " {byte:02x}")?;
+                }
+            }
+            Err(e) => write!(self.writer, " error {e:?}")?,
+        }
+        writeln!(self.writer)
+    }
+
+    /// Flush and get the writer back.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+fn parse_error(e: &str) -> Option<MonitorError> {
+    Some(match e {
+        "ReadFailed" => MonitorError::ReadFailed,
+        "MissingTerminatorByte" => MonitorError::MissingTerminatorByte,
+        "ChecksumInvalid" => MonitorError::ChecksumInvalid,
+        "Timeout" => MonitorError::Timeout,
+        "Cancelled" => MonitorError::Cancelled,
+        _ => return None,
+    })
+}
+
+fn parse_line(line: &str) -> Result<CapturedRead, String> {
+    let mut fields = line.split_whitespace();
+    let elapsed_ms = fields
+        .next()
+        .unwrap_or_default()
+        .parse()
+        .map_err(|e| format!("bad timestamp: {e}"))?;
+    let mut report = [0u8; 8];
+    let mut len = 0;
+    while let Some(field) = fields.next() {
+        if field == "error" {
+            let name = fields.next().unwrap_or_default();
+            let error = parse_error(name).ok_or_else(|| format!("unknown error {name:?}"))?;
+            return Ok(CapturedRead {
+                elapsed_ms,
+                result: Err(error),
+                report,
+            });
+        }
+        let byte = report
+            .get_mut(len)
+            .ok_or("more than 8 bytes in a single read")?;
+        *byte = u8::from_str_radix(field, 16).map_err(|e| format!("bad byte {field:?}: {e}"))?;
+        len += 1;
+    }
+    Ok(CapturedRead {
+        elapsed_ms,
+        result: Ok(len),
+        report,
+    })
+}
+
+/// Read a whole capture. Fails with [`io::ErrorKind::InvalidData`] on the first line that can't be parsed.
+pub fn read_capture(reader: impl BufRead) -> io::Result<Vec<CapturedRead>> {
+    let mut reads = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let read = parse_line(line).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {e}", i + 1))
+        })?;
+        reads.push(read);
+    }
+    Ok(reads)
+}
+
+/// Plays a capture back, with the original timing or faster.
+///
+/// Once the capture is over, every read times out without data, see [`ReplayMonitor::is_finished`].
+#[derive(Debug)]
+pub struct ReplayMonitor {
+    reads: Vec<CapturedRead>,
+    speed: f64,
+    next: Cell<usize>,
+    started: Cell<Option<Instant>>,
+}
+
+impl ReplayMonitor {
+    /// Replay `reads`. A `speed` of 1 keeps the original timing, 10 is ten times as fast, and 0 doesn't wait at all.
+    pub fn new(reads: Vec<CapturedRead>, speed: f64) -> Self {
+        Self {
+            reads,
+            speed,
+            next: Cell::new(0),
+            started: Cell::new(None),
+        }
+    }
+
+    /// Replay the capture in the file at `path`.
+    pub fn open(path: impl AsRef<Path>, speed: f64) -> io::Result<Self> {
+        let reads = read_capture(BufReader::new(File::open(path)?))?;
+        Ok(Self::new(reads, speed))
+    }
+
+    /// Whether every read of the capture was played.
+    pub fn is_finished(&self) -> bool {
+        self.next.get() >= self.reads.len()
+    }
+
+    /// How many reads the capture has in total.
+    pub fn len(&self) -> usize {
+        self.reads.len()
+    }
+
+    /// Whether the capture has no reads at all.
+    pub fn is_empty(&self) -> bool {
+        self.reads.is_empty()
+    }
+}
+
+impl Co2MonitorCommunication for ReplayMonitor {
+    /// An empty replay, which only times out. Use [`ReplayMonitor::open`] instead.
+    fn init_and_connect() -> Self {
+        Self::new(Vec::new(), 1.0)
+    }
+
+    fn read(&self, read_buffer: &mut [u8; 8]) -> Result<usize, MonitorError> {
+        let Some(read) = self.reads.get(self.next.get()) else {
+            return Ok(0);
+        };
+        self.next.set(self.next.get() + 1);
+        if self.speed > 0.0 {
+            let started = *self.started.get().get_or_insert_with(Instant::now);
+            self.started.set(Some(started));
+            let offset_ms = read.elapsed_ms.saturating_sub(self.reads[0].elapsed_ms);
+            let due = started + Duration::from_secs_f64(offset_ms as f64 / 1000.0 / self.speed);
+            std::thread::sleep(due.saturating_duration_since(Instant::now()));
+        }
+        *read_buffer = read.report;
+        read.result
+    }
+}
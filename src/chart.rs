@@ -0,0 +1,479 @@
+//! Render a downsampled series as an SVG chart, small enough to put into a report or a web page.
+//!
+//! [`Chart`] takes the [`HistoryPoint`]s of a [`Resampler`](crate::history::Resampler) and draws co2 (mean, with the
+//! range from min to max shaded) on the left axis and the mean temperature on the right one. The background shows the
+//! [air quality](crate::quality) bands, and configured thresholds are dashed lines. Gaps stay empty, and
+//! [ventilations](HistoryPoint::Ventilation) are light blue stripes.
+//!
+//! Only SVG is written, which browsers show directly. Most chat apps don't, so convert it to a PNG for them, e.g. with
+//! `rsvg-convert chart.svg -o chart.png`. Rendering PNGs here would need a rasterizer and fonts, which I left out.
+use crate::{
+    TemperatureUnit,
+    history::{Bucket, HistoryPoint},
+    quality::QualityBoundaries,
+};
+use core::fmt::Write;
+use std::{format, string::String, vec::Vec};
+
+const CO2_COLOR: &str = "#1565c0";
+const TEMPERATURE_COLOR: &str = "#ef6c00";
+const LIMIT_COLOR: &str = "#c62828";
+const VENTILATION_COLOR: &str = "#4fc3f7";
+
+/// Space around the plot area for title, axes and labels, in pixels: top, right, bottom, left.
+const MARGIN: (f32, f32, f32, f32) = (40.0, 64.0, 40.0, 64.0);
+
+/// An SVG chart of a downsampled series, see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chart {
+    width: u32,
+    height: u32,
+    title: Option<String>,
+    unit: TemperatureUnit,
+    bands: QualityBoundaries,
+    max_co2: Option<u16>,
+    temperature_limits: (Option<f32>, Option<f32>),
+    points: Vec<HistoryPoint>,
+}
+
+impl Chart {
+    /// An empty chart of `width` × `height` pixels, in degrees Celsius with the default quality bands.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width: width.max(2 * (MARGIN.1 + MARGIN.3) as u32),
+            height: height.max(2 * (MARGIN.0 + MARGIN.2) as u32),
+            title: None,
+            unit: TemperatureUnit::Celsius,
+            bands: QualityBoundaries::DEFAULT,
+            max_co2: None,
+            temperature_limits: (None, None),
+            points: Vec::new(),
+        }
+    }
+
+    /// Print `title` above the chart.
+    pub fn with_title(mut self, title: &str) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Draw temperatures in `unit`.
+    pub fn with_unit(mut self, unit: TemperatureUnit) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    /// Use other boundaries for the background bands.
+    pub fn with_bands(mut self, bands: QualityBoundaries) -> Self {
+        self.bands = bands;
+        self
+    }
+
+    /// Draw the co2 threshold.
+    pub fn with_max_co2(mut self, max_co2: Option<u16>) -> Self {
+        self.max_co2 = max_co2;
+        self
+    }
+
+    /// Draw the temperature thresholds, in the unit of the chart.
+    pub fn with_temperature_limits(mut self, min: Option<f32>, max: Option<f32>) -> Self {
+        self.temperature_limits = (min, max);
+        self
+    }
+
+    /// Add the next point of the series.
+    pub fn push(&mut self, point: HistoryPoint) {
+        self.points.push(point);
+    }
+
+    /// Whether there is nothing to draw.
+    pub fn is_empty(&self) -> bool {
+        !self
+            .points
+            .iter()
+            .any(|point| matches!(point, HistoryPoint::Bucket(_)))
+    }
+
+    /// The chart as an SVG document. Timestamps are taken as local times, like those of [`LogReader`] rows turned into
+    /// milliseconds with [`and_utc`](chrono::NaiveDateTime::and_utc).
+    ///
+    /// [`LogReader`]: crate::log_format::LogReader
+    pub fn to_svg(&self) -> String {
+        let buckets: Vec<&Bucket> = self
+            .points
+            .iter()
+            .filter_map(|point| match point {
+                HistoryPoint::Bucket(bucket) => Some(bucket),
+                _ => None,
+            })
+            .collect();
+        let (top, right, bottom, left) = MARGIN;
+        let (width, height) = (self.width as f32, self.height as f32);
+        let plot = Area {
+            x0: left,
+            x1: width - right,
+            y0: top,
+            y1: height - bottom,
+        };
+        // Milliseconds since the start, timestamps are too large for an f32.
+        let start_ms = buckets.first().map_or(0, |bucket| bucket.start_ms);
+        let time = Scale::new(
+            0.0,
+            buckets
+                .last()
+                .map_or(1.0, |bucket| (bucket.end_ms - start_ms) as f32),
+        );
+        let co2 = co2_scale(&buckets, self.max_co2);
+        let temperature = self.temperature_scale(&buckets);
+
+        let mut svg = String::new();
+        let _ = write!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {0} {1}\" \
+             font-family=\"sans-serif\" font-size=\"12\">\n<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n",
+            self.width, self.height
+        );
+
+        // Quality bands behind everything else.
+        let bands = [
+            (0, self.bands.excellent_below, "#e8f5e9"),
+            (self.bands.excellent_below, self.bands.good_below, "#f1f8e9"),
+            (self.bands.good_below, self.bands.moderate_below, "#fff8e1"),
+            (self.bands.moderate_below, u16::MAX, "#ffebee"),
+        ];
+        for (from, to, color) in bands {
+            let from = f32::from(from).max(co2.min);
+            let to = f32::from(to).min(co2.max);
+            if from < to {
+                let (y_to, y_from) = (plot.y(co2, to), plot.y(co2, from));
+                let _ = writeln!(
+                    svg,
+                    "<rect x=\"{:.1}\" y=\"{y_to:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{color}\"/>",
+                    plot.x0,
+                    plot.x1 - plot.x0,
+                    y_from - y_to
+                );
+            }
+        }
+
+        // Grid and axis labels.
+        for value in co2.ticks() {
+            let y = plot.y(co2, value);
+            let _ = writeln!(
+                svg,
+                "<line x1=\"{:.1}\" y1=\"{y:.1}\" x2=\"{:.1}\" y2=\"{y:.1}\" stroke=\"#ddd\"/>\
+                 <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\" fill=\"{CO2_COLOR}\">{value:.0}</text>",
+                plot.x0,
+                plot.x1,
+                plot.x0 - 6.0,
+                y + 4.0
+            );
+        }
+        for value in temperature.ticks() {
+            let _ = writeln!(
+                svg,
+                "<text x=\"{:.1}\" y=\"{:.1}\" fill=\"{TEMPERATURE_COLOR}\">{value}</text>",
+                plot.x1 + 6.0,
+                plot.y(temperature, value) + 4.0
+            );
+        }
+        let span_ms = time.max;
+        let format = if span_ms <= 86_400_000.0 {
+            "%H:%M"
+        } else if span_ms <= 7.0 * 86_400_000.0 {
+            "%a %H:%M"
+        } else {
+            "%Y-%m-%d"
+        };
+        if !buckets.is_empty() {
+            for i in 0..=5 {
+                let ms = span_ms * i as f32 / 5.0;
+                let label = chrono::DateTime::from_timestamp_millis((start_ms + ms as u64) as i64)
+                    .unwrap_or_default()
+                    .naive_utc()
+                    .format(format);
+                let x = plot.x(time, ms);
+                let _ = writeln!(
+                    svg,
+                    "<line x1=\"{x:.1}\" y1=\"{:.1}\" x2=\"{x:.1}\" y2=\"{:.1}\" stroke=\"#ddd\"/>\
+                     <text x=\"{x:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{label}</text>",
+                    plot.y0,
+                    plot.y1,
+                    plot.y1 + 18.0
+                );
+            }
+        }
+        let _ = writeln!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{:.1}\" fill=\"{CO2_COLOR}\">ppm</text>\
+             <text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\" fill=\"{TEMPERATURE_COLOR}\">{}</text>",
+            plot.x0 - 40.0,
+            plot.y0 - 8.0,
+            plot.x1 + 40.0,
+            plot.y0 - 8.0,
+            self.unit.symbol()
+        );
+
+        // Thresholds.
+        let mut limit = |scale: Scale, value: f32, color: &str| {
+            if (scale.min..=scale.max).contains(&value) {
+                let y = plot.y(scale, value);
+                let _ = writeln!(
+                    svg,
+                    "<line x1=\"{:.1}\" y1=\"{y:.1}\" x2=\"{:.1}\" y2=\"{y:.1}\" stroke=\"{color}\" \
+                     stroke-dasharray=\"6 4\"/>",
+                    plot.x0, plot.x1
+                );
+            }
+        };
+        if let Some(max_co2) = self.max_co2 {
+            limit(co2, f32::from(max_co2), LIMIT_COLOR);
+        }
+        for value in [self.temperature_limits.0, self.temperature_limits.1]
+            .into_iter()
+            .flatten()
+        {
+            limit(temperature, value, TEMPERATURE_COLOR);
+        }
+
+        // Ventilations, at least 2 pixels wide to be visible in long charts.
+        for point in &self.points {
+            if let HistoryPoint::Ventilation(event) = point {
+                let from = plot.x(time, event.start_ms.saturating_sub(start_ms) as f32);
+                let to = plot.x(time, event.end_ms.saturating_sub(start_ms) as f32);
+                let _ = writeln!(
+                    svg,
+                    "<rect x=\"{from:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{VENTILATION_COLOR}\" \
+                     fill-opacity=\"0.4\"><title>ventilation, {} to {} ppm</title></rect>",
+                    plot.y0,
+                    (to - from).max(2.0),
+                    plot.y1 - plot.y0,
+                    event.start_ppm,
+                    event.end_ppm
+                );
+            }
+        }
+
+        // The series, split at gaps and at buckets without a value.
+        let mut co2_segments = Segments::default();
+        let mut temperature_segments = Segments::default();
+        for point in &self.points {
+            match point {
+                HistoryPoint::Bucket(bucket) => {
+                    let middle_ms = (bucket.start_ms + bucket.end_ms) / 2 - start_ms;
+                    let x = plot.x(time, middle_ms as f32);
+                    match bucket.co2 {
+                        Some(value) => co2_segments.push(
+                            x,
+                            plot.y(co2, value.mean),
+                            (plot.y(co2, value.min), plot.y(co2, value.max)),
+                        ),
+                        None => co2_segments.split(),
+                    }
+                    let value = bucket.temperature;
+                    temperature_segments.push(
+                        x,
+                        plot.y(temperature, self.unit.from_celsius(value.mean)),
+                        (0.0, 0.0),
+                    );
+                }
+                HistoryPoint::Gap { .. } => {
+                    co2_segments.split();
+                    temperature_segments.split();
+                }
+                _ => (),
+            }
+        }
+        for segment in co2_segments.finish() {
+            // Min to max as a shaded area: along the max values and back along the min values.
+            let mut range = String::new();
+            for (x, _, (_, max)) in &segment {
+                let _ = write!(range, "{x:.1},{max:.1} ");
+            }
+            for (x, _, (min, _)) in segment.iter().rev() {
+                let _ = write!(range, "{x:.1},{min:.1} ");
+            }
+            let _ = writeln!(
+                svg,
+                "<polygon points=\"{}\" fill=\"{CO2_COLOR}\" fill-opacity=\"0.15\"/>",
+                range.trim_end()
+            );
+            let _ = writeln!(svg, "{}", polyline(&segment, CO2_COLOR));
+        }
+        for segment in temperature_segments.finish() {
+            let _ = writeln!(svg, "{}", polyline(&segment, TEMPERATURE_COLOR));
+        }
+
+        // Frame, title and legend.
+        let _ = writeln!(
+            svg,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"none\" stroke=\"#999\"/>",
+            plot.x0,
+            plot.y0,
+            plot.x1 - plot.x0,
+            plot.y1 - plot.y0
+        );
+        if let Some(title) = &self.title {
+            let _ = writeln!(
+                svg,
+                "<text x=\"{:.1}\" y=\"24\" font-size=\"16\">{}</text>",
+                plot.x0,
+                escape_xml(title)
+            );
+        }
+        let _ = writeln!(
+            svg,
+            "<text x=\"{:.1}\" y=\"24\" text-anchor=\"end\"><tspan fill=\"{CO2_COLOR}\">— co2</tspan>  \
+             <tspan fill=\"{TEMPERATURE_COLOR}\">— temperature</tspan></text>",
+            plot.x1
+        );
+        if buckets.is_empty() {
+            let _ = writeln!(
+                svg,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">no readings</text>",
+                (plot.x0 + plot.x1) / 2.0,
+                (plot.y0 + plot.y1) / 2.0
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Whole degrees around the mean temperatures and the limits, with some room above and below.
+    fn temperature_scale(&self, buckets: &[&Bucket]) -> Scale {
+        let values = buckets
+            .iter()
+            .map(|bucket| self.unit.from_celsius(bucket.temperature.mean))
+            .chain(
+                [self.temperature_limits.0, self.temperature_limits.1]
+                    .into_iter()
+                    .flatten(),
+            );
+        let (min, max) = min_max(values).unwrap_or((15.0, 25.0));
+        Scale::new((min - 1.0).floor(), (max + 1.0).ceil())
+    }
+}
+
+/// Co2 in steps of 200 ppm around the values and the limit, with some room above and below.
+fn co2_scale(buckets: &[&Bucket], max_co2: Option<u16>) -> Scale {
+    let values = buckets
+        .iter()
+        .filter_map(|bucket| bucket.co2)
+        .flat_map(|co2| [co2.min, co2.max])
+        .chain(max_co2.map(f32::from));
+    let (min, max) = min_max(values).unwrap_or((400.0, 1400.0));
+    Scale::new(
+        ((min - 100.0) / 200.0).floor().max(0.0) * 200.0,
+        ((max + 100.0) / 200.0).ceil() * 200.0,
+    )
+}
+
+fn min_max(values: impl Iterator<Item = f32>) -> Option<(f32, f32)> {
+    values.fold(None, |range, value| match range {
+        None => Some((value, value)),
+        Some((min, max)) => Some((min.min(value), max.max(value))),
+    })
+}
+
+/// A range of values on an axis.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Scale {
+    min: f32,
+    max: f32,
+}
+
+impl Scale {
+    fn new(min: f32, max: f32) -> Self {
+        Self {
+            min,
+            max: if max > min { max } else { min + 1.0 },
+        }
+    }
+
+    /// Where 0.0 is `min` and 1.0 is `max`.
+    fn fraction(self, value: f32) -> f32 {
+        (value - self.min) / (self.max - self.min)
+    }
+
+    /// Round values from `min` to `max`, at most 6 of them: multiples of 1, 2 or 5 times a power of ten.
+    fn ticks(self) -> impl Iterator<Item = f32> {
+        let rough = (self.max - self.min) / 5.0;
+        let magnitude = 10f32.powf(rough.log10().floor());
+        let step = [1.0, 2.0, 5.0, 10.0]
+            .into_iter()
+            .map(|factor| factor * magnitude)
+            .find(|step| *step >= rough)
+            .unwrap_or(rough);
+        let first = (self.min / step).ceil() as i32;
+        let last = (self.max / step).floor() as i32;
+        (first..=last).map(move |i| i as f32 * step)
+    }
+}
+
+/// The plot area in pixels, `y0` is the top.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Area {
+    x0: f32,
+    x1: f32,
+    y0: f32,
+    y1: f32,
+}
+
+impl Area {
+    fn x(self, scale: Scale, value: f32) -> f32 {
+        self.x0 + scale.fraction(value) * (self.x1 - self.x0)
+    }
+
+    fn y(self, scale: Scale, value: f32) -> f32 {
+        self.y1 - scale.fraction(value) * (self.y1 - self.y0)
+    }
+}
+
+/// A connected run of points: x, y and the y of a range around it.
+type Segment = Vec<(f32, f32, (f32, f32))>;
+
+/// The runs of a series.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Segments {
+    done: Vec<Segment>,
+    current: Segment,
+}
+
+impl Segments {
+    fn push(&mut self, x: f32, y: f32, range: (f32, f32)) {
+        self.current.push((x, y, range));
+    }
+
+    fn split(&mut self) {
+        if !self.current.is_empty() {
+            self.done.push(core::mem::take(&mut self.current));
+        }
+    }
+
+    fn finish(mut self) -> Vec<Segment> {
+        self.split();
+        self.done
+    }
+}
+
+/// A single point is drawn as a dot, so buckets between gaps don't disappear.
+fn polyline(segment: &[(f32, f32, (f32, f32))], color: &str) -> String {
+    if let [(x, y, _)] = segment {
+        return format!("<circle cx=\"{x:.1}\" cy=\"{y:.1}\" r=\"2\" fill=\"{color}\"/>");
+    }
+    let mut points = String::new();
+    for (x, y, _) in segment {
+        let _ = write!(points, "{x:.1},{y:.1} ");
+    }
+    format!(
+        "<polyline points=\"{}\" fill=\"none\" stroke=\"{color}\" stroke-width=\"1.5\"/>",
+        points.trim_end()
+    )
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,105 @@
+//! Time sources for timestamping readings.
+//!
+//! The library itself has no notion of time. Implement [`Clock`] for whatever your platform offers (a monotonic timer
+//! on a microcontroller, the system time on a PC), or just pass a closure returning milliseconds.
+use crate::MonitorReading;
+
+/// A source of timestamps in milliseconds.
+///
+/// Whether these are milliseconds since boot or since the unix epoch is up to the implementation, the library only
+/// ever compares timestamps of the same clock.
+pub trait Clock {
+    /// The current time in milliseconds.
+    fn now_millis(&self) -> u64;
+}
+
+impl<F: Fn() -> u64> Clock for F {
+    fn now_millis(&self) -> u64 {
+        self()
+    }
+}
+
+/// A value together with the time it was completed at, according to some [`Clock`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimestampedReading<T = MonitorReading> {
+    /// The wrapped reading.
+    pub reading: T,
+    /// When the reading was completed, in milliseconds of the clock that was used.
+    pub timestamp_ms: u64,
+    /// Counts the readings of a source, so a consumer can tell the same value read twice from the same reading
+    /// delivered twice, and notice gaps after filters like [`ChangeFilter`](crate::dedup::ChangeFilter). 0 where the
+    /// source doesn't count, see [`with_sequence`](Self::with_sequence).
+    pub sequence: u32,
+}
+
+impl<T> TimestampedReading<T> {
+    /// Wrap `reading` with the current time of `clock`, and sequence number 0.
+    pub fn now(reading: T, clock: &impl Clock) -> Self {
+        Self {
+            reading,
+            timestamp_ms: clock.now_millis(),
+            sequence: 0,
+        }
+    }
+
+    /// Set the sequence number, usually from a counter of the reading loop that wraps around.
+    pub fn with_sequence(mut self, sequence: u32) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Transform the reading, keeping the timestamp and the sequence number.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> TimestampedReading<U> {
+        TimestampedReading {
+            reading: f(self.reading),
+            timestamp_ms: self.timestamp_ms,
+            sequence: self.sequence,
+        }
+    }
+}
+
+/// Milliseconds since the unix epoch, from `std::time::SystemTime`. Jumps when the system clock is adjusted.
+#[cfg(feature = "pc")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "pc")]
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64)
+    }
+}
+
+/// Milliseconds since this clock was created, from `std::time::Instant`. Never jumps.
+#[cfg(feature = "pc")]
+#[derive(Copy, Clone, Debug)]
+pub struct MonotonicClock {
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "pc")]
+impl MonotonicClock {
+    /// Start a new clock at 0.
+    pub fn new() -> Self {
+        Self {
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "pc")]
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "pc")]
+impl Clock for MonotonicClock {
+    fn now_millis(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+}
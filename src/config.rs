@@ -0,0 +1,1310 @@
+//! Configuration file for the binaries.
+//!
+//! The config lives in `~/.config/co2-monitor/config.toml` (see [`Config::default_path`]) and is entirely optional,
+//! as is every value in it. A full example:
+//!
+//! ```toml
+//! [device]
+//! backend = "hidapi"
+//! simulate = false
+//! conversions = "v2"
+//! # A built-in profile (see `co2_monitor::profiles::BUILTIN`) or one of [profiles]. Without it, the profile is picked
+//! # by the VID/PID of the device.
+//! profile = "zgm053u"
+//!
+//! [sinks]
+//! csv = "/var/log/co2/log.csv"
+//! extended_columns = true
+//! on_change = false
+//! # Readings network sinks couldn't send, until they can.
+//! spool_dir = "/var/lib/co2-monitor/spool"
+//! spool_max_items = 8640
+//! spool_drop = "oldest"
+//! # Send to network sinks every 10 readings or 60 seconds, and drop readings when 1000 are waiting.
+//! batch_readings = 10
+//! batch_seconds = 60
+//! queue_limit = 1000
+//! # Keep the software ABC, the anomaly detector and raised alerts across restarts.
+//! state_file = "/var/lib/co2-monitor/state.json"
+//!
+//! [units]
+//! temperature = "C"
+//!
+//! # Temperatures are in [units] temperature.
+//! [thresholds]
+//! min_temperature = 16.0
+//! max_temperature = 28.0
+//! temperature_hysteresis = 0.5
+//! max_co2 = 1200
+//! quiet_hours = "22-7"
+//!
+//! [calibration]
+//! sanity_offset = 12811.0
+//! sanity_scale = 1.0
+//! min_valid_co2 = 250
+//! max_valid_co2 = 3000
+//! # Correct co2 so the lowest value of the last days is the outdoor level, see co2_monitor::abc.
+//! abc = false
+//! abc_days = 7
+//! abc_max_offset = 200 # ppm
+//!
+//! [intervals]
+//! read_ms = 200
+//! reconnect_after_seconds = 60
+//! sample_seconds = 60
+//! heartbeat_seconds = 600
+//! warm_up_seconds = 60
+//!
+//! # Rooms of the monitors for `fleet`, by serial number or device path.
+//! [fleet]
+//! "0123456789" = "office"
+//! "/dev/hidraw3" = "bedroom"
+//!
+//! # Models this crate doesn't know, by name. Unset values are those of the ZGm053U.
+//! [profiles.big-range]
+//! vendor_id = 0x04d9
+//! product_id = 0xa052
+//! max_co2 = 5000
+//! humidity = false
+//! encrypted = true
+//! key = [0, 0, 0, 0, 0, 0, 0, 0]
+//! # Or a new key on every connect, for firmware that doesn't accept the zero key.
+//! random_key = false
+//!
+//! # For the ventilation advice.
+//! [room]
+//! volume = 40.0
+//! occupants = 2.0
+//! # Air changes per hour with closed windows, for the occupancy estimate of the `occupancy` feature.
+//! air_changes = 0.5
+//!
+//! # When a drop of co2 counts as ventilation, e.g. an opened window.
+//! [ventilation]
+//! min_rate = 30.0 # ppm per minute
+//! min_drop = 150  # ppm
+//!
+//! # When to warn that the sensor is stuck or drifted, 0 turns a check off.
+//! [anomaly]
+//! stuck_minutes = 60
+//! drift_tolerance = 150 # ppm from the outdoor level
+//! drift_days = 7
+//!
+//! # Which warnings are printed and spoken, see co2_monitor::notification. Quiet hours only hold back the notification,
+//! # critical ones still get through. [thresholds] quiet_hours end up here too, so set only one of them.
+//! [notifications]
+//! min_interval_minutes = 30
+//! quiet_hours = "22-7"
+//! critical_co2 = 2000 # ppm
+//! escalate_after_minutes = 15
+//!
+//! # Processing of the readings before they're logged, in order. Replaces sample_seconds, on_change and
+//! # heartbeat_seconds.
+//! [pipeline]
+//! stages = ["sample:60", "on-change:600"]
+//!
+//! [zabbix]
+//! server = "zabbix.example.com:10051"
+//! host = "office"
+//! co2_key = "co2_monitor.co2"
+//! co2_is_valid_key = "co2_monitor.co2_is_valid"
+//! temperature_key = "co2_monitor.temperature"
+//! # Derived values like co2_deviation go to co2_monitor.co2_deviation.
+//! key_prefix = "co2_monitor."
+//!
+//! # Pushing readings to a Grafana Live stream, with a service account token.
+//! [grafana]
+//! server = "http://localhost:3000"
+//! stream = "co2_monitor"
+//! token = "glsa_..."
+//! measurement = "office"
+//!
+//! # Serving the latest reading as Modbus TCP input registers.
+//! [modbus]
+//! listen = "0.0.0.0:502"
+//! unit_id = 1
+//! co2_register = 0
+//! temperature_register = 1
+//! humidity_register = 2
+//! status_register = 3
+//! scale = 10
+//!
+//! # Serving an HTTP API for other services and apps.
+//! [api]
+//! listen = "0.0.0.0:8080"
+//! token = "a long random string"
+//! cors_origin = "https://dashboard.example.org"
+//! # With the `mdns` feature, the API is advertised as `_co2monitor._tcp` under this name.
+//! mdns = true
+//! mdns_name = "co2-monitor in the office"
+//!
+//! # Switching a fan by co2, with a Tasmota or Shelly plug, two URLs or (with the `gpio` feature) a GPIO line.
+//! [fan]
+//! on_co2 = 1200
+//! off_co2 = 1000
+//! min_on_seconds = 300
+//! min_off_seconds = 60
+//! tasmota = "192.168.1.50"
+//! # shelly = "192.168.1.51"
+//! # on_url = "http://192.168.1.52/relay?state=1"
+//! # off_url = "http://192.168.1.52/relay?state=0"
+//! # gpio_chip = "/dev/gpiochip0"
+//! # gpio_line = 17
+//! # gpio_active_low = false
+//!
+//! # Running a command on alerts (or every reading, or changed ones), see co2_monitor::hook for its environment.
+//! [hook]
+//! command = "notify-send \"co2 $ALERT_TRANSITION\" \"$CO2_PPM ppm\""
+//! on = "alert"
+//! timeout_seconds = 10
+//! max_running = 1
+//!
+//! # Sending readings as KNX group telegrams, with the `knx` feature.
+//! [knx]
+//! co2_group = "1/2/3"
+//! temperature_group = "1/2/4"
+//! humidity_group = "1/2/5"
+//! source = "1.1.250"
+//! # A router to send to instead of the KNXnet/IP routing multicast group.
+//! router = "192.168.1.20:3671"
+//! ```
+//!
+//! Command line flags take precedence, which is what [`Config::or`] is for: turn the flags into a `Config` too, and
+//! fill what they leave open from the file.
+#[cfg(feature = "knx")]
+use crate::knx::{GroupAddress, IndividualAddress, KnxGroups, KnxSender};
+#[cfg(feature = "occupancy")]
+use crate::occupancy::{self, OccupancyEstimator};
+use crate::{
+    Conversions, SanityCheckModel, TemperatureUnit, ValidityModel,
+    abc::{self, AutoBaseline},
+    actuator::{self, Actuator, FanController},
+    advisor::Advisor,
+    alert::{AlertRule, QuietHours, TemperatureAlert},
+    anomaly::{self, AnomalyDetector},
+    api::Access,
+    backend::Backend,
+    grafana::GrafanaLive,
+    hook::{self, ExecHook, HookTrigger},
+    modbus::RegisterMap,
+    notification::NotificationPolicy,
+    pipeline::StageConfig,
+    profiles::{self, DeviceProfile, ProfileRegistry},
+    sink::Batching,
+    spool::DropPolicy,
+    switch::HttpSwitch,
+    ventilation::{self, VentilationDetector},
+    zabbix::ZabbixKeys,
+};
+use core::{fmt::Display, str::FromStr};
+use serde::{Deserialize, Deserializer};
+use std::{
+    boxed::Box,
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    string::String,
+    time::Duration,
+    vec::Vec,
+};
+
+/// Everything that can be configured. `None` means "not configured", so the binary's default applies.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Which device to read from and how to interpret it.
+    pub device: DeviceConfig,
+    /// Where readings go.
+    pub sinks: SinkConfig,
+    /// Units for printing and for the thresholds.
+    pub units: UnitConfig,
+    /// When to warn.
+    pub thresholds: ThresholdConfig,
+    /// Calibration of the individual device.
+    pub calibration: CalibrationConfig,
+    /// Timing of the logger.
+    pub intervals: IntervalConfig,
+    /// Rooms of several monitors, by serial number or device path, see [`Fleet`](crate::fleet::Fleet).
+    pub fleet: BTreeMap<String, String>,
+    /// Custom device profiles by name, see [`Config::device_profile_for`].
+    pub profiles: BTreeMap<String, ProfileConfig>,
+    /// The room the monitor is in.
+    pub room: RoomConfig,
+    /// Detecting ventilation.
+    pub ventilation: VentilationConfig,
+    /// Watching the sensor for being stuck or drifted.
+    pub anomaly: AnomalyConfig,
+    /// Which alerts are notified, and when.
+    pub notifications: NotificationConfig,
+    /// Processing of the readings before they're logged.
+    pub pipeline: PipelineConfig,
+    /// Sending readings to Zabbix.
+    pub zabbix: ZabbixConfig,
+    /// Pushing readings to Grafana Live.
+    pub grafana: GrafanaConfig,
+    /// Serving readings over Modbus TCP.
+    pub modbus: ModbusConfig,
+    /// Serving an HTTP API.
+    pub api: ApiConfig,
+    /// Switching a fan by co2.
+    pub fan: FanConfig,
+    /// Running a command on readings or alerts.
+    pub hook: HookConfig,
+    /// Sending readings to KNX.
+    #[cfg(feature = "knx")]
+    pub knx: KnxConfig,
+}
+
+/// The `[device]` section.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceConfig {
+    /// How to read from the monitor: `hidapi`, `simulate` or `replay:<file>`.
+    #[serde(deserialize_with = "from_str")]
+    pub backend: Option<Backend>,
+    /// Read from [`SimulatedCo2Monitor`](crate::simulate::SimulatedCo2Monitor) instead of a real device, the same as
+    /// `backend = "simulate"`.
+    pub simulate: Option<bool>,
+    /// Conversions of the raw reports.
+    #[serde(deserialize_with = "from_str")]
+    pub conversions: Option<Conversions>,
+    /// Name of the device profile, built-in or from the `[profiles]` section.
+    pub profile: Option<String>,
+}
+
+/// A custom device profile in the `[profiles]` section. What isn't configured is the same as for the ZGm053U.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileConfig {
+    /// [`DeviceProfile::vendor_id`].
+    pub vendor_id: Option<u16>,
+    /// [`DeviceProfile::product_id`].
+    pub product_id: Option<u16>,
+    /// [`DeviceProfile::max_ppm`].
+    pub max_co2: Option<u16>,
+    /// [`DeviceProfile::has_humidity`].
+    pub humidity: Option<bool>,
+    /// [`DeviceProfile::encrypted`].
+    pub encrypted: Option<bool>,
+    /// [`DeviceProfile::key`].
+    pub key: Option<[u8; 8]>,
+    /// Send a new [random key](profiles::random_key) on every connect instead of `key`.
+    pub random_key: Option<bool>,
+}
+
+impl ProfileConfig {
+    /// The configured profile. With `random_key`, every call makes a new key.
+    pub fn profile(&self) -> DeviceProfile {
+        let default = DeviceProfile::ZGM053U;
+        DeviceProfile {
+            vendor_id: self.vendor_id.unwrap_or(default.vendor_id),
+            product_id: self.product_id.unwrap_or(default.product_id),
+            max_ppm: self.max_co2.unwrap_or(default.max_ppm),
+            has_humidity: self.humidity.unwrap_or(default.has_humidity),
+            encrypted: self.encrypted.unwrap_or(default.encrypted),
+            key: match self.random_key {
+                Some(true) => profiles::random_key(),
+                _ => self.key.unwrap_or(default.key),
+            },
+        }
+    }
+}
+
+/// The `[sinks]` section.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SinkConfig {
+    /// Path of the csv log.
+    pub csv: Option<PathBuf>,
+    /// Add humidity, device, quality and the raw sanity check value to new csv logs.
+    pub extended_columns: Option<bool>,
+    /// Only log (and send) readings that differ from the last one, see [`ChangeFilter`](crate::dedup::ChangeFilter).
+    pub on_change: Option<bool>,
+    /// Directory for the [`Spool`](crate::spool::Spool)s of the network sinks. Without it, what can't be sent is lost.
+    pub spool_dir: Option<PathBuf>,
+    /// Items per spool, see [`spool::DEFAULT_MAX_ITEMS`](crate::spool::DEFAULT_MAX_ITEMS).
+    pub spool_max_items: Option<usize>,
+    /// What to drop when a spool is full: `oldest` or `newest`.
+    #[serde(deserialize_with = "from_str")]
+    pub spool_drop: Option<DropPolicy>,
+    /// Send to network sinks once this many readings are collected, see [`Batching`](crate::sink::Batching).
+    pub batch_readings: Option<usize>,
+    /// Send to network sinks at the latest this many seconds after the first reading of a batch.
+    pub batch_seconds: Option<u64>,
+    /// Readings that may wait for a network sink, more are dropped.
+    pub queue_limit: Option<usize>,
+    /// Where to keep what the logger learned across restarts, see [`StateFile`](crate::state::StateFile).
+    pub state_file: Option<PathBuf>,
+}
+
+impl SinkConfig {
+    /// The configured batching, with the default for whatever isn't configured.
+    pub fn batching(&self) -> Batching {
+        let default = Batching::default();
+        Batching {
+            readings: self.batch_readings.unwrap_or(default.readings),
+            max_delay: self
+                .batch_seconds
+                .map_or(default.max_delay, Duration::from_secs),
+            queue_limit: self.queue_limit.unwrap_or(default.queue_limit),
+        }
+    }
+}
+
+/// The `[units]` section.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UnitConfig {
+    /// Unit for printed temperatures and the temperature thresholds.
+    #[serde(deserialize_with = "from_str")]
+    pub temperature: Option<TemperatureUnit>,
+}
+
+/// The `[thresholds]` section. Temperatures are in [`UnitConfig::temperature`].
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThresholdConfig {
+    /// Warn when the temperature drops below this.
+    pub min_temperature: Option<f32>,
+    /// Warn when the temperature rises above this.
+    pub max_temperature: Option<f32>,
+    /// How far the temperature has to be back within range before a warning is cleared.
+    pub temperature_hysteresis: Option<f32>,
+    /// Warn when co2 rises above this many ppm.
+    pub max_co2: Option<u16>,
+    /// Hours in which no warnings are raised, e.g. `"22-7"`. The logger leaves these to the notification policy
+    /// instead, see [`Config::notification_policy`].
+    #[serde(deserialize_with = "from_str")]
+    pub quiet_hours: Option<QuietHours>,
+}
+
+impl ThresholdConfig {
+    /// The configured temperature alert (with a hysteresis of 0.5 degrees by default) and co2 alert, with the quiet
+    /// hours. Temperatures are in `unit`.
+    pub fn alerts(&self, unit: TemperatureUnit) -> (TemperatureAlert, Option<AlertRule>) {
+        let mut temperature_alert = TemperatureAlert::new(
+            self.min_temperature
+                .map_or(f32::NEG_INFINITY, |t| unit.to_celsius(t)),
+            self.max_temperature
+                .map_or(f32::INFINITY, |t| unit.to_celsius(t)),
+            unit.to_celsius(self.temperature_hysteresis.unwrap_or(0.5)) - unit.to_celsius(0.0),
+        );
+        let mut co2_alert = self.max_co2.map(|limit| AlertRule::co2_above(limit, 100));
+        if let Some(quiet_hours) = self.quiet_hours {
+            temperature_alert = temperature_alert.with_quiet_hours(quiet_hours);
+            co2_alert = co2_alert.map(|rule| rule.with_quiet_hours(quiet_hours));
+        }
+        (temperature_alert, co2_alert)
+    }
+}
+
+/// The `[calibration]` section.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CalibrationConfig {
+    /// [`SanityCheckModel::offset`].
+    pub sanity_offset: Option<f32>,
+    /// [`SanityCheckModel::scale`].
+    pub sanity_scale: Option<f32>,
+    /// [`ValidityModel::min_ppm`] [default: 250 with the v2 conversions, 0 (off) with the legacy ones].
+    pub min_valid_co2: Option<u16>,
+    /// [`ValidityModel::max_ppm`].
+    pub max_valid_co2: Option<u16>,
+    /// Correct co2 with the software [`AutoBaseline`] [default: false].
+    pub abc: Option<bool>,
+    /// [`AutoBaseline::with_days`] [default: 7].
+    pub abc_days: Option<u8>,
+    /// [`AutoBaseline::with_max_offset`] in ppm [default: 200].
+    pub abc_max_offset: Option<u16>,
+}
+
+impl CalibrationConfig {
+    /// The configured sanity check model, with the default for whatever isn't configured.
+    pub fn sanity_model(&self) -> SanityCheckModel {
+        let default = SanityCheckModel::default();
+        SanityCheckModel::new(
+            self.sanity_offset.unwrap_or(default.offset),
+            self.sanity_scale.unwrap_or(default.scale),
+        )
+    }
+
+    /// The configured limits of plausible co2, with the limits of `profile` with `conversions` for whatever isn't
+    /// configured.
+    pub fn validity_model(
+        &self,
+        profile: &DeviceProfile,
+        conversions: Conversions,
+    ) -> ValidityModel {
+        let default = profile.validity(conversions);
+        ValidityModel::new(
+            self.min_valid_co2.unwrap_or(default.min_ppm),
+            self.max_valid_co2.unwrap_or(default.max_ppm),
+        )
+    }
+
+    /// The software ABC, if it's turned on.
+    pub fn auto_baseline(&self) -> Option<AutoBaseline> {
+        self.abc.unwrap_or(false).then(|| {
+            AutoBaseline::new()
+                .with_days(self.abc_days.unwrap_or(abc::DEFAULT_DAYS))
+                .with_max_offset(self.abc_max_offset.unwrap_or(abc::DEFAULT_MAX_OFFSET_PPM))
+        })
+    }
+}
+
+/// The `[intervals]` section.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IntervalConfig {
+    /// Pause between two reads, in milliseconds.
+    pub read_ms: Option<u64>,
+    /// Reconnect when there was no reading for this long.
+    pub reconnect_after_seconds: Option<u64>,
+    /// Log one averaged reading per this many seconds, instead of every reading.
+    pub sample_seconds: Option<u64>,
+    /// With [`SinkConfig::on_change`], log an unchanged reading anyway when nothing was logged for this long.
+    pub heartbeat_seconds: Option<u64>,
+    /// Mark co2 as warming up for at most this long after connecting, see [`WarmUp`](crate::warmup::WarmUp). 0 turns
+    /// it off.
+    pub warm_up_seconds: Option<u64>,
+}
+
+impl DeviceConfig {
+    /// The configured backend, with `simulate` as a shorthand for [`Backend::Simulate`].
+    pub fn backend_or_default(&self) -> Backend {
+        match (&self.backend, self.simulate) {
+            (Some(backend), _) => backend.clone(),
+            (None, Some(true)) => Backend::Simulate,
+            (None, _) => Backend::default(),
+        }
+    }
+}
+
+/// The `[room]` section, for the ventilation advice of [`Advisor`].
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoomConfig {
+    /// Volume of the room in m³, i.e. floor area times ceiling height.
+    pub volume: Option<f32>,
+    /// How many people are usually in it [default: 1].
+    pub occupants: Option<f32>,
+    /// Air changes per hour with closed windows, for the occupancy estimate [default: 0.5].
+    #[cfg(feature = "occupancy")]
+    pub air_changes: Option<f32>,
+}
+
+impl RoomConfig {
+    /// The advisor for the room, if its volume is configured. It ventilates at `max_co2` (default 1000 ppm).
+    pub fn advisor(&self, max_co2: Option<u16>) -> Option<Advisor> {
+        let advisor = Advisor::new(self.volume?, self.occupants.unwrap_or(1.0));
+        Some(match max_co2 {
+            Some(limit) => advisor.with_limits(limit, limit.saturating_sub(300).max(500)),
+            None => advisor,
+        })
+    }
+
+    /// The occupancy estimate for the room, if its volume is configured.
+    #[cfg(feature = "occupancy")]
+    pub fn occupancy(&self) -> Option<OccupancyEstimator> {
+        Some(OccupancyEstimator::new(
+            self.volume?,
+            self.air_changes
+                .unwrap_or(occupancy::DEFAULT_AIR_CHANGES_PER_H),
+        ))
+    }
+}
+
+/// The `[ventilation]` section, for [`VentilationDetector`].
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VentilationConfig {
+    /// Co2 has to fall at least this fast, in ppm per minute [default: 30].
+    pub min_rate: Option<f32>,
+    /// And at least this far, in ppm [default: 150].
+    pub min_drop: Option<u16>,
+}
+
+impl VentilationConfig {
+    /// The detector, with the default for whatever isn't configured.
+    pub fn detector(&self) -> VentilationDetector {
+        VentilationDetector::new(
+            self.min_rate.unwrap_or(ventilation::DEFAULT_MIN_RATE),
+            self.min_drop.unwrap_or(ventilation::DEFAULT_MIN_DROP),
+        )
+    }
+}
+
+/// The `[anomaly]` section, for [`AnomalyDetector`].
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnomalyConfig {
+    /// The same co2 value for this many minutes is a stuck sensor, 0 turns the check off [default: 60].
+    pub stuck_minutes: Option<u32>,
+    /// How far the lowest co2 may be from the outdoor level, in ppm [default: 150].
+    pub drift_tolerance: Option<u16>,
+    /// Over how many days, at most 31 and 0 turns the check off [default: 7].
+    pub drift_days: Option<u8>,
+}
+
+impl AnomalyConfig {
+    /// The detector, with the default for whatever isn't configured.
+    pub fn detector(&self) -> AnomalyDetector {
+        AnomalyDetector::new()
+            .with_stuck_minutes(self.stuck_minutes.unwrap_or(anomaly::DEFAULT_STUCK_MINUTES))
+            .with_drift(
+                self.drift_tolerance
+                    .unwrap_or(anomaly::DEFAULT_DRIFT_TOLERANCE_PPM),
+                self.drift_days.unwrap_or(anomaly::DEFAULT_DRIFT_DAYS),
+            )
+    }
+}
+
+/// The `[notifications]` section, for [`NotificationPolicy`]. Unlike with [`ThresholdConfig::alerts`], alerts are still
+/// raised (and logged) during these quiet hours, only not notified.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationConfig {
+    /// Notify the same alert at most once in this many minutes [default: 0].
+    pub min_interval_minutes: Option<u32>,
+    /// Hours in which only critical alerts are notified, e.g. `"22-7"`.
+    #[serde(deserialize_with = "from_str")]
+    pub quiet_hours: Option<QuietHours>,
+    /// Co2 at or above this many ppm is critical.
+    pub critical_co2: Option<u16>,
+    /// Notify again when co2 stays critical for this many minutes.
+    pub escalate_after_minutes: Option<u32>,
+}
+
+impl NotificationConfig {
+    /// The policy, passing every alert for whatever isn't configured.
+    pub fn policy(&self) -> NotificationPolicy {
+        let mut policy =
+            NotificationPolicy::new().with_min_interval(self.min_interval_minutes.unwrap_or(0));
+        if let Some(quiet_hours) = self.quiet_hours {
+            policy = policy.with_quiet_hours(quiet_hours);
+        }
+        if let Some(ppm) = self.critical_co2 {
+            policy = policy.with_critical_co2(ppm);
+        }
+        if let Some(minutes) = self.escalate_after_minutes {
+            policy = policy.with_escalation(minutes);
+        }
+        policy
+    }
+}
+
+/// The `[pipeline]` section.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipelineConfig {
+    /// The stages, e.g. `["sample:60", "on-change"]`, see [`StageConfig`].
+    #[serde(deserialize_with = "from_str_list")]
+    pub stages: Option<Vec<StageConfig>>,
+}
+
+/// The `[zabbix]` section. Readings are only sent when both `server` and `host` are configured.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ZabbixConfig {
+    /// Zabbix server or proxy, `address[:port]`.
+    pub server: Option<String>,
+    /// Host name as configured in Zabbix.
+    pub host: Option<String>,
+    /// Item key for co2, see [`ZabbixKeys`].
+    pub co2_key: Option<String>,
+    /// Item key for the co2 validity.
+    pub co2_is_valid_key: Option<String>,
+    /// Item key for the temperature.
+    pub temperature_key: Option<String>,
+    /// Put in front of the names of derived values to make their item keys.
+    pub key_prefix: Option<String>,
+}
+
+impl ZabbixConfig {
+    /// The configured keys, with the default for whatever isn't configured.
+    pub fn keys(&self) -> ZabbixKeys {
+        let default = ZabbixKeys::default();
+        ZabbixKeys {
+            co2: self.co2_key.clone().unwrap_or(default.co2),
+            co2_is_valid: self
+                .co2_is_valid_key
+                .clone()
+                .unwrap_or(default.co2_is_valid),
+            temperature: self.temperature_key.clone().unwrap_or(default.temperature),
+            prefix: self.key_prefix.clone().unwrap_or(default.prefix),
+        }
+    }
+}
+
+/// The `[grafana]` section. Readings are only pushed when both `server` and `token` are configured.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GrafanaConfig {
+    /// Grafana, `[http://]address[:port]`.
+    pub server: Option<String>,
+    /// Stream id, the middle part of the channel [default: co2_monitor].
+    pub stream: Option<String>,
+    /// Service account token.
+    pub token: Option<String>,
+    /// Measurement name, the last part of the channel [default: co2_monitor].
+    pub measurement: Option<String>,
+}
+
+impl GrafanaConfig {
+    /// A sink for the configured stream, `None` unless both `server` and `token` are configured.
+    pub fn sink(&self) -> Option<GrafanaLive> {
+        let sink = GrafanaLive::new(
+            self.server.as_ref()?,
+            self.stream.as_deref().unwrap_or("co2_monitor"),
+            self.token.as_ref()?,
+        );
+        Some(match &self.measurement {
+            Some(measurement) => sink.with_measurement(measurement),
+            None => sink,
+        })
+    }
+}
+
+/// The `[modbus]` section. Readings are only served when `listen` is configured.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModbusConfig {
+    /// Address to listen on, `address[:port]`.
+    pub listen: Option<String>,
+    /// Unit id to answer as [default: 1].
+    pub unit_id: Option<u8>,
+    /// [`RegisterMap::co2`].
+    pub co2_register: Option<u16>,
+    /// [`RegisterMap::temperature`].
+    pub temperature_register: Option<u16>,
+    /// [`RegisterMap::humidity`].
+    pub humidity_register: Option<u16>,
+    /// [`RegisterMap::status`].
+    pub status_register: Option<u16>,
+    /// [`RegisterMap::scale`].
+    pub scale: Option<u16>,
+}
+
+impl ModbusConfig {
+    /// The configured register map, with the default for whatever isn't configured.
+    pub fn register_map(&self) -> RegisterMap {
+        let default = RegisterMap::default();
+        RegisterMap {
+            co2: self.co2_register.unwrap_or(default.co2),
+            temperature: self.temperature_register.unwrap_or(default.temperature),
+            humidity: self.humidity_register.unwrap_or(default.humidity),
+            status: self.status_register.unwrap_or(default.status),
+            scale: self.scale.unwrap_or(default.scale),
+        }
+    }
+}
+
+/// The `[api]` section. The API is only served when `listen` is configured.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiConfig {
+    /// Address to listen on, `address[:port]`.
+    pub listen: Option<String>,
+    /// [`Access::token`].
+    pub token: Option<String>,
+    /// [`Access::cors_origin`].
+    pub cors_origin: Option<String>,
+    /// Advertise the API with mDNS [default: true].
+    #[cfg(feature = "mdns")]
+    pub mdns: Option<bool>,
+    /// Instance name of the mDNS advertisement [default: `co2-monitor on <host name>`].
+    #[cfg(feature = "mdns")]
+    pub mdns_name: Option<String>,
+}
+
+impl ApiConfig {
+    /// Who may use the API.
+    pub fn access(&self) -> Access {
+        Access {
+            token: self.token.clone(),
+            cors_origin: self.cors_origin.clone(),
+        }
+    }
+}
+
+/// The `[fan]` section, for [`FanController`]. The fan is only switched when `on_co2` is configured, and then there
+/// has to be exactly one switch: `tasmota`, `shelly`, `on_url` and `off_url`, or with the `gpio` feature `gpio_line`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FanConfig {
+    /// Switch on when co2 reaches this many ppm.
+    pub on_co2: Option<u16>,
+    /// Switch off again when co2 is below this many ppm [default: 200 below `on_co2`].
+    pub off_co2: Option<u16>,
+    /// Stay on for at least this many seconds [default: 60].
+    pub min_on_seconds: Option<u32>,
+    /// Stay off for at least this many seconds [default: 60].
+    pub min_off_seconds: Option<u32>,
+    /// A Tasmota plug, `address[:port]`.
+    pub tasmota: Option<String>,
+    /// A Shelly plug (Plus or later), `address[:port]`.
+    pub shelly: Option<String>,
+    /// URL to GET to switch on, `http://address[:port]/path`.
+    pub on_url: Option<String>,
+    /// URL to GET to switch off.
+    pub off_url: Option<String>,
+    /// GPIO chip of `gpio_line` [default: /dev/gpiochip0].
+    #[cfg(feature = "gpio")]
+    pub gpio_chip: Option<PathBuf>,
+    /// GPIO line to switch, the BCM number on a Raspberry Pi.
+    #[cfg(feature = "gpio")]
+    pub gpio_line: Option<u32>,
+    /// Whether on is the low level [default: false].
+    #[cfg(feature = "gpio")]
+    pub gpio_active_low: Option<bool>,
+}
+
+impl FanConfig {
+    /// The controller, if `on_co2` is configured, with the default for whatever else isn't.
+    pub fn controller(&self) -> Option<FanController> {
+        let on_co2 = self.on_co2?;
+        Some(
+            FanController::new(on_co2, self.off_co2.unwrap_or(on_co2.saturating_sub(200)))
+                .with_min_times(
+                    self.min_on_seconds.unwrap_or(actuator::DEFAULT_MIN_SECONDS),
+                    self.min_off_seconds
+                        .unwrap_or(actuator::DEFAULT_MIN_SECONDS),
+                ),
+        )
+    }
+
+    /// The switch, if one is configured. An error if there's more than one, or it can't be opened.
+    pub fn switch(&self) -> Option<io::Result<Box<dyn Actuator<Error = io::Error>>>> {
+        let mut switches: Vec<io::Result<Box<dyn Actuator<Error = io::Error>>>> = Vec::new();
+        if let Some(host) = &self.tasmota {
+            switches.push(Ok(Box::new(HttpSwitch::tasmota(host))));
+        }
+        if let Some(host) = &self.shelly {
+            switches.push(Ok(Box::new(HttpSwitch::shelly(host))));
+        }
+        match (&self.on_url, &self.off_url) {
+            (Some(on_url), Some(off_url)) => {
+                switches.push(Ok(Box::new(HttpSwitch::new(on_url, off_url))))
+            }
+            (None, None) => {}
+            _ => switches.push(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "on_url and off_url go together",
+            ))),
+        }
+        #[cfg(feature = "gpio")]
+        if let Some(line) = self.gpio_line {
+            let chip = self
+                .gpio_chip
+                .clone()
+                .unwrap_or_else(|| PathBuf::from("/dev/gpiochip0"));
+            #[cfg(target_os = "linux")]
+            switches.push(
+                crate::gpio::GpioSwitch::open(chip, line, self.gpio_active_low.unwrap_or(false))
+                    .map(|switch| Box::new(switch) as Box<dyn Actuator<Error = io::Error>>),
+            );
+            #[cfg(not(target_os = "linux"))]
+            switches.push(Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "GPIO is only supported on linux",
+            )));
+            #[cfg(not(target_os = "linux"))]
+            let _ = (chip, line);
+        }
+        if switches.len() > 1 {
+            return Some(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "more than one switch for the fan",
+            )));
+        }
+        switches.pop()
+    }
+}
+
+/// The `[hook]` section. The hook only runs when `command` is configured.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HookConfig {
+    /// Shell command to run, see [`ExecHook`].
+    pub command: Option<String>,
+    /// When to run it: `reading`, `change` or `alert` [default: alert].
+    #[serde(deserialize_with = "from_str")]
+    pub on: Option<HookTrigger>,
+    /// Kill the command after this many seconds [default: 10].
+    pub timeout_seconds: Option<u64>,
+    /// Run at most this many commands at the same time [default: 1].
+    pub max_running: Option<usize>,
+}
+
+impl HookConfig {
+    /// The hook, if there's a command, with the default for whatever else isn't configured. Alert values are in `unit`.
+    pub fn hook(&self, unit: TemperatureUnit) -> Option<ExecHook> {
+        let command = self.command.as_ref()?;
+        Some(
+            ExecHook::new(command)
+                .with_trigger(self.on.unwrap_or_default())
+                .with_unit(unit)
+                .with_timeout(
+                    self.timeout_seconds
+                        .map_or(hook::DEFAULT_TIMEOUT, Duration::from_secs),
+                )
+                .with_max_running(self.max_running.unwrap_or(hook::DEFAULT_MAX_RUNNING)),
+        )
+    }
+}
+
+/// The `[knx]` section. Only values with a group address are sent.
+#[cfg(feature = "knx")]
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KnxConfig {
+    /// [`KnxGroups::co2`].
+    #[serde(deserialize_with = "from_str")]
+    pub co2_group: Option<GroupAddress>,
+    /// [`KnxGroups::temperature`].
+    #[serde(deserialize_with = "from_str")]
+    pub temperature_group: Option<GroupAddress>,
+    /// [`KnxGroups::humidity`].
+    #[serde(deserialize_with = "from_str")]
+    pub humidity_group: Option<GroupAddress>,
+    /// Individual address to send from [default: 15.15.250].
+    #[serde(deserialize_with = "from_str")]
+    pub source: Option<IndividualAddress>,
+    /// Router to send to, `address:port` [default: the routing multicast group].
+    #[serde(deserialize_with = "from_str")]
+    pub router: Option<std::net::SocketAddr>,
+}
+
+#[cfg(feature = "knx")]
+impl KnxConfig {
+    /// The configured group addresses, `None` if there are none.
+    pub fn groups(&self) -> Option<KnxGroups> {
+        let groups = KnxGroups {
+            co2: self.co2_group,
+            temperature: self.temperature_group,
+            humidity: self.humidity_group,
+        };
+        (groups != KnxGroups::default()).then_some(groups)
+    }
+
+    /// A sender for the configured groups, source and router, `None` if there are no group addresses.
+    pub fn sender(&self) -> Option<io::Result<KnxSender>> {
+        let groups = self.groups()?;
+        Some(KnxSender::new(groups).map(|sender| {
+            let sender = sender.with_source(self.source.unwrap_or_default());
+            match self.router {
+                Some(router) => sender.with_target(router),
+                None => sender,
+            }
+        }))
+    }
+}
+
+/// Why a config couldn't be loaded.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// The file couldn't be read.
+    Io(io::Error),
+    /// The file isn't valid TOML, or contains unknown keys or invalid values.
+    Parse(toml::de::Error),
+    /// `[device] profile` is neither built in nor in the `[profiles]` section.
+    UnknownProfile(String),
+    /// `[thresholds]` and `[notifications]` both set quiet hours, and they differ.
+    ConflictingQuietHours,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "could not read config: {e}"),
+            ConfigError::Parse(e) => write!(f, "invalid config: {e}"),
+            ConfigError::UnknownProfile(name) => write!(f, "unknown device profile {name}"),
+            ConfigError::ConflictingQuietHours => {
+                write!(
+                    f,
+                    "set quiet hours in either [thresholds] or [notifications], not both"
+                )
+            }
+        }
+    }
+}
+
+impl core::error::Error for ConfigError {}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/co2-monitor/config.toml`, falling back to `~/.config` (or `%APPDATA%` on windows).
+    pub fn default_path() -> Option<PathBuf> {
+        let base = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+        Some(base.join("co2-monitor").join("config.toml"))
+    }
+
+    /// Parse a config from a TOML string.
+    pub fn parse(toml: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(toml).map_err(ConfigError::Parse)?;
+        config.check()
+    }
+
+    /// `self`, if its values fit together. Run this again after [`Config::or`].
+    pub fn check(self) -> Result<Self, ConfigError> {
+        if let Some(name) = &self.device.profile
+            && !self.profiles.contains_key(name)
+            && profiles::builtin(name).is_none()
+        {
+            return Err(ConfigError::UnknownProfile(name.clone()));
+        }
+        if let (Some(thresholds), Some(notifications)) =
+            (self.thresholds.quiet_hours, self.notifications.quiet_hours)
+            && thresholds != notifications
+        {
+            return Err(ConfigError::ConflictingQuietHours);
+        }
+        Ok(self)
+    }
+
+    /// The configured alerts, like [`ThresholdConfig::alerts`] but without the quiet hours: those are up to
+    /// [`Config::notification_policy`], so critical co2 still gets through them.
+    pub fn alerts(&self, unit: TemperatureUnit) -> (TemperatureAlert, Option<AlertRule>) {
+        ThresholdConfig {
+            quiet_hours: None,
+            ..self.thresholds.clone()
+        }
+        .alerts(unit)
+    }
+
+    /// The policy of the `[notifications]` section, with the `[thresholds]` quiet hours if it doesn't set any.
+    pub fn notification_policy(&self) -> NotificationPolicy {
+        NotificationConfig {
+            quiet_hours: self
+                .notifications
+                .quiet_hours
+                .or(self.thresholds.quiet_hours),
+            ..self.notifications.clone()
+        }
+        .policy()
+    }
+
+    /// The profile of a device with these ids: the one `[device] profile` names, otherwise the first custom profile with
+    /// these ids, a built-in one, or a plain [`DeviceProfile::new`].
+    pub fn device_profile_for(&self, vendor_id: u16, product_id: u16) -> DeviceProfile {
+        let registry = self.profile_registry();
+        self.device
+            .profile
+            .as_ref()
+            .and_then(|name| registry.get(name))
+            .or_else(|| {
+                registry
+                    .find(vendor_id, product_id)
+                    .map(|(_, profile)| profile)
+            })
+            .unwrap_or(DeviceProfile::new(vendor_id, product_id))
+    }
+
+    /// The built-in profiles, and the ones of the `[profiles]` section on top. With `[device] profile`, only that one,
+    /// so enumeration only finds devices of that profile.
+    pub fn profile_registry(&self) -> ProfileRegistry {
+        let mut registry = ProfileRegistry::new();
+        for (name, profile) in &self.profiles {
+            registry.register(name.clone(), profile.profile());
+        }
+        if let Some(name) = &self.device.profile
+            && let Some(profile) = registry.get(name)
+        {
+            return ProfileRegistry::empty().with(name.clone(), profile);
+        }
+        registry
+    }
+
+    /// The profile to connect with when the device isn't known yet, i.e. the one `[device] profile` names or the
+    /// ZGm053U.
+    pub fn device_profile(&self) -> DeviceProfile {
+        let default = DeviceProfile::ZGM053U;
+        self.device_profile_for(default.vendor_id, default.product_id)
+    }
+
+    /// Load the config at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Self::parse(&fs::read_to_string(path).map_err(ConfigError::Io)?)
+    }
+
+    /// The stages of the pipeline: the `[pipeline]` section if there is one, otherwise what `sample_seconds`,
+    /// `on_change` and `heartbeat_seconds` amount to.
+    pub fn pipeline_stages(&self) -> Vec<StageConfig> {
+        if let Some(stages) = &self.pipeline.stages {
+            return stages.clone();
+        }
+        let mut stages = Vec::new();
+        if let Some(seconds) = self.intervals.sample_seconds {
+            stages.push(StageConfig::Sample(seconds));
+        }
+        if self.sinks.on_change == Some(true) {
+            stages.push(StageConfig::OnChange(self.intervals.heartbeat_seconds));
+        }
+        stages
+    }
+
+    /// Load the config at [`Config::default_path`], or the empty config if there is no file.
+    pub fn load_default() -> Result<Self, ConfigError> {
+        match Self::default_path() {
+            Some(path) if path.exists() => Self::load(path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Every value of `self`, and the ones `self` doesn't configure from `fallback`.
+    pub fn or(self, fallback: Config) -> Config {
+        Config {
+            device: DeviceConfig {
+                backend: self.device.backend.or(fallback.device.backend),
+                simulate: self.device.simulate.or(fallback.device.simulate),
+                conversions: self.device.conversions.or(fallback.device.conversions),
+                profile: self.device.profile.or(fallback.device.profile),
+            },
+            sinks: SinkConfig {
+                csv: self.sinks.csv.or(fallback.sinks.csv),
+                extended_columns: self
+                    .sinks
+                    .extended_columns
+                    .or(fallback.sinks.extended_columns),
+                on_change: self.sinks.on_change.or(fallback.sinks.on_change),
+                spool_dir: self.sinks.spool_dir.or(fallback.sinks.spool_dir),
+                spool_max_items: self
+                    .sinks
+                    .spool_max_items
+                    .or(fallback.sinks.spool_max_items),
+                spool_drop: self.sinks.spool_drop.or(fallback.sinks.spool_drop),
+                batch_readings: self.sinks.batch_readings.or(fallback.sinks.batch_readings),
+                batch_seconds: self.sinks.batch_seconds.or(fallback.sinks.batch_seconds),
+                queue_limit: self.sinks.queue_limit.or(fallback.sinks.queue_limit),
+                state_file: self.sinks.state_file.or(fallback.sinks.state_file),
+            },
+            units: UnitConfig {
+                temperature: self.units.temperature.or(fallback.units.temperature),
+            },
+            thresholds: ThresholdConfig {
+                min_temperature: self
+                    .thresholds
+                    .min_temperature
+                    .or(fallback.thresholds.min_temperature),
+                max_temperature: self
+                    .thresholds
+                    .max_temperature
+                    .or(fallback.thresholds.max_temperature),
+                temperature_hysteresis: self
+                    .thresholds
+                    .temperature_hysteresis
+                    .or(fallback.thresholds.temperature_hysteresis),
+                max_co2: self.thresholds.max_co2.or(fallback.thresholds.max_co2),
+                quiet_hours: self
+                    .thresholds
+                    .quiet_hours
+                    .or(fallback.thresholds.quiet_hours),
+            },
+            calibration: CalibrationConfig {
+                sanity_offset: self
+                    .calibration
+                    .sanity_offset
+                    .or(fallback.calibration.sanity_offset),
+                sanity_scale: self
+                    .calibration
+                    .sanity_scale
+                    .or(fallback.calibration.sanity_scale),
+                min_valid_co2: self
+                    .calibration
+                    .min_valid_co2
+                    .or(fallback.calibration.min_valid_co2),
+                max_valid_co2: self
+                    .calibration
+                    .max_valid_co2
+                    .or(fallback.calibration.max_valid_co2),
+                abc: self.calibration.abc.or(fallback.calibration.abc),
+                abc_days: self.calibration.abc_days.or(fallback.calibration.abc_days),
+                abc_max_offset: self
+                    .calibration
+                    .abc_max_offset
+                    .or(fallback.calibration.abc_max_offset),
+            },
+            intervals: IntervalConfig {
+                read_ms: self.intervals.read_ms.or(fallback.intervals.read_ms),
+                reconnect_after_seconds: self
+                    .intervals
+                    .reconnect_after_seconds
+                    .or(fallback.intervals.reconnect_after_seconds),
+                sample_seconds: self
+                    .intervals
+                    .sample_seconds
+                    .or(fallback.intervals.sample_seconds),
+                heartbeat_seconds: self
+                    .intervals
+                    .heartbeat_seconds
+                    .or(fallback.intervals.heartbeat_seconds),
+                warm_up_seconds: self
+                    .intervals
+                    .warm_up_seconds
+                    .or(fallback.intervals.warm_up_seconds),
+            },
+            fleet: {
+                let mut fleet = fallback.fleet;
+                fleet.extend(self.fleet);
+                fleet
+            },
+            profiles: {
+                let mut profiles = fallback.profiles;
+                profiles.extend(self.profiles);
+                profiles
+            },
+            room: RoomConfig {
+                volume: self.room.volume.or(fallback.room.volume),
+                occupants: self.room.occupants.or(fallback.room.occupants),
+                #[cfg(feature = "occupancy")]
+                air_changes: self.room.air_changes.or(fallback.room.air_changes),
+            },
+            ventilation: VentilationConfig {
+                min_rate: self.ventilation.min_rate.or(fallback.ventilation.min_rate),
+                min_drop: self.ventilation.min_drop.or(fallback.ventilation.min_drop),
+            },
+            anomaly: AnomalyConfig {
+                stuck_minutes: self
+                    .anomaly
+                    .stuck_minutes
+                    .or(fallback.anomaly.stuck_minutes),
+                drift_tolerance: self
+                    .anomaly
+                    .drift_tolerance
+                    .or(fallback.anomaly.drift_tolerance),
+                drift_days: self.anomaly.drift_days.or(fallback.anomaly.drift_days),
+            },
+            notifications: NotificationConfig {
+                min_interval_minutes: self
+                    .notifications
+                    .min_interval_minutes
+                    .or(fallback.notifications.min_interval_minutes),
+                quiet_hours: self
+                    .notifications
+                    .quiet_hours
+                    .or(fallback.notifications.quiet_hours),
+                critical_co2: self
+                    .notifications
+                    .critical_co2
+                    .or(fallback.notifications.critical_co2),
+                escalate_after_minutes: self
+                    .notifications
+                    .escalate_after_minutes
+                    .or(fallback.notifications.escalate_after_minutes),
+            },
+            pipeline: PipelineConfig {
+                stages: self.pipeline.stages.or(fallback.pipeline.stages),
+            },
+            zabbix: ZabbixConfig {
+                server: self.zabbix.server.or(fallback.zabbix.server),
+                host: self.zabbix.host.or(fallback.zabbix.host),
+                co2_key: self.zabbix.co2_key.or(fallback.zabbix.co2_key),
+                co2_is_valid_key: self
+                    .zabbix
+                    .co2_is_valid_key
+                    .or(fallback.zabbix.co2_is_valid_key),
+                temperature_key: self
+                    .zabbix
+                    .temperature_key
+                    .or(fallback.zabbix.temperature_key),
+                key_prefix: self.zabbix.key_prefix.or(fallback.zabbix.key_prefix),
+            },
+            grafana: GrafanaConfig {
+                server: self.grafana.server.or(fallback.grafana.server),
+                stream: self.grafana.stream.or(fallback.grafana.stream),
+                token: self.grafana.token.or(fallback.grafana.token),
+                measurement: self.grafana.measurement.or(fallback.grafana.measurement),
+            },
+            modbus: ModbusConfig {
+                listen: self.modbus.listen.or(fallback.modbus.listen),
+                unit_id: self.modbus.unit_id.or(fallback.modbus.unit_id),
+                co2_register: self.modbus.co2_register.or(fallback.modbus.co2_register),
+                temperature_register: self
+                    .modbus
+                    .temperature_register
+                    .or(fallback.modbus.temperature_register),
+                humidity_register: self
+                    .modbus
+                    .humidity_register
+                    .or(fallback.modbus.humidity_register),
+                status_register: self
+                    .modbus
+                    .status_register
+                    .or(fallback.modbus.status_register),
+                scale: self.modbus.scale.or(fallback.modbus.scale),
+            },
+            api: ApiConfig {
+                listen: self.api.listen.or(fallback.api.listen),
+                token: self.api.token.or(fallback.api.token),
+                cors_origin: self.api.cors_origin.or(fallback.api.cors_origin),
+                #[cfg(feature = "mdns")]
+                mdns: self.api.mdns.or(fallback.api.mdns),
+                #[cfg(feature = "mdns")]
+                mdns_name: self.api.mdns_name.or(fallback.api.mdns_name),
+            },
+            fan: FanConfig {
+                on_co2: self.fan.on_co2.or(fallback.fan.on_co2),
+                off_co2: self.fan.off_co2.or(fallback.fan.off_co2),
+                min_on_seconds: self.fan.min_on_seconds.or(fallback.fan.min_on_seconds),
+                min_off_seconds: self.fan.min_off_seconds.or(fallback.fan.min_off_seconds),
+                tasmota: self.fan.tasmota.or(fallback.fan.tasmota),
+                shelly: self.fan.shelly.or(fallback.fan.shelly),
+                on_url: self.fan.on_url.or(fallback.fan.on_url),
+                off_url: self.fan.off_url.or(fallback.fan.off_url),
+                #[cfg(feature = "gpio")]
+                gpio_chip: self.fan.gpio_chip.or(fallback.fan.gpio_chip),
+                #[cfg(feature = "gpio")]
+                gpio_line: self.fan.gpio_line.or(fallback.fan.gpio_line),
+                #[cfg(feature = "gpio")]
+                gpio_active_low: self.fan.gpio_active_low.or(fallback.fan.gpio_active_low),
+            },
+            hook: HookConfig {
+                command: self.hook.command.or(fallback.hook.command),
+                on: self.hook.on.or(fallback.hook.on),
+                timeout_seconds: self.hook.timeout_seconds.or(fallback.hook.timeout_seconds),
+                max_running: self.hook.max_running.or(fallback.hook.max_running),
+            },
+            #[cfg(feature = "knx")]
+            knx: KnxConfig {
+                co2_group: self.knx.co2_group.or(fallback.knx.co2_group),
+                temperature_group: self
+                    .knx
+                    .temperature_group
+                    .or(fallback.knx.temperature_group),
+                humidity_group: self.knx.humidity_group.or(fallback.knx.humidity_group),
+                source: self.knx.source.or(fallback.knx.source),
+                router: self.knx.router.or(fallback.knx.router),
+            },
+        }
+    }
+}
+
+/// Like [`from_str`], for a list of strings.
+fn from_str_list<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Option::<Vec<String>>::deserialize(deserializer)?
+        .map(|list| {
+            list.iter()
+                .map(|s| s.parse().map_err(serde::de::Error::custom))
+                .collect()
+        })
+        .transpose()
+}
+
+/// Deserialize the types that have a `FromStr` implementation (and no serde one) from strings.
+fn from_str<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| s.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
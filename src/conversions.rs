@@ -0,0 +1,74 @@
+//! Selects between the original and the corrected conversion of raw reports.
+//!
+//! Some fixes change the numbers a reading ends up with. A logger that has been running for a year shouldn't get a
+//! silent jump in its series just because it was rebuilt, so the old behavior stays available as
+//! [`Conversions::Legacy`] until whoever runs it switches deliberately. The `v2-conversions` feature changes the
+//! default, [`MonitorReadingParts::conversions`](crate::MonitorReadingParts::conversions) selects it at runtime.
+
+/// Which set of conversions [`MonitorReadingParts`](crate::MonitorReadingParts) uses.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Conversions {
+    /// How readings were always converted:
+    /// - The float temperature is computed on its own as `raw / 16 - 273.15`.
+    /// - A sanity check value that the [`crate::SanityCheckModel`] can't explain marks the co2 value as too high.
+    ///   With the default model that's also what happened to values beyond its zero point, which are actually very
+    ///   *low* co2, due to the unsigned subtraction wrapping around.
+    Legacy,
+    /// The corrected conversions:
+    /// - The float temperature is derived from the integer [`crate::centi_celsius_from_raw`], so both always agree.
+    /// - A sanity check value beyond the zero point of the model counts as 0 ppm, i.e. doesn't mark co2 as too high.
+    V2,
+}
+
+impl Conversions {
+    /// [`V2`](Self::V2) with the `v2-conversions` feature, [`Legacy`](Self::Legacy) otherwise.
+    pub const DEFAULT: Self = if cfg!(feature = "v2-conversions") {
+        Self::V2
+    } else {
+        Self::Legacy
+    };
+}
+
+impl Default for Conversions {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl core::fmt::Display for Conversions {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Conversions::Legacy => "legacy",
+            Conversions::V2 => "v2",
+        })
+    }
+}
+
+/// Returned when parsing [`Conversions`] from an unknown string.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseConversionsError;
+
+impl core::fmt::Display for ParseConversionsError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("expected legacy or v2")
+    }
+}
+
+impl core::error::Error for ParseConversionsError {}
+
+impl core::str::FromStr for Conversions {
+    type Err = ParseConversionsError;
+
+    /// Accepts `legacy`/`v1` and `v2`, in any case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("legacy") || s.eq_ignore_ascii_case("v1") {
+            Ok(Self::Legacy)
+        } else if s.eq_ignore_ascii_case("v2") {
+            Ok(Self::V2)
+        } else {
+            Err(ParseConversionsError)
+        }
+    }
+}
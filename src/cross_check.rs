@@ -0,0 +1,107 @@
+//! Compares the co2 channel (`0x50`) with the co2 implied by the sanity check channel (`0x6e`).
+//!
+//! Below 3000 ppm both should roughly agree. Some units have a failure mode where one of the channels freezes, which
+//! then shows up as a growing disagreement between the two. Feed every raw report into a [`ChannelCrossCheck`], e.g.
+//! from the observer of [`crate::Co2MonitorExt::read_to_part_with`].
+use crate::{MonitorReportRaw, SanityCheckModel};
+
+/// Both channels disagreed by more than the configured margin for a number of consecutive cycles.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SensorDisagreement {
+    /// The value of the co2 channel.
+    pub co2_ppm: u16,
+    /// The co2 implied by the sanity check channel.
+    pub sanity_estimate: u16,
+    /// For how many consecutive cycles the channels disagreed.
+    pub cycles: u16,
+}
+
+impl core::fmt::Display for SensorDisagreement {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "co2 channel reports {} ppm but the sanity check implies {} ppm (for {} cycles)",
+            self.co2_ppm, self.sanity_estimate, self.cycles
+        )
+    }
+}
+
+/// Tracks both co2 channels and reports when they disagree.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ChannelCrossCheck {
+    model: SanityCheckModel,
+    margin_ppm: u16,
+    min_cycles: u16,
+    co2_ppm: Option<u16>,
+    diverging_cycles: u16,
+}
+
+impl ChannelCrossCheck {
+    /// Above this, the co2 channel isn't trustworthy anyway, so nothing is compared.
+    pub const SPEC_MAX_CO2: u16 = 3000;
+    /// The sanity check is only a rough estimate, so allow a generous difference by default.
+    pub const DEFAULT_MARGIN_PPM: u16 = 500;
+    /// Don't report single outliers.
+    pub const DEFAULT_MIN_CYCLES: u16 = 3;
+
+    /// Cross check using `model` to interpret the sanity check channel, with the default margin.
+    pub fn new(model: SanityCheckModel) -> Self {
+        Self::with_margin(model, Self::DEFAULT_MARGIN_PPM, Self::DEFAULT_MIN_CYCLES)
+    }
+
+    /// Cross check, reporting a disagreement once the channels differ by more than `margin_ppm` for `min_cycles`
+    /// consecutive cycles.
+    pub fn with_margin(model: SanityCheckModel, margin_ppm: u16, min_cycles: u16) -> Self {
+        Self {
+            model,
+            margin_ppm,
+            min_cycles: min_cycles.max(1),
+            co2_ppm: None,
+            diverging_cycles: 0,
+        }
+    }
+
+    /// Whether the channels currently disagree for at least the configured number of cycles.
+    pub fn is_disagreeing(&self) -> bool {
+        self.diverging_cycles >= self.min_cycles
+    }
+
+    /// Feed a raw report. Returns `Some` once per episode, in the cycle where the disagreement is first reported.
+    pub fn observe(&mut self, raw: MonitorReportRaw) -> Option<SensorDisagreement> {
+        match raw {
+            MonitorReportRaw::Co2Value(ppm) => {
+                self.co2_ppm = Some(ppm);
+                None
+            }
+            // The sanity check is sent once per cycle, so this is where both channels get compared.
+            MonitorReportRaw::Co2SanityCheck(raw) => {
+                let co2_ppm = self.co2_ppm?;
+                let Some(sanity_estimate) = self.model.checked_estimate(raw) else {
+                    self.diverging_cycles = 0;
+                    return None;
+                };
+                if co2_ppm > Self::SPEC_MAX_CO2 || sanity_estimate > Self::SPEC_MAX_CO2 {
+                    self.diverging_cycles = 0;
+                    return None;
+                }
+                if co2_ppm.abs_diff(sanity_estimate) <= self.margin_ppm {
+                    self.diverging_cycles = 0;
+                    return None;
+                }
+                self.diverging_cycles = self.diverging_cycles.saturating_add(1);
+                (self.diverging_cycles == self.min_cycles).then_some(SensorDisagreement {
+                    co2_ppm,
+                    sanity_estimate,
+                    cycles: self.diverging_cycles,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for ChannelCrossCheck {
+    fn default() -> Self {
+        Self::new(SanityCheckModel::default())
+    }
+}
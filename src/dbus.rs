@@ -0,0 +1,147 @@
+//! Publish readings on DBus, so desktop widgets (GNOME extensions, KDE plasmoids, ...) can show them without running
+//! the CLI.
+//!
+//! [`DbusService`] owns the name [`BUS_NAME`] and serves the interface `org.co2monitor.Reading` at [`OBJECT_PATH`]. The
+//! latest reading is in its properties, which send `PropertiesChanged` as usual, and every new reading is also emitted
+//! as the `NewReading` signal with all values at once. Something like
+//!
+//! ```text
+//! gdbus monitor --session --dest org.co2monitor
+//! ```
+//!
+//! shows them as they come in.
+use crate::MonitorReading;
+use interface::ReadingInterface;
+use zbus::blocking::{Connection, connection};
+
+/// Well-known name the service owns.
+pub const BUS_NAME: &str = "org.co2monitor";
+
+/// Object the interface is served at.
+pub const OBJECT_PATH: &str = "/org/co2monitor/Reading";
+
+// A module of its own, `interface` also generates an (undocumented) public `ReadingInterfaceSignals` trait.
+mod interface {
+    use crate::MonitorReading;
+    // The code generated by `interface` expects the std prelude.
+    use std::{boxed::Box, string::ToString};
+    use zbus::{interface, object_server::SignalEmitter};
+
+    /// The `org.co2monitor.Reading` interface, holding the latest reading.
+    #[derive(Debug, Default)]
+    pub(super) struct ReadingInterface {
+        pub(super) reading: Option<MonitorReading>,
+        pub(super) timestamp: i64,
+    }
+
+    #[interface(name = "org.co2monitor.Reading")]
+    impl ReadingInterface {
+        /// Co2 in ppm, 0 before the first reading.
+        #[zbus(property)]
+        fn co2_ppm(&self) -> u16 {
+            self.reading
+                .map_or(0, |reading| reading.co2_value.as_num_and_bool().0)
+        }
+
+        /// Whether co2 is within the specified range of the sensor.
+        #[zbus(property)]
+        fn co2_is_valid(&self) -> bool {
+            self.reading
+                .is_some_and(|reading| reading.co2_value.as_num_and_bool().1)
+        }
+
+        /// Temperature in degrees Celsius, NaN before the first reading.
+        #[zbus(property)]
+        fn temperature(&self) -> f64 {
+            self.reading
+                .map_or(f64::NAN, |reading| f64::from(reading.temperature))
+        }
+
+        /// Relative humidity in percent, NaN if the device doesn't measure it.
+        #[zbus(property)]
+        fn humidity(&self) -> f64 {
+            self.reading
+                .and_then(|reading| reading.humidity)
+                .map_or(f64::NAN, f64::from)
+        }
+
+        /// Unix timestamp (in seconds) of the latest reading, 0 before the first reading.
+        #[zbus(property)]
+        fn timestamp(&self) -> i64 {
+            self.timestamp
+        }
+
+        /// Emitted for every new reading.
+        #[zbus(signal)]
+        pub(super) async fn new_reading(
+            emitter: &SignalEmitter<'_>,
+            co2_ppm: u16,
+            co2_is_valid: bool,
+            temperature: f64,
+            timestamp: i64,
+        ) -> zbus::Result<()>;
+    }
+}
+
+/// Serves the latest reading on DBus for as long as it lives.
+#[derive(Debug)]
+pub struct DbusService {
+    connection: Connection,
+}
+
+impl DbusService {
+    /// Serve on the session bus, which is what desktop widgets use.
+    pub fn session() -> zbus::Result<Self> {
+        Self::serve(connection::Builder::session()?)
+    }
+
+    /// Serve on the system bus. Owning [`BUS_NAME`] there needs a policy file in `/etc/dbus-1/system.d`.
+    pub fn system() -> zbus::Result<Self> {
+        Self::serve(connection::Builder::system()?)
+    }
+
+    fn serve(builder: connection::Builder<'_>) -> zbus::Result<Self> {
+        let connection = builder
+            .name(BUS_NAME)?
+            .serve_at(OBJECT_PATH, ReadingInterface::default())?
+            .build()?;
+        Ok(Self { connection })
+    }
+
+    /// Make `reading`, taken at the unix timestamp `timestamp`, the latest one and announce it.
+    pub fn publish(&self, reading: &MonitorReading, timestamp: i64) -> zbus::Result<()> {
+        let interface = self
+            .connection
+            .object_server()
+            .interface::<_, ReadingInterface>(OBJECT_PATH)?;
+        let emitter = interface.signal_emitter();
+        let mut latest = interface.get_mut();
+        let previous = latest.reading.replace(*reading);
+        latest.timestamp = timestamp;
+        let (co2, valid) = reading.co2_value.as_num_and_bool();
+        zbus::block_on(async {
+            // Only announce properties that actually changed, widgets tend to redraw on every change.
+            let first = previous.is_none();
+            let previous = previous.unwrap_or_default();
+            if first || previous.co2_value != reading.co2_value {
+                latest.co2_ppm_changed(emitter).await?;
+                latest.co2_is_valid_changed(emitter).await?;
+            }
+            if first || previous.temperature != reading.temperature {
+                latest.temperature_changed(emitter).await?;
+            }
+            if first || previous.humidity != reading.humidity {
+                latest.humidity_changed(emitter).await?;
+            }
+            latest.timestamp_changed(emitter).await?;
+            ReadingInterface::new_reading(
+                emitter,
+                co2,
+                valid,
+                f64::from(reading.temperature),
+                timestamp,
+            )
+            .await
+        })
+    }
+}
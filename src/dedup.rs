@@ -0,0 +1,49 @@
+//! Drop readings that repeat the previous one, for consumers that only care about changes.
+//!
+//! The device sends the same values for many cycles in a row, especially at night. [`ChangeFilter`] only passes a
+//! reading on when it differs from the last one that was passed on, and with a heartbeat at least every so often, so
+//! a consumer can still tell "nothing changed" from "nothing is running". Gaps in the
+//! [sequence numbers](crate::TimestampedReading::sequence) of what comes out are the readings that were dropped.
+use crate::TimestampedReading;
+
+/// Passes on a reading only when it changed, see the [module documentation](self).
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChangeFilter {
+    heartbeat_ms: Option<u64>,
+    last: Option<TimestampedReading>,
+}
+
+impl ChangeFilter {
+    /// Pass on changes only, and an unchanged reading when `heartbeat_ms` passed since the last one that was passed on
+    /// (never with `None`).
+    pub const fn new(heartbeat_ms: Option<u64>) -> Self {
+        Self {
+            heartbeat_ms,
+            last: None,
+        }
+    }
+
+    /// Add a reading. Returns it if it should be passed on: the first one, one with other values than the last one
+    /// that was passed on, or the heartbeat. Only the values count, not the timestamp or the sequence number.
+    pub fn push(&mut self, reading: &TimestampedReading) -> Option<TimestampedReading> {
+        let pass = match self.last {
+            None => true,
+            Some(last) => {
+                last.reading != reading.reading
+                    || self.heartbeat_ms.is_some_and(|heartbeat_ms| {
+                        reading.timestamp_ms.saturating_sub(last.timestamp_ms) >= heartbeat_ms
+                    })
+            }
+        };
+        if pass {
+            self.last = Some(*reading);
+        }
+        pass.then_some(*reading)
+    }
+
+    /// Pass on the next reading in any case, e.g. after reconnecting.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+}
@@ -1,10 +1,16 @@
 //! Contains device specific handling code and the trait definition for the `Co2Monitor`.
-use crate::{MonitorReading, MonitorReadingParts};
+use crate::{
+    Clock, FrameError, MonitorHealth, MonitorReading, MonitorReadingParts, MonitorReportRaw,
+    TimestampedReading, parse_report,
+    policy::{ReadOutcome, ReadPolicy},
+};
 
 const VID: u16 = 0x04d9;
 const PID: u16 = 0xa052;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 /// For most errors the embedded way of dealing with things is try again...
 pub enum MonitorError {
     /// Something during the read failed.
@@ -15,67 +21,234 @@ pub enum MonitorError {
     ChecksumInvalid,
     /// A timeout interrupted the USB-HID read.
     Timeout,
+    /// The read was cancelled from elsewhere, e.g. with a [`CancellationToken`](crate::pc::CancellationToken).
+    Cancelled,
+}
+
+impl From<FrameError> for MonitorError {
+    fn from(e: FrameError) -> Self {
+        match e {
+            FrameError::MissingTerminatorByte => Self::MissingTerminatorByte,
+            FrameError::ChecksumInvalid => Self::ChecksumInvalid,
+        }
+    }
 }
 
 /// Implement this trait for your struct that handles talking over USB-HID. See `pc.rs` for an example implementation
 /// that uses the hidapi rust crate.
+///
+/// The trait is dyn compatible, the constructor and the static methods are just not available on trait objects. So an
+/// application can pick the transport at runtime and hold it as `Box<dyn Co2MonitorCommunication>`, which still has
+/// all of [`Co2MonitorExt`].
+///
+/// Only the transport specific parts live here. Everything built on top of `read` is provided by
+/// [`Co2MonitorExt`], which is implemented automatically for every implementor of this trait.
 pub trait Co2MonitorCommunication {
     /// This method should create your managing struct and set up the necessary connection.
-    fn init_and_connect() -> Self;
+    fn init_and_connect() -> Self
+    where
+        Self: Sized;
 
-    /// This rarely needs to be called directly, use `read_to_part` instead.
+    /// This rarely needs to be called directly, use [`Co2MonitorExt::read_to_part`] instead.
     /// It should read a single 8-byte HID report to the `read_buffer`.
     fn read(&self, read_buffer: &mut [u8; 8]) -> Result<usize, MonitorError>;
 
+    /// Like `read`, but gives up after `timeout_ms` and returns `Ok(0)` (or [`MonitorError::Timeout`]). The default
+    /// ignores the timeout and just calls `read`, which is fine as long as `read` doesn't block forever.
+    fn read_timeout(
+        &self,
+        read_buffer: &mut [u8; 8],
+        timeout_ms: u32,
+    ) -> Result<usize, MonitorError> {
+        let _ = timeout_ms;
+        self.read(read_buffer)
+    }
+
     /// Online resources have some key or magic table in here, but for my co2 device it works with just zeroes...
-    /// Sending the feature report is still necessary. Otherwise no HID data will be available.
-    fn get_feature_report() -> &'static [u8; 9] {
+    /// Sending the feature report is still necessary. Otherwise no HID data will be available. On a PC, the key comes
+    /// from the [`DeviceProfile`](crate::profiles::DeviceProfile) instead, see
+    /// [`DeviceProfile::with_key`](crate::profiles::DeviceProfile::with_key).
+    fn get_feature_report() -> &'static [u8; 9]
+    where
+        Self: Sized,
+    {
         &[0u8; 9]
     }
 
     /// The vendor ID of the used ZGm053U
-    fn get_vid() -> u16 {
+    fn get_vid() -> u16
+    where
+        Self: Sized,
+    {
         VID
     }
 
     /// The product ID of the used ZGm053U
-    fn get_pid() -> u16 {
+    fn get_pid() -> u16
+    where
+        Self: Sized,
+    {
         PID
     }
+}
+
+mod sealed {
+    pub trait Sealed {}
 
+    impl<T: super::Co2MonitorCommunication + ?Sized> Sealed for T {}
+}
+
+/// Functionality provided on top of every `Co2MonitorCommunication` implementation.
+///
+/// This trait is sealed and implemented for all `Co2MonitorCommunication` types, so new methods can be added
+/// here without breaking downstream implementations.
+pub trait Co2MonitorExt: Co2MonitorCommunication + sealed::Sealed {
     /// If `read` is implemented, this function reads a single HID report and, if a correct op-code was read, fills
     /// the passed partial reading. If all parts have been read, it returns Some(...) with a complete reading.
     fn read_to_part(
         &self,
         part: &mut MonitorReadingParts,
     ) -> Result<Option<MonitorReading>, MonitorError> {
+        self.read_to_part_with(part, &mut |_| ())
+    }
+
+    /// Like `read_to_part`, but the complete reading is stamped with the current time of `clock`.
+    fn read_to_part_timestamped(
+        &self,
+        part: &mut MonitorReadingParts,
+        clock: &impl Clock,
+    ) -> Result<Option<TimestampedReading>, MonitorError> {
+        let reading = self.read_to_part(part)?;
+        Ok(reading.map(|reading| TimestampedReading::now(reading, clock)))
+    }
+
+    /// Like `read_to_part`, but every valid report is also passed to `observer`. See
+    /// [`MonitorReadingParts::set_op_val_with`].
+    fn read_to_part_with(
+        &self,
+        part: &mut MonitorReadingParts,
+        observer: &mut impl FnMut(MonitorReportRaw),
+    ) -> Result<Option<MonitorReading>, MonitorError> {
+        self.read_to_part_with_health(part, &mut MonitorHealth::new(), observer)
+    }
+
+    /// Like `read_to_part_with`, but the outcome of the read is also counted in `health`.
+    fn read_to_part_with_health(
+        &self,
+        part: &mut MonitorReadingParts,
+        health: &mut MonitorHealth,
+        observer: &mut impl FnMut(MonitorReportRaw),
+    ) -> Result<Option<MonitorReading>, MonitorError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("read").entered();
         let mut read_buffer = [0u8; 8];
         let read_len = self.read(&mut read_buffer);
-        match read_len {
-            Ok(8) => {
-                if read_buffer[4] != 0x0d {
-                    return Err(MonitorError::MissingTerminatorByte);
-                }
-                if ((read_buffer[0] as u16 + read_buffer[1] as u16 + read_buffer[2] as u16) & 0xff)
-                    as u8
-                    != read_buffer[3]
-                {
-                    return Err(MonitorError::ChecksumInvalid);
-                }
+        decode_to_part(read_len, &read_buffer, part, health, observer)
+    }
 
-                let op = read_buffer[0];
-                let val = ((read_buffer[1] as u16) << 8) | read_buffer[2] as u16;
-                // This will fill once the report values container is saturated.
-                // let _ = self.report_values.insert(op, val);
-                part.set_op_val(op, val);
+    /// Keep reading until `part` is complete, for scripts and status bars that only want a single reading. Gives up
+    /// with [`MonitorError::Timeout`] after `timeout_ms` milliseconds of `clock`.
+    ///
+    /// Invalid reports and read timeouts are skipped, the next report comes soon enough. Any other read error is
+    /// returned right away, since it usually means the device is gone.
+    fn read_complete(
+        &self,
+        part: &mut MonitorReadingParts,
+        clock: &impl Clock,
+        timeout_ms: u64,
+    ) -> Result<MonitorReading, MonitorError> {
+        let deadline = clock.now_millis().saturating_add(timeout_ms);
+        let mut health = MonitorHealth::new();
+        loop {
+            let now = clock.now_millis();
+            if now >= deadline {
+                return Err(MonitorError::Timeout);
             }
-
-            // Too few bytes read. Even though we only need the first 5, it should've been 8.
-            Ok(_) => (),
-            Err(_e) => {
-                // eprintln!("read error: {}", e);
+            let remaining_ms = u32::try_from(deadline - now).unwrap_or(u32::MAX);
+            let mut read_buffer = [0u8; 8];
+            let read_len = self.read_timeout(&mut read_buffer, remaining_ms);
+            if let Err(e) = read_len
+                && e != MonitorError::Timeout
+            {
+                return Err(e);
+            }
+            if let Ok(Some(reading)) =
+                decode_to_part(read_len, &read_buffer, part, &mut health, &mut |_| ())
+            {
+                return Ok(reading);
             }
         }
-        Ok(part.to_reading())
     }
+
+    /// Like `read_complete`, but starts from scratch with the default conversions, i.e. a one-shot read. On a PC,
+    /// [`PcCo2Monitor::read_one`](crate::pc::PcCo2Monitor::read_one) does the same with a `Duration`.
+    fn read_one_with_clock(
+        &self,
+        clock: &impl Clock,
+        timeout_ms: u64,
+    ) -> Result<MonitorReading, MonitorError> {
+        self.read_complete(&mut MonitorReadingParts::default(), clock, timeout_ms)
+    }
+
+    /// Read a single report with the timeout of `policy`, and tell whether to keep reading, back off or give up. See
+    /// [`crate::policy`].
+    fn read_with_policy(
+        &self,
+        part: &mut MonitorReadingParts,
+        policy: &mut ReadPolicy,
+        observer: &mut impl FnMut(MonitorReportRaw),
+    ) -> ReadOutcome {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("read").entered();
+        let mut read_buffer = [0u8; 8];
+        let read_len = self.read_timeout(&mut read_buffer, policy.read_timeout_ms());
+        let error = match read_len {
+            Ok(8) => None,
+            Ok(0) => Some(MonitorError::Timeout),
+            Ok(_) => Some(MonitorError::ReadFailed),
+            Err(e) => Some(e),
+        };
+        let result = decode_to_part(read_len, &read_buffer, part, policy.health_mut(), observer);
+        policy.judge(error, result)
+    }
+}
+
+impl<T: Co2MonitorCommunication + ?Sized> Co2MonitorExt for T {}
+
+/// The part of `read_to_part` that doesn't depend on the transport: given the result of reading a report into
+/// `read_buffer`, feeds the report into `part` and counts the outcome in `health`.
+pub(crate) fn decode_to_part(
+    read_len: Result<usize, MonitorError>,
+    read_buffer: &[u8; 8],
+    part: &mut MonitorReadingParts,
+    health: &mut MonitorHealth,
+    observer: &mut impl FnMut(MonitorReportRaw),
+) -> Result<Option<MonitorReading>, MonitorError> {
+    health.record_read(&read_len);
+    match read_len {
+        Ok(8) => {
+            let frame = parse_report(read_buffer);
+            health.record_frame(&frame);
+            let (op, val) = frame.inspect_err(|e| {
+                event!(warn, "invalid report {:02x?}: {:?}", read_buffer, e);
+            })?;
+            event!(trace, "report {:#04x} = {}", op, val);
+            part.set_op_val_with(op, val, &mut |raw| {
+                if let MonitorReportRaw::Unknown(_, _) = raw {
+                    health.unknown_opcodes += 1;
+                }
+                observer(raw);
+            });
+        }
+
+        // Too few bytes read. Even though we only need the first 5, it should've been 8.
+        Ok(n) => event!(debug, "short read of {} bytes", n),
+        Err(e) => event!(debug, "read error: {:?}", e),
+    }
+    let reading = part.to_reading();
+    if let Some(reading) = &reading {
+        health.readings += 1;
+        event!(debug, "complete reading: {}", reading);
+    }
+    Ok(reading)
 }
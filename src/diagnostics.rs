@@ -0,0 +1,135 @@
+//! Figures out why the monitor can't be opened, and writes the udev rule that fixes it in most cases.
+//!
+//! On linux the hidraw device nodes are only accessible by root by default, so "Unable to open HID device" almost
+//! always means missing permissions rather than a missing device.
+use crate::{pc::DeviceInfo, profiles::ProfileRegistry};
+use hidapi::HidApi;
+use std::{
+    format, io,
+    path::Path,
+    process::Command,
+    string::{String, ToString},
+};
+
+/// Where [`install_udev_rule`] puts the rule.
+pub const UDEV_RULE_PATH: &str = "/etc/udev/rules.d/60-co2-monitor.rules";
+
+/// Result of [`diagnose`].
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Diagnosis {
+    /// The device is there and can be opened.
+    Ok(DeviceInfo),
+    /// hidapi itself couldn't be initialized.
+    HidApiUnavailable(String),
+    /// No device with the expected VID/PID is connected.
+    NoDevice,
+    /// The device is there, but the current user isn't allowed to open it.
+    PermissionDenied(DeviceInfo),
+    /// The device is there, but opening it failed for another reason, e.g. another program holds it exclusively.
+    OpenFailed(DeviceInfo, String),
+}
+
+impl core::fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Diagnosis::Ok(device) => write!(f, "The monitor at {} works.", device.path),
+            Diagnosis::HidApiUnavailable(e) => write!(f, "Could not initialize hidapi: {e}"),
+            Diagnosis::NoDevice => {
+                f.write_str("No monitor with any of")?;
+                for (vendor_id, product_id) in ProfileRegistry::new().ids() {
+                    write!(f, " {vendor_id:04x}:{product_id:04x}")?;
+                }
+                f.write_str(
+                    " is connected. Check the cable, and that the device shows up in `lsusb`.",
+                )
+            }
+            Diagnosis::PermissionDenied(device) => write!(
+                f,
+                "The monitor is connected at {}, but you are not allowed to open it. Install the udev rule \
+                 (`doctor --install-rule --reload` as root) and re-plug the device.",
+                device.path
+            ),
+            Diagnosis::OpenFailed(device, e) => write!(
+                f,
+                "The monitor is connected at {}, but could not be opened: {e}. Is another program reading it?",
+                device.path
+            ),
+        }
+    }
+}
+
+/// Look for the monitor and try to open it.
+pub fn diagnose() -> Diagnosis {
+    let api = match HidApi::new() {
+        Ok(api) => api,
+        Err(e) => return Diagnosis::HidApiUnavailable(e.to_string()),
+    };
+    let registry = ProfileRegistry::new();
+    let Some(info) = api
+        .device_list()
+        .find(|info| registry.find(info.vendor_id(), info.product_id()).is_some())
+    else {
+        return Diagnosis::NoDevice;
+    };
+    let device = DeviceInfo::from(info);
+    match api.open_path(info.path()) {
+        Ok(_) => Diagnosis::Ok(device),
+        Err(e) => {
+            // hidapi's error messages don't reliably tell permission problems apart, the device node does.
+            let node = Path::new(&device.path);
+            let denied = node.exists()
+                && std::fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(node)
+                    .is_err_and(|e| e.kind() == io::ErrorKind::PermissionDenied);
+            if denied {
+                Diagnosis::PermissionDenied(device)
+            } else {
+                Diagnosis::OpenFailed(device, e.to_string())
+            }
+        }
+    }
+}
+
+/// A udev rule that lets every user read the monitor, i.e. every device of a built-in profile. It's a sensor, there's
+/// nothing to protect.
+pub fn udev_rule() -> String {
+    let mut rule = String::from("# ZyAura co2 monitors (co2-monitor crate)\n");
+    for (vendor_id, product_id) in ProfileRegistry::new().ids() {
+        rule += &format!(
+            "SUBSYSTEM==\"hidraw\", ATTRS{{idVendor}}==\"{vendor_id:04x}\", ATTRS{{idProduct}}==\"{product_id:04x}\", \
+             MODE=\"0666\"\n"
+        );
+    }
+    rule
+}
+
+/// Whether a rule was installed at [`UDEV_RULE_PATH`].
+pub fn udev_rule_installed() -> bool {
+    Path::new(UDEV_RULE_PATH).exists()
+}
+
+/// Write [`udev_rule`] to [`UDEV_RULE_PATH`], which needs root. With `reload`, udev is told to reload its rules and
+/// apply them to connected hidraw devices, so re-plugging isn't necessary.
+pub fn install_udev_rule(reload: bool) -> io::Result<()> {
+    std::fs::write(UDEV_RULE_PATH, udev_rule())?;
+    if reload {
+        udevadm(&["control", "--reload-rules"])?;
+        udevadm(&["trigger", "--subsystem-match=hidraw"])?;
+    }
+    Ok(())
+}
+
+fn udevadm(args: &[&str]) -> io::Result<()> {
+    let status = Command::new("udevadm").args(args).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "udevadm {} failed: {status}",
+            args.join(" ")
+        )))
+    }
+}
@@ -0,0 +1,48 @@
+//! Async integration for embassy based firmware.
+//!
+//! Implement [`AsyncReportSource`] on top of your USB host driver, create a [`ReadingChannel`] in a `static`, and spawn
+//! a task that runs [`reading_task`]. Other tasks then just `receive` complete readings from the channel.
+use crate::{
+    MonitorError, MonitorHealth, MonitorReading, MonitorReadingParts, device::decode_to_part,
+};
+use core::future::Future;
+use embassy_sync::{
+    blocking_mutex::raw::RawMutex,
+    channel::{Channel, Sender},
+};
+
+/// Async counterpart of [`Co2MonitorCommunication::read`](crate::Co2MonitorCommunication::read).
+pub trait AsyncReportSource {
+    /// Wait for a single 8-byte HID report and write it to `read_buffer`. Returns the number of bytes read.
+    fn read_report(
+        &mut self,
+        read_buffer: &mut [u8; 8],
+    ) -> impl Future<Output = Result<usize, MonitorError>>;
+}
+
+/// Channel type to pass complete readings from [`reading_task`] to the rest of the firmware.
+pub type ReadingChannel<M, const N: usize> = Channel<M, MonitorReading, N>;
+
+/// Read reports from `source` forever, and send every complete reading to `sender`.
+///
+/// Bad reports are skipped. If the channel is full, this waits until there is space again, so slow consumers don't
+/// lose readings but the device's report queue might overflow in the meantime.
+pub async fn reading_task<S, M, const N: usize>(
+    mut source: S,
+    mut part: MonitorReadingParts,
+    sender: Sender<'_, M, MonitorReading, N>,
+) where
+    S: AsyncReportSource,
+    M: RawMutex,
+{
+    let mut read_buffer = [0u8; 8];
+    let mut health = MonitorHealth::new();
+    loop {
+        let read_len = source.read_report(&mut read_buffer).await;
+        if let Ok(Some(reading)) =
+            decode_to_part(read_len, &read_buffer, &mut part, &mut health, &mut |_| ())
+        {
+            sender.send(reading).await;
+        }
+    }
+}
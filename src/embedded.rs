@@ -0,0 +1,84 @@
+//! Transport-agnostic reading for microcontrollers and other custom USB stacks.
+//!
+//! [`Co2MonitorCommunication`](crate::Co2MonitorCommunication) wants to set up the connection by itself, which rarely
+//! fits firmware where the USB host stack is initialized elsewhere and handed around. [`ReportReader`] instead just
+//! takes a closure that reads a single 8-byte interrupt IN report, and does the rest. See
+//! `examples/embedded_reader.rs` for how this is wired up.
+//!
+//! Don't forget to send the feature report (9 zero bytes, see
+//! [`Co2MonitorCommunication::get_feature_report`](crate::Co2MonitorCommunication::get_feature_report)) as a
+//! `SET_REPORT` control transfer after enumeration, otherwise the device won't send anything.
+use crate::{
+    MonitorError, MonitorHealth, MonitorReading, MonitorReadingParts, MonitorReportRaw,
+    device::decode_to_part,
+};
+
+/// Reads reports through a user supplied closure and assembles them into readings.
+///
+/// The closure gets an 8 byte buffer and should return how many bytes were read into it, just like
+/// [`Co2MonitorCommunication::read`](crate::Co2MonitorCommunication::read).
+pub struct ReportReader<F> {
+    read: F,
+    part: MonitorReadingParts,
+    health: MonitorHealth,
+}
+
+impl<F> ReportReader<F>
+where
+    F: FnMut(&mut [u8; 8]) -> Result<usize, MonitorError>,
+{
+    /// Create a reader that reads reports with `read`.
+    pub fn new(read: F) -> Self {
+        Self::with_parts(read, MonitorReadingParts::new())
+    }
+
+    /// Create a reader that assembles readings in `part`, e.g. to use a custom sanity check model.
+    pub fn with_parts(read: F, part: MonitorReadingParts) -> Self {
+        Self {
+            read,
+            part,
+            health: MonitorHealth::new(),
+        }
+    }
+
+    /// Read a single report. Returns `Some` whenever it completed a reading.
+    pub fn read_reading(&mut self) -> Result<Option<MonitorReading>, MonitorError> {
+        self.read_reading_with(&mut |_| ())
+    }
+
+    /// Like `read_reading`, but every valid report is also passed to `observer`.
+    pub fn read_reading_with(
+        &mut self,
+        observer: &mut impl FnMut(MonitorReportRaw),
+    ) -> Result<Option<MonitorReading>, MonitorError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("read").entered();
+        let mut read_buffer = [0u8; 8];
+        let read_len = (self.read)(&mut read_buffer);
+        decode_to_part(
+            read_len,
+            &read_buffer,
+            &mut self.part,
+            &mut self.health,
+            observer,
+        )
+    }
+
+    /// What went wrong (and right) in all reads so far.
+    pub fn health(&self) -> &MonitorHealth {
+        &self.health
+    }
+
+    /// Get the closure back, e.g. to release the USB stack.
+    pub fn into_inner(self) -> F {
+        self.read
+    }
+}
+
+impl<F> core::fmt::Debug for ReportReader<F> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ReportReader")
+            .field("part", &self.part)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,444 @@
+//! Pretend to be an ESPHome device, so Home Assistant can add the monitor with its ESPHome integration, without MQTT.
+//!
+//! [`EsphomeServer`] speaks the plaintext variant of the ESPHome native API on TCP port 6053: frames of a zero byte,
+//! the length and type of the message as varints, and a protobuf message. It only implements what a device with
+//! sensors needs: the handshake, device info, listing the sensors (co2, temperature and optionally humidity), and
+//! sending their states to subscribed clients. Anything else Home Assistant asks for is ignored. There's no password
+//! and no encryption, and no mDNS either, so add the device in Home Assistant by its host name or IP address.
+//!
+//! The protobuf messages are written by hand, they are few and small enough to not need `prost`.
+//!
+//! Every connection has a thread reading requests and one writing frames, which get them through a short queue. A
+//! client that stops reading only fills its own queue and is dropped then, publishing never waits for it. At most
+//! [`MAX_CONNECTIONS`] clients are served at a time.
+use crate::{Co2Value, MonitorReading};
+use std::{
+    format,
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    string::{String, ToString},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, SyncSender, TrySendError},
+    },
+    time::Duration,
+    vec::Vec,
+};
+
+/// The port of the native API, when none is given.
+pub const DEFAULT_PORT: u16 = 6053;
+
+/// The version of the native API this implements.
+const API_VERSION: (u32, u32) = (1, 10);
+
+// Message types, from `api.proto` of ESPHome.
+const HELLO_REQUEST: u32 = 1;
+const HELLO_RESPONSE: u32 = 2;
+const CONNECT_REQUEST: u32 = 3;
+const CONNECT_RESPONSE: u32 = 4;
+const DISCONNECT_REQUEST: u32 = 5;
+const DISCONNECT_RESPONSE: u32 = 6;
+const PING_REQUEST: u32 = 7;
+const PING_RESPONSE: u32 = 8;
+const DEVICE_INFO_REQUEST: u32 = 9;
+const DEVICE_INFO_RESPONSE: u32 = 10;
+const LIST_ENTITIES_REQUEST: u32 = 11;
+const LIST_ENTITIES_SENSOR_RESPONSE: u32 = 16;
+const LIST_ENTITIES_DONE_RESPONSE: u32 = 19;
+const SUBSCRIBE_STATES_REQUEST: u32 = 20;
+const SENSOR_STATE_RESPONSE: u32 = 25;
+
+/// Frames longer than this are not from a client of a sensor.
+const MAX_FRAME_LENGTH: usize = 4096;
+
+/// Clients that are served at the same time. Usually there's one Home Assistant, further connections are closed.
+pub const MAX_CONNECTIONS: usize = 8;
+
+/// Home Assistant pings every 20 seconds, a client that is quiet for longer than this is gone.
+const READ_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// A client that doesn't take a frame within this is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Frames waiting for the writer thread of a connection, a few readings' worth.
+const QUEUE_FRAMES: usize = 16;
+
+/// How the device shows up in Home Assistant.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EsphomeDevice {
+    /// The node name, lowercase letters, digits and `-`, e.g. `office-co2`.
+    pub name: String,
+    /// The name shown in Home Assistant, e.g. `Office co2`.
+    pub friendly_name: String,
+    /// The model, e.g. the name of the device profile.
+    pub model: String,
+    /// Home Assistant identifies ESPHome devices by their MAC address, this one is made up from `name`.
+    pub mac_address: String,
+    /// Whether the device has a humidity sensor.
+    pub has_humidity: bool,
+}
+
+impl EsphomeDevice {
+    /// A ZGm053U called `name`, without humidity.
+    pub fn new(name: &str) -> Self {
+        // A locally administered address, from a hash of the name (FNV-1a), so it's the same on every start.
+        let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        let mut mac = hash.to_be_bytes();
+        mac[2] = (mac[2] | 0x02) & 0xfe;
+        Self {
+            name: name.to_string(),
+            friendly_name: name.to_string(),
+            model: String::from("ZGm053U"),
+            mac_address: mac[2..]
+                .iter()
+                .map(|byte| format!("{byte:02X}"))
+                .collect::<Vec<_>>()
+                .join(":"),
+            has_humidity: false,
+        }
+    }
+
+    /// The sensors of the device.
+    fn sensors(&self) -> &'static [Sensor] {
+        &SENSORS[..if self.has_humidity { 3 } else { 2 }]
+    }
+}
+
+/// A sensor entity.
+#[derive(Debug)]
+struct Sensor {
+    key: u32,
+    object_id: &'static str,
+    name: &'static str,
+    unit: &'static str,
+    accuracy_decimals: u64,
+    device_class: &'static str,
+}
+
+const SENSORS: [Sensor; 3] = [
+    Sensor {
+        key: 1,
+        object_id: "co2",
+        name: "Co2",
+        unit: "ppm",
+        accuracy_decimals: 0,
+        device_class: "carbon_dioxide",
+    },
+    Sensor {
+        key: 2,
+        object_id: "temperature",
+        name: "Temperature",
+        unit: "°C",
+        accuracy_decimals: 1,
+        device_class: "temperature",
+    },
+    Sensor {
+        key: 3,
+        object_id: "humidity",
+        name: "Humidity",
+        unit: "%",
+        accuracy_decimals: 0,
+        device_class: "humidity",
+    },
+];
+
+/// Protobuf encoding of the few field types the messages use.
+#[derive(Debug, Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u8) {
+        self.varint(u64::from(field << 3 | u32::from(wire_type)));
+    }
+
+    fn uint(&mut self, field: u32, value: u64) -> &mut Self {
+        if value != 0 {
+            self.key(field, 0);
+            self.varint(value);
+        }
+        self
+    }
+
+    fn bool(&mut self, field: u32, value: bool) -> &mut Self {
+        self.uint(field, u64::from(value))
+    }
+
+    fn string(&mut self, field: u32, value: &str) -> &mut Self {
+        if !value.is_empty() {
+            self.key(field, 2);
+            self.varint(value.len() as u64);
+            self.0.extend_from_slice(value.as_bytes());
+        }
+        self
+    }
+
+    fn fixed32(&mut self, field: u32, value: u32) -> &mut Self {
+        self.key(field, 5);
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    /// The message in a plaintext frame of type `message_type`.
+    fn frame(&self, message_type: u32) -> Vec<u8> {
+        let mut frame = Message(Vec::from([0]));
+        frame.varint(self.0.len() as u64);
+        frame.varint(u64::from(message_type));
+        frame.0.extend_from_slice(&self.0);
+        frame.0
+    }
+}
+
+/// State shared with the connections.
+#[derive(Debug)]
+struct Shared {
+    device: EsphomeDevice,
+    reading: Mutex<Option<MonitorReading>>,
+    /// The queues of the connections that subscribed to the states.
+    subscribers: Mutex<Vec<SyncSender<Vec<u8>>>>,
+}
+
+impl Shared {
+    /// The state frames of `reading`, a missing state for co2 while it isn't valid.
+    fn states(&self, reading: &MonitorReading) -> Vec<u8> {
+        let co2 = match reading.co2_value {
+            Co2Value::Valid(ppm) => Some(f32::from(ppm)),
+            _ => None,
+        };
+        let mut values = Vec::from([(1, co2), (2, Some(reading.temperature))]);
+        if self.device.has_humidity {
+            values.push((3, reading.humidity));
+        }
+        let mut frames = Vec::new();
+        for (key, value) in values {
+            let mut state = Message::default();
+            state
+                .fixed32(1, key)
+                .fixed32(2, value.unwrap_or(f32::NAN).to_bits())
+                .bool(3, value.is_none());
+            frames.extend(state.frame(SENSOR_STATE_RESPONSE));
+        }
+        frames
+    }
+}
+
+/// Serves the monitor over the ESPHome native API in the background, see the [module documentation](self).
+#[derive(Debug)]
+pub struct EsphomeServer {
+    shared: Arc<Shared>,
+    local_addr: SocketAddr,
+}
+
+impl EsphomeServer {
+    /// Listen on `address` (`address[:port]`, the port defaults to [`DEFAULT_PORT`]) as `device`.
+    pub fn bind(address: &str, device: EsphomeDevice) -> io::Result<Self> {
+        let address = if address.contains(':') {
+            String::from(address)
+        } else {
+            format!("{address}:{DEFAULT_PORT}")
+        };
+        let listener = TcpListener::bind(address)?;
+        let local_addr = listener.local_addr()?;
+        let shared = Arc::new(Shared {
+            device,
+            reading: Mutex::new(None),
+            subscribers: Mutex::new(Vec::new()),
+        });
+        let accepting = Arc::clone(&shared);
+        let open = Arc::new(AtomicUsize::new(0));
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        event!(warn, "could not accept an esphome connection: {e}");
+                        continue;
+                    }
+                };
+                if open.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+                    open.fetch_sub(1, Ordering::Relaxed);
+                    event!(debug, "too many esphome connections, closing one");
+                    continue;
+                }
+                let (shared, open) = (Arc::clone(&accepting), Arc::clone(&open));
+                std::thread::spawn(move || {
+                    if let Err(e) = serve(stream, &shared) {
+                        event!(debug, "esphome connection closed: {e}");
+                    }
+                    open.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+        });
+        event!(info, "serving the esphome native api on {local_addr}");
+        Ok(Self { shared, local_addr })
+    }
+
+    /// Where the server listens, e.g. to find the port after binding to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Make `reading` the current state, and send it to the subscribed clients.
+    pub fn publish(&self, reading: &MonitorReading) {
+        *self
+            .shared
+            .reading
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some(*reading);
+        let states = self.shared.states(reading);
+        // Clients that are gone or don't keep up are dropped, their writer thread closes the connection.
+        self.shared
+            .subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|queue| match queue.try_send(states.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    event!(debug, "an esphome client doesn't keep up, dropping it");
+                    false
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+    }
+}
+
+/// Read a varint from `stream`.
+fn read_varint(stream: &mut impl Read) -> io::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8];
+        stream.read_exact(&mut byte)?;
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "varint too long",
+    ))
+}
+
+/// Start the writer thread of `stream`: it writes the frames of the returned queue until the queue is dropped or a
+/// write fails, and then closes the connection, which also ends the reading side.
+fn writer(stream: &TcpStream) -> io::Result<SyncSender<Vec<u8>>> {
+    let mut stream = stream.try_clone()?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let (queue, frames) = mpsc::sync_channel::<Vec<u8>>(QUEUE_FRAMES);
+    std::thread::spawn(move || {
+        for frame in frames {
+            if let Err(e) = stream.write_all(&frame) {
+                event!(debug, "could not write to an esphome client: {e}");
+                break;
+            }
+        }
+        let _ = stream.shutdown(Shutdown::Both);
+    });
+    Ok(queue)
+}
+
+/// Queue `frames` for the writer thread, an error if it has ended.
+fn write(queue: &SyncSender<Vec<u8>>, frames: Vec<u8>) -> io::Result<()> {
+    queue
+        .send(frames)
+        .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the connection is closed"))
+}
+
+/// Answer requests on `stream` until the client hangs up or stays quiet for too long.
+fn serve(mut stream: TcpStream, shared: &Shared) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let writer = writer(&stream)?;
+    let result = answer(&mut stream, shared, &writer);
+    // The writer thread still sends what's queued, and ends with the next state it can't send.
+    let _ = stream.shutdown(Shutdown::Read);
+    result
+}
+
+fn answer(stream: &mut TcpStream, shared: &Shared, writer: &SyncSender<Vec<u8>>) -> io::Result<()> {
+    let device = &shared.device;
+    loop {
+        let mut preamble = [0u8];
+        stream.read_exact(&mut preamble)?;
+        if preamble[0] != 0 {
+            // Most likely a client that wants encryption.
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a plaintext frame",
+            ));
+        }
+        let length = read_varint(stream)? as usize;
+        let message_type = read_varint(stream)? as u32;
+        if length > MAX_FRAME_LENGTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too long"));
+        }
+        // None of the requests have anything this needs.
+        io::copy(&mut stream.take(length as u64), &mut io::sink())?;
+        let mut response = Message::default();
+        let frame = match message_type {
+            HELLO_REQUEST => response
+                .uint(1, u64::from(API_VERSION.0))
+                .uint(2, u64::from(API_VERSION.1))
+                .string(3, concat!("co2-monitor ", env!("CARGO_PKG_VERSION")))
+                .string(4, &device.name)
+                .frame(HELLO_RESPONSE),
+            CONNECT_REQUEST => response.bool(1, false).frame(CONNECT_RESPONSE),
+            DISCONNECT_REQUEST => return write(writer, response.frame(DISCONNECT_RESPONSE)),
+            PING_REQUEST => response.frame(PING_RESPONSE),
+            DEVICE_INFO_REQUEST => response
+                .bool(1, false)
+                .string(2, &device.name)
+                .string(3, &device.mac_address)
+                .string(4, env!("CARGO_PKG_VERSION"))
+                .string(6, &device.model)
+                .string(12, "ZyAura")
+                .string(13, &device.friendly_name)
+                .frame(DEVICE_INFO_RESPONSE),
+            LIST_ENTITIES_REQUEST => {
+                let mut frames = Vec::new();
+                for sensor in device.sensors() {
+                    let mut entity = Message::default();
+                    entity
+                        .string(1, sensor.object_id)
+                        .fixed32(2, sensor.key)
+                        .string(3, sensor.name)
+                        .string(4, &format!("{}-{}", device.name, sensor.object_id))
+                        .string(6, sensor.unit)
+                        .uint(7, sensor.accuracy_decimals)
+                        .string(9, sensor.device_class)
+                        // STATE_CLASS_MEASUREMENT
+                        .uint(10, 1);
+                    frames.extend(entity.frame(LIST_ENTITIES_SENSOR_RESPONSE));
+                }
+                frames.extend(response.frame(LIST_ENTITIES_DONE_RESPONSE));
+                frames
+            }
+            SUBSCRIBE_STATES_REQUEST => {
+                let reading = *shared.reading.lock().unwrap_or_else(|e| e.into_inner());
+                if let Some(reading) = reading {
+                    write(writer, shared.states(&reading))?;
+                }
+                shared
+                    .subscribers
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(writer.clone());
+                continue;
+            }
+            _ => {
+                event!(trace, "ignoring esphome message {message_type}");
+                continue;
+            }
+        };
+        write(writer, frame)?;
+    }
+}
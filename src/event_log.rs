@@ -0,0 +1,202 @@
+//! The windows event log as a `tracing` writer, for running as a service where nobody sees stderr.
+//!
+//! [`install_source`] registers the event source in the registry (needs admin rights), otherwise the event viewer
+//! complains that it can't find the event descriptions. It borrows the message file of the .NET framework, whose
+//! message 0 just shows the text, which is what every .NET service does as well.
+use std::{
+    ffi::OsStr,
+    io::{self, Write},
+    iter,
+    os::windows::ffi::OsStrExt,
+    ptr,
+    vec::Vec,
+};
+use tracing::{Level, Metadata};
+use tracing_subscriber::fmt::MakeWriter;
+use windows_sys::Win32::{
+    Foundation::{ERROR_SUCCESS, HANDLE, WIN32_ERROR},
+    System::{
+        EventLog::{
+            DeregisterEventSource, EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE,
+            EVENTLOG_WARNING_TYPE, RegisterEventSourceW, ReportEventW,
+        },
+        Registry::{
+            HKEY, HKEY_LOCAL_MACHINE, KEY_SET_VALUE, REG_DWORD, REG_EXPAND_SZ,
+            REG_OPTION_NON_VOLATILE, RegCloseKey, RegCreateKeyExW, RegDeleteKeyW, RegSetValueExW,
+        },
+    },
+};
+
+const SOURCES_KEY: &str = r"SYSTEM\CurrentControlSet\Services\EventLog\Application\";
+const MESSAGE_FILE: &str =
+    r"%SystemRoot%\Microsoft.NET\Framework64\v4.0.30319\EventLogMessages.dll";
+
+fn wide(s: impl AsRef<OsStr>) -> Vec<u16> {
+    s.as_ref().encode_wide().chain(iter::once(0)).collect()
+}
+
+fn check(error: WIN32_ERROR) -> io::Result<()> {
+    if error == ERROR_SUCCESS {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(error as i32))
+    }
+}
+
+/// A registered event source in the application log.
+#[derive(Debug)]
+pub struct EventLog {
+    handle: HANDLE,
+}
+
+// The handle can be used from any thread, ReportEvent does its own locking.
+unsafe impl Send for EventLog {}
+unsafe impl Sync for EventLog {}
+
+impl EventLog {
+    /// Write events as `source`.
+    pub fn register(source: &str) -> io::Result<Self> {
+        let source = wide(source);
+        let handle = unsafe { RegisterEventSourceW(ptr::null(), source.as_ptr()) };
+        if handle.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { handle })
+    }
+
+    /// Write a single event. Errors and warnings keep their level, everything else is information.
+    pub fn report(&self, level: Level, message: &str) {
+        let kind = match level {
+            Level::ERROR => EVENTLOG_ERROR_TYPE,
+            Level::WARN => EVENTLOG_WARNING_TYPE,
+            _ => EVENTLOG_INFORMATION_TYPE,
+        };
+        let message = wide(message.trim_end());
+        let strings = [message.as_ptr()];
+        unsafe {
+            ReportEventW(
+                self.handle,
+                kind,
+                0,
+                0,
+                ptr::null_mut(),
+                1,
+                0,
+                strings.as_ptr(),
+                ptr::null(),
+            );
+        }
+    }
+}
+
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        unsafe {
+            DeregisterEventSource(self.handle);
+        }
+    }
+}
+
+/// Collects one formatted `tracing` event and reports it on drop, see [`EventLog`]'s `MakeWriter` implementation.
+#[derive(Debug)]
+pub struct EventWriter<'a> {
+    log: &'a EventLog,
+    level: Level,
+    buffer: Vec<u8>,
+}
+
+impl Write for EventWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for EventWriter<'_> {
+    fn drop(&mut self) {
+        if !self.buffer.is_empty() {
+            self.log.report(
+                self.level,
+                &std::string::String::from_utf8_lossy(&self.buffer),
+            );
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for EventLog {
+    type Writer = EventWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        EventWriter {
+            log: self,
+            level: Level::INFO,
+            buffer: Vec::new(),
+        }
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        EventWriter {
+            log: self,
+            level: *meta.level(),
+            buffer: Vec::new(),
+        }
+    }
+}
+
+/// Register `source` in the registry, so the event viewer can show its events. Needs admin rights.
+pub fn install_source(source: &str) -> io::Result<()> {
+    let key_name = wide(std::format!("{SOURCES_KEY}{source}"));
+    let mut key: HKEY = ptr::null_mut();
+    check(unsafe {
+        RegCreateKeyExW(
+            HKEY_LOCAL_MACHINE,
+            key_name.as_ptr(),
+            0,
+            ptr::null(),
+            REG_OPTION_NON_VOLATILE,
+            KEY_SET_VALUE,
+            ptr::null(),
+            &mut key,
+            ptr::null_mut(),
+        )
+    })?;
+    let message_file = wide(MESSAGE_FILE);
+    let types_supported: u32 =
+        (EVENTLOG_ERROR_TYPE | EVENTLOG_WARNING_TYPE | EVENTLOG_INFORMATION_TYPE).into();
+    let result = check(unsafe {
+        RegSetValueExW(
+            key,
+            wide("EventMessageFile").as_ptr(),
+            0,
+            REG_EXPAND_SZ,
+            message_file.as_ptr().cast(),
+            (message_file.len() * 2) as u32,
+        )
+    })
+    .and_then(|_| {
+        check(unsafe {
+            RegSetValueExW(
+                key,
+                wide("TypesSupported").as_ptr(),
+                0,
+                REG_DWORD,
+                ptr::from_ref(&types_supported).cast(),
+                4,
+            )
+        })
+    });
+    unsafe {
+        RegCloseKey(key);
+    }
+    result
+}
+
+/// Remove `source` from the registry again.
+pub fn uninstall_source(source: &str) -> io::Result<()> {
+    let key_name = wide(std::format!("{SOURCES_KEY}{source}"));
+    check(unsafe { RegDeleteKeyW(HKEY_LOCAL_MACHINE, key_name.as_ptr()) })
+}
@@ -0,0 +1,300 @@
+//! Convert csv logs to other formats, to migrate historical data into dashboards.
+//!
+//! [`ExportWriter`] writes [`Row`]s one at a time, so together with [`LogReader`](crate::log_format::LogReader) a log
+//! of any size can be converted with constant memory.
+//!
+//! [`ClockCorrection`] fixes the timestamps from before the wall clock was set, which takes a second pass over the log.
+use crate::log_format::Row;
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use std::{
+    format,
+    io::{self, Write},
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Formats [`ExportWriter`] can write.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ExportFormat {
+    /// JSON lines, i.e. one JSON object per row and line, with the same fields as the csv log.
+    JsonLines,
+    /// InfluxDB line protocol, with nanosecond timestamps. The room of `fleet` logs is a tag.
+    Influx,
+    /// Apache Parquet, see [`crate::parquet`].
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl core::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            ExportFormat::JsonLines => "jsonl",
+            ExportFormat::Influx => "influx",
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => "parquet",
+        })
+    }
+}
+
+/// Returned when parsing [`ExportFormat`] from an unknown string.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseExportFormatError;
+
+impl core::fmt::Display for ParseExportFormatError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("expected jsonl, influx or parquet (with the `parquet` feature)")
+    }
+}
+
+impl core::error::Error for ParseExportFormatError {}
+
+impl core::str::FromStr for ExportFormat {
+    type Err = ParseExportFormatError;
+
+    /// Accepts `jsonl` (or `json`), `influx` and `parquet` (with the `parquet` feature), in any case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        #[cfg(feature = "parquet")]
+        if s.eq_ignore_ascii_case("parquet") {
+            return Ok(Self::Parquet);
+        }
+        if s.eq_ignore_ascii_case("jsonl") || s.eq_ignore_ascii_case("json") {
+            Ok(Self::JsonLines)
+        } else if s.eq_ignore_ascii_case("influx") {
+            Ok(Self::Influx)
+        } else {
+            Err(ParseExportFormatError)
+        }
+    }
+}
+
+/// Writes rows in an [`ExportFormat`].
+#[derive(Debug)]
+pub struct ExportWriter<W> {
+    writer: W,
+    format: ExportFormat,
+    measurement: String,
+    #[cfg(feature = "parquet")]
+    parquet: Option<crate::parquet::Encoder>,
+}
+
+impl<W: Write> ExportWriter<W> {
+    /// Default measurement name for [`ExportFormat::Influx`].
+    pub const DEFAULT_MEASUREMENT: &'static str = "co2_monitor";
+
+    /// Write rows in `format` to `writer`.
+    pub fn new(writer: W, format: ExportFormat) -> Self {
+        Self {
+            writer,
+            format,
+            measurement: String::from(Self::DEFAULT_MEASUREMENT),
+            #[cfg(feature = "parquet")]
+            parquet: None,
+        }
+    }
+
+    /// Use another measurement name for [`ExportFormat::Influx`].
+    pub fn with_measurement(mut self, measurement: &str) -> Self {
+        self.measurement = escape_influx(measurement);
+        self
+    }
+
+    /// Write a single row.
+    pub fn write(&mut self, row: &Row) -> io::Result<()> {
+        match self.format {
+            ExportFormat::JsonLines => {
+                serde_json::to_writer(&mut self.writer, row)?;
+                writeln!(self.writer)
+            }
+            ExportFormat::Influx => writeln!(
+                self.writer,
+                "{},provenance={}{}{} co2_ppm={}i,co2_is_valid={},temperature={}{} {}",
+                self.measurement,
+                escape_influx(&row.provenance.to_string()),
+                row.room
+                    .as_deref()
+                    .map(|room| format!(",room={}", escape_influx(room)))
+                    .unwrap_or_default(),
+                row.device
+                    .as_deref()
+                    .map(|device| format!(",device={}", escape_influx(device)))
+                    .unwrap_or_default(),
+                row.co2_ppm,
+                row.co2_is_valid,
+                row.temperature,
+                row.humidity
+                    .map(|humidity| format!(",humidity={humidity}"))
+                    .unwrap_or_default(),
+                local_nanos(row.timestamp),
+            ),
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => {
+                let encoder = match &mut self.parquet {
+                    Some(encoder) => encoder,
+                    None => self
+                        .parquet
+                        .insert(crate::parquet::Encoder::new().map_err(io::Error::other)?),
+                };
+                encoder
+                    .write(row, &mut self.writer)
+                    .map_err(io::Error::other)
+            }
+        }
+    }
+
+    /// Flush and get the writer back. For parquet, this writes the footer, so don't just drop the writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        #[cfg(feature = "parquet")]
+        if self.format == ExportFormat::Parquet {
+            let encoder = match self.parquet.take() {
+                Some(encoder) => encoder,
+                None => crate::parquet::Encoder::new().map_err(io::Error::other)?,
+            };
+            encoder.finish(&mut self.writer).map_err(io::Error::other)?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// The log has local timestamps, Influx wants them since the unix epoch. Ambiguous times (when the clock is turned back)
+/// use the earlier one, and times that don't exist (when it's turned forward) are taken as UTC.
+fn local_nanos(timestamp: NaiveDateTime) -> i64 {
+    let utc = Local
+        .from_local_datetime(&timestamp)
+        .earliest()
+        .map_or_else(|| timestamp.and_utc(), |t| t.to_utc());
+    utc.timestamp_nanos_opt().unwrap_or_default()
+}
+
+/// Escape commas, spaces and equal signs for measurement names and tag values.
+pub(crate) fn escape_influx(s: &str) -> String {
+    s.chars().fold(String::new(), |mut escaped, c| {
+        if matches!(c, ',' | ' ' | '=') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        escaped
+    })
+}
+
+/// Only jumps of the wall clock within this long after the logger started count as the clock being set.
+pub const CLOCK_SYNC_WINDOW_MS: u64 = 60 * 60 * 1000;
+
+/// Smaller jumps of the wall clock are just drift that NTP corrected.
+pub const MIN_CLOCK_JUMP_MS: u64 = 60 * 1000;
+
+/// Where the wall clock jumped in one run of the logger.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct ClockJump {
+    /// Monotonic time of the first reading with the right time.
+    synced_at_ms: u64,
+    /// Wall clock minus monotonic time, from then on.
+    offset_ms: i64,
+}
+
+/// Realigns readings that were logged before the wall clock was set, e.g. by NTP on a Raspberry Pi without a real time
+/// clock, using the monotonic time of the log (see [`Row::monotonic_ms`]).
+///
+/// Every time the logger starts, the monotonic time starts at 0 again, which is how the runs are told apart. A run whose
+/// wall clock jumps by more than [`MIN_CLOCK_JUMP_MS`] within [`CLOCK_SYNC_WINDOW_MS`] gets the timestamps before the
+/// jump recalculated from the monotonic time and the clock after the jump. Rows without monotonic time (logs before
+/// schema version 3) are left alone. Timestamps are local time, so the switch to or from daylight saving time in the
+/// first hour of a run looks like the clock being set, and is "fixed" as well.
+///
+/// [`observe`](Self::observe) every row first, then [`apply`](Self::apply) to every row in the same order.
+#[derive(Clone, Debug, Default)]
+pub struct ClockCorrection {
+    /// One entry per run, `None` if the clock didn't jump.
+    runs: Vec<Option<ClockJump>>,
+    last_monotonic_ms: Option<u64>,
+    last_offset_ms: i64,
+    /// The run `apply` is at, and the monotonic time of the row before.
+    run: Option<usize>,
+    applied_monotonic_ms: Option<u64>,
+}
+
+impl ClockCorrection {
+    /// Nothing observed yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// First pass, look for jumps of the wall clock.
+    pub fn observe(&mut self, row: &Row) {
+        let Some(monotonic_ms) = row.monotonic_ms else {
+            self.last_monotonic_ms = None;
+            return;
+        };
+        let offset_ms = wall_ms(row) - monotonic_ms as i64;
+        if self
+            .last_monotonic_ms
+            .is_none_or(|last| monotonic_ms < last)
+        {
+            self.runs.push(None);
+        } else if let Some(run @ None) = self.runs.last_mut()
+            && monotonic_ms <= CLOCK_SYNC_WINDOW_MS
+            && offset_ms.abs_diff(self.last_offset_ms) > MIN_CLOCK_JUMP_MS
+        {
+            *run = Some(ClockJump {
+                synced_at_ms: monotonic_ms,
+                offset_ms,
+            });
+        }
+        self.last_monotonic_ms = Some(monotonic_ms);
+        self.last_offset_ms = offset_ms;
+    }
+
+    /// How many runs of the logger had their clock set, i.e. how many will be fixed.
+    pub fn jumps(&self) -> usize {
+        self.runs.iter().flatten().count()
+    }
+
+    /// Second pass, fix the timestamp of `row` if it was logged before the clock was set. Returns whether it was.
+    pub fn apply(&mut self, row: &mut Row) -> bool {
+        let Some(monotonic_ms) = row.monotonic_ms else {
+            self.applied_monotonic_ms = None;
+            return false;
+        };
+        if self
+            .applied_monotonic_ms
+            .is_none_or(|last| monotonic_ms < last)
+        {
+            self.run = Some(self.run.map_or(0, |run| run + 1));
+        }
+        self.applied_monotonic_ms = Some(monotonic_ms);
+        let Some(Some(jump)) = self.run.and_then(|run| self.runs.get(run)) else {
+            return false;
+        };
+        if monotonic_ms >= jump.synced_at_ms {
+            return false;
+        }
+        let Some(timestamp) = DateTime::from_timestamp_millis(jump.offset_ms + monotonic_ms as i64)
+        else {
+            return false;
+        };
+        row.timestamp = timestamp.naive_utc();
+        true
+    }
+}
+
+/// The timestamp of `row` in milliseconds, as if the local time was UTC, which is all that's needed for differences.
+fn wall_ms(row: &Row) -> i64 {
+    row.timestamp.and_utc().timestamp_millis()
+}
+
+/// Whether `row` is within `from..to`, where a bound of `None` is open.
+pub fn in_range(row: &Row, from: Option<NaiveDateTime>, to: Option<NaiveDateTime>) -> bool {
+    from.is_none_or(|from| row.timestamp >= from) && to.is_none_or(|to| row.timestamp < to)
+}
+
+/// Parse `YYYY-MM-DDTHH:MM:SS` (or with a space instead of the `T`), or a plain `YYYY-MM-DD` meaning midnight.
+pub fn parse_timestamp(s: &str) -> Result<NaiveDateTime, chrono::ParseError> {
+    s.parse::<NaiveDateTime>()
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S"))
+        .or_else(|e| {
+            s.parse::<chrono::NaiveDate>()
+                .map(|date| date.and_time(chrono::NaiveTime::MIN))
+                .map_err(|_| e)
+        })
+}
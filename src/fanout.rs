@@ -0,0 +1,121 @@
+//! Hand the same readings to several consumers from a single reader, e.g. to a logger and a display.
+//!
+//! On a PC, [`FanOut`] keeps an mpsc channel per subscriber, so every consumer can live in its own thread. Without std,
+//! [`SpscFanOut`] (with the `heapless` feature) pushes into a fixed set of `heapless` SPSC queues, which the consumers
+//! pop from in their own time, e.g. in the main loop while the reader runs in an interrupt.
+#[cfg(feature = "pc")]
+use std::{sync::mpsc, vec::Vec};
+
+/// Sends everything that is published to every subscriber, see the [module documentation](self).
+#[cfg(feature = "pc")]
+#[derive(Debug)]
+pub struct FanOut<T> {
+    subscribers: Vec<mpsc::Sender<T>>,
+}
+
+#[cfg(feature = "pc")]
+impl<T> Default for FanOut<T> {
+    fn default() -> Self {
+        Self {
+            subscribers: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "pc")]
+impl<T: Clone> FanOut<T> {
+    /// No subscribers yet, publishing just drops the items.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get everything that is published from now on. Unsubscribe by dropping the receiver.
+    pub fn subscribe(&mut self) -> mpsc::Receiver<T> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// Send `item` to every subscriber, and forget the ones that dropped their receiver. Returns how many got it.
+    pub fn publish(&mut self, item: T) -> usize {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(item.clone()).is_ok());
+        self.subscribers.len()
+    }
+
+    /// Subscribers as of the last `publish`, including ones that dropped their receiver since then.
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    /// Forget all subscribers, which closes their channels.
+    pub fn clear(&mut self) {
+        self.subscribers.clear();
+    }
+}
+
+/// Pushes everything that is published into up to `S` `heapless::spsc::Queue<T, N>`s (which hold `N - 1` items each),
+/// see the [module documentation](self).
+///
+/// Create a `heapless::spsc::Queue` per consumer (usually in a `static`), `split` it, [`add`](Self::add) the producer
+/// here and hand the consumer to whoever wants the readings. A consumer that doesn't keep up misses items, the reader
+/// never waits for it.
+#[cfg(feature = "heapless")]
+pub struct SpscFanOut<'a, T, const N: usize, const S: usize> {
+    producers: heapless::Vec<heapless::spsc::Producer<'a, T, N>, S>,
+    dropped: u32,
+}
+
+#[cfg(feature = "heapless")]
+impl<T, const N: usize, const S: usize> core::fmt::Debug for SpscFanOut<'_, T, N, S> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SpscFanOut")
+            .field("subscribers", &self.producers.len())
+            .field("dropped", &self.dropped)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "heapless")]
+impl<T: Clone, const N: usize, const S: usize> Default for SpscFanOut<'_, T, N, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "heapless")]
+impl<'a, T: Clone, const N: usize, const S: usize> SpscFanOut<'a, T, N, S> {
+    /// No queues yet.
+    pub const fn new() -> Self {
+        Self {
+            producers: heapless::Vec::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Add a queue. Gives the producer back if there are already `S` of them.
+    pub fn add(
+        &mut self,
+        producer: heapless::spsc::Producer<'a, T, N>,
+    ) -> Result<(), heapless::spsc::Producer<'a, T, N>> {
+        self.producers.push(producer)
+    }
+
+    /// Push `item` into every queue that has space. Returns how many queues got it.
+    pub fn publish(&mut self, item: T) -> usize {
+        let mut delivered = 0;
+        for producer in &mut self.producers {
+            if producer.enqueue(item.clone()).is_ok() {
+                delivered += 1;
+            } else {
+                self.dropped = self.dropped.saturating_add(1);
+            }
+        }
+        delivered
+    }
+
+    /// How many items were dropped so far because a queue was full, summed over all queues.
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+}
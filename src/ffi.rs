@@ -0,0 +1,196 @@
+//! A small C API, so daemons in C or C++ can reuse the protocol handling instead of reimplementing it.
+//!
+//! Build it as a shared library with `cargo rustc --release --lib --features ffi --crate-type cdylib`, the header is
+//! `include/co2_monitor.h` (made by cbindgen with `cbindgen.toml`). The API is deliberately tiny: parse a single report
+//! with [`co2_parse_report`], or let the library do everything with [`co2_open`], [`co2_read_blocking`] and
+//! [`co2_close`]. Functions return a [`Co2Status`], 0 is success and errors are negative.
+use crate::{
+    Co2MonitorExt, Co2Value, FrameError, MonitorError, MonitorReading, MonitorReadingParts,
+    parse_report, pc::PcCo2Monitor,
+};
+use std::boxed::Box;
+
+/// Result of the C functions.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Co2Status {
+    /// Success.
+    Ok = 0,
+    /// Reading from the device failed, it's probably gone.
+    ReadFailed = -1,
+    /// The report doesn't have the terminator byte.
+    MissingTerminatorByte = -2,
+    /// The checksum of the report is wrong.
+    ChecksumInvalid = -3,
+    /// No complete reading within the timeout.
+    Timeout = -4,
+    /// The read was cancelled.
+    Cancelled = -5,
+    /// A pointer argument was NULL.
+    NullPointer = -6,
+}
+
+impl From<MonitorError> for Co2Status {
+    fn from(e: MonitorError) -> Self {
+        match e {
+            MonitorError::ReadFailed => Co2Status::ReadFailed,
+            MonitorError::MissingTerminatorByte => Co2Status::MissingTerminatorByte,
+            MonitorError::ChecksumInvalid => Co2Status::ChecksumInvalid,
+            MonitorError::Timeout => Co2Status::Timeout,
+            MonitorError::Cancelled => Co2Status::Cancelled,
+        }
+    }
+}
+
+impl From<FrameError> for Co2Status {
+    fn from(e: FrameError) -> Self {
+        MonitorError::from(e).into()
+    }
+}
+
+/// What the co2 value of a [`Co2Reading`] means, see [`Co2Value`].
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Co2State {
+    /// Within spec.
+    Valid = 0,
+    /// Above the range of the sensor.
+    TooHigh = 1,
+    /// The sensor is still warming up.
+    WarmingUp = 2,
+    /// Implausibly low.
+    TooLow = 3,
+    /// The sensor isn't measuring.
+    SensorFault = 4,
+}
+
+/// A complete reading.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Co2Reading {
+    /// Co2 in ppm, only meaningful if `co2_state` is `Valid`.
+    pub co2_ppm: u16,
+    /// Whether co2 is valid, and if not, why.
+    pub co2_state: Co2State,
+    /// Temperature in degrees Celsius.
+    pub temperature: f32,
+    /// Relative humidity in percent, NaN if the device has no humidity sensor.
+    pub humidity: f32,
+}
+
+impl From<&MonitorReading> for Co2Reading {
+    fn from(reading: &MonitorReading) -> Self {
+        let (co2_ppm, _) = reading.co2_value.as_num_and_bool();
+        Self {
+            co2_ppm,
+            co2_state: match reading.co2_value {
+                Co2Value::Valid(_) => Co2State::Valid,
+                Co2Value::TooHigh(_) => Co2State::TooHigh,
+                Co2Value::WarmingUp(_) => Co2State::WarmingUp,
+                Co2Value::TooLow(_) => Co2State::TooLow,
+                Co2Value::SensorFault(_) => Co2State::SensorFault,
+            },
+            temperature: reading.temperature,
+            humidity: reading.humidity.unwrap_or(f32::NAN),
+        }
+    }
+}
+
+/// An open monitor, opaque to C.
+#[derive(Debug)]
+pub struct Co2Monitor {
+    monitor: PcCo2Monitor,
+    parts: MonitorReadingParts,
+    start: std::time::Instant,
+}
+
+/// Validate a single 8-byte report and extract its opcode and value.
+///
+/// # Safety
+///
+/// `report` must point to 8 readable bytes, `opcode` and `value` must be valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn co2_parse_report(
+    report: *const [u8; 8],
+    opcode: *mut u8,
+    value: *mut u16,
+) -> Co2Status {
+    if report.is_null() || opcode.is_null() || value.is_null() {
+        return Co2Status::NullPointer;
+    }
+    // SAFETY: checked for NULL above, the caller guarantees the rest.
+    let report = unsafe { &*report };
+    match parse_report(report) {
+        Ok((op, val)) => {
+            // SAFETY: checked for NULL above, the caller guarantees the rest.
+            unsafe {
+                *opcode = op;
+                *value = val;
+            }
+            Co2Status::Ok
+        }
+        Err(e) => e.into(),
+    }
+}
+
+/// Open the first connected monitor of any known model. Returns NULL if there is none or it can't be opened. Free it
+/// with [`co2_close`].
+#[unsafe(no_mangle)]
+pub extern "C" fn co2_open() -> *mut Co2Monitor {
+    match PcCo2Monitor::open_any() {
+        Ok(monitor) => Box::into_raw(Box::new(Co2Monitor {
+            monitor,
+            parts: MonitorReadingParts::default(),
+            start: std::time::Instant::now(),
+        })),
+        Err(_) => core::ptr::null_mut(),
+    }
+}
+
+/// Wait up to `timeout_ms` for the next complete reading and write it to `reading`. A complete reading takes about 5
+/// seconds.
+///
+/// # Safety
+///
+/// `monitor` must come from [`co2_open`] and not be closed yet, and not be used from another thread at the same time.
+/// `reading` must be valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn co2_read_blocking(
+    monitor: *mut Co2Monitor,
+    timeout_ms: u32,
+    reading: *mut Co2Reading,
+) -> Co2Status {
+    if monitor.is_null() || reading.is_null() {
+        return Co2Status::NullPointer;
+    }
+    // SAFETY: checked for NULL above, the caller guarantees the rest.
+    let monitor = unsafe { &mut *monitor };
+    let start = monitor.start;
+    let clock = move || start.elapsed().as_millis() as u64;
+    match monitor
+        .monitor
+        .read_complete(&mut monitor.parts, &clock, u64::from(timeout_ms))
+    {
+        Ok(complete) => {
+            // SAFETY: checked for NULL above, the caller guarantees the rest.
+            unsafe { *reading = Co2Reading::from(&complete) };
+            Co2Status::Ok
+        }
+        Err(e) => e.into(),
+    }
+}
+
+/// Close a monitor from [`co2_open`]. NULL is ignored.
+///
+/// # Safety
+///
+/// `monitor` must come from [`co2_open`] and not be closed already.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn co2_close(monitor: *mut Co2Monitor) {
+    if !monitor.is_null() {
+        // SAFETY: the caller guarantees it came from `Box::into_raw` in `co2_open`.
+        drop(unsafe { Box::from_raw(monitor) });
+    }
+}
@@ -0,0 +1,89 @@
+//! Several monitors at once, each labeled with the room it's in.
+//!
+//! Rooms are assigned by serial number or by device path (see [`DeviceInfo`]), usually from the `[fleet]` section of
+//! the config. Readings of all monitors in the same room are combined like [`Aggregator`] does, so a room with two
+//! monitors still gets a single series.
+use crate::{Co2Value, MonitorReading, aggregate::Aggregator, pc::DeviceInfo};
+use std::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Maps monitors to rooms, and combines the readings per room.
+#[derive(Clone, Debug, Default)]
+pub struct Fleet {
+    rooms: BTreeMap<String, String>,
+    pending: BTreeMap<String, Aggregator>,
+}
+
+impl Fleet {
+    /// A fleet without any assigned rooms, so every monitor is labeled with its serial number or path.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Assign rooms from `(serial number or path, room)` pairs.
+    pub fn with_rooms<K: Into<String>, V: Into<String>>(
+        rooms: impl IntoIterator<Item = (K, V)>,
+    ) -> Self {
+        let mut fleet = Self::new();
+        for (device, room) in rooms {
+            fleet.assign(device, room);
+        }
+        fleet
+    }
+
+    /// Put the monitor with the serial number or path `device` in `room`.
+    pub fn assign(&mut self, device: impl Into<String>, room: impl Into<String>) {
+        self.rooms.insert(device.into(), room.into());
+    }
+
+    /// The room assigned to `device`, by serial number first and by path second.
+    pub fn room(&self, device: &DeviceInfo) -> Option<&str> {
+        device
+            .serial_number
+            .as_ref()
+            .and_then(|serial| self.rooms.get(serial))
+            .or_else(|| self.rooms.get(&device.path))
+            .map(String::as_str)
+    }
+
+    /// The room of `device`, or its serial number (or path, if it has none) for monitors without a room.
+    pub fn label(&self, device: &DeviceInfo) -> String {
+        self.room(device).unwrap_or(device.identifier()).to_string()
+    }
+
+    /// Add a reading of `device` to its room.
+    pub fn push(&mut self, device: &DeviceInfo, reading: &MonitorReading) {
+        self.pending
+            .entry(self.label(device))
+            .or_insert_with(|| Aggregator::new(u32::MAX))
+            .add(reading);
+    }
+
+    /// The combined reading of every room since the last `take`, sorted by room. Rooms without readings since then
+    /// are left out.
+    pub fn take(&mut self) -> Vec<(String, MonitorReading)> {
+        core::mem::take(&mut self.pending)
+            .into_iter()
+            .filter_map(|(room, mut aggregator)| Some((room, aggregator.take()?)))
+            .collect()
+    }
+}
+
+/// The room with the most co2 among `readings` (as returned by [`Fleet::take`]), if there's more than one room to
+/// compare. Rooms without a valid co2 value don't count.
+pub fn worst_room(readings: &[(String, MonitorReading)]) -> Option<&str> {
+    if readings.len() < 2 {
+        return None;
+    }
+    readings
+        .iter()
+        .filter_map(|(room, reading)| match reading.co2_value {
+            Co2Value::Valid(ppm) => Some((room, ppm)),
+            _ => None,
+        })
+        .max_by_key(|(_, ppm)| *ppm)
+        .map(|(room, _)| room.as_str())
+}
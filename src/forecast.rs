@@ -0,0 +1,80 @@
+//! When co2 will cross a limit: minutes until it gets there while it's rising, and until it's back below once a
+//! window is open.
+//!
+//! [`Forecast::new`] extrapolates the line of a [`Trend`] to the limit. Co2 doesn't really rise in a straight line (it
+//! levels off towards the balance of breathing and air exchange) and falls exponentially when ventilating, so the
+//! further away the crossing is, the less it's worth. Anything beyond [`HORIZON_MINUTES`] isn't forecast at all.
+use crate::trend::Trend;
+
+/// Crossings further ahead than this many minutes are left out.
+pub const HORIZON_MINUTES: f32 = 120.0;
+
+/// Where co2 is headed relative to a limit, see the [module documentation](self).
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Forecast {
+    /// There's no trend yet, or co2 won't cross the limit within [`HORIZON_MINUTES`].
+    Steady,
+    /// Co2 is below the limit and will reach it in about `minutes`.
+    Reaches {
+        /// The limit in ppm.
+        limit_ppm: u16,
+        /// Minutes until co2 is at the limit.
+        minutes: f32,
+    },
+    /// Co2 is at or above the limit and will be back below in about `minutes`.
+    BelowIn {
+        /// The limit in ppm.
+        limit_ppm: u16,
+        /// Minutes until co2 is below the limit.
+        minutes: f32,
+    },
+}
+
+impl Forecast {
+    /// The forecast for `limit_ppm`, from the trend up to now.
+    pub fn new<const N: usize>(trend: &Trend<N>, limit_ppm: u16) -> Self {
+        let limit = f32::from(limit_ppm);
+        let (Some(slope), Some(minutes)) = (trend.ppm_per_minute(), trend.minutes_until(limit))
+        else {
+            return Forecast::Steady;
+        };
+        match minutes {
+            minutes if minutes > HORIZON_MINUTES => Forecast::Steady,
+            minutes if slope > 0.0 => Forecast::Reaches { limit_ppm, minutes },
+            minutes => Forecast::BelowIn { limit_ppm, minutes },
+        }
+    }
+
+    /// Minutes until co2 reaches the limit, if it's rising towards it.
+    pub fn minutes_to_limit(&self) -> Option<f32> {
+        match self {
+            Forecast::Reaches { minutes, .. } => Some(*minutes),
+            _ => None,
+        }
+    }
+
+    /// Minutes until co2 is back below the limit, if it's falling towards it.
+    pub fn minutes_to_below(&self) -> Option<f32> {
+        match self {
+            Forecast::BelowIn { minutes, .. } => Some(*minutes),
+            _ => None,
+        }
+    }
+}
+
+impl core::fmt::Display for Forecast {
+    /// E.g. `~12 min to 1200 ppm`, or `~4 min to below 1200 ppm`. Nothing for [`Forecast::Steady`].
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Forecast::Steady => Ok(()),
+            Forecast::Reaches { limit_ppm, minutes } => {
+                write!(f, "~{:.0} min to {} ppm", minutes.max(1.0), limit_ppm)
+            }
+            Forecast::BelowIn { limit_ppm, minutes } => {
+                write!(f, "~{:.0} min to below {} ppm", minutes.max(1.0), limit_ppm)
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! An [`Actuator`] on a GPIO pin, e.g. a relay for a fan on a Raspberry Pi.
+//!
+//! [`GpioSwitch`] takes a line of a GPIO chip of the kernel's character device (`/dev/gpiochip0` is the header of a
+//! Raspberry Pi, the line is the BCM number) as an output and keeps it while it's alive. That's the interface the
+//! kernel wants userspace to use, it works on any linux board, and it needs nothing but libc. The user needs read and
+//! write access to the chip, on Raspberry Pi OS that's the `gpio` group.
+//!
+//! Dropping the switch releases the line, which usually leaves it where it was, so switch off first if that matters.
+use crate::actuator::Actuator;
+use std::{
+    fs::OpenOptions,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::Path,
+};
+
+/// Like `_IOWR(0xB4, nr, size)` of the GPIO uAPI. Most architectures use the generic layout, mips, powerpc and sparc
+/// have fewer size bits and other direction bits.
+const fn gpio_iowr(nr: u8, size: usize) -> libc::c_ulong {
+    #[cfg(any(
+        target_arch = "mips",
+        target_arch = "mips64",
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "sparc",
+        target_arch = "sparc64"
+    ))]
+    const READ_WRITE: libc::c_ulong = (2 | 4) << 29;
+    #[cfg(not(any(
+        target_arch = "mips",
+        target_arch = "mips64",
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "sparc",
+        target_arch = "sparc64"
+    )))]
+    const READ_WRITE: libc::c_ulong = (1 | 2) << 30;
+    READ_WRITE | ((size as libc::c_ulong) << 16) | (0xb4 << 8) | nr as libc::c_ulong
+}
+
+/// Most lines of a single request.
+const GPIOHANDLES_MAX: usize = 64;
+const GPIOHANDLE_REQUEST_OUTPUT: u32 = 1 << 1;
+const GPIOHANDLE_REQUEST_ACTIVE_LOW: u32 = 1 << 2;
+
+/// `struct gpiohandle_request` of `linux/gpio.h`.
+#[repr(C)]
+struct HandleRequest {
+    line_offsets: [u32; GPIOHANDLES_MAX],
+    flags: u32,
+    default_values: [u8; GPIOHANDLES_MAX],
+    consumer_label: [u8; 32],
+    lines: u32,
+    fd: libc::c_int,
+}
+
+/// `struct gpiohandle_data` of `linux/gpio.h`.
+#[repr(C)]
+struct HandleData {
+    values: [u8; GPIOHANDLES_MAX],
+}
+
+const GPIO_GET_LINEHANDLE_IOCTL: libc::c_ulong = gpio_iowr(0x03, size_of::<HandleRequest>());
+const GPIOHANDLE_SET_LINE_VALUES_IOCTL: libc::c_ulong = gpio_iowr(0x09, size_of::<HandleData>());
+
+/// Switches a GPIO line, see the [module documentation](self).
+#[derive(Debug)]
+pub struct GpioSwitch {
+    handle: OwnedFd,
+}
+
+impl GpioSwitch {
+    /// Take `line` of the GPIO chip at `chip` (e.g. `/dev/gpiochip0`) as an output, starting off. With `active_low`,
+    /// on is the low level, for relay boards that switch on low.
+    pub fn open(chip: impl AsRef<Path>, line: u32, active_low: bool) -> io::Result<Self> {
+        let chip = OpenOptions::new().read(true).write(true).open(chip)?;
+        let mut request = HandleRequest {
+            line_offsets: [0; GPIOHANDLES_MAX],
+            flags: GPIOHANDLE_REQUEST_OUTPUT,
+            default_values: [0; GPIOHANDLES_MAX],
+            consumer_label: [0; 32],
+            lines: 1,
+            fd: -1,
+        };
+        request.line_offsets[0] = line;
+        if active_low {
+            request.flags |= GPIOHANDLE_REQUEST_ACTIVE_LOW;
+        }
+        let label = b"co2-monitor";
+        request.consumer_label[..label.len()].copy_from_slice(label);
+        // SAFETY: the request is a `struct gpiohandle_request`, whose size is encoded in the request number.
+        let result = unsafe {
+            libc::ioctl(
+                chip.as_raw_fd(),
+                GPIO_GET_LINEHANDLE_IOCTL as _,
+                &mut request as *mut HandleRequest,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the kernel opened the handle for us, nobody else owns it.
+        let handle = unsafe { OwnedFd::from_raw_fd(request.fd) };
+        Ok(Self { handle })
+    }
+}
+
+impl Actuator for GpioSwitch {
+    type Error = io::Error;
+
+    fn set(&mut self, on: bool) -> io::Result<()> {
+        let mut data = HandleData {
+            values: [0; GPIOHANDLES_MAX],
+        };
+        data.values[0] = u8::from(on);
+        // SAFETY: the data is a `struct gpiohandle_data`, whose size is encoded in the request number.
+        let result = unsafe {
+            libc::ioctl(
+                self.handle.as_raw_fd(),
+                GPIOHANDLE_SET_LINE_VALUES_IOCTL as _,
+                &mut data as *mut HandleData,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+//! Push readings to Grafana Live, so a dashboard shows them the moment they're logged, without a time-series database
+//! in between. Nice on small installs where Grafana is the only thing running next to the logger.
+//!
+//! Grafana takes pushes into its `stream/<stream id>/<measurement>` channels over WebSocket or plain HTTP POSTs to
+//! `/api/live/push/<stream id>`. [`GrafanaLive`] uses the latter, one request per batch with a line of InfluxDB line
+//! protocol per reading, so there's no connection to keep up and it works with [`SinkQueue`](crate::sink::SinkQueue)
+//! like the other sinks. It needs a service account token with the Editor (or Admin) role. Only `http` is supported,
+//! so either run the logger on the Grafana host or put a TLS proxy in front of it.
+//!
+//! In a panel, pick the "-- Grafana --" data source, "Live Measurements" and the channel, e.g.
+//! `stream/co2_monitor/co2_monitor`. Live channels have no history, a refresh of the dashboard starts empty.
+use crate::{MonitorReading, export::escape_influx, http, sink::BatchSink};
+use std::{
+    format, io,
+    string::{String, ToString},
+    time::Duration,
+};
+
+/// Port of Grafana, when none is given.
+pub const DEFAULT_PORT: u16 = 3000;
+
+/// Pushes readings to a Grafana Live stream, see the [module documentation](self).
+#[derive(Clone, Debug)]
+pub struct GrafanaLive {
+    server: String,
+    stream_id: String,
+    token: String,
+    measurement: String,
+    timeout: Duration,
+}
+
+impl GrafanaLive {
+    /// Push to the stream `stream_id` of the Grafana at `server` (`[http://]address[:port]`, the port defaults to
+    /// [`DEFAULT_PORT`]), authenticating with the service account token `token`. The measurement is `co2_monitor`.
+    pub fn new(server: &str, stream_id: &str, token: &str) -> Self {
+        let server = server.strip_prefix("http://").unwrap_or(server);
+        let server = server.trim_end_matches('/');
+        let server = if server.contains(':') {
+            server.to_string()
+        } else {
+            format!("{server}:{DEFAULT_PORT}")
+        };
+        Self {
+            server,
+            stream_id: stream_id.to_string(),
+            token: token.to_string(),
+            measurement: String::from("co2_monitor"),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Use another measurement name, which is the last part of the channel.
+    pub fn with_measurement(mut self, measurement: &str) -> Self {
+        self.measurement = escape_influx(measurement);
+        self
+    }
+
+    /// Connect, write and read timeout. The default is 5 seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The channel the readings show up in.
+    pub fn channel(&self) -> String {
+        format!("stream/{}/{}", self.stream_id, self.measurement)
+    }
+
+    /// `reading`, taken at the unix timestamp `clock`, as a line of line protocol (without the newline). Grafana
+    /// turns every field into a column of the channel.
+    pub fn line(&self, reading: &MonitorReading, clock: i64) -> String {
+        let (co2, valid) = reading.co2_value.as_num_and_bool();
+        format!(
+            "{} co2_ppm={co2}i,co2_is_valid={valid},temperature={}{} {}",
+            self.measurement,
+            reading.temperature,
+            reading
+                .humidity
+                .map(|humidity| format!(",humidity={humidity}"))
+                .unwrap_or_default(),
+            clock.saturating_mul(1_000_000_000),
+        )
+    }
+
+    /// Like [`line`](Self::line), with more fields, e.g. the estimated number of people in the room as `occupancy`
+    /// or the forecast as `minutes_to_limit`. The names have to be valid field keys.
+    pub fn line_with(
+        &self,
+        reading: &MonitorReading,
+        fields: &[(&str, f32)],
+        clock: i64,
+    ) -> String {
+        let mut line = self.line(reading, clock);
+        if let Some(timestamp) = line.rfind(' ') {
+            let fields: String = fields
+                .iter()
+                .map(|(name, value)| format!(",{name}={value:.1}"))
+                .collect();
+            line.insert_str(timestamp, &fields);
+        }
+        line
+    }
+
+    /// Push `reading`, taken at the unix timestamp `clock`.
+    pub fn push(&self, reading: &MonitorReading, clock: i64) -> io::Result<()> {
+        self.push_lines(&[self.line(reading, clock)])
+    }
+
+    /// Push lines of line protocol in a single request. An error for anything but a 2xx response.
+    pub fn push_lines(&self, lines: &[String]) -> io::Result<()> {
+        match self.post(lines)? {
+            (200..=299, _) => Ok(()),
+            (_, status) => Err(io::Error::other(format!("grafana answered {status}"))),
+        }
+    }
+
+    /// POST `lines` and return the status code and the status (code and reason phrase).
+    fn post(&self, lines: &[String]) -> io::Result<(u16, String)> {
+        let body = lines.join("\n");
+        let request = format!(
+            "POST /api/live/push/{} HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {}\r\n\
+             Content-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            self.stream_id,
+            self.server,
+            self.token,
+            body.len(),
+        );
+        http::request(&self.server, &request, self.timeout)
+    }
+}
+
+impl BatchSink for GrafanaLive {
+    type Item = String;
+
+    fn name(&self) -> &str {
+        "grafana"
+    }
+
+    fn send(&mut self, items: &[String]) -> io::Result<()> {
+        match self.post(items)? {
+            (200..=299, _) => Ok(()),
+            // A wrong token or stream id won't fix itself, sending the same lines again wouldn't change that.
+            (400..=499, status) => {
+                event!(warn, "grafana rejected {} readings: {status}", items.len());
+                Ok(())
+            }
+            (_, status) => Err(io::Error::other(format!("grafana answered {status}"))),
+        }
+    }
+}
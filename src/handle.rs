@@ -0,0 +1,202 @@
+//! Read the monitor in a background thread, and keep the latest reading around.
+//!
+//! GUIs and servers usually don't want to own the read loop, they want "what's the co2 right now" and maybe a
+//! notification when that changes. [`MonitorHandle`] runs the loop in its own thread, reconnects when the device goes
+//! away, and hands out the latest reading, waits for the next one, or sends every reading to subscribers.
+use crate::{
+    MonitorReadingParts, TimestampedReading,
+    backend::Backend,
+    clock::SystemClock,
+    device::{Co2MonitorCommunication, Co2MonitorExt},
+    fanout::FanOut,
+    policy::{ReadOutcome, ReadPolicy},
+};
+use std::{
+    boxed::Box,
+    io,
+    sync::{Arc, Condvar, Mutex, MutexGuard, mpsc},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+/// How long to wait before connecting again, after the device couldn't be opened or stopped answering.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default)]
+struct State {
+    latest: Option<TimestampedReading>,
+    /// Counts the readings, so `wait_for_next` can tell a new reading from the same one.
+    count: u64,
+    subscribers: FanOut<TimestampedReading>,
+    stop: bool,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // A panic while holding the lock can't leave the state half updated, so just carry on.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn stopped(&self) -> bool {
+        self.lock().stop
+    }
+
+    /// Sleep for `duration`, or until the handle is stopped. Returns whether it was stopped.
+    fn sleep(&self, duration: Duration) -> bool {
+        let state = self.lock();
+        let (state, _) = self
+            .changed
+            .wait_timeout_while(state, duration, |state| !state.stop)
+            .unwrap_or_else(|e| e.into_inner());
+        state.stop
+    }
+
+    fn publish(&self, reading: TimestampedReading) {
+        let mut state = self.lock();
+        let reading = reading.with_sequence(state.count as u32);
+        state.latest = Some(reading);
+        state.count += 1;
+        state.subscribers.publish(reading);
+        self.changed.notify_all();
+    }
+}
+
+/// A background thread that reads the monitor, see the [module documentation](self).
+///
+/// Dropping the handle stops the thread, which takes up to a second (the read timeout).
+#[derive(Debug)]
+pub struct MonitorHandle {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MonitorHandle {
+    /// Start reading from whatever `connect` returns. `connect` is called in the background thread, again whenever the
+    /// device couldn't be opened or stopped answering, so `Backend::connect` fits right in.
+    pub fn spawn<F>(mut connect: F) -> Self
+    where
+        F: FnMut() -> io::Result<Box<dyn Co2MonitorCommunication>> + Send + 'static,
+    {
+        let shared = Arc::new(Shared::default());
+        let thread = std::thread::spawn({
+            let shared = Arc::clone(&shared);
+            move || {
+                while !shared.stopped() {
+                    let monitor = match connect() {
+                        Ok(monitor) => monitor,
+                        Err(e) => {
+                            event!(warn, "could not connect to the monitor: {}", e);
+                            shared.sleep(RECONNECT_DELAY);
+                            continue;
+                        }
+                    };
+                    if read_until_unhealthy(&*monitor, &shared) {
+                        return;
+                    }
+                    shared.sleep(RECONNECT_DELAY);
+                }
+            }
+        });
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Start reading from `backend`.
+    pub fn spawn_backend(backend: Backend) -> Self {
+        Self::spawn(move || backend.connect())
+    }
+
+    /// The latest reading and when it was completed (milliseconds since the unix epoch), `None` before the first
+    /// complete reading, which takes about 5 seconds after connecting. Readings are numbered from 0, across
+    /// reconnects.
+    pub fn latest(&self) -> Option<TimestampedReading> {
+        self.shared.lock().latest
+    }
+
+    /// Wait for the next reading after the current one, at most `timeout`. `None` on timeout.
+    pub fn wait_for_next(&self, timeout: Duration) -> Option<TimestampedReading> {
+        let state = self.shared.lock();
+        let count = state.count;
+        let (state, result) = self
+            .shared
+            .changed
+            .wait_timeout_while(state, timeout, |state| state.count == count && !state.stop)
+            .unwrap_or_else(|e| e.into_inner());
+        if result.timed_out() || state.count == count {
+            None
+        } else {
+            state.latest
+        }
+    }
+
+    /// Get every reading from now on. The channel is dropped from the handle once the receiver is gone, and closes
+    /// when the handle is stopped.
+    pub fn subscribe(&self) -> mpsc::Receiver<TimestampedReading> {
+        self.shared.lock().subscribers.subscribe()
+    }
+
+    /// Stop reading and wait for the thread to finish.
+    pub fn stop(mut self) {
+        self.stop_and_join();
+    }
+
+    fn stop_and_join(&mut self) {
+        {
+            let mut state = self.shared.lock();
+            state.stop = true;
+            state.subscribers.clear();
+        }
+        self.shared.changed.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for MonitorHandle {
+    fn drop(&mut self) {
+        self.stop_and_join();
+    }
+}
+
+/// Read and publish until the device stops answering (`false`) or the handle is stopped (`true`).
+fn read_until_unhealthy(monitor: &dyn Co2MonitorCommunication, shared: &Shared) -> bool {
+    let mut part = MonitorReadingParts::default();
+    let mut policy = ReadPolicy::new();
+    loop {
+        if shared.stopped() {
+            return true;
+        }
+        match monitor.read_with_policy(&mut part, &mut policy, &mut |_| ()) {
+            ReadOutcome::Reading(reading) => {
+                shared.publish(TimestampedReading::now(reading, &SystemClock))
+            }
+            // Sleeps through the backoff, and only stops early when the handle is stopped.
+            ReadOutcome::TransientError { retry_after_ms, .. }
+                if shared.sleep(Duration::from_millis(u64::from(retry_after_ms))) =>
+            {
+                return true;
+            }
+            ReadOutcome::Unhealthy {
+                consecutive_failures,
+            } => {
+                event!(
+                    warn,
+                    "{} reads in a row failed, reconnecting",
+                    consecutive_failures
+                );
+                return false;
+            }
+            ReadOutcome::Cancelled => return true,
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Counts what goes wrong while reading, so a flaky cable shows up as numbers instead of just fewer readings.
+//!
+//! The reading functions swallow most errors, since trying again is all one can do about them. They still count them
+//! in a [`MonitorHealth`] when given one, see
+//! [`Co2MonitorExt::read_to_part_with_health`](crate::Co2MonitorExt::read_to_part_with_health) and
+//! [`ReportReader::health`](crate::embedded::ReportReader::health).
+use crate::{FrameError, MonitorError};
+
+/// Counters of read attempts and their outcomes.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MonitorHealth {
+    /// Read attempts.
+    pub reads: u64,
+    /// Valid reports.
+    pub reports: u64,
+    /// Complete readings.
+    pub readings: u64,
+    /// Reads that returned nothing (hidapi returns 0 bytes on timeout) or failed with [`MonitorError::Timeout`].
+    pub timeouts: u64,
+    /// Reads that failed for any other reason.
+    pub read_errors: u64,
+    /// Reads that returned less than 8 bytes.
+    pub short_reads: u64,
+    /// Reports whose checksum didn't match.
+    pub checksum_failures: u64,
+    /// Reports without the terminator byte.
+    pub framing_failures: u64,
+    /// Valid reports with an opcode this library doesn't know.
+    pub unknown_opcodes: u64,
+}
+
+/// [`MonitorHealth`] counters per minute, see [`MonitorHealth::rates`].
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct HealthRates {
+    /// Valid reports per minute.
+    pub reports: f32,
+    /// Complete readings per minute.
+    pub readings: f32,
+    /// Failed reads and invalid reports per minute, timeouts not included.
+    pub failures: f32,
+    /// Timeouts per minute.
+    pub timeouts: f32,
+}
+
+impl MonitorHealth {
+    /// All counters at zero.
+    pub const fn new() -> Self {
+        Self {
+            reads: 0,
+            reports: 0,
+            readings: 0,
+            timeouts: 0,
+            read_errors: 0,
+            short_reads: 0,
+            checksum_failures: 0,
+            framing_failures: 0,
+            unknown_opcodes: 0,
+        }
+    }
+
+    pub(crate) fn record_read(&mut self, read_len: &Result<usize, MonitorError>) {
+        self.reads += 1;
+        match read_len {
+            Ok(8) => (),
+            Ok(0) | Err(MonitorError::Timeout | MonitorError::Cancelled) => self.timeouts += 1,
+            Ok(_) => self.short_reads += 1,
+            Err(_) => self.read_errors += 1,
+        }
+    }
+
+    pub(crate) fn record_frame(&mut self, frame: &Result<(u8, u16), FrameError>) {
+        match frame {
+            Ok(_) => self.reports += 1,
+            Err(FrameError::ChecksumInvalid) => self.checksum_failures += 1,
+            Err(FrameError::MissingTerminatorByte) => self.framing_failures += 1,
+        }
+    }
+
+    /// Reads that failed, returned too little or contained an invalid report. Timeouts don't count, the device just
+    /// didn't have anything to say.
+    pub fn failures(&self) -> u64 {
+        self.read_errors + self.short_reads + self.checksum_failures + self.framing_failures
+    }
+
+    /// Fraction of the reads that were failures, 0 if nothing was read yet.
+    pub fn failure_ratio(&self) -> f32 {
+        let attempts = self.reads.saturating_sub(self.timeouts);
+        if attempts == 0 {
+            0.0
+        } else {
+            self.failures() as f32 / attempts as f32
+        }
+    }
+
+    /// What happened since the `earlier` snapshot of the same counters, e.g. to get the numbers of the last hour.
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self {
+            reads: self.reads.saturating_sub(earlier.reads),
+            reports: self.reports.saturating_sub(earlier.reports),
+            readings: self.readings.saturating_sub(earlier.readings),
+            timeouts: self.timeouts.saturating_sub(earlier.timeouts),
+            read_errors: self.read_errors.saturating_sub(earlier.read_errors),
+            short_reads: self.short_reads.saturating_sub(earlier.short_reads),
+            checksum_failures: self
+                .checksum_failures
+                .saturating_sub(earlier.checksum_failures),
+            framing_failures: self
+                .framing_failures
+                .saturating_sub(earlier.framing_failures),
+            unknown_opcodes: self.unknown_opcodes.saturating_sub(earlier.unknown_opcodes),
+        }
+    }
+
+    /// The counters per minute, given they were collected over `elapsed_ms`. Combine with [`since`](Self::since) for
+    /// the rates of a recent interval.
+    pub fn rates(&self, elapsed_ms: u64) -> HealthRates {
+        let minutes = elapsed_ms.max(1) as f32 / 60_000.0;
+        HealthRates {
+            reports: self.reports as f32 / minutes,
+            readings: self.readings as f32 / minutes,
+            failures: self.failures() as f32 / minutes,
+            timeouts: self.timeouts as f32 / minutes,
+        }
+    }
+}
+
+impl core::fmt::Display for MonitorHealth {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} reads, {} reports, {} readings, {} timeouts, {} read errors, {} short reads, {} checksum failures, \
+             {} framing failures, {} unknown opcodes",
+            self.reads,
+            self.reports,
+            self.readings,
+            self.timeouts,
+            self.read_errors,
+            self.short_reads,
+            self.checksum_failures,
+            self.framing_failures,
+            self.unknown_opcodes
+        )
+    }
+}
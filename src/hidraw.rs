@@ -0,0 +1,198 @@
+//! Implement the Co2 monitor communication on linux with plain `/dev/hidraw*` nodes, without hidapi or libusb.
+//!
+//! That's all the kernel's HID driver needs: the monitor is found by matching VID/PID in sysfs, the feature report is
+//! the `HIDIOCSFEATURE` ioctl and reports are read from the device node. Nothing but libc is linked, which makes this
+//! the backend for minimal systems like Alpine containers or OpenWrt routers. The user needs read and write access to
+//! the node, see `co2_cli doctor` for a udev rule.
+//!
+//! Like with hidapi, the key of the [`DeviceProfile`] goes into the feature report, and reports of encrypting devices
+//! are decrypted with it, also when the profile says the device doesn't encrypt (see [`EncryptionDetector`]).
+use crate::{
+    device::{Co2MonitorCommunication, MonitorError},
+    profiles::{self, DeviceProfile, EncryptionDetector},
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    vec::Vec,
+};
+
+/// `_IOC(_IOC_READ | _IOC_WRITE, 'H', 0x06, len)`, i.e. `HIDIOCSFEATURE(len)`. Most architectures use the generic
+/// layout, mips, powerpc and sparc have fewer size bits and other direction bits.
+const fn hidiocsfeature(len: usize) -> libc::c_ulong {
+    #[cfg(any(
+        target_arch = "mips",
+        target_arch = "mips64",
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "sparc",
+        target_arch = "sparc64"
+    ))]
+    const READ_WRITE: libc::c_ulong = (2 | 4) << 29;
+    #[cfg(not(any(
+        target_arch = "mips",
+        target_arch = "mips64",
+        target_arch = "powerpc",
+        target_arch = "powerpc64",
+        target_arch = "sparc",
+        target_arch = "sparc64"
+    )))]
+    const READ_WRITE: libc::c_ulong = (1 | 2) << 30;
+    READ_WRITE | ((len as libc::c_ulong) << 16) | ((b'H' as libc::c_ulong) << 8) | 0x06
+}
+
+/// Talks to the monitor through its hidraw node.
+#[derive(Debug)]
+pub struct HidrawCo2Monitor {
+    file: File,
+    path: PathBuf,
+    profile: DeviceProfile,
+    encryption: EncryptionDetector,
+}
+
+impl HidrawCo2Monitor {
+    /// The hidraw nodes of all connected monitors of a built-in profile, e.g. `/dev/hidraw3`.
+    pub fn find() -> io::Result<Vec<PathBuf>> {
+        Ok(nodes(|vid, pid| profiles::find(vid, pid).is_some())?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect())
+    }
+
+    /// Open the first connected monitor, with the built-in profile for its ids.
+    pub fn open() -> io::Result<Self> {
+        let (path, profile) = nodes(|vid, pid| profiles::find(vid, pid).is_some())?
+            .into_iter()
+            .find_map(|(path, (vid, pid))| Some((path, profiles::find(vid, pid)?)))
+            .ok_or_else(not_found)?;
+        Self::open_path(path, profile)
+    }
+
+    /// Open the first connected monitor with the ids of `profile`.
+    pub fn open_with_profile(profile: DeviceProfile) -> io::Result<Self> {
+        let (path, _) = nodes(|vid, pid| profile.matches(vid, pid))?
+            .into_iter()
+            .next()
+            .ok_or_else(not_found)?;
+        Self::open_path(path, profile)
+    }
+
+    /// Open the monitor at `path` and send the feature report with the key of `profile`.
+    pub fn open_path(path: impl Into<PathBuf>, profile: DeviceProfile) -> io::Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+        let mut feature_report = profile.feature_report();
+        // SAFETY: the buffer is valid for its whole length, which is encoded in the request.
+        let result = unsafe {
+            libc::ioctl(
+                file.as_raw_fd(),
+                hidiocsfeature(feature_report.len()) as _,
+                feature_report.as_mut_ptr(),
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        event!(info, "connected to {}", path.display());
+        Ok(Self {
+            file,
+            path,
+            profile,
+            encryption: EncryptionDetector::new(profile),
+        })
+    }
+
+    /// The hidraw node of the monitor.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The profile the monitor was opened with.
+    pub fn profile(&self) -> &DeviceProfile {
+        &self.profile
+    }
+
+    /// Whether the reports are decrypted, because the profile says so or because they turned out to be encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption.is_encrypted()
+    }
+}
+
+/// The hidraw nodes whose ids `matches`, sorted, with their ids.
+fn nodes(matches: impl Fn(u16, u16) -> bool) -> io::Result<Vec<(PathBuf, (u16, u16))>> {
+    let mut found = Vec::new();
+    for entry in fs::read_dir("/sys/class/hidraw")? {
+        let entry = entry?;
+        let Ok(uevent) = fs::read_to_string(entry.path().join("device/uevent")) else {
+            continue;
+        };
+        if let Some((vid, pid)) = hid_id(&uevent).filter(|&(vid, pid)| matches(vid, pid)) {
+            found.push((Path::new("/dev").join(entry.file_name()), (vid, pid)));
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+fn not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "no monitor in /sys/class/hidraw")
+}
+
+/// Vendor and product ID from the `HID_ID=0003:000004D9:0000A052` line of a sysfs uevent file.
+fn hid_id(uevent: &str) -> Option<(u16, u16)> {
+    let id = uevent
+        .lines()
+        .find_map(|line| line.strip_prefix("HID_ID="))?;
+    let mut parts = id.split(':').skip(1);
+    let vid = u32::from_str_radix(parts.next()?, 16).ok()?;
+    let pid = u32::from_str_radix(parts.next()?, 16).ok()?;
+    Some((u16::try_from(vid).ok()?, u16::try_from(pid).ok()?))
+}
+
+impl Co2MonitorCommunication for HidrawCo2Monitor {
+    fn init_and_connect() -> Self {
+        Self::open().expect(
+            "Unable to open the hidraw device. Is it connected to this computer? Do you have sufficient permissions?",
+        )
+    }
+
+    fn read(&self, read_buffer: &mut [u8; 8]) -> Result<usize, MonitorError> {
+        self.read_timeout(read_buffer, 1000)
+    }
+
+    fn read_timeout(
+        &self,
+        read_buffer: &mut [u8; 8],
+        timeout_ms: u32,
+    ) -> Result<usize, MonitorError> {
+        let mut poll_fd = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout_ms = libc::c_int::try_from(timeout_ms).unwrap_or(libc::c_int::MAX);
+        // SAFETY: a single, valid pollfd.
+        match unsafe { libc::poll(&mut poll_fd, 1, timeout_ms) } {
+            0 => return Ok(0),
+            n if n < 0 => {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    return Ok(0);
+                }
+                event!(debug, "hidraw poll failed: {}", error);
+                return Err(MonitorError::ReadFailed);
+            }
+            _ => (),
+        }
+        let read_len = (&self.file).read(read_buffer).map_err(|e| {
+            event!(debug, "hidraw read failed: {}", e);
+            MonitorError::ReadFailed
+        })?;
+        if read_len == read_buffer.len() {
+            self.encryption.decrypt(read_buffer);
+        }
+        Ok(read_len)
+    }
+}
@@ -0,0 +1,202 @@
+//! Downsample long series of readings into buckets, for charts and exports that don't need every single reading.
+//!
+//! [`Resampler`] combines the readings of each bucket (e.g. an hour) into min, mean and max, and marks the times when
+//! the device was offline with an explicit [`HistoryPoint::Gap`], so a chart can leave a hole instead of drawing a line
+//! across it. It needs the readings in chronological order, and keeps only the current bucket in memory.
+//!
+//! Ventilations found by a [`VentilationDetector`](crate::ventilation::VentilationDetector) can be labeled in the same
+//! series with [`HistoryPoint::Ventilation`], the resampler itself doesn't look for them.
+use crate::{Co2Value, TimestampedReading, ventilation::VentilationEvent};
+
+/// Smallest, mean and largest value of a quantity in a bucket.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MinMeanMax {
+    /// Smallest value.
+    pub min: f32,
+    /// Arithmetic mean.
+    pub mean: f32,
+    /// Largest value.
+    pub max: f32,
+}
+
+/// Combined readings of one bucket.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Bucket {
+    /// Start of the bucket, a multiple of the bucket size.
+    pub start_ms: u64,
+    /// End of the bucket (exclusive).
+    pub end_ms: u64,
+    /// Readings in the bucket.
+    pub count: u32,
+    /// Co2 in ppm of the readings within spec, `None` if there are none.
+    pub co2: Option<MinMeanMax>,
+    /// Readings whose co2 was too high to be measured.
+    pub too_high: u32,
+    /// Temperature in degrees Celsius.
+    pub temperature: MinMeanMax,
+    /// Relative humidity in percent, `None` without a humidity sensor.
+    pub humidity: Option<MinMeanMax>,
+}
+
+/// An item of the downsampled series.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "lowercase"))]
+pub enum HistoryPoint {
+    /// Readings were there.
+    Bucket(Bucket),
+    /// No readings between the last reading before the gap and the first one after it.
+    Gap {
+        /// Timestamp of the last reading before the gap.
+        start_ms: u64,
+        /// Timestamp of the first reading after the gap.
+        end_ms: u64,
+    },
+    /// Co2 fell like it does when a window is opened.
+    Ventilation(VentilationEvent),
+}
+
+/// Running min, sum and max of a quantity.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Accumulator {
+    count: u32,
+    min: f32,
+    sum: f32,
+    max: f32,
+}
+
+impl Accumulator {
+    const fn new() -> Self {
+        Self {
+            count: 0,
+            min: f32::INFINITY,
+            sum: 0.0,
+            max: f32::NEG_INFINITY,
+        }
+    }
+
+    fn add(&mut self, value: f32) {
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.sum += value;
+        self.max = self.max.max(value);
+    }
+
+    fn summary(&self) -> Option<MinMeanMax> {
+        (self.count > 0).then(|| MinMeanMax {
+            min: self.min,
+            mean: self.sum / self.count as f32,
+            max: self.max,
+        })
+    }
+}
+
+/// The bucket that is currently being filled.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Current {
+    start_ms: u64,
+    count: u32,
+    too_high: u32,
+    co2: Accumulator,
+    temperature: Accumulator,
+    humidity: Accumulator,
+}
+
+/// Gaps are never shorter than this, a device that didn't report for less than a minute was just a bit slow.
+pub const MIN_GAP_MS: u64 = 60_000;
+
+/// Downsamples readings into buckets of a fixed size, see the [module documentation](self).
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Resampler {
+    bucket_ms: u64,
+    current: Option<Current>,
+    last_ms: Option<u64>,
+}
+
+impl Resampler {
+    /// Buckets of `bucket_ms` milliseconds (0 is treated like 1). Whenever at least one whole bucket (and at least
+    /// [`MIN_GAP_MS`]) goes by without readings, that's a gap.
+    pub const fn new(bucket_ms: u64) -> Self {
+        Self {
+            bucket_ms: if bucket_ms == 0 { 1 } else { bucket_ms },
+            current: None,
+            last_ms: None,
+        }
+    }
+
+    /// Add a reading, and pass the buckets (and gaps) it completes to `emit`. Readings older than the current bucket
+    /// are ignored.
+    pub fn push(&mut self, reading: &TimestampedReading, emit: &mut impl FnMut(HistoryPoint)) {
+        let timestamp_ms = reading.timestamp_ms;
+        let start_ms = timestamp_ms - timestamp_ms % self.bucket_ms;
+        if self
+            .current
+            .is_some_and(|current| start_ms < current.start_ms)
+        {
+            return;
+        }
+        if let Some(last_ms) = self.last_ms
+            && start_ms > last_ms - last_ms % self.bucket_ms + self.bucket_ms
+            && timestamp_ms - last_ms > MIN_GAP_MS
+        {
+            self.finish(emit);
+            emit(HistoryPoint::Gap {
+                start_ms: last_ms,
+                end_ms: timestamp_ms,
+            });
+        } else if self
+            .current
+            .is_some_and(|current| current.start_ms != start_ms)
+        {
+            self.finish(emit);
+        }
+        self.last_ms = Some(timestamp_ms);
+
+        let current = self.current.get_or_insert(Current {
+            start_ms,
+            count: 0,
+            too_high: 0,
+            co2: Accumulator::new(),
+            temperature: Accumulator::new(),
+            humidity: Accumulator::new(),
+        });
+        current.count += 1;
+        match reading.reading.co2_value {
+            Co2Value::Valid(ppm) => current.co2.add(f32::from(ppm)),
+            Co2Value::TooHigh(_) => current.too_high += 1,
+            Co2Value::WarmingUp(_) | Co2Value::TooLow(_) | Co2Value::SensorFault(_) => (),
+        }
+        current.temperature.add(reading.reading.temperature);
+        if let Some(humidity) = reading.reading.humidity {
+            current.humidity.add(humidity);
+        }
+    }
+
+    /// Pass the current, incomplete bucket to `emit`, e.g. at the end of the series.
+    pub fn finish(&mut self, emit: &mut impl FnMut(HistoryPoint)) {
+        let Some(current) = self.current.take() else {
+            return;
+        };
+        let Some(temperature) = current.temperature.summary() else {
+            return;
+        };
+        emit(HistoryPoint::Bucket(Bucket {
+            start_ms: current.start_ms,
+            end_ms: current.start_ms.saturating_add(self.bucket_ms),
+            count: current.count,
+            co2: current.co2.summary(),
+            too_high: current.too_high,
+            temperature,
+            humidity: current.humidity.summary(),
+        }))
+    }
+}
@@ -0,0 +1,411 @@
+//! The MQTT messages for Home Assistant: discovery, state and availability, for whatever MQTT client is at hand.
+//!
+//! [`HomeAssistant`] makes the retained discovery configs under `homeassistant/sensor/...`, so the monitor shows up as
+//! a device with a co2 sensor (device class `carbon_dioxide`), a temperature sensor and, if the model has one, a
+//! humidity sensor. All of them read the same JSON state topic. They also share an availability topic, which is set to `online`
+//! with the first reading and to `offline` when the device is gone, so the entities become "unavailable" instead of
+//! showing the last value forever. Set [`HomeAssistant::last_will`] as the last will of the MQTT connection to cover
+//! the logger itself going away, and `expire_after` covers everything else.
+//!
+//! Ventilations (see [`crate::ventilation`]) are an event entity, which automations can trigger on to confirm that a
+//! window was opened, without a contact sensor. Publish [`HomeAssistant::ventilation`] for every detected event.
+//!
+//! With the `occupancy` feature, [`HomeAssistant::with_occupancy`] adds a sensor for the estimated number of people
+//! (see [`crate::occupancy`]), which [`HomeAssistant::reading_with_occupancy`] fills in.
+//!
+//! There's no MQTT client in this crate, every method returns [`MqttMessage`]s to publish with your own: the
+//! [discovery](HomeAssistant::discovery) configs after connecting to the broker, then the messages of every
+//! [reading](HomeAssistant::reading) and of [`HomeAssistant::disconnected`] when the device is gone. It has to be a
+//! client that stays connected, for the last will and for requests.
+//!
+//! The state is retained, so a new subscriber gets the latest reading right away. With MQTT v5, the
+//! [`MqttProperties`] of the state expire it on the broker after `expire_after`, so a retained state doesn't outlive
+//! the logger for long. Clients that want a fresh state can publish anything to [`HomeAssistant::command_topic`]
+//! (`.../cmd/get`), subscribe to it and pass what arrives to [`HomeAssistant::respond`]. The answer goes to the state
+//! topic, and with MQTT v5 also to the response topic of the request, with its correlation data.
+use crate::{Co2Value, MonitorReading, ventilation::VentilationEvent};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+/// Where Home Assistant looks for discovery configs, unless configured otherwise.
+pub const DEFAULT_DISCOVERY_PREFIX: &str = "homeassistant";
+
+/// A message to publish, or one that arrived.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MqttMessage {
+    /// The topic.
+    pub topic: String,
+    /// The payload, JSON or `online`/`offline`.
+    pub payload: String,
+    /// Whether the broker should keep it for new subscribers.
+    pub retain: bool,
+    /// The MQTT v5 properties, for clients that speak v5. Older clients ignore them.
+    pub properties: MqttProperties,
+}
+
+/// The MQTT v5 properties of a [`MqttMessage`] that matter here.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MqttProperties {
+    /// The content type, `application/json` for JSON payloads.
+    pub content_type: Option<String>,
+    /// Seconds after which the broker drops the message, including a retained one.
+    pub message_expiry_seconds: Option<u32>,
+    /// Where the sender of a request wants the response.
+    pub response_topic: Option<String>,
+    /// What the sender of a request wants back with the response, to match them up.
+    pub correlation_data: Option<Vec<u8>>,
+}
+
+/// A message with a JSON payload.
+fn json_message(topic: String, payload: String, retain: bool) -> MqttMessage {
+    MqttMessage {
+        topic,
+        payload,
+        retain,
+        properties: MqttProperties {
+            content_type: Some(String::from("application/json")),
+            ..MqttProperties::default()
+        },
+    }
+}
+
+/// The device in the Home Assistant device registry.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct HaDevice {
+    /// Identifies the device, e.g. the serial number. Also used in topics and entity ids, after replacing anything but
+    /// letters, digits, `-` and `_` with `_`.
+    #[serde(rename = "identifiers")]
+    pub identifier: String,
+    /// The name shown in Home Assistant.
+    pub name: String,
+    /// The model, e.g. the name of the device profile.
+    pub model: String,
+    /// The manufacturer.
+    pub manufacturer: String,
+    /// The firmware version, if known.
+    #[serde(rename = "sw_version", skip_serializing_if = "Option::is_none")]
+    pub firmware_version: Option<String>,
+}
+
+impl HaDevice {
+    /// A ZGm053U with this identifier and name.
+    pub fn new(identifier: &str, name: &str) -> Self {
+        Self {
+            identifier: identifier.to_string(),
+            name: name.to_string(),
+            model: String::from("ZGm053U"),
+            manufacturer: String::from("ZyAura"),
+            firmware_version: None,
+        }
+    }
+
+    /// The device as found on the USB bus, named after the room it is in. The model is the name of the built-in profile
+    /// for its ids.
+    pub fn from_device(device: &crate::pc::DeviceInfo, room: &str) -> Self {
+        Self {
+            model: crate::profiles::name_of(device.vendor_id, device.product_id)
+                .unwrap_or("ZGm053U")
+                .to_string(),
+            firmware_version: Some(device.firmware_version()),
+            ..Self::new(device.identifier(), &format!("{room} co2 monitor"))
+        }
+    }
+
+    /// The identifier, usable in topics and entity ids.
+    pub fn object_id(&self) -> String {
+        self.identifier
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    }
+}
+
+/// The messages for one monitor, see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq)]
+pub struct HomeAssistant {
+    device: HaDevice,
+    discovery_prefix: String,
+    base_topic: String,
+    expire_after_seconds: u32,
+    has_humidity: bool,
+    #[cfg(feature = "occupancy")]
+    has_occupancy: bool,
+    online: bool,
+    // The last state, for requests.
+    state: Option<MqttMessage>,
+}
+
+impl HomeAssistant {
+    /// Discovery under [`DEFAULT_DISCOVERY_PREFIX`], state and availability under `co2_monitor/<object id>`, values
+    /// expiring after 5 minutes and no humidity.
+    pub fn new(device: HaDevice) -> Self {
+        let base_topic = format!("co2_monitor/{}", device.object_id());
+        Self {
+            device,
+            discovery_prefix: String::from(DEFAULT_DISCOVERY_PREFIX),
+            base_topic,
+            expire_after_seconds: 300,
+            has_humidity: false,
+            #[cfg(feature = "occupancy")]
+            has_occupancy: false,
+            online: false,
+            state: None,
+        }
+    }
+
+    /// Publish the discovery configs under another prefix, if Home Assistant is configured that way.
+    pub fn with_discovery_prefix(self, discovery_prefix: &str) -> Self {
+        Self {
+            discovery_prefix: discovery_prefix.to_string(),
+            ..self
+        }
+    }
+
+    /// Publish state and availability under this topic.
+    pub fn with_base_topic(self, base_topic: &str) -> Self {
+        Self {
+            base_topic: base_topic.to_string(),
+            ..self
+        }
+    }
+
+    /// Have Home Assistant mark the values unavailable when there was no state for this many seconds, 0 turns that off.
+    /// A reading comes every ~5 seconds, but a pipeline may send them less often.
+    pub fn with_expire_after(self, expire_after_seconds: u32) -> Self {
+        Self {
+            expire_after_seconds,
+            ..self
+        }
+    }
+
+    /// Whether to announce a humidity sensor, see
+    /// [`DeviceProfile::has_humidity`](crate::profiles::DeviceProfile::has_humidity).
+    pub fn with_humidity(self, has_humidity: bool) -> Self {
+        Self {
+            has_humidity,
+            ..self
+        }
+    }
+
+    /// Whether to announce a sensor for the estimated occupancy.
+    #[cfg(feature = "occupancy")]
+    pub fn with_occupancy(self, has_occupancy: bool) -> Self {
+        Self {
+            has_occupancy,
+            ..self
+        }
+    }
+
+    /// The topic of the readings.
+    pub fn state_topic(&self) -> String {
+        format!("{}/state", self.base_topic)
+    }
+
+    /// The topic of the ventilation events.
+    pub fn ventilation_topic(&self) -> String {
+        format!("{}/ventilation", self.base_topic)
+    }
+
+    /// The topic to subscribe to for requests of the state, see [`respond`](Self::respond).
+    pub fn command_topic(&self) -> String {
+        format!("{}/cmd/get", self.base_topic)
+    }
+
+    /// The topic with `online` or `offline`.
+    pub fn availability_topic(&self) -> String {
+        format!("{}/availability", self.base_topic)
+    }
+
+    /// The retained discovery configs of the sensors and the ventilation event. Publish them on every connect to the
+    /// broker, Home Assistant takes unchanged configs in stride.
+    pub fn discovery(&self) -> Vec<MqttMessage> {
+        let mut sensors = vec![
+            ("co2", Some("carbon_dioxide"), "ppm", "Co2"),
+            ("temperature", Some("temperature"), "°C", "Temperature"),
+        ];
+        if self.has_humidity {
+            sensors.push(("humidity", Some("humidity"), "%", "Humidity"));
+        }
+        // There's no device class for people.
+        #[cfg(feature = "occupancy")]
+        if self.has_occupancy {
+            sensors.push(("occupancy", None, "people", "Occupancy"));
+        }
+        let object_id = self.device.object_id();
+        let ventilation = json_message(
+            format!(
+                "{}/event/co2_monitor_{object_id}/ventilation/config",
+                self.discovery_prefix
+            ),
+            json!({
+                "name": "Ventilation",
+                "unique_id": format!("co2_monitor_{object_id}_ventilation"),
+                "object_id": format!("co2_monitor_{object_id}_ventilation"),
+                "event_types": ["ventilation"],
+                "state_topic": self.ventilation_topic(),
+                "availability_topic": self.availability_topic(),
+                "payload_available": "online",
+                "payload_not_available": "offline",
+                "device": self.device,
+            })
+            .to_string(),
+            true,
+        );
+        sensors
+            .into_iter()
+            .map(|(key, device_class, unit, name)| {
+                let mut config = json!({
+                    "name": name,
+                    "unique_id": format!("co2_monitor_{object_id}_{key}"),
+                    "object_id": format!("co2_monitor_{object_id}_{key}"),
+                    "state_class": "measurement",
+                    "unit_of_measurement": unit,
+                    "state_topic": self.state_topic(),
+                    "value_template": format!("{{{{ value_json.{key} }}}}"),
+                    "availability_topic": self.availability_topic(),
+                    "payload_available": "online",
+                    "payload_not_available": "offline",
+                    "device": self.device,
+                });
+                if let Some(device_class) = device_class {
+                    config["device_class"] = device_class.into();
+                }
+                if self.expire_after_seconds > 0 {
+                    config["expire_after"] = self.expire_after_seconds.into();
+                }
+                json_message(
+                    format!(
+                        "{}/sensor/co2_monitor_{object_id}/{key}/config",
+                        self.discovery_prefix
+                    ),
+                    config.to_string(),
+                    true,
+                )
+            })
+            .chain([ventilation])
+            .collect()
+    }
+
+    /// The message for a detected ventilation. The start and end co2 are attributes of the event.
+    pub fn ventilation(&self, event: &VentilationEvent) -> MqttMessage {
+        json_message(
+            self.ventilation_topic(),
+            json!({
+                "event_type": "ventilation",
+                "start_ppm": event.start_ppm,
+                "end_ppm": event.end_ppm,
+                "minutes": (event.minutes() * 10.0).round() / 10.0,
+            })
+            .to_string(),
+            false,
+        )
+    }
+
+    /// The messages for a new reading: `online` if the device wasn't, and the retained state. Co2 is `null` while it
+    /// isn't [valid](Co2Value::Valid), which Home Assistant shows as unknown.
+    pub fn reading(&mut self, reading: &MonitorReading) -> Vec<MqttMessage> {
+        self.messages(reading, None)
+    }
+
+    /// Like [`reading`](Self::reading), with the estimated number of people, if there is an estimate yet.
+    #[cfg(feature = "occupancy")]
+    pub fn reading_with_occupancy(
+        &mut self,
+        reading: &MonitorReading,
+        occupancy: Option<f32>,
+    ) -> Vec<MqttMessage> {
+        self.messages(reading, occupancy)
+    }
+
+    fn messages(&mut self, reading: &MonitorReading, occupancy: Option<f32>) -> Vec<MqttMessage> {
+        let mut messages = Vec::new();
+        if !self.online {
+            self.online = true;
+            messages.push(self.availability());
+        }
+        let co2 = match reading.co2_value {
+            Co2Value::Valid(ppm) => Some(ppm),
+            _ => None,
+        };
+        let mut state = json!({
+            "co2": co2,
+            "temperature": (reading.temperature * 10.0).round() / 10.0,
+            "humidity": reading.humidity.map(|humidity| humidity.round()),
+        });
+        if let Some(occupancy) = occupancy {
+            state["occupancy"] = ((occupancy * 10.0).round() / 10.0).into();
+        }
+        let mut state = json_message(self.state_topic(), state.to_string(), true);
+        if self.expire_after_seconds > 0 {
+            state.properties.message_expiry_seconds = Some(self.expire_after_seconds);
+        }
+        self.state = Some(state.clone());
+        messages.push(state);
+        messages
+    }
+
+    /// The answer to `request`, if it arrived on the [command topic](Self::command_topic): the last state again, and a
+    /// copy for the response topic of the request, if it has one. Nothing before the first reading or while the
+    /// device is offline.
+    pub fn respond(&self, request: &MqttMessage) -> Vec<MqttMessage> {
+        let Some(state) = self.state.as_ref().filter(|_| self.online) else {
+            return Vec::new();
+        };
+        if request.topic != self.command_topic() {
+            return Vec::new();
+        }
+        let mut messages = vec![state.clone()];
+        if let Some(response_topic) = &request.properties.response_topic {
+            let mut response = state.clone();
+            response.topic = response_topic.clone();
+            response.retain = false;
+            response.properties.correlation_data = request.properties.correlation_data.clone();
+            messages.push(response);
+        }
+        messages
+    }
+
+    /// The device is gone: `offline`, if it was online.
+    pub fn disconnected(&mut self) -> Option<MqttMessage> {
+        self.online.then(|| {
+            self.online = false;
+            self.availability()
+        })
+    }
+
+    /// `offline`, to set as the last will of the MQTT connection.
+    pub fn last_will(&self) -> MqttMessage {
+        MqttMessage {
+            topic: self.availability_topic(),
+            payload: String::from("offline"),
+            retain: true,
+            ..MqttMessage::default()
+        }
+    }
+
+    /// Whether the device is online, as last published.
+    pub fn is_online(&self) -> bool {
+        self.online
+    }
+
+    fn availability(&self) -> MqttMessage {
+        MqttMessage {
+            topic: self.availability_topic(),
+            payload: String::from(if self.online { "online" } else { "offline" }),
+            retain: true,
+            ..MqttMessage::default()
+        }
+    }
+}
@@ -0,0 +1,274 @@
+//! Run a shell command on readings or alerts, for whatever there's no sink for yet.
+//!
+//! An [`ExecHook`] runs its command with `sh -c` (`cmd /C` on Windows) and describes what happened in environment
+//! variables:
+//!
+//! - `EVENT`: `reading`, `alert` or `escalation`, see [`Notification`].
+//! - `CO2_PPM`, `CO2_VALID` (`1` or `0`), `TEMP_C` and `HUMIDITY` (if the device has a humidity sensor): the reading.
+//! - `TIMESTAMP`: unix seconds.
+//! - For alerts, `ALERT_KIND` (e.g. `co2_high`), `ALERT_TRANSITION` (`raised` or `cleared`), `ALERT_VALUE` and
+//!   `ALERT_LIMIT`. Those are in ppm, or for temperature alerts in the unit of [`ExecHook::with_unit`] (Celsius by
+//!   default).
+//!
+//! Commands run in the background, the logger doesn't wait for them. At most [`ExecHook::with_max_running`] of them run
+//! at the same time, and while that many are still running, new events are skipped (and counted) rather than queued.
+//! Commands running longer than the timeout are killed. There's no thread watching them, so that (and noticing that a
+//! command is done) happens on the next event. Dropping the hook kills the commands that are still running, so none
+//! outlive the logger.
+use crate::{MonitorReading, TemperatureUnit, notification::Notification};
+use std::{
+    format,
+    process::{Child, Command, Stdio},
+    string::{String, ToString},
+    time::{Duration, Instant},
+    vec,
+    vec::Vec,
+};
+
+/// Commands running longer than this are killed, unless configured otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// At most this many commands run at the same time, unless configured otherwise.
+pub const DEFAULT_MAX_RUNNING: usize = 1;
+
+/// When an [`ExecHook`] runs.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HookTrigger {
+    /// On every reading.
+    Reading,
+    /// On readings that differ from the previous one.
+    Change,
+    /// On alert notifications, see [`crate::notification`].
+    #[default]
+    Alert,
+}
+
+impl core::fmt::Display for HookTrigger {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            HookTrigger::Reading => "reading",
+            HookTrigger::Change => "change",
+            HookTrigger::Alert => "alert",
+        })
+    }
+}
+
+/// Error for a [`HookTrigger`] that is none of `reading`, `change` or `alert`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseHookTriggerError;
+
+impl core::fmt::Display for ParseHookTriggerError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("expected reading, change or alert")
+    }
+}
+
+impl core::error::Error for ParseHookTriggerError {}
+
+impl core::str::FromStr for HookTrigger {
+    type Err = ParseHookTriggerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reading" => Ok(HookTrigger::Reading),
+            "change" => Ok(HookTrigger::Change),
+            "alert" => Ok(HookTrigger::Alert),
+            _ => Err(ParseHookTriggerError),
+        }
+    }
+}
+
+/// Runs a command on events, see the [module documentation](self).
+#[derive(Debug)]
+pub struct ExecHook {
+    command: String,
+    trigger: HookTrigger,
+    timeout: Duration,
+    max_running: usize,
+    unit: TemperatureUnit,
+    running: Vec<(Child, Instant)>,
+    previous: Option<MonitorReading>,
+    skipped: u64,
+}
+
+impl ExecHook {
+    /// Run `command` on alerts, with [`DEFAULT_TIMEOUT`] and [`DEFAULT_MAX_RUNNING`].
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            trigger: HookTrigger::Alert,
+            timeout: DEFAULT_TIMEOUT,
+            max_running: DEFAULT_MAX_RUNNING,
+            unit: TemperatureUnit::Celsius,
+            running: Vec::new(),
+            previous: None,
+            skipped: 0,
+        }
+    }
+
+    /// Run on `trigger` instead.
+    pub fn with_trigger(mut self, trigger: HookTrigger) -> Self {
+        self.trigger = trigger;
+        self
+    }
+
+    /// Kill commands running longer than `timeout`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Run at most `max_running` (at least 1) commands at the same time.
+    pub fn with_max_running(mut self, max_running: usize) -> Self {
+        self.max_running = max_running.max(1);
+        self
+    }
+
+    /// Give the values of temperature alerts in `unit`.
+    pub fn with_unit(mut self, unit: TemperatureUnit) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    /// When the hook runs.
+    pub fn trigger(&self) -> HookTrigger {
+        self.trigger
+    }
+
+    /// Events skipped so far because too many commands were still running.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// A new reading, taken at `timestamp` (unix seconds). Runs the command for [`HookTrigger::Reading`], and for
+    /// [`HookTrigger::Change`] if it differs from the previous one.
+    pub fn reading(&mut self, reading: &MonitorReading, timestamp: i64) {
+        let previous = self.previous.replace(*reading);
+        let changed = previous.is_none_or(|previous| {
+            (
+                previous.co2_value,
+                previous.temperature_centi,
+                previous.humidity,
+            ) != (
+                reading.co2_value,
+                reading.temperature_centi,
+                reading.humidity,
+            )
+        });
+        match self.trigger {
+            HookTrigger::Reading => {}
+            HookTrigger::Change if changed => {}
+            _ => return,
+        }
+        self.run(vec![("EVENT", "reading".to_string())], reading, timestamp);
+    }
+
+    /// A notification about `reading`, taken at `timestamp` (unix seconds). Runs the command for
+    /// [`HookTrigger::Alert`].
+    pub fn notification(
+        &mut self,
+        notification: &Notification,
+        reading: &MonitorReading,
+        timestamp: i64,
+    ) {
+        if self.trigger != HookTrigger::Alert {
+            return;
+        }
+        let env = match notification {
+            Notification::Alert { event, .. } => {
+                let (value, limit) = event.value_and_limit_in(self.unit);
+                vec![
+                    ("EVENT", "alert".to_string()),
+                    ("ALERT_KIND", event.kind.to_string()),
+                    ("ALERT_TRANSITION", event.transition.to_string()),
+                    ("ALERT_VALUE", value.to_string()),
+                    ("ALERT_LIMIT", limit.to_string()),
+                ]
+            }
+            Notification::Escalation { .. } => vec![("EVENT", "escalation".to_string())],
+        };
+        self.run(env, reading, timestamp);
+    }
+
+    fn run(&mut self, mut env: Vec<(&str, String)>, reading: &MonitorReading, timestamp: i64) {
+        self.reap();
+        if self.running.len() >= self.max_running {
+            self.skipped += 1;
+            event!(
+                warn,
+                "the hook is still running, skipped {} events so far",
+                self.skipped
+            );
+            return;
+        }
+        let (ppm, valid) = reading.co2_value.as_num_and_bool();
+        env.push(("CO2_PPM", ppm.to_string()));
+        env.push(("CO2_VALID", u8::from(valid).to_string()));
+        env.push(("TEMP_C", format!("{:.2}", reading.temperature)));
+        if let Some(humidity) = reading.humidity {
+            env.push(("HUMIDITY", format!("{humidity:.1}")));
+        }
+        env.push(("TIMESTAMP", timestamp.to_string()));
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C");
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c");
+            command
+        };
+        // The output of the command would end up between the printed readings.
+        let spawned = command
+            .arg(&self.command)
+            .envs(env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .spawn();
+        match spawned {
+            Ok(child) => self.running.push((child, Instant::now())),
+            Err(e) => event!(warn, "could not run the hook `{}`: {e}", self.command),
+        }
+    }
+
+    /// Forget commands that are done, and kill those running for too long.
+    fn reap(&mut self) {
+        let timeout = self.timeout;
+        let command = &self.command;
+        self.running
+            .retain_mut(|(child, started)| match child.try_wait() {
+                Ok(Some(status)) => {
+                    if !status.success() {
+                        event!(warn, "the hook `{command}` failed: {status}");
+                    }
+                    false
+                }
+                Ok(None) if started.elapsed() < timeout => true,
+                Ok(None) => {
+                    event!(
+                        warn,
+                        "the hook `{command}` ran for longer than {timeout:?}, killing it"
+                    );
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    false
+                }
+                Err(e) => {
+                    event!(warn, "could not check on the hook `{command}`: {e}");
+                    false
+                }
+            });
+    }
+}
+
+impl Drop for ExecHook {
+    fn drop(&mut self) {
+        for (child, _) in &mut self.running {
+            if let Ok(None) = child.try_wait() {
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+        }
+    }
+}
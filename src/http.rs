@@ -0,0 +1,37 @@
+//! The little HTTP client the sinks and switches share: one request per connection, and only the status line of the
+//! response matters. Only `http`, whatever needs TLS goes through a proxy.
+use std::{
+    format,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    string::{String, ToString},
+    time::Duration,
+};
+
+/// Send `request` (the head and body, with `Connection: close`) to `server` (`address:port`) and return the status
+/// code and the status (code and reason phrase, e.g. `401 Unauthorized`). `timeout` applies to connecting, writing and
+/// reading alike.
+pub(crate) fn request(server: &str, request: &str, timeout: Duration) -> io::Result<(u16, String)> {
+    let address = server.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("{server} has no address"))
+    })?;
+    let mut stream = TcpStream::connect_timeout(&address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    stream.write_all(request.as_bytes())?;
+
+    // E.g. `HTTP/1.1 401 Unauthorized`.
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    let status = status_line
+        .trim_end()
+        .split_once(' ')
+        .map_or("", |(_, status)| status);
+    match status.get(..3).map(str::parse) {
+        Some(Ok(code)) => Ok((code, status.to_string())),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an HTTP response",
+        )),
+    }
+}
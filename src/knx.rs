@@ -0,0 +1,238 @@
+//! Send readings to a KNX installation as group telegrams, so HVAC control (or a visualization) can use them like those
+//! of a wired KNX co2 sensor.
+//!
+//! [`KnxSender`] writes each value to its group address with KNXnet/IP routing, i.e. as a UDP multicast that every
+//! KNX IP router (or IP interface with routing) in the network puts on the bus. Nothing has to be paired, the group
+//! addresses just have to be known to the devices that should listen. The values are 2-byte floats (DPT 9): co2 as
+//! DPT 9.008 (ppm), temperature as DPT 9.001 (°C) and humidity as DPT 9.007 (%). Co2 is only sent while it's
+//! [valid](crate::Co2Value::Valid), so nothing acts on the value of a sensor that is warming up or out of range.
+use crate::{Co2Value, MonitorReading};
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+};
+
+/// The multicast group and port of KNXnet/IP routing.
+pub const ROUTING_MULTICAST: &str = "224.0.23.12:3671";
+
+/// A KNX group address in the 3-level form `main/middle/sub`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GroupAddress(pub u16);
+
+impl GroupAddress {
+    /// `main/middle/sub`, `None` if a part is out of range (0-31, 0-7 and 0-255).
+    pub fn new(main: u8, middle: u8, sub: u8) -> Option<Self> {
+        (main < 32 && middle < 8)
+            .then(|| Self((u16::from(main) << 11) | (u16::from(middle) << 8) | u16::from(sub)))
+    }
+}
+
+impl core::fmt::Display for GroupAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{}/{}/{}",
+            self.0 >> 11,
+            (self.0 >> 8) & 0x07,
+            self.0 & 0xff
+        )
+    }
+}
+
+/// Returned when parsing [`GroupAddress`] from an invalid string.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseGroupAddressError;
+
+impl core::fmt::Display for ParseGroupAddressError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("expected a group address like 1/2/3, with parts up to 31/7/255")
+    }
+}
+
+impl core::error::Error for ParseGroupAddressError {}
+
+impl core::str::FromStr for GroupAddress {
+    type Err = ParseGroupAddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('/').map(|part| part.trim().parse::<u8>());
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(main)), Some(Ok(middle)), Some(Ok(sub)), None) => {
+                Self::new(main, middle, sub).ok_or(ParseGroupAddressError)
+            }
+            _ => Err(ParseGroupAddressError),
+        }
+    }
+}
+
+/// A KNX individual address `area.line.device`, which telegrams are sent from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct IndividualAddress(pub u16);
+
+impl IndividualAddress {
+    /// `area.line.device`, `None` if a part is out of range (0-15, 0-15 and 0-255).
+    pub fn new(area: u8, line: u8, device: u8) -> Option<Self> {
+        (area < 16 && line < 16)
+            .then(|| Self((u16::from(area) << 12) | (u16::from(line) << 8) | u16::from(device)))
+    }
+}
+
+impl Default for IndividualAddress {
+    /// 15.15.250, pick a free address of the line of the router instead.
+    fn default() -> Self {
+        Self(0xfffa)
+    }
+}
+
+impl core::fmt::Display for IndividualAddress {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}",
+            self.0 >> 12,
+            (self.0 >> 8) & 0x0f,
+            self.0 & 0xff
+        )
+    }
+}
+
+/// Returned when parsing [`IndividualAddress`] from an invalid string.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseIndividualAddressError;
+
+impl core::fmt::Display for ParseIndividualAddressError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("expected an individual address like 1.1.250, with parts up to 15.15.255")
+    }
+}
+
+impl core::error::Error for ParseIndividualAddressError {}
+
+impl core::str::FromStr for IndividualAddress {
+    type Err = ParseIndividualAddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('.').map(|part| part.trim().parse::<u8>());
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(area)), Some(Ok(line)), Some(Ok(device)), None) => {
+                Self::new(area, line, device).ok_or(ParseIndividualAddressError)
+            }
+            _ => Err(ParseIndividualAddressError),
+        }
+    }
+}
+
+/// Which value goes to which group address. Values without an address aren't sent.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct KnxGroups {
+    /// Co2 in ppm (DPT 9.008).
+    pub co2: Option<GroupAddress>,
+    /// Temperature in degrees Celsius (DPT 9.001).
+    pub temperature: Option<GroupAddress>,
+    /// Relative humidity in percent (DPT 9.007).
+    pub humidity: Option<GroupAddress>,
+}
+
+/// Encode `value` as a KNX 2-byte float (DPT 9): `0.01 * mantissa * 2^exponent`, with a 12 bit mantissa. Values out of
+/// range are clamped to ±670760.96.
+pub fn encode_dpt9(value: f32) -> [u8; 2] {
+    let hundredths = value * 100.0;
+    let mantissa = |exponent: u16| (hundredths / f32::from(1u16 << exponent)).round();
+    let mut exponent = 0u16;
+    while exponent < 15 && !(-2048.0..=2047.0).contains(&mantissa(exponent)) {
+        exponent += 1;
+    }
+    let mantissa = mantissa(exponent).clamp(-2048.0, 2047.0) as i16;
+    let sign = if mantissa < 0 { 0x8000 } else { 0 };
+    (sign | (exponent << 11) | (mantissa as u16 & 0x07ff)).to_be_bytes()
+}
+
+/// A KNXnet/IP routing indication that writes the 2-byte `data` to `destination`, sent by `source`.
+pub fn routing_indication(
+    source: IndividualAddress,
+    destination: GroupAddress,
+    data: [u8; 2],
+) -> [u8; 19] {
+    let [source_hi, source_lo] = source.0.to_be_bytes();
+    let [destination_hi, destination_lo] = destination.0.to_be_bytes();
+    [
+        // KNXnet/IP header: header length, protocol version, ROUTING_INDICATION, total length.
+        0x06,
+        0x10,
+        0x05,
+        0x30,
+        0x00,
+        19,
+        // cEMI: L_Data.ind, no additional info.
+        0x29,
+        0x00,
+        // Standard frame, not repeated, broadcast, low priority. Group address, hop count 6.
+        0xbc,
+        0xe0,
+        source_hi,
+        source_lo,
+        destination_hi,
+        destination_lo,
+        // Number of bytes after the TPCI, TPCI, APCI GroupValueWrite, data.
+        3,
+        0x00,
+        0x80,
+        data[0],
+        data[1],
+    ]
+}
+
+/// Sends readings as group telegrams, see the [module documentation](self).
+#[derive(Debug)]
+pub struct KnxSender {
+    socket: UdpSocket,
+    target: SocketAddr,
+    source: IndividualAddress,
+    groups: KnxGroups,
+}
+
+impl KnxSender {
+    /// Send to `groups` with KNXnet/IP routing, from the default [`IndividualAddress`].
+    pub fn new(groups: KnxGroups) -> io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind("0.0.0.0:0")?,
+            target: ROUTING_MULTICAST
+                .parse()
+                .expect("the routing multicast address is valid"),
+            source: IndividualAddress::default(),
+            groups,
+        })
+    }
+
+    /// Send telegrams from `source`.
+    pub fn with_source(self, source: IndividualAddress) -> Self {
+        Self { source, ..self }
+    }
+
+    /// Send to `target` instead of the routing multicast group, e.g. straight to a router in another subnet.
+    pub fn with_target(self, target: SocketAddr) -> Self {
+        Self { target, ..self }
+    }
+
+    /// Send the values of `reading` that have a group address.
+    pub fn send(&self, reading: &MonitorReading) -> io::Result<()> {
+        let values = [
+            (
+                self.groups.co2,
+                match reading.co2_value {
+                    Co2Value::Valid(ppm) => Some(f32::from(ppm)),
+                    _ => None,
+                },
+            ),
+            (self.groups.temperature, Some(reading.temperature)),
+            (self.groups.humidity, reading.humidity),
+        ];
+        for (group, value) in values {
+            if let (Some(group), Some(value)) = (group, value) {
+                let telegram = routing_indication(self.source, group, encode_dpt9(value));
+                self.socket.send_to(&telegram, self.target)?;
+            }
+        }
+        Ok(())
+    }
+}
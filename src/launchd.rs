@@ -0,0 +1,124 @@
+//! launchd agents, to run the logger at login on macOS.
+//!
+//! The plist is plain text, so [`agent_plist`] works everywhere. Installing it is macOS only.
+use std::{
+    format,
+    path::{Path, PathBuf},
+    string::String,
+};
+
+/// Label of the agent, which is also the name of its plist.
+pub const DEFAULT_LABEL: &str = "com.github.jsosulski.co2-monitor";
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A launchd agent that runs `program_arguments` (the first being the absolute path of the binary) at login in
+/// `working_directory`, restarts it when it exits, and appends its output to `log_file`.
+pub fn agent_plist(
+    label: &str,
+    program_arguments: &[String],
+    working_directory: &Path,
+    log_file: &Path,
+) -> String {
+    let arguments: String = program_arguments
+        .iter()
+        .map(|argument| format!("        <string>{}</string>\n", escape_xml(argument)))
+        .collect();
+    let log_file = escape_xml(&log_file.to_string_lossy());
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+{arguments}    </array>
+    <key>WorkingDirectory</key>
+    <string>{working_directory}</string>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <true/>
+    <key>ThrottleInterval</key>
+    <integer>30</integer>
+    <key>ProcessType</key>
+    <string>Background</string>
+    <key>StandardOutPath</key>
+    <string>{log_file}</string>
+    <key>StandardErrorPath</key>
+    <string>{log_file}</string>
+</dict>
+</plist>
+"#,
+        label = escape_xml(label),
+        working_directory = escape_xml(&working_directory.to_string_lossy()),
+    )
+}
+
+/// `~/Library/LaunchAgents/<label>.plist`.
+pub fn agent_path(label: &str) -> Option<PathBuf> {
+    let home = std::env::var_os("HOME")?;
+    Some(
+        Path::new(&home)
+            .join("Library/LaunchAgents")
+            .join(format!("{label}.plist")),
+    )
+}
+
+/// `~/Library/Logs/co2-monitor.log`.
+pub fn default_log_file() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME")?;
+    Some(Path::new(&home).join("Library/Logs/co2-monitor.log"))
+}
+
+/// Write the plist to [`agent_path`] and load it, which also starts the agent right away.
+#[cfg(target_os = "macos")]
+pub fn install_agent(label: &str, plist: &str) -> std::io::Result<PathBuf> {
+    use std::io;
+    let path = agent_path(label)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    if path.exists() {
+        // Reinstalling, stop the old agent first. Fails if it isn't loaded, which is fine.
+        let _ = launchctl(&["unload", "-w"], &path);
+    }
+    std::fs::write(&path, plist)?;
+    launchctl(&["load", "-w"], &path)?;
+    Ok(path)
+}
+
+/// Unload the agent and remove its plist.
+#[cfg(target_os = "macos")]
+pub fn uninstall_agent(label: &str) -> std::io::Result<PathBuf> {
+    use std::io;
+    let path = agent_path(label)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+    let _ = launchctl(&["unload", "-w"], &path);
+    std::fs::remove_file(&path)?;
+    Ok(path)
+}
+
+#[cfg(target_os = "macos")]
+fn launchctl(arguments: &[&str], plist: &Path) -> std::io::Result<()> {
+    let status = std::process::Command::new("launchctl")
+        .args(arguments)
+        .arg(plist)
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(format!(
+            "launchctl {} failed with {status}",
+            arguments.join(" ")
+        )))
+    }
+}
@@ -2,32 +2,200 @@
 #![doc = include_str!("../README.md")]
 #![no_std]
 #![warn(missing_docs)]
+#![warn(missing_debug_implementations)]
 
+#[cfg(any(
+    feature = "pc",
+    feature = "libusb",
+    feature = "hidraw",
+    feature = "wasm"
+))]
+extern crate std;
+
+/// Emits a `tracing` event with the `tracing` feature, and compiles to nothing (but still uses the arguments) without.
+/// Only plain format strings are supported, no structured fields. Defined before the modules so they can all use it.
+macro_rules! event {
+    ($level:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)+);
+        #[cfg(not(feature = "tracing"))]
+        let _ = format_args!($($arg)+);
+    }};
+}
+
+pub mod abc;
+pub mod actuator;
+pub mod advisor;
+pub mod aggregate;
+pub mod alert;
+pub mod anomaly;
+#[cfg(feature = "pc")]
+pub mod api;
+#[cfg(feature = "pc")]
+pub mod backend;
+pub mod baseline;
+pub mod cadence;
+#[cfg(feature = "pc")]
+pub mod capture;
+#[cfg(feature = "pc")]
+pub mod chart;
+pub mod clock;
+#[cfg(feature = "pc")]
+pub mod config;
+pub mod conversions;
+pub mod cross_check;
+#[cfg(all(target_os = "linux", feature = "dbus"))]
+pub mod dbus;
+pub mod dedup;
 pub mod device;
 #[cfg(feature = "pc")]
+pub mod diagnostics;
+#[cfg(feature = "embassy")]
+pub mod embassy;
+pub mod embedded;
+#[cfg(feature = "esphome")]
+pub mod esphome;
+#[cfg(all(windows, feature = "windows-service"))]
+pub mod event_log;
+#[cfg(feature = "pc")]
+pub mod export;
+#[cfg(any(feature = "pc", feature = "heapless"))]
+pub mod fanout;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "pc")]
+pub mod fleet;
+pub mod forecast;
+#[cfg(all(target_os = "linux", feature = "gpio"))]
+pub mod gpio;
+#[cfg(feature = "pc")]
+pub mod grafana;
+#[cfg(feature = "pc")]
+pub mod handle;
+pub mod health;
+#[cfg(all(target_os = "linux", feature = "hidraw"))]
+pub mod hidraw;
+pub mod history;
+#[cfg(feature = "pc")]
+pub mod homeassistant;
+#[cfg(feature = "pc")]
+pub mod hook;
+#[cfg(feature = "pc")]
+pub(crate) mod http;
+#[cfg(feature = "knx")]
+pub mod knx;
+#[cfg(feature = "pc")]
+pub mod launchd;
+#[cfg(feature = "libusb")]
+pub mod libusb;
+#[cfg(feature = "pc")]
+pub mod log_format;
+#[cfg(feature = "mdns")]
+pub mod mdns;
+#[cfg(feature = "pc")]
+pub mod modbus;
+pub mod notification;
+#[cfg(feature = "occupancy")]
+pub mod occupancy;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+#[cfg(feature = "pc")]
 pub mod pc;
+pub mod pipeline;
+pub mod policy;
+pub mod profiles;
+pub mod provenance;
+pub mod quality;
+#[cfg(feature = "pc")]
+pub mod report;
+pub mod sanity;
+pub mod simulate;
+#[cfg(feature = "pc")]
+pub mod sink;
+#[cfg(feature = "tts")]
+pub mod speech;
+#[cfg(feature = "pc")]
+pub mod spool;
+#[cfg(feature = "pc")]
+pub mod state;
+pub mod stats;
+#[cfg(feature = "pc")]
+pub mod status;
+#[cfg(feature = "pc")]
+pub mod stdio_json;
+#[cfg(feature = "pc")]
+pub mod switch;
+pub mod trend;
+pub mod units;
+pub mod validity;
+pub mod ventilation;
+pub mod warmup;
+#[cfg(feature = "wasm")]
+pub mod webhid;
+#[cfg(feature = "pc")]
+pub mod zabbix;
 
 #[doc(inline)]
-pub use device::{Co2MonitorCommunication, MonitorError};
+pub use clock::{Clock, TimestampedReading};
+#[doc(inline)]
+pub use conversions::Conversions;
+#[doc(inline)]
+pub use device::{Co2MonitorCommunication, Co2MonitorExt, MonitorError};
+#[doc(inline)]
+pub use health::MonitorHealth;
+#[doc(inline)]
+pub use provenance::Provenance;
+#[doc(inline)]
+pub use quality::{Co2Quality, QualityBoundaries};
+#[doc(inline)]
+pub use sanity::{SanityCheck, SanityCheckModel};
+#[doc(inline)]
+pub use units::TemperatureUnit;
+#[doc(inline)]
+pub use validity::ValidityModel;
 /// Contains the individual parts that can be read from the monitor.
 ///
 /// Use this to read from the device, and write whatever value is coming in, to this struct.
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct MonitorReadingParts {
     /// Temperature in degrees celsius if set.
     pub temperature: Option<f32>,
+    /// Temperature in hundredths of degrees celsius if set, see [`centi_celsius_from_raw`].
+    pub temperature_centi: Option<i32>,
     /// Co2 PPM if set.
     pub co2_value: Option<u16>,
     /// Co2 sanity check value if set.
-    pub co2_sanity_check: Option<u16>,
+    pub co2_sanity_check: Option<SanityCheck>,
+    /// Relative humidity in percent if set. Only models with a humidity sensor report this.
+    pub humidity: Option<f32>,
+    /// Model used to turn the raw sanity check report into `co2_sanity_check`. Not reset by `clear`.
+    pub sanity_model: SanityCheckModel,
+    /// Conversions applied to the raw reports, see [`Conversions`]. Not reset by `clear`.
+    pub conversions: Conversions,
+    /// Limits of plausible co2 values, see [`ValidityModel`]. When changing `conversions`, this usually wants
+    /// [`ValidityModel::for_conversions`] as well. Not reset by `clear`.
+    pub validity: ValidityModel,
 }
 
 /// Contains the read out values as u16, if the opcode was unknown, it was returned as well.
+///
+/// Besides temperature, co2 and the sanity check, other ZyAura devices send humidity and a couple of values whose
+/// meaning is not really known. These are still recognized, so they can be told apart from truly unknown opcodes.
 #[allow(missing_docs)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum MonitorReportRaw {
     Temperature(u16),
     Co2Value(u16),
     Co2SanityCheck(u16),
+    Humidity(u16),
+    DiagnosticC(u16),
+    DiagnosticD(u16),
+    DiagnosticO(u16),
+    Co2Unfiltered(u16),
     Unknown(u8, u16),
 }
 
@@ -37,6 +205,76 @@ pub const OPCODE_TEMPERATURE: u8 = 0x42;
 pub const OPCODE_CO2_VALUE: u8 = 0x50;
 /// Reported opcode when the HID report is a co2 sanity check value. (At least as far as i know)
 pub const OPCODE_CO2_SANITY_CHECK: u8 = 0x6e;
+/// Reported opcode when the HID report is a relative humidity in centi-percent. Only sent by models with a
+/// humidity sensor.
+pub const OPCODE_HUMIDITY: u8 = 0x41;
+/// Reported opcode ('C') of an undocumented diagnostic value.
+pub const OPCODE_DIAGNOSTIC_C: u8 = 0x43;
+/// Reported opcode ('D') of an undocumented diagnostic value.
+pub const OPCODE_DIAGNOSTIC_D: u8 = 0x44;
+/// Reported opcode ('O') of an undocumented diagnostic value.
+pub const OPCODE_DIAGNOSTIC_O: u8 = 0x4f;
+/// Reported opcode ('q') of what other projects believe to be the unfiltered co2 value.
+pub const OPCODE_CO2_UNFILTERED: u8 = 0x71;
+
+/// Convert a raw temperature report (in 1/16 Kelvin) to hundredths of degrees Celsius, using only integer math.
+pub const fn centi_celsius_from_raw(raw: u16) -> i32 {
+    // raw / 16 * 100 = raw * 25 / 4, rounded to the nearest value.
+    (raw as i32 * 25 + 2) / 4 - 27315
+}
+
+/// Reasons why an 8-byte HID report is not a valid frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FrameError {
+    /// The report doesn't contain the terminator byte in 5-th position: 0x0d.
+    MissingTerminatorByte,
+    /// Bytes 1, 2 and 3, don't sum to byte 4 (in the lowest byte).
+    ChecksumInvalid,
+}
+
+/// Validate a single 8-byte HID report and extract its opcode and value.
+///
+/// This is all the framing there is, so if you bring your own USB stack, read the report and pass it in here.
+pub fn parse_report(report: &[u8; 8]) -> Result<(u8, u16), FrameError> {
+    if report[4] != 0x0d {
+        return Err(FrameError::MissingTerminatorByte);
+    }
+    let checksum = (u16::from(report[0]) + u16::from(report[1]) + u16::from(report[2])) & 0xff;
+    if checksum as u8 != report[3] {
+        return Err(FrameError::ChecksumInvalid);
+    }
+
+    let op = report[0];
+    let val = (u16::from(report[1]) << 8) | u16::from(report[2]);
+    Ok((op, val))
+}
+
+impl TryFrom<&[u8; 8]> for MonitorReportRaw {
+    type Error = FrameError;
+
+    fn try_from(report: &[u8; 8]) -> Result<Self, Self::Error> {
+        parse_report(report).map(Self::from)
+    }
+}
+
+impl MonitorReportRaw {
+    /// The opcode this report was sent with.
+    pub fn opcode(&self) -> u8 {
+        match self {
+            Self::Temperature(_) => OPCODE_TEMPERATURE,
+            Self::Co2Value(_) => OPCODE_CO2_VALUE,
+            Self::Co2SanityCheck(_) => OPCODE_CO2_SANITY_CHECK,
+            Self::Humidity(_) => OPCODE_HUMIDITY,
+            Self::DiagnosticC(_) => OPCODE_DIAGNOSTIC_C,
+            Self::DiagnosticD(_) => OPCODE_DIAGNOSTIC_D,
+            Self::DiagnosticO(_) => OPCODE_DIAGNOSTIC_O,
+            Self::Co2Unfiltered(_) => OPCODE_CO2_UNFILTERED,
+            Self::Unknown(op, _) => *op,
+        }
+    }
+}
 
 impl From<(u8, u16)> for MonitorReportRaw {
     fn from((op, val): (u8, u16)) -> Self {
@@ -44,6 +282,11 @@ impl From<(u8, u16)> for MonitorReportRaw {
             OPCODE_TEMPERATURE => Self::Temperature(val),
             OPCODE_CO2_VALUE => Self::Co2Value(val),
             OPCODE_CO2_SANITY_CHECK => Self::Co2SanityCheck(val),
+            OPCODE_HUMIDITY => Self::Humidity(val),
+            OPCODE_DIAGNOSTIC_C => Self::DiagnosticC(val),
+            OPCODE_DIAGNOSTIC_D => Self::DiagnosticD(val),
+            OPCODE_DIAGNOSTIC_O => Self::DiagnosticO(val),
+            OPCODE_CO2_UNFILTERED => Self::Co2Unfiltered(val),
             _ => Self::Unknown(op, val),
         }
     }
@@ -52,13 +295,31 @@ impl From<(u8, u16)> for MonitorReportRaw {
 impl MonitorReadingParts {
     /// Given the opcode as `u8` and the value as `u16`, sets the corresponding field.
     pub fn set_op_val(&mut self, op: u8, val: u16) {
+        self.set_op_val_with(op, val, &mut |_| ());
+    }
+
+    /// Like `set_op_val`, but passes every decoded report to `observer` first, including `Unknown` ones that would
+    /// otherwise be dropped silently. Useful for logging raw reports when reverse-engineering other opcodes.
+    pub fn set_op_val_with(
+        &mut self,
+        op: u8,
+        val: u16,
+        observer: &mut impl FnMut(MonitorReportRaw),
+    ) {
         let raw_report = MonitorReportRaw::from((op, val));
+        observer(raw_report);
 
         match raw_report {
             MonitorReportRaw::Temperature(val) => {
-                let temperature_in_kelvin = f32::from(val) / 16.0;
-                let temperature_in_c = temperature_in_kelvin - 273.15;
-                self.temperature = Some(temperature_in_c);
+                let centi = centi_celsius_from_raw(val);
+                self.temperature = Some(match self.conversions {
+                    Conversions::Legacy => {
+                        let temperature_in_kelvin = f32::from(val) / 16.0;
+                        temperature_in_kelvin - 273.15
+                    }
+                    Conversions::V2 => centi as f32 / 100.0,
+                });
+                self.temperature_centi = Some(centi);
             }
             MonitorReportRaw::Co2Value(val) => {
                 self.co2_value = Some(val);
@@ -66,29 +327,50 @@ impl MonitorReadingParts {
             MonitorReportRaw::Co2SanityCheck(val) => {
                 // For very large values, sometimes the "actual" co2 code simply reports 1065, even though
                 // the diplay indicates "HI". However, there's a second number that decreases with in-
-                // creasing CO2 values. It is not quite 1:1, there is some small-ish factor involved,
-                // but for now this offset should be enough.
-                const MAGIC_OFFSET_THAT_NEEDS_BETTER_ESTIMATE: u16 = 12811;
-                self.co2_sanity_check = Some(MAGIC_OFFSET_THAT_NEEDS_BETTER_ESTIMATE - val);
+                // creasing CO2 values. See `SanityCheckModel` for how it is turned into ppm.
+                self.co2_sanity_check = Some(self.sanity_model.classify(val));
+            }
+            MonitorReportRaw::Humidity(val) => {
+                self.humidity = Some(f32::from(val) / 100.0);
             }
-            MonitorReportRaw::Unknown(_, _) => (),
+            // These are only passed to the observer, nothing in a reading depends on them (yet).
+            MonitorReportRaw::DiagnosticC(_)
+            | MonitorReportRaw::DiagnosticD(_)
+            | MonitorReportRaw::DiagnosticO(_)
+            | MonitorReportRaw::Co2Unfiltered(_)
+            | MonitorReportRaw::Unknown(_, _) => (),
         }
     }
 
     /// If all values are available, returns a complete `MonitorReading`. Otherwise returns `None`.
     pub fn to_reading(&mut self) -> Option<MonitorReading> {
-        if let (Some(t), Some(c), Some(cs)) =
-            (self.temperature, self.co2_value, self.co2_sanity_check)
-        {
-            const SPEC_MAX_CO2_THRESHOLD: u16 = 3000;
-            let co2_value = if cs > SPEC_MAX_CO2_THRESHOLD || c > SPEC_MAX_CO2_THRESHOLD {
-                Co2Value::TooHigh(c)
+        if let (Some(t), Some(tc), Some(c), Some(cs)) = (
+            self.temperature,
+            self.temperature_centi,
+            self.co2_value,
+            self.co2_sanity_check,
+        ) {
+            let sanity_estimate = match cs {
+                SanityCheck::InRange(estimate) => Some(estimate),
+                SanityCheck::OutOfModel(raw) => match self.conversions {
+                    // If the model can't explain the sanity check, we can't tell whether the co2 value is fine.
+                    Conversions::Legacy => None,
+                    // Beyond the zero point the estimate saturates at 0 ppm, which is certainly not too high.
+                    Conversions::V2 => Some(self.sanity_model.saturating_estimate(raw)),
+                },
+            };
+            let co2_value = self.validity.classify(c, sanity_estimate);
+            let provenance = if self.sanity_model == SanityCheckModel::default() {
+                Provenance::RAW
             } else {
-                Co2Value::Valid(c)
+                Provenance::CALIBRATED
             };
             let mr = MonitorReading {
                 temperature: t,
+                temperature_centi: tc,
                 co2_value,
+                humidity: self.humidity,
+                provenance,
             };
             self.clear();
             return Some(mr);
@@ -98,18 +380,30 @@ impl MonitorReadingParts {
 
     /// Create a new container with no values set.
     pub fn new() -> Self {
+        Self::with_sanity_model(SanityCheckModel::default())
+    }
+
+    /// Create a new container with no values set, that uses `sanity_model` to interpret the sanity check reports.
+    pub fn with_sanity_model(sanity_model: SanityCheckModel) -> Self {
         Self {
             temperature: None,
+            temperature_centi: None,
             co2_value: None,
             co2_sanity_check: None,
+            humidity: None,
+            sanity_model,
+            conversions: Conversions::DEFAULT,
+            validity: ValidityModel::for_conversions(Conversions::DEFAULT),
         }
     }
 
     /// Reset all values.
     pub fn clear(&mut self) {
         self.temperature = None;
+        self.temperature_centi = None;
         self.co2_value = None;
         self.co2_sanity_check = None;
+        self.humidity = None;
     }
 }
 
@@ -121,11 +415,19 @@ impl Default for MonitorReadingParts {
 
 /// A complete reading from the co2 monitor device.
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct MonitorReading {
     /// Temperature in degrees Celsius.
     pub temperature: f32,
+    /// Temperature in hundredths of degrees Celsius, computed without any float math. For targets without an FPU.
+    pub temperature_centi: i32,
     /// A valid/invalid co2 reading in ppm.
     pub co2_value: Co2Value,
+    /// Relative humidity in percent, if the device has a humidity sensor.
+    pub humidity: Option<f32>,
+    /// Processing applied to this reading. Mark your own processing with [`Provenance::insert`].
+    pub provenance: Provenance,
 }
 
 impl MonitorReading {
@@ -133,8 +435,69 @@ impl MonitorReading {
     pub fn new() -> MonitorReading {
         Self {
             temperature: 0.0,
+            temperature_centi: 0,
             co2_value: Co2Value::TooHigh(0),
+            humidity: None,
+            provenance: Provenance::RAW,
+        }
+    }
+}
+
+impl MonitorReading {
+    /// Temperature in degrees Fahrenheit.
+    pub fn temperature_fahrenheit(&self) -> f32 {
+        TemperatureUnit::Fahrenheit.from_celsius(self.temperature)
+    }
+
+    /// Temperature in Kelvin.
+    pub fn temperature_kelvin(&self) -> f32 {
+        TemperatureUnit::Kelvin.from_celsius(self.temperature)
+    }
+
+    /// Air quality category of the co2 value, with the default boundaries.
+    pub fn quality(&self) -> Co2Quality {
+        QualityBoundaries::DEFAULT.classify(self.co2_value)
+    }
+
+    /// Air quality category of the co2 value, with custom boundaries.
+    pub fn quality_with(&self, boundaries: &QualityBoundaries) -> Co2Quality {
+        boundaries.classify(self.co2_value)
+    }
+
+    /// Temperature in the given unit.
+    pub fn temperature_in(&self, unit: TemperatureUnit) -> f32 {
+        unit.from_celsius(self.temperature)
+    }
+
+    /// Display the reading with the temperature in `unit`. Like the plain `Display` impl, but the unit symbol is
+    /// appended for anything other than Celsius.
+    pub fn display_in(&self, unit: TemperatureUnit) -> DisplayIn<'_> {
+        DisplayIn {
+            reading: self,
+            unit,
+        }
+    }
+}
+
+/// Displays a reading in a specific temperature unit, see [`MonitorReading::display_in`].
+#[derive(Debug)]
+pub struct DisplayIn<'a> {
+    reading: &'a MonitorReading,
+    unit: TemperatureUnit,
+}
+
+impl core::fmt::Display for DisplayIn<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let reading = self.reading;
+        write!(f, "T:{:.1}", reading.temperature_in(self.unit))?;
+        if self.unit != TemperatureUnit::Celsius {
+            f.write_str(self.unit.symbol())?;
         }
+        write!(f, " Co2:{}", reading.co2_value)?;
+        if let Some(humidity) = reading.humidity {
+            write!(f, " H:{:.1}", humidity)?;
+        }
+        Ok(())
     }
 }
 
@@ -146,18 +509,27 @@ impl Default for MonitorReading {
 
 impl core::fmt::Display for MonitorReading {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "T:{:.1} Co2:{}", self.temperature, self.co2_value)
+        self.display_in(TemperatureUnit::Celsius).fmt(f)
     }
 }
 
 /// A Co2Value that knows whether it is/was out of spec.
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Co2Value {
     /// A valid Co2 reading
     Valid(u16),
     /// The sanity check variable indicated that this reading was too high. You might still want to see the actually
     /// read value tho. You do you.
     TooHigh(u16),
+    /// The sensor is still warming up after being plugged in, and the value (often 0) means nothing yet. Only set by
+    /// [`WarmUp`](warmup::WarmUp), the device itself doesn't tell.
+    WarmingUp(u16),
+    /// Implausibly low, e.g. while the sensor is failing. See [`ValidityModel`].
+    TooLow(u16),
+    /// The sensor reported 0 ppm after its warm-up, i.e. it isn't measuring at all.
+    SensorFault(u16),
 }
 
 impl Co2Value {
@@ -166,6 +538,20 @@ impl Co2Value {
         match self {
             Co2Value::Valid(n) => (*n, true),
             Co2Value::TooHigh(n) => (*n, false),
+            Co2Value::WarmingUp(n) => (*n, false),
+            Co2Value::TooLow(n) => (*n, false),
+            Co2Value::SensorFault(n) => (*n, false),
+        }
+    }
+
+    /// The same kind of value, with another ppm, e.g. for the mean of several readings.
+    pub fn with_ppm(self, ppm: u16) -> Self {
+        match self {
+            Co2Value::Valid(_) => Co2Value::Valid(ppm),
+            Co2Value::TooHigh(_) => Co2Value::TooHigh(ppm),
+            Co2Value::WarmingUp(_) => Co2Value::WarmingUp(ppm),
+            Co2Value::TooLow(_) => Co2Value::TooLow(ppm),
+            Co2Value::SensorFault(_) => Co2Value::SensorFault(ppm),
         }
     }
 }
@@ -175,6 +561,9 @@ impl core::fmt::Display for Co2Value {
         match self {
             Co2Value::Valid(n) => write!(f, "{}", n),
             Co2Value::TooHigh(_) => f.write_str("too high"),
+            Co2Value::WarmingUp(_) => f.write_str("warming up"),
+            Co2Value::TooLow(_) => f.write_str("too low"),
+            Co2Value::SensorFault(_) => f.write_str("sensor fault"),
         }
     }
 }
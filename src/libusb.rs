@@ -0,0 +1,147 @@
+//! Implement the Co2 monitor communication with libusb (through `rusb`), for applications that can't use hidapi.
+//!
+//! The monitor is a plain HID device, so libusb can talk to it directly: the feature report is a HID `SET_REPORT`
+//! control transfer, and the reports arrive on the interrupt IN endpoint. On linux the kernel's HID driver is detached
+//! while the interface is claimed, and attached again when the monitor is dropped.
+//!
+//! [`UsbCo2Monitor::open_with_profile`] opens other models, and sends the key of their [`DeviceProfile`] for the
+//! encrypting ones. Like with hidapi, encrypted reports are noticed even if the profile says there are none, see
+//! [`EncryptionDetector`].
+use crate::{
+    device::{Co2MonitorCommunication, MonitorError},
+    profiles::{DeviceProfile, EncryptionDetector},
+};
+use rusb::{Direction, GlobalContext, TransferType};
+use std::time::Duration;
+
+/// `bmRequestType` of a class request to an interface, host to device.
+const REQUEST_TYPE_CLASS_INTERFACE_OUT: u8 = 0x21;
+/// The HID `SET_REPORT` request.
+const HID_SET_REPORT: u8 = 0x09;
+/// Report type feature (3) in the high byte, report ID 0 in the low byte.
+const FEATURE_REPORT_ID_0: u16 = 0x0300;
+/// Endpoint of the reports, if the descriptors don't say otherwise.
+const DEFAULT_ENDPOINT: u8 = 0x81;
+
+/// Talks to the monitor with libusb instead of hidapi.
+#[derive(Debug)]
+pub struct UsbCo2Monitor {
+    handle: rusb::DeviceHandle<GlobalContext>,
+    interface: u8,
+    endpoint: u8,
+    profile: DeviceProfile,
+    encryption: EncryptionDetector,
+}
+
+impl UsbCo2Monitor {
+    /// Open the first monitor with the default VID/PID, claim its HID interface and send the feature report.
+    pub fn open() -> Result<Self, rusb::Error> {
+        Self::open_with_profile(DeviceProfile::default())
+    }
+
+    /// Like `open`, but with the ids and the key of `profile`.
+    pub fn open_with_profile(profile: DeviceProfile) -> Result<Self, rusb::Error> {
+        let handle = rusb::open_device_with_vid_pid(profile.vendor_id, profile.product_id)
+            .ok_or(rusb::Error::NoDevice)?;
+        let (interface, endpoint) = interrupt_in_endpoint(&handle.device())?;
+        // Not supported everywhere (e.g. on macOS and windows there's no kernel driver to detach), which is fine.
+        let _ = handle.set_auto_detach_kernel_driver(true);
+        handle.claim_interface(interface)?;
+
+        // hidapi sends the report ID as first byte, on the wire it's only in `wValue`.
+        let feature_report = &profile.feature_report()[1..];
+        handle.write_control(
+            REQUEST_TYPE_CLASS_INTERFACE_OUT,
+            HID_SET_REPORT,
+            FEATURE_REPORT_ID_0,
+            u16::from(interface),
+            feature_report,
+            Duration::from_secs(1),
+        )?;
+        event!(
+            info,
+            "connected to {:04x}:{:04x} with libusb, endpoint {:#04x}",
+            profile.vendor_id,
+            profile.product_id,
+            endpoint
+        );
+        Ok(Self {
+            handle,
+            interface,
+            endpoint,
+            profile,
+            encryption: EncryptionDetector::new(profile),
+        })
+    }
+
+    /// The profile the monitor was opened with.
+    pub fn profile(&self) -> &DeviceProfile {
+        &self.profile
+    }
+
+    /// Whether the reports are decrypted, because the profile says so or because they turned out to be encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption.is_encrypted()
+    }
+}
+
+/// The first interrupt IN endpoint of the active configuration, and its interface.
+fn interrupt_in_endpoint(device: &rusb::Device<GlobalContext>) -> Result<(u8, u8), rusb::Error> {
+    let config = device.active_config_descriptor()?;
+    let found = config
+        .interfaces()
+        .flat_map(|interface| interface.descriptors())
+        .find_map(|descriptor| {
+            descriptor
+                .endpoint_descriptors()
+                .find(|endpoint| {
+                    endpoint.direction() == Direction::In
+                        && endpoint.transfer_type() == TransferType::Interrupt
+                })
+                .map(|endpoint| (descriptor.interface_number(), endpoint.address()))
+        });
+    Ok(found.unwrap_or((0, DEFAULT_ENDPOINT)))
+}
+
+impl Drop for UsbCo2Monitor {
+    fn drop(&mut self) {
+        let _ = self.handle.release_interface(self.interface);
+    }
+}
+
+impl Co2MonitorCommunication for UsbCo2Monitor {
+    fn init_and_connect() -> Self {
+        Self::open().expect(
+            "Unable to open the USB device with libusb. Is it connected to this computer? Do you have sufficient \
+             permissions?",
+        )
+    }
+
+    fn read(&self, read_buffer: &mut [u8; 8]) -> Result<usize, MonitorError> {
+        self.read_timeout(read_buffer, 1000)
+    }
+
+    fn read_timeout(
+        &self,
+        read_buffer: &mut [u8; 8],
+        timeout_ms: u32,
+    ) -> Result<usize, MonitorError> {
+        match self.handle.read_interrupt(
+            self.endpoint,
+            read_buffer,
+            Duration::from_millis(u64::from(timeout_ms)),
+        ) {
+            Ok(n) => {
+                if n == read_buffer.len() {
+                    self.encryption.decrypt(read_buffer);
+                }
+                Ok(n)
+            }
+            Err(rusb::Error::Timeout) => Ok(0),
+            Err(e) => {
+                event!(debug, "libusb read failed: {}", e);
+                Err(MonitorError::ReadFailed)
+            }
+        }
+    }
+}
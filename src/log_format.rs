@@ -0,0 +1,437 @@
+//! The csv format written by the CLI logger, and a streaming reader for it.
+//!
+//! The reader never loads a whole file, rows are deserialized one at a time while iterating. This keeps memory usage
+//! constant, so even year-long logs can be processed on the Raspberry Pi that recorded them.
+//!
+//! New logs start with a comment line with the [`SCHEMA_VERSION`], logs without it are version 1. Columns are only
+//! ever added, so the reader handles every version: columns it doesn't know are ignored, and columns a log doesn't have
+//! read as their default. The extended columns (humidity, device, quality, the raw sanity check value and, since
+//! version 5, the deviation from the typical co2 of the hour, since version 6 the sequence number) are only written
+//! when asked for, see [`LogWriter`].
+//!
+//! Besides the wall clock time, the logger records milliseconds since it started (`monotonic_ms`). On a Raspberry Pi
+//! without a real time clock the wall clock jumps once NTP syncs after boot, the monotonic time doesn't, which is what
+//! [`ClockCorrection`](crate::export::ClockCorrection) uses to fix the timestamps of early readings.
+//!
+//! `co2_is_valid` only tells whether co2 was valid, the `co2_state` column of version 4 also tells why it wasn't (see
+//! [`Co2State`]). Invalid rows of older logs read as too high, the only reason there was back then.
+use crate::{Co2Quality, Co2Value, MonitorReading, Provenance};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Write},
+    path::Path,
+    string::{String, ToString},
+};
+
+/// Default name of the log file, relative to the working directory.
+pub const DEFAULT_LOG_NAME: &str = "log.csv";
+
+/// Version of the log layout. 2 added the schema comment and the extended columns, 3 the monotonic time, 4 the co2
+/// state, 5 the co2 deviation, 6 the sequence number.
+pub const SCHEMA_VERSION: u32 = 6;
+
+/// Which kind of [`Co2Value`] a row has, the `co2_state` column.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+#[serde(rename_all = "snake_case")]
+pub enum Co2State {
+    /// [`Co2Value::Valid`].
+    Valid,
+    /// [`Co2Value::TooHigh`].
+    TooHigh,
+    /// [`Co2Value::WarmingUp`].
+    WarmingUp,
+    /// [`Co2Value::TooLow`].
+    TooLow,
+    /// [`Co2Value::SensorFault`].
+    SensorFault,
+}
+
+impl Co2State {
+    /// The state of `value`.
+    pub fn of(value: Co2Value) -> Self {
+        match value {
+            Co2Value::Valid(_) => Self::Valid,
+            Co2Value::TooHigh(_) => Self::TooHigh,
+            Co2Value::WarmingUp(_) => Self::WarmingUp,
+            Co2Value::TooLow(_) => Self::TooLow,
+            Co2Value::SensorFault(_) => Self::SensorFault,
+        }
+    }
+
+    /// A co2 value of this state with `ppm`.
+    pub fn with_ppm(self, ppm: u16) -> Co2Value {
+        match self {
+            Self::Valid => Co2Value::Valid(ppm),
+            Self::TooHigh => Co2Value::TooHigh(ppm),
+            Self::WarmingUp => Co2Value::WarmingUp(ppm),
+            Self::TooLow => Co2Value::TooLow(ppm),
+            Self::SensorFault => Co2Value::SensorFault(ppm),
+        }
+    }
+}
+
+impl From<Co2Value> for Co2State {
+    fn from(value: Co2Value) -> Self {
+        Self::of(value)
+    }
+}
+
+/// The name in the `co2_state` column, e.g. `too_high`.
+impl core::fmt::Display for Co2State {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Self::Valid => "valid",
+            Self::TooHigh => "too_high",
+            Self::WarmingUp => "warming_up",
+            Self::TooLow => "too_low",
+            Self::SensorFault => "sensor_fault",
+        })
+    }
+}
+
+/// A single row of the csv log.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Row {
+    /// Local time at which the reading was completed.
+    pub timestamp: NaiveDateTime,
+    /// Temperature in degrees Celsius.
+    pub temperature: f32,
+    /// Co2 in ppm, also set when the reading was out of spec.
+    pub co2_ppm: usize,
+    /// Whether `co2_ppm` was within spec.
+    pub co2_is_valid: bool,
+    /// Processing applied to the reading. Logs written before this column existed read as raw.
+    #[serde(default)]
+    pub provenance: Provenance,
+    /// Room of the monitor, in logs of several monitors (see [`crate::fleet::Fleet`]). The column is left out
+    /// otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub room: Option<String>,
+    /// Relative humidity in percent, in extended logs of monitors with a humidity sensor.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub humidity: Option<f32>,
+    /// Serial number or path of the monitor (or the backend, if it isn't a device), in extended logs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    /// Air quality of the reading with the default boundaries, in extended logs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quality: Option<Co2Quality>,
+    /// The last raw sanity check report before the reading, in extended logs. See [`crate::sanity`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sanity_raw: Option<u16>,
+    /// Milliseconds since the logger started, which unlike `timestamp` never jumps. Logs before schema version 3 don't
+    /// have it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monotonic_ms: Option<u64>,
+    /// Why co2 was invalid, or that it was valid. Logs before schema version 4 don't have it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub co2_state: Option<Co2State>,
+    /// Ppm above (or below) the typical co2 of the hour, in extended logs, once the logger learned what's typical. See
+    /// [`crate::baseline`]. Logs before schema version 5 don't have it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub co2_deviation: Option<f32>,
+    /// The [sequence number](crate::TimestampedReading::sequence) of the reading, in extended logs. Gaps are readings
+    /// the pipeline dropped, and it starts from 0 again when the logger restarts. Logs before schema version 6 don't
+    /// have it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u32>,
+}
+
+impl Row {
+    /// Create a row from a complete reading.
+    pub fn from_reading(reading: &MonitorReading, timestamp: NaiveDateTime) -> Self {
+        let (ppm, valid) = reading.co2_value.as_num_and_bool();
+        Self {
+            timestamp,
+            temperature: reading.temperature,
+            co2_ppm: ppm as usize,
+            co2_is_valid: valid,
+            provenance: reading.provenance,
+            room: None,
+            humidity: None,
+            device: None,
+            quality: None,
+            sanity_raw: None,
+            monotonic_ms: None,
+            co2_state: Some(Co2State::of(reading.co2_value)),
+            co2_deviation: None,
+            sequence: None,
+        }
+    }
+
+    /// Record the monotonic time of the row, see [`Row::monotonic_ms`].
+    pub fn with_monotonic_ms(mut self, monotonic_ms: u64) -> Self {
+        self.monotonic_ms = Some(monotonic_ms);
+        self
+    }
+
+    /// Fill the extended columns: humidity and quality from `reading`, plus the `device` it came from and the raw
+    /// sanity check value, if there was one.
+    pub fn with_extended(
+        mut self,
+        reading: &MonitorReading,
+        device: &str,
+        sanity_raw: Option<u16>,
+    ) -> Self {
+        self.humidity = reading.humidity;
+        self.device = Some(device.to_string());
+        self.quality = Some(reading.quality());
+        self.sanity_raw = sanity_raw;
+        self
+    }
+
+    /// Record the deviation from the typical co2 of the hour, see [`Row::co2_deviation`].
+    pub fn with_deviation(mut self, co2_deviation: Option<f32>) -> Self {
+        self.co2_deviation = co2_deviation;
+        self
+    }
+
+    /// Record the sequence number of the reading, see [`Row::sequence`].
+    pub fn with_sequence(mut self, sequence: u32) -> Self {
+        self.sequence = Some(sequence);
+        self
+    }
+
+    /// Tag the row with a room.
+    pub fn with_room(mut self, room: &str) -> Self {
+        self.room = Some(room.to_string());
+        self
+    }
+
+    /// The reading this row was created from, as far as the log has it. Humidity is only there in extended logs, and
+    /// invalid co2 without the co2 state is too high.
+    pub fn to_reading(&self) -> MonitorReading {
+        let ppm = u16::try_from(self.co2_ppm).unwrap_or(u16::MAX);
+        let state = self.co2_state.unwrap_or(if self.co2_is_valid {
+            Co2State::Valid
+        } else {
+            Co2State::TooHigh
+        });
+        MonitorReading {
+            temperature: self.temperature,
+            temperature_centi: (self.temperature * 100.0).round() as i32,
+            co2_value: state.with_ppm(ppm),
+            humidity: self.humidity,
+            provenance: self.provenance,
+        }
+    }
+}
+
+/// A column that may or may not be part of the layout of a log. Unlike with a plain `Option`, a column that is part of
+/// the layout is always written, empty if there's no value, so every row has the same number of columns.
+enum Column<T> {
+    Absent,
+    Present(Option<T>),
+}
+
+impl<T> Column<T> {
+    fn new(present: bool, value: Option<T>) -> Self {
+        if present {
+            Self::Present(value)
+        } else {
+            Self::Absent
+        }
+    }
+
+    fn is_absent(&self) -> bool {
+        matches!(self, Self::Absent)
+    }
+}
+
+impl<T: Serialize> Serialize for Column<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Absent => serializer.serialize_none(),
+            Self::Present(value) => value.serialize(serializer),
+        }
+    }
+}
+
+/// A [`Row`] in the layout of a particular log. The room is only there in logs of several monitors, and then in every
+/// row.
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    timestamp: NaiveDateTime,
+    temperature: f32,
+    co2_ppm: usize,
+    co2_is_valid: bool,
+    #[serde(skip_serializing_if = "Column::is_absent")]
+    provenance: Column<Provenance>,
+    #[serde(skip_serializing_if = "Column::is_absent")]
+    room: Column<&'a str>,
+    #[serde(skip_serializing_if = "Column::is_absent")]
+    humidity: Column<f32>,
+    #[serde(skip_serializing_if = "Column::is_absent")]
+    device: Column<&'a str>,
+    #[serde(skip_serializing_if = "Column::is_absent")]
+    quality: Column<Co2Quality>,
+    #[serde(skip_serializing_if = "Column::is_absent")]
+    sanity_raw: Column<u16>,
+    #[serde(skip_serializing_if = "Column::is_absent")]
+    monotonic_ms: Column<u64>,
+    #[serde(skip_serializing_if = "Column::is_absent")]
+    co2_state: Column<Co2State>,
+    #[serde(skip_serializing_if = "Column::is_absent")]
+    co2_deviation: Column<f32>,
+    #[serde(skip_serializing_if = "Column::is_absent")]
+    sequence: Column<u32>,
+}
+
+/// Writes [`Row`]s to a log, starting new logs with the schema comment and the header.
+pub struct LogWriter<W: Write> {
+    writer: csv::Writer<W>,
+    provenance: bool,
+    // `None` in new logs, which have the room if the rows have one.
+    room: Option<bool>,
+    extended: bool,
+    monotonic: bool,
+    state: bool,
+    deviation: bool,
+    sequence: bool,
+}
+
+impl LogWriter<File> {
+    /// Append to the log at `path`, or create it. An existing log keeps its layout, so `extended` only applies to new
+    /// logs, check [`extended`](Self::extended) for what is actually written. The same goes for every other column
+    /// that was added later, see [`without_header`](Self::without_header).
+    pub fn append(path: impl AsRef<Path>, extended: bool) -> io::Result<Self> {
+        let path = path.as_ref();
+        let header = match File::open(path) {
+            Ok(file) => BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                .find(|line| !line.starts_with('#')),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        match header {
+            Some(header) => Ok(Self::without_header(file, &header)),
+            None => Self::new(file, extended),
+        }
+    }
+}
+
+impl<W: Write> LogWriter<W> {
+    /// Start a new log in `writer`, with the extended columns or without.
+    pub fn new(mut writer: W, extended: bool) -> io::Result<Self> {
+        writeln!(writer, "# co2-monitor log, schema version {SCHEMA_VERSION}")?;
+        Ok(Self {
+            writer: csv::WriterBuilder::new().from_writer(writer),
+            provenance: true,
+            room: None,
+            extended,
+            monotonic: true,
+            state: true,
+            deviation: extended,
+            sequence: extended,
+        })
+    }
+
+    /// Continue a log in `writer` whose header line is `header`. Only the columns of the header are written, so rows
+    /// always line up with it, whichever version of the logger started the log.
+    pub fn without_header(writer: W, header: &str) -> Self {
+        let has = |name: &str| header.split(',').any(|column| column.trim() == name);
+        Self {
+            writer: csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(writer),
+            provenance: has("provenance"),
+            room: Some(has("room")),
+            extended: has("quality"),
+            monotonic: has("monotonic_ms"),
+            state: has("co2_state"),
+            deviation: has("co2_deviation"),
+            sequence: has("sequence"),
+        }
+    }
+
+    /// Whether the extended columns are written.
+    pub fn extended(&self) -> bool {
+        self.extended
+    }
+
+    /// Write a single row. Columns that aren't part of the log are dropped.
+    pub fn write(&mut self, row: &Row) -> csv::Result<()> {
+        self.writer.serialize(CsvRow {
+            timestamp: row.timestamp,
+            temperature: row.temperature,
+            co2_ppm: row.co2_ppm,
+            co2_is_valid: row.co2_is_valid,
+            provenance: Column::new(self.provenance, Some(row.provenance)),
+            room: Column::new(self.room.unwrap_or(row.room.is_some()), row.room.as_deref()),
+            humidity: Column::new(self.extended, row.humidity),
+            device: Column::new(self.extended, row.device.as_deref()),
+            quality: Column::new(self.extended, row.quality),
+            sanity_raw: Column::new(self.extended, row.sanity_raw),
+            monotonic_ms: Column::new(self.monotonic, row.monotonic_ms),
+            co2_state: Column::new(self.state, row.co2_state),
+            co2_deviation: Column::new(self.deviation, row.co2_deviation),
+            sequence: Column::new(self.sequence, row.sequence),
+        })
+    }
+
+    /// Flush the csv writer's buffer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> core::fmt::Debug for LogWriter<W> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("LogWriter")
+            .field("provenance", &self.provenance)
+            .field("room", &self.room)
+            .field("extended", &self.extended)
+            .field("monotonic", &self.monotonic)
+            .field("state", &self.state)
+            .field("deviation", &self.deviation)
+            .field("sequence", &self.sequence)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Iterates over the rows of a csv log without reading the whole file into memory.
+pub struct LogReader<R: Read> {
+    rows: csv::DeserializeRecordsIntoIter<R, Row>,
+}
+
+impl LogReader<File> {
+    /// Open the log at `path`.
+    pub fn open(path: impl AsRef<Path>) -> csv::Result<Self> {
+        Ok(Self::from_reader(File::open(path)?))
+    }
+}
+
+impl<R: Read> LogReader<R> {
+    /// Read a log from anything implementing `Read`. The csv reader does its own buffering.
+    ///
+    /// Rows may have more columns than the header, which older loggers wrote when appending to a log of an older
+    /// version. The extra columns are ignored, and so is the schema comment.
+    pub fn from_reader(reader: R) -> Self {
+        Self {
+            rows: csv::ReaderBuilder::new()
+                .flexible(true)
+                .comment(Some(b'#'))
+                .from_reader(reader)
+                .into_deserialize(),
+        }
+    }
+}
+
+impl<R: Read> Iterator for LogReader<R> {
+    type Item = csv::Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rows.next()
+    }
+}
+
+impl<R: Read> core::fmt::Debug for LogReader<R> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("LogReader").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,470 @@
+//! Advertise the [API](crate::api) with multicast DNS, so dashboards and apps on the LAN find it without an address.
+//!
+//! [`MdnsAdvertiser`] is a minimal mDNS responder (RFC 6762) for DNS service discovery (RFC 6763) of a single service
+//! of type [`SERVICE_TYPE`]. It announces the service when it starts and whenever its TXT record changes, and answers
+//! queries for the service type, the instance and the host name. The host name is that of the machine, with the
+//! address the service is reachable at, and the TXT record carries whatever [`MdnsAdvertiser::set_txt`] was given,
+//! e.g. the model and serial number of the monitor.
+//!
+//! Port 5353 is usually taken by the responder of the system (Avahi, Bonjour). On Linux, the socket is shared with it
+//! like Avahi shares it with others, elsewhere binding fails if the port is taken. Use the system's tools to publish
+//! the service then, e.g. `dns-sd -R`.
+//!
+//! On Linux, `avahi-browse -r _co2monitor._tcp` shows what is advertised.
+use std::{
+    format, io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    string::{String, ToString},
+    sync::{Arc, Mutex},
+    time::Duration,
+    vec::Vec,
+};
+
+/// The service type, in the `.local` domain.
+pub const SERVICE_TYPE: &str = "_co2monitor._tcp.local";
+
+/// The mDNS multicast group and port.
+const GROUP: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(224, 0, 0, 251), 5353);
+
+/// Where browsers ask for every service type.
+const SERVICES: &str = "_services._dns-sd._udp.local";
+
+// Record types and classes.
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// Set on records only this responder has, so caches replace older ones.
+const CACHE_FLUSH: u16 = 0x8000;
+/// Set on questions that want a unicast answer.
+const UNICAST_RESPONSE: u16 = 0x8000;
+
+/// Time to live of the records with the host name, and of all others, in seconds (see RFC 6762, section 10).
+const HOST_TTL: u32 = 120;
+const OTHER_TTL: u32 = 4500;
+/// Time to live of all records in answers to resolvers that aren't mDNS.
+const LEGACY_TTL: u32 = 10;
+
+/// What is advertised.
+#[derive(Clone, Debug)]
+struct Service {
+    instance: String,
+    host: String,
+    address: Ipv4Addr,
+    port: u16,
+    txt: Vec<(String, String)>,
+}
+
+/// Records of a response, as a set.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+struct Records {
+    services: bool,
+    ptr: bool,
+    srv: bool,
+    txt: bool,
+    a: bool,
+}
+
+impl Records {
+    fn all() -> Self {
+        Self {
+            services: false,
+            ptr: true,
+            srv: true,
+            txt: true,
+            a: true,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Advertises a service in the background, see the [module documentation](self). Dropping it says goodbye, so
+/// browsers forget the service right away.
+#[derive(Debug)]
+pub struct MdnsAdvertiser {
+    socket: UdpSocket,
+    service: Arc<Mutex<Service>>,
+}
+
+impl MdnsAdvertiser {
+    /// Advertise the instance `instance` of [`SERVICE_TYPE`] at `address`, with `txt` as `key=value` pairs. An
+    /// unspecified address (`0.0.0.0`) is replaced by the one of the interface multicasts go out on. Dots in `instance`
+    /// become dashes.
+    pub fn start(instance: &str, address: SocketAddr, txt: &[(&str, &str)]) -> io::Result<Self> {
+        let ip = match address.ip() {
+            IpAddr::V4(ip) if ip.is_unspecified() => outgoing_address()?,
+            IpAddr::V4(ip) if !ip.is_loopback() => ip,
+            ip => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{ip} isn't reachable over IPv4 multicast"),
+                ));
+            }
+        };
+        let socket = bind_shared(GROUP.port())?;
+        socket.join_multicast_v4(GROUP.ip(), &Ipv4Addr::UNSPECIFIED)?;
+        socket.set_multicast_loop_v4(true)?;
+        let service = Arc::new(Mutex::new(Service {
+            instance: instance.replace('.', "-"),
+            host: format!("{}.local", host_name()),
+            address: ip,
+            port: address.port(),
+            txt: txt_pairs(txt),
+        }));
+        let answering = Arc::clone(&service);
+        let receiver = socket.try_clone()?;
+        std::thread::spawn(move || answer(&receiver, &answering));
+        let advertiser = Self { socket, service };
+        advertiser.announce(OTHER_TTL)?;
+        event!(info, "advertising {instance} as {SERVICE_TYPE} on {ip}");
+        Ok(advertiser)
+    }
+
+    /// Replace the TXT record with `key=value` pairs, and announce the change.
+    pub fn set_txt(&self, txt: &[(&str, &str)]) -> io::Result<()> {
+        self.service.lock().unwrap_or_else(|e| e.into_inner()).txt = txt_pairs(txt);
+        self.announce(OTHER_TTL)
+    }
+
+    /// Send all records unasked, twice as RFC 6762 asks for, or with a `ttl` of 0 to say goodbye.
+    fn announce(&self, ttl: u32) -> io::Result<()> {
+        let service = self
+            .service
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        let packet = response(0, &service, Records::all(), ttl, None);
+        self.socket.send_to(&packet, GROUP)?;
+        let socket = self.socket.try_clone()?;
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_secs(1));
+            let _ = socket.send_to(&packet, GROUP);
+        });
+        Ok(())
+    }
+}
+
+fn txt_pairs(txt: &[(&str, &str)]) -> Vec<(String, String)> {
+    txt.iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+impl Drop for MdnsAdvertiser {
+    fn drop(&mut self) {
+        let _ = self.announce(0);
+    }
+}
+
+/// Answer queries for `service` on `socket`, until it fails.
+fn answer(socket: &UdpSocket, service: &Mutex<Service>) {
+    let mut buffer = [0u8; 9000];
+    loop {
+        let (length, source) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) => {
+                event!(warn, "stopped answering mdns queries: {e}");
+                return;
+            }
+        };
+        let service = service.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let packet = &buffer[..length];
+        let Some(query) = query(packet, &service) else {
+            continue;
+        };
+        if query.records.is_empty() {
+            continue;
+        }
+        // Resolvers that aren't mDNS (from another port) get a unicast answer like from DNS, see RFC 6762, section 6.7.
+        let legacy = source.port() != GROUP.port();
+        let packet = if legacy {
+            let questions = (query.questions, &packet[12..query.end]);
+            response(
+                query.id,
+                &service,
+                query.records,
+                LEGACY_TTL,
+                Some(questions),
+            )
+        } else {
+            response(0, &service, query.records, OTHER_TTL, None)
+        };
+        let target = if legacy || query.unicast {
+            source
+        } else {
+            SocketAddr::V4(GROUP)
+        };
+        if let Err(e) = socket.send_to(&packet, target) {
+            event!(debug, "could not answer an mdns query: {e}");
+        }
+    }
+}
+
+/// What a query asks for.
+#[derive(Copy, Clone, Debug)]
+struct Query {
+    id: u16,
+    /// The records that answer it.
+    records: Records,
+    /// Whether a unicast answer was asked for.
+    unicast: bool,
+    /// Number of questions, and where they end.
+    questions: u16,
+    end: usize,
+}
+
+/// The query in `packet`, `None` for responses and malformed packets.
+fn query(packet: &[u8], service: &Service) -> Option<Query> {
+    let id = u16::from_be_bytes([*packet.first()?, *packet.get(1)?]);
+    let flags = u16::from_be_bytes([*packet.get(2)?, *packet.get(3)?]);
+    if flags & 0x8000 != 0 {
+        return None;
+    }
+    let questions = u16::from_be_bytes([*packet.get(4)?, *packet.get(5)?]);
+    let instance = format!("{}.{SERVICE_TYPE}", service.instance);
+    let mut records = Records::default();
+    let mut unicast = false;
+    let mut offset = 12;
+    for _ in 0..questions {
+        let (name, end) = read_name(packet, offset)?;
+        let kind = u16::from_be_bytes([*packet.get(end)?, *packet.get(end + 1)?]);
+        let class = u16::from_be_bytes([*packet.get(end + 2)?, *packet.get(end + 3)?]);
+        offset = end + 4;
+        let any = kind == TYPE_ANY;
+        let before = records;
+        if name.eq_ignore_ascii_case(SERVICES) && (any || kind == TYPE_PTR) {
+            records.services = true;
+        } else if name.eq_ignore_ascii_case(SERVICE_TYPE) && (any || kind == TYPE_PTR) {
+            records = Records {
+                services: records.services,
+                ..Records::all()
+            };
+        } else if name.eq_ignore_ascii_case(&instance) {
+            records.srv |= any || kind == TYPE_SRV;
+            records.txt |= any || kind == TYPE_TXT;
+            // The address goes along with the SRV record, it's what the asker needs next.
+            records.a |= any || kind == TYPE_SRV;
+        } else if name.eq_ignore_ascii_case(&service.host) {
+            records.a |= any || kind == TYPE_A;
+        }
+        unicast |= records != before && class & UNICAST_RESPONSE != 0;
+    }
+    Some(Query {
+        id,
+        records,
+        unicast,
+        questions,
+        end: offset,
+    })
+}
+
+/// The name at `offset` of `packet`, and the offset after it. Follows compression pointers.
+fn read_name(packet: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut name = String::new();
+    let mut end = None;
+    // Enough for any name that fits in a packet, and stops pointer loops.
+    for _ in 0..128 {
+        let length = *packet.get(offset)?;
+        match length {
+            0 => return Some((name, end.unwrap_or(offset + 1))),
+            length if length & 0xc0 == 0xc0 => {
+                let pointer =
+                    usize::from(length & 0x3f) << 8 | usize::from(*packet.get(offset + 1)?);
+                end.get_or_insert(offset + 2);
+                offset = pointer;
+            }
+            length => {
+                let label = packet.get(offset + 1..offset + 1 + usize::from(length))?;
+                if !name.is_empty() {
+                    name.push('.');
+                }
+                name.push_str(&String::from_utf8_lossy(label));
+                offset += 1 + usize::from(length);
+            }
+        }
+    }
+    None
+}
+
+/// A response packet with `records` of `service`. Answers to legacy resolvers repeat their `questions` (the number and
+/// the raw questions), and don't flush caches.
+fn response(
+    id: u16,
+    service: &Service,
+    records: Records,
+    ttl: u32,
+    questions: Option<(u16, &[u8])>,
+) -> Vec<u8> {
+    let instance = format!("{}.{SERVICE_TYPE}", service.instance);
+    let host_ttl = ttl.min(HOST_TTL);
+    let unique = if questions.is_some() {
+        CLASS_IN
+    } else {
+        CLASS_IN | CACHE_FLUSH
+    };
+    let mut answers = Vec::new();
+    let mut count = 0u16;
+    let mut record = |name: &str, kind: u16, class: u16, ttl: u32, data: &[u8]| {
+        write_name(&mut answers, name);
+        answers.extend_from_slice(&kind.to_be_bytes());
+        answers.extend_from_slice(&class.to_be_bytes());
+        answers.extend_from_slice(&ttl.to_be_bytes());
+        answers.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        answers.extend_from_slice(data);
+        count += 1;
+    };
+    if records.services {
+        let mut data = Vec::new();
+        write_name(&mut data, SERVICE_TYPE);
+        record(SERVICES, TYPE_PTR, CLASS_IN, ttl, &data);
+    }
+    if records.ptr {
+        let mut data = Vec::new();
+        write_name(&mut data, &instance);
+        record(SERVICE_TYPE, TYPE_PTR, CLASS_IN, ttl, &data);
+    }
+    if records.srv {
+        // Priority and weight 0.
+        let mut data = Vec::from([0, 0, 0, 0]);
+        data.extend_from_slice(&service.port.to_be_bytes());
+        write_name(&mut data, &service.host);
+        record(&instance, TYPE_SRV, unique, host_ttl, &data);
+    }
+    if records.txt {
+        let mut data = Vec::new();
+        for (key, value) in &service.txt {
+            let entry = format!("{key}={value}");
+            let entry = &entry.as_bytes()[..entry.len().min(255)];
+            data.push(entry.len() as u8);
+            data.extend_from_slice(entry);
+        }
+        // An empty TXT record still has an empty string.
+        if data.is_empty() {
+            data.push(0);
+        }
+        record(&instance, TYPE_TXT, unique, ttl, &data);
+    }
+    if records.a {
+        let address = service.address.octets();
+        record(&service.host, TYPE_A, unique, host_ttl, &address);
+    }
+    // Header: id, flags (an authoritative response), the questions, the answers, no authority or additional records.
+    // The questions are at the same offset as in the query, so their compressed names still point to the right place.
+    let (question_count, questions) = questions.unwrap_or((0, &[]));
+    let mut packet = Vec::with_capacity(12 + questions.len() + answers.len());
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&0x8400u16.to_be_bytes());
+    packet.extend_from_slice(&question_count.to_be_bytes());
+    packet.extend_from_slice(&count.to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0, 0]);
+    packet.extend_from_slice(questions);
+    packet.extend_from_slice(&answers);
+    packet
+}
+
+/// Write `name` as labels, without compression. The first label of an instance name may contain anything but dots.
+fn write_name(buffer: &mut Vec<u8>, name: &str) {
+    for label in name.split('.') {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        buffer.push(label.len() as u8);
+        buffer.extend_from_slice(label);
+    }
+    buffer.push(0);
+}
+
+/// The address of the interface multicasts go out on.
+fn outgoing_address() -> io::Result<Ipv4Addr> {
+    // Connecting a UDP socket sends nothing, but picks the route.
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.connect(GROUP)?;
+    match socket.local_addr()?.ip() {
+        IpAddr::V4(ip) if !ip.is_unspecified() => Ok(ip),
+        _ => Err(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            "no interface for multicast",
+        )),
+    }
+}
+
+/// The name of this machine as a DNS label, `co2-monitor` if it can't be found out.
+pub fn host_name() -> String {
+    #[cfg(target_os = "linux")]
+    let name = std::fs::read_to_string("/proc/sys/kernel/hostname").ok();
+    #[cfg(not(target_os = "linux"))]
+    let name = None::<String>;
+    let name = name
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .unwrap_or_default();
+    let label: String = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .take(63)
+        .collect();
+    if label.is_empty() {
+        String::from("co2-monitor")
+    } else {
+        label
+    }
+}
+
+/// A UDP socket on `port` of all interfaces, shared with other responders.
+#[cfg(target_os = "linux")]
+fn bind_shared(port: u16) -> io::Result<UdpSocket> {
+    use std::os::fd::FromRawFd;
+    // SAFETY: a plain socket call.
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the socket was just opened, and is owned by nothing else. It's closed when this is dropped.
+    let socket = unsafe { UdpSocket::from_raw_fd(fd) };
+    let one: libc::c_int = 1;
+    for option in [libc::SO_REUSEADDR, libc::SO_REUSEPORT] {
+        // SAFETY: the option value is a valid c_int, and its size is passed along.
+        let result = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                option,
+                (&one as *const libc::c_int).cast(),
+                core::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    let address = libc::sockaddr_in {
+        sin_family: libc::AF_INET as libc::sa_family_t,
+        sin_port: port.to_be(),
+        sin_addr: libc::in_addr {
+            s_addr: u32::from(Ipv4Addr::UNSPECIFIED).to_be(),
+        },
+        sin_zero: [0; 8],
+    };
+    // SAFETY: the address is a valid sockaddr_in, and its size is passed along.
+    let result = unsafe {
+        libc::bind(
+            fd,
+            (&address as *const libc::sockaddr_in).cast(),
+            core::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(socket)
+}
+
+/// A UDP socket on `port` of all interfaces.
+#[cfg(not(target_os = "linux"))]
+fn bind_shared(port: u16) -> io::Result<UdpSocket> {
+    UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))
+}
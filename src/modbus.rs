@@ -0,0 +1,254 @@
+//! Serve the latest reading as Modbus TCP input registers, so the monitor can stand in for a wired co2 transmitter in a
+//! building automation system.
+//!
+//! [`ModbusServer`] listens in the background and answers "read input registers" (function 4). Some building
+//! management systems can only read holding registers (function 3), so those return the same values. Everything else,
+//! including writes, is answered with the "illegal function" exception. The registers are laid out by a
+//! [`RegisterMap`], by default:
+//!
+//! | Register | Value                                                                          |
+//! |----------|--------------------------------------------------------------------------------|
+//! | 0        | Co2 in ppm                                                                     |
+//! | 1        | Temperature in degrees Celsius × 10, signed                                    |
+//! | 2        | Relative humidity in percent × 10, 0xffff if the device has no humidity sensor |
+//! | 3        | Status of the co2 value                                                        |
+//!
+//! The status is 0 for valid, 1 for too high, 2 while warming up, 3 for too low and 4 for a sensor fault (see
+//! [`Co2Value`]). Before the first reading, it's 0xffff.
+//!
+//! Every connection gets a thread, up to [`MAX_CONNECTIONS`] at a time, further ones are closed right away. A client
+//! that sends nothing for [`READ_TIMEOUT`] is dropped.
+//!
+//! Port 502 is privileged on most systems, either give the binary `CAP_NET_BIND_SERVICE` or listen on another port.
+use crate::{Co2Value, MonitorReading};
+use core::time::Duration;
+use std::{
+    format,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    string::String,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    vec,
+    vec::Vec,
+};
+
+/// The standard Modbus TCP port, when none is given.
+pub const DEFAULT_PORT: u16 = 502;
+
+/// Register values that mean "no value".
+pub const NO_VALUE: u16 = 0xffff;
+
+/// Clients that are served at the same time. A building management system usually keeps one or two connections open.
+pub const MAX_CONNECTIONS: usize = 8;
+
+/// Polling systems ask at least every few minutes, a client that sends no request for this long is gone.
+pub const READ_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// A client that doesn't take a response within this is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Exception code: the function isn't supported.
+const ILLEGAL_FUNCTION: u8 = 0x01;
+/// Exception code: a register in the range isn't mapped.
+const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+/// Exception code: the request is malformed, e.g. too many registers.
+const ILLEGAL_DATA_VALUE: u8 = 0x03;
+
+/// Where the values are, see the [module documentation](self).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RegisterMap {
+    /// Register of co2 in ppm.
+    pub co2: u16,
+    /// Register of the temperature in degrees Celsius, multiplied by `scale`.
+    pub temperature: u16,
+    /// Register of the relative humidity in percent, multiplied by `scale`.
+    pub humidity: u16,
+    /// Register of the status.
+    pub status: u16,
+    /// Temperature and humidity are multiplied by this, as registers only hold integers.
+    pub scale: u16,
+}
+
+impl Default for RegisterMap {
+    fn default() -> Self {
+        Self {
+            co2: 0,
+            temperature: 1,
+            humidity: 2,
+            status: 3,
+            scale: 10,
+        }
+    }
+}
+
+impl RegisterMap {
+    /// The value of the register at `address`, with `reading` as the latest reading. `None` if nothing is mapped
+    /// there.
+    pub fn value(&self, address: u16, reading: Option<&MonitorReading>) -> Option<u16> {
+        let scaled = |value: f32| (value * f32::from(self.scale)).round();
+        let value = if address == self.co2 {
+            reading.map_or(0, |reading| reading.co2_value.as_num_and_bool().0)
+        } else if address == self.temperature {
+            // Two's complement, for temperatures below zero.
+            reading.map_or(0, |reading| scaled(reading.temperature) as i16 as u16)
+        } else if address == self.humidity {
+            reading
+                .and_then(|reading| reading.humidity)
+                .map_or(NO_VALUE, |humidity| scaled(humidity) as u16)
+        } else if address == self.status {
+            reading.map_or(NO_VALUE, |reading| match reading.co2_value {
+                Co2Value::Valid(_) => 0,
+                Co2Value::TooHigh(_) => 1,
+                Co2Value::WarmingUp(_) => 2,
+                Co2Value::TooLow(_) => 3,
+                Co2Value::SensorFault(_) => 4,
+            })
+        } else {
+            return None;
+        };
+        Some(value)
+    }
+
+    /// Answer the request PDU `request` (function code and data, without the MBAP header) with the response PDU.
+    pub fn respond(&self, request: &[u8], reading: Option<&MonitorReading>) -> Vec<u8> {
+        let Some((&function, data)) = request.split_first() else {
+            return exception(0, ILLEGAL_FUNCTION);
+        };
+        if function != 0x03 && function != 0x04 {
+            return exception(function, ILLEGAL_FUNCTION);
+        }
+        let [start_hi, start_lo, count_hi, count_lo] = *data else {
+            return exception(function, ILLEGAL_DATA_VALUE);
+        };
+        let start = u16::from_be_bytes([start_hi, start_lo]);
+        let count = u16::from_be_bytes([count_hi, count_lo]);
+        if !(1..=125).contains(&count) {
+            return exception(function, ILLEGAL_DATA_VALUE);
+        }
+        let mut response = vec![function, (count * 2) as u8];
+        for offset in 0..count {
+            let value = start
+                .checked_add(offset)
+                .and_then(|address| self.value(address, reading));
+            match value {
+                Some(value) => response.extend_from_slice(&value.to_be_bytes()),
+                None => return exception(function, ILLEGAL_DATA_ADDRESS),
+            }
+        }
+        response
+    }
+}
+
+fn exception(function: u8, code: u8) -> Vec<u8> {
+    vec![function | 0x80, code]
+}
+
+/// State shared with the connections.
+#[derive(Debug)]
+struct Shared {
+    map: RegisterMap,
+    unit_id: u8,
+    reading: Mutex<Option<MonitorReading>>,
+}
+
+/// Serves the latest reading over Modbus TCP in the background, see the [module documentation](self).
+#[derive(Debug)]
+pub struct ModbusServer {
+    shared: Arc<Shared>,
+    local_addr: SocketAddr,
+}
+
+impl ModbusServer {
+    /// Listen on `address` (`address[:port]`, the port defaults to [`DEFAULT_PORT`]) as unit `unit_id`. Requests for
+    /// other units are ignored, except for 0 and 255, which gateways use for "whoever is there".
+    pub fn bind(address: &str, unit_id: u8, map: RegisterMap) -> io::Result<Self> {
+        let address = if address.contains(':') {
+            String::from(address)
+        } else {
+            format!("{address}:{DEFAULT_PORT}")
+        };
+        let listener = TcpListener::bind(address)?;
+        let local_addr = listener.local_addr()?;
+        let shared = Arc::new(Shared {
+            map,
+            unit_id,
+            reading: Mutex::new(None),
+        });
+        let accepting = Arc::clone(&shared);
+        let open = Arc::new(AtomicUsize::new(0));
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        event!(warn, "could not accept a modbus connection: {e}");
+                        continue;
+                    }
+                };
+                if open.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+                    open.fetch_sub(1, Ordering::Relaxed);
+                    event!(debug, "too many modbus connections, closing one");
+                    continue;
+                }
+                let (shared, open) = (Arc::clone(&accepting), Arc::clone(&open));
+                std::thread::spawn(move || {
+                    if let Err(e) = serve(stream, &shared) {
+                        event!(debug, "modbus connection closed: {e}");
+                    }
+                    open.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+        });
+        event!(info, "serving modbus on {local_addr}");
+        Ok(Self { shared, local_addr })
+    }
+
+    /// Where the server listens, e.g. to find the port after binding to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Make `reading` the one in the registers.
+    pub fn publish(&self, reading: &MonitorReading) {
+        *self
+            .shared
+            .reading
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some(*reading);
+    }
+}
+
+/// Answer requests on `stream` until the client hangs up.
+fn serve(mut stream: TcpStream, shared: &Shared) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    loop {
+        // MBAP header: transaction id, protocol id (0 for Modbus), length of the rest, unit id.
+        let mut header = [0u8; 7];
+        stream.read_exact(&mut header)?;
+        let length = usize::from(u16::from_be_bytes([header[4], header[5]]));
+        if header[2..4] != [0, 0] || !(2..=254).contains(&length) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a modbus request",
+            ));
+        }
+        let mut request = vec![0u8; length - 1];
+        stream.read_exact(&mut request)?;
+        let unit_id = header[6];
+        if unit_id != shared.unit_id && unit_id != 0 && unit_id != 255 {
+            continue;
+        }
+        let reading = *shared.reading.lock().unwrap_or_else(|e| e.into_inner());
+        let response = shared.map.respond(&request, reading.as_ref());
+        let mut frame = Vec::with_capacity(7 + response.len());
+        frame.extend_from_slice(&header[..4]);
+        frame.extend_from_slice(&(response.len() as u16 + 1).to_be_bytes());
+        frame.push(unit_id);
+        frame.extend_from_slice(&response);
+        stream.write_all(&frame)?;
+    }
+}
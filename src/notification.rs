@@ -0,0 +1,449 @@
+//! Decide which alert events are worth a notification: rate limits, quiet hours and escalation, for every sink alike.
+//!
+//! An [`AlertRule`](crate::alert::AlertRule) already keeps a value hovering around its limit from raising again and
+//! again, but a value swinging further than the hysteresis still does, and some alerts (like co2 in a meeting room)
+//! are simply raised many times a day. A [`NotificationPolicy`] sits between the alert events and whatever notifies
+//! about them, and passes them on as [`Notification`]s:
+//!
+//! - A raise of the same kind less than the minimum interval after the last notified one is held back, and only
+//!   notified once the interval is over, if the alert is still raised by then.
+//! - During quiet hours, raises are held back until the quiet hours are over, again only if the alert is still raised
+//!   by then. Clears are only notified for raises that were, and are held back by quiet hours the same way. A raise
+//!   while its clear is held back cancels both, as far as anyone was notified the alert never went away.
+//! - Co2 at or above the critical level is critical, which passes quiet hours and the rate limit. When it stays there
+//!   for the escalation time, there's a [`Notification::Escalation`], once until co2 is back below the critical level.
+//!
+//! A policy without any of these passes every event as it is. Held back raises and escalations need to see the
+//! readings, so pass every reading to [`NotificationPolicy::observe`], not only the events to
+//! [`NotificationPolicy::push`].
+use crate::{
+    Co2Value, MonitorReading, TemperatureUnit,
+    alert::{AlertEvent, AlertKind, QuietHours, Transition},
+};
+
+/// What to notify about.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Notification {
+    /// An alert was raised or cleared.
+    Alert {
+        /// The event, for held back raises the one that was held back.
+        event: AlertEvent,
+        /// Whether co2 was at or above the critical level.
+        critical: bool,
+    },
+    /// Co2 has been at or above the critical level for the escalation time.
+    Escalation {
+        /// The current co2 in ppm.
+        co2_ppm: f32,
+        /// The critical level.
+        critical_ppm: u16,
+        /// Minutes since co2 reached the critical level.
+        minutes: f32,
+    },
+}
+
+impl Notification {
+    /// Display the notification with temperatures in `unit`. The plain `Display` impl uses Celsius.
+    pub fn display_in(&self, unit: TemperatureUnit) -> NotificationDisplay<'_> {
+        NotificationDisplay {
+            notification: self,
+            unit,
+        }
+    }
+}
+
+impl core::fmt::Display for Notification {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.display_in(TemperatureUnit::Celsius).fmt(f)
+    }
+}
+
+/// Displays a notification in a specific temperature unit, see [`Notification::display_in`].
+#[derive(Debug)]
+pub struct NotificationDisplay<'a> {
+    notification: &'a Notification,
+    unit: TemperatureUnit,
+}
+
+impl core::fmt::Display for NotificationDisplay<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.notification {
+            Notification::Alert {
+                event,
+                critical: true,
+            } => write!(f, "CRITICAL, {}", event.display_in(self.unit)),
+            Notification::Alert { event, .. } => write!(f, "{}", event.display_in(self.unit)),
+            Notification::Escalation {
+                co2_ppm,
+                critical_ppm,
+                minutes,
+            } => write!(
+                f,
+                "co2 at or above {critical_ppm}ppm for {minutes:.0} min, now {co2_ppm:.0}ppm, ventilate now"
+            ),
+        }
+    }
+}
+
+/// Per kind of alert.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+struct KindState {
+    // The raise, while the alert is raised.
+    raised: Option<AlertEvent>,
+    notified: bool,
+    // A clear of a notified raise, held back by quiet hours.
+    cleared: Option<AlertEvent>,
+    last_notified_ms: Option<u64>,
+}
+
+/// Number of [`AlertKind`]s.
+const KINDS: usize = 5;
+
+fn slot(kind: AlertKind) -> usize {
+    match kind {
+        AlertKind::Co2High => 0,
+        AlertKind::TemperatureLow => 1,
+        AlertKind::TemperatureHigh => 2,
+        AlertKind::SensorStuck => 3,
+        AlertKind::SensorDrift => 4,
+    }
+}
+
+/// Turns alert events into notifications, see the [module documentation](self).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NotificationPolicy {
+    min_interval_ms: u64,
+    quiet_hours: Option<QuietHours>,
+    critical_ppm: Option<u16>,
+    escalate_after_ms: Option<u64>,
+    kinds: [KindState; KINDS],
+    // Since when co2 is critical, and whether that was escalated.
+    critical_since_ms: Option<u64>,
+    escalated: bool,
+}
+
+impl NotificationPolicy {
+    /// A policy that passes every event.
+    pub const fn new() -> Self {
+        Self {
+            min_interval_ms: 0,
+            quiet_hours: None,
+            critical_ppm: None,
+            escalate_after_ms: None,
+            kinds: [KindState {
+                raised: None,
+                notified: false,
+                cleared: None,
+                last_notified_ms: None,
+            }; KINDS],
+            critical_since_ms: None,
+            escalated: false,
+        }
+    }
+
+    /// Notify raises of the same kind at most once every `minutes`.
+    pub const fn with_min_interval(mut self, minutes: u32) -> Self {
+        self.min_interval_ms = minutes as u64 * 60 * 1000;
+        self
+    }
+
+    /// Only notify critical alerts during `quiet_hours`.
+    pub const fn with_quiet_hours(mut self, quiet_hours: QuietHours) -> Self {
+        self.quiet_hours = Some(quiet_hours);
+        self
+    }
+
+    /// Co2 at or above `ppm` is critical.
+    pub const fn with_critical_co2(mut self, ppm: u16) -> Self {
+        self.critical_ppm = Some(ppm);
+        self
+    }
+
+    /// Escalate when co2 stays critical for `minutes`. Needs [`with_critical_co2`](Self::with_critical_co2).
+    pub const fn with_escalation(mut self, minutes: u32) -> Self {
+        self.escalate_after_ms = Some(minutes as u64 * 60 * 1000);
+        self
+    }
+
+    /// Pass `event`, which happened at `timestamp_ms` in the local `hour`, to `emit` if it's worth a notification now.
+    /// Timestamps have to be in chronological order.
+    pub fn push(
+        &mut self,
+        event: &AlertEvent,
+        timestamp_ms: u64,
+        hour: u8,
+        emit: &mut impl FnMut(Notification),
+    ) {
+        let critical = self.is_critical(event.kind, event.value);
+        let quiet = self.is_quiet(hour);
+        let min_interval_ms = self.min_interval_ms;
+        let state = &mut self.kinds[slot(event.kind)];
+        match event.transition {
+            Transition::Raised => {
+                let clear_held = state.cleared.take().is_some();
+                state.raised = Some(*event);
+                state.notified = clear_held;
+                if clear_held && !critical {
+                    return;
+                }
+                let rested = state
+                    .last_notified_ms
+                    .is_none_or(|last| timestamp_ms.saturating_sub(last) >= min_interval_ms);
+                if critical || (!quiet && rested) {
+                    state.notified = true;
+                    state.last_notified_ms = Some(timestamp_ms);
+                    emit(Notification::Alert {
+                        event: *event,
+                        critical,
+                    });
+                }
+            }
+            Transition::Cleared => {
+                let notified = state.notified;
+                state.raised = None;
+                state.notified = false;
+                if !notified {
+                    return;
+                }
+                if quiet && !critical {
+                    state.cleared = Some(*event);
+                } else {
+                    emit(Notification::Alert {
+                        event: *event,
+                        critical: false,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Look at `reading`, taken at `timestamp_ms` in the local `hour`: notify raises and clears that were held back and
+    /// can be notified now, and escalate co2 that has been critical for long enough.
+    pub fn observe(
+        &mut self,
+        reading: &MonitorReading,
+        timestamp_ms: u64,
+        hour: u8,
+        emit: &mut impl FnMut(Notification),
+    ) {
+        let co2 = match reading.co2_value {
+            Co2Value::Valid(ppm) | Co2Value::TooHigh(ppm) => Some(f32::from(ppm)),
+            _ => None,
+        };
+        let co2_critical = co2.is_some_and(|ppm| self.is_critical(AlertKind::Co2High, ppm));
+        let quiet = self.is_quiet(hour);
+        for state in &mut self.kinds {
+            if !quiet && let Some(event) = state.cleared.take() {
+                emit(Notification::Alert {
+                    event,
+                    critical: false,
+                });
+            }
+            let Some(event) = state.raised.filter(|_| !state.notified) else {
+                continue;
+            };
+            let critical = event.kind == AlertKind::Co2High && co2_critical;
+            let rested = state
+                .last_notified_ms
+                .is_none_or(|last| timestamp_ms.saturating_sub(last) >= self.min_interval_ms);
+            if critical || (!quiet && rested) {
+                state.notified = true;
+                state.last_notified_ms = Some(timestamp_ms);
+                emit(Notification::Alert { event, critical });
+            }
+        }
+        let (Some(co2_ppm), Some(critical_ppm), true) = (co2, self.critical_ppm, co2_critical)
+        else {
+            // Out of spec values neither start nor end a critical phase.
+            if co2.is_some() {
+                self.critical_since_ms = None;
+                self.escalated = false;
+            }
+            return;
+        };
+        let since_ms = *self.critical_since_ms.get_or_insert(timestamp_ms);
+        let critical_ms = timestamp_ms.saturating_sub(since_ms);
+        if let Some(escalate_after_ms) = self.escalate_after_ms
+            && !self.escalated
+            && critical_ms >= escalate_after_ms
+        {
+            self.escalated = true;
+            emit(Notification::Escalation {
+                co2_ppm,
+                critical_ppm,
+                minutes: critical_ms as f32 / 60_000.0,
+            });
+        }
+    }
+
+    fn is_critical(&self, kind: AlertKind, value: f32) -> bool {
+        kind == AlertKind::Co2High && self.critical_ppm.is_some_and(|ppm| value >= f32::from(ppm))
+    }
+
+    fn is_quiet(&self, hour: u8) -> bool {
+        self.quiet_hours.is_some_and(|quiet| quiet.contains(hour))
+    }
+}
+
+impl Default for NotificationPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::{Notification, NotificationPolicy};
+    use crate::{
+        Co2Value, MonitorReading, Provenance,
+        alert::{AlertEvent, AlertKind, QuietHours, Transition},
+    };
+    use std::{vec, vec::Vec};
+
+    const MINUTE: u64 = 60 * 1000;
+
+    fn co2_event(transition: Transition, ppm: f32) -> AlertEvent {
+        AlertEvent {
+            kind: AlertKind::Co2High,
+            transition,
+            value: ppm,
+            limit: 1200.0,
+        }
+    }
+
+    fn reading(ppm: u16) -> MonitorReading {
+        MonitorReading {
+            temperature: 21.0,
+            temperature_centi: 2100,
+            co2_value: Co2Value::Valid(ppm),
+            humidity: None,
+            provenance: Provenance::RAW,
+        }
+    }
+
+    fn push(
+        policy: &mut NotificationPolicy,
+        event: AlertEvent,
+        timestamp_ms: u64,
+        hour: u8,
+    ) -> Vec<Notification> {
+        let mut notifications = Vec::new();
+        policy.push(&event, timestamp_ms, hour, &mut |n| notifications.push(n));
+        notifications
+    }
+
+    fn observe(
+        policy: &mut NotificationPolicy,
+        ppm: u16,
+        timestamp_ms: u64,
+        hour: u8,
+    ) -> Vec<Notification> {
+        let mut notifications = Vec::new();
+        policy.observe(&reading(ppm), timestamp_ms, hour, &mut |n| {
+            notifications.push(n)
+        });
+        notifications
+    }
+
+    fn alert(event: AlertEvent, critical: bool) -> Notification {
+        Notification::Alert { event, critical }
+    }
+
+    #[test]
+    fn raise_within_min_interval_is_notified_later() {
+        let mut policy = NotificationPolicy::new().with_min_interval(30);
+        let raised = co2_event(Transition::Raised, 1300.0);
+        let cleared = co2_event(Transition::Cleared, 1100.0);
+        assert_eq!(push(&mut policy, raised, 0, 12), vec![alert(raised, false)]);
+        assert_eq!(
+            push(&mut policy, cleared, MINUTE, 12),
+            vec![alert(cleared, false)]
+        );
+
+        let again = co2_event(Transition::Raised, 1250.0);
+        assert_eq!(push(&mut policy, again, 5 * MINUTE, 12), vec![]);
+        assert_eq!(observe(&mut policy, 1250, 10 * MINUTE, 12), vec![]);
+        assert_eq!(
+            observe(&mut policy, 1250, 30 * MINUTE, 12),
+            vec![alert(again, false)]
+        );
+        assert_eq!(observe(&mut policy, 1250, 31 * MINUTE, 12), vec![]);
+    }
+
+    #[test]
+    fn quiet_hours_hold_back_non_critical_raises() {
+        let mut policy = NotificationPolicy::new()
+            .with_quiet_hours(QuietHours::new(22, 7))
+            .with_critical_co2(2000);
+        let raised = co2_event(Transition::Raised, 1300.0);
+        assert_eq!(push(&mut policy, raised, 0, 23), vec![]);
+        assert_eq!(observe(&mut policy, 1300, MINUTE, 23), vec![]);
+        assert_eq!(
+            observe(&mut policy, 1300, 2 * MINUTE, 7),
+            vec![alert(raised, false)]
+        );
+    }
+
+    #[test]
+    fn quiet_hours_pass_critical_raises() {
+        let mut policy = NotificationPolicy::new()
+            .with_quiet_hours(QuietHours::new(22, 7))
+            .with_critical_co2(2000);
+        let raised = co2_event(Transition::Raised, 2100.0);
+        assert_eq!(push(&mut policy, raised, 0, 23), vec![alert(raised, true)]);
+    }
+
+    #[test]
+    fn escalation_fires_once_and_rearms_below_critical() {
+        let mut policy = NotificationPolicy::new()
+            .with_critical_co2(2000)
+            .with_escalation(15);
+        let escalations = |policy: &mut NotificationPolicy, ppm, minutes: u64| {
+            observe(policy, ppm, minutes * MINUTE, 12)
+                .into_iter()
+                .filter(|n| matches!(n, Notification::Escalation { .. }))
+                .count()
+        };
+        assert_eq!(escalations(&mut policy, 2100, 0), 0);
+        assert_eq!(escalations(&mut policy, 2100, 14), 0);
+        assert_eq!(escalations(&mut policy, 2100, 15), 1);
+        assert_eq!(escalations(&mut policy, 2200, 20), 0);
+        assert_eq!(escalations(&mut policy, 1900, 21), 0);
+        assert_eq!(escalations(&mut policy, 2100, 22), 0);
+        assert_eq!(escalations(&mut policy, 2100, 36), 0);
+        assert_eq!(escalations(&mut policy, 2100, 37), 1);
+    }
+
+    #[test]
+    fn clear_during_quiet_hours_is_notified_after_them() {
+        let mut policy = NotificationPolicy::new().with_quiet_hours(QuietHours::new(22, 7));
+        let raised = co2_event(Transition::Raised, 1300.0);
+        let cleared = co2_event(Transition::Cleared, 1100.0);
+        assert_eq!(push(&mut policy, raised, 0, 21), vec![alert(raised, false)]);
+        assert_eq!(push(&mut policy, cleared, MINUTE, 23), vec![]);
+        assert_eq!(observe(&mut policy, 1100, 2 * MINUTE, 23), vec![]);
+        assert_eq!(
+            observe(&mut policy, 1100, 3 * MINUTE, 7),
+            vec![alert(cleared, false)]
+        );
+        assert_eq!(observe(&mut policy, 1100, 4 * MINUTE, 7), vec![]);
+    }
+
+    #[test]
+    fn raise_during_quiet_hours_cancels_held_clear() {
+        let mut policy = NotificationPolicy::new().with_quiet_hours(QuietHours::new(22, 7));
+        let raised = co2_event(Transition::Raised, 1300.0);
+        assert_eq!(push(&mut policy, raised, 0, 21), vec![alert(raised, false)]);
+        let cleared = co2_event(Transition::Cleared, 1100.0);
+        assert_eq!(push(&mut policy, cleared, MINUTE, 23), vec![]);
+        assert_eq!(push(&mut policy, raised, 2 * MINUTE, 23), vec![]);
+        assert_eq!(observe(&mut policy, 1300, 3 * MINUTE, 7), vec![]);
+        assert_eq!(
+            push(&mut policy, cleared, 4 * MINUTE, 7),
+            vec![alert(cleared, false)]
+        );
+    }
+}
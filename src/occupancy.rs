@@ -0,0 +1,77 @@
+//! Guess how many people are in a room from how fast co2 rises. Experimental, behind the `occupancy` feature.
+//!
+//! This is the mass balance of [`crate::advisor`] the other way round: given the volume of the room and how much air it
+//! exchanges with the windows closed, the co2 level and its slope tell how much co2 is being added, and that divided
+//! by what a sitting adult exhales is the number of people. It's a heuristic with a lot of slack. The exchange rate of
+//! a room is rarely known better than within a factor of two, people exhale more when they move, and right after a
+//! window was closed (or someone came in) co2 takes a while to settle on the new slope. Take the result as "nobody",
+//! "a few" or "a crowd", not as a head count, and don't use it for anything that matters.
+use crate::{Co2Value, TimestampedReading, advisor::CO2_PER_PERSON_M3_PER_H, trend::Trend};
+
+/// Air changes per hour of a room with closed windows and doors, unless configured otherwise. Old buildings are at 0.5
+/// to 1, airtight new ones well below.
+pub const DEFAULT_AIR_CHANGES_PER_H: f32 = 0.5;
+
+/// Estimates the occupancy of a single room, see the [module documentation](self).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OccupancyEstimator {
+    volume_m3: f32,
+    air_changes_per_h: f32,
+    outdoor_ppm: f32,
+    co2_per_person_m3_per_h: f32,
+    // 10 minutes of readings every 5 seconds.
+    trend: Trend<120>,
+}
+
+impl OccupancyEstimator {
+    /// A room of `volume_m3` cubic meters which exchanges `air_changes_per_h` times its volume per hour, with 420 ppm
+    /// outside.
+    pub const fn new(volume_m3: f32, air_changes_per_h: f32) -> Self {
+        Self {
+            volume_m3,
+            air_changes_per_h,
+            outdoor_ppm: 420.0,
+            co2_per_person_m3_per_h: CO2_PER_PERSON_M3_PER_H,
+            trend: Trend::new(),
+        }
+    }
+
+    /// Co2 outside, 420 ppm by default.
+    pub const fn with_outdoor_ppm(mut self, outdoor_ppm: f32) -> Self {
+        self.outdoor_ppm = outdoor_ppm;
+        self
+    }
+
+    /// Co2 exhaled per person in m³ per hour, [`CO2_PER_PERSON_M3_PER_H`] by default. Higher for people who aren't
+    /// sitting still, lower for children.
+    pub const fn with_co2_per_person(mut self, m3_per_h: f32) -> Self {
+        self.co2_per_person_m3_per_h = m3_per_h;
+        self
+    }
+
+    /// The people it takes to keep co2 at `co2_ppm` changing by `ppm_per_minute`. Never negative, co2 falling faster
+    /// than the air exchange explains (i.e. a window is open) is an empty room.
+    pub fn estimate(&self, co2_ppm: f32, ppm_per_minute: f32) -> f32 {
+        if self.co2_per_person_m3_per_h <= 0.0 {
+            return 0.0;
+        }
+        let ppm_per_h =
+            ppm_per_minute * 60.0 + self.air_changes_per_h * (co2_ppm - self.outdoor_ppm);
+        (ppm_per_h * self.volume_m3 / 1e6 / self.co2_per_person_m3_per_h).max(0.0)
+    }
+
+    /// Add a reading and return the estimate, once there's a trend. The readings have to be in chronological order,
+    /// out of spec values are skipped.
+    pub fn push(&mut self, reading: &TimestampedReading) -> Option<f32> {
+        let Co2Value::Valid(ppm) = reading.reading.co2_value else {
+            return None;
+        };
+        self.trend.observe_reading(reading);
+        Some(self.estimate(f32::from(ppm), self.trend.ppm_per_minute()?))
+    }
+
+    /// Forget the trend, e.g. after reconnecting to the device.
+    pub fn clear(&mut self) {
+        self.trend.clear();
+    }
+}
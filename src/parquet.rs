@@ -0,0 +1,193 @@
+//! Write logs as Apache Parquet, for month-long datasets that are unwieldy as csv.
+//!
+//! Parquet is columnar and compressed (snappy), and pandas, polars and duckdb load it directly with the right types:
+//! the timestamp is a timestamp (local time, without a time zone, like in the csv log), co2 and the raw sanity value
+//! are `uint16`, and the optional columns are nullable. Rows are grouped by [`ROWS_PER_GROUP`], which keeps the memory
+//! needed for writing bounded, while still allowing readers to skip whole weeks by their statistics.
+//!
+//! Parquet files can only be read once their footer is written, so this isn't a sink for the logger, which usually runs
+//! until it's killed. Log to csv, and convert with `co2_cli export --format parquet` (or [`ParquetWriter`]) instead.
+use crate::log_format::Row;
+use ::parquet::{
+    arrow::ArrowWriter,
+    basic::Compression,
+    errors::{ParquetError, Result},
+    file::properties::WriterProperties,
+};
+use arrow_array::{
+    ArrayRef, RecordBatch,
+    builder::{
+        BooleanBuilder, Float32Builder, StringBuilder, TimestampMicrosecondBuilder, UInt16Builder,
+        UInt32Builder, UInt64Builder,
+    },
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use std::{io::Write, string::ToString, sync::Arc, vec, vec::Vec};
+
+/// Rows per row group, about a week of readings every 5 seconds.
+pub const ROWS_PER_GROUP: usize = 128 * 1024;
+
+/// Rows that are collected before they are handed to the parquet writer.
+const ROWS_PER_BATCH: usize = 8 * 1024;
+
+/// The columns of the parquet file, the same as in an extended csv log.
+pub fn schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new(
+            "timestamp",
+            DataType::Timestamp(TimeUnit::Microsecond, None),
+            false,
+        ),
+        Field::new("temperature", DataType::Float32, false),
+        Field::new("co2_ppm", DataType::UInt16, false),
+        Field::new("co2_is_valid", DataType::Boolean, false),
+        Field::new("provenance", DataType::Utf8, false),
+        Field::new("room", DataType::Utf8, true),
+        Field::new("humidity", DataType::Float32, true),
+        Field::new("device", DataType::Utf8, true),
+        Field::new("quality", DataType::Utf8, true),
+        Field::new("sanity_raw", DataType::UInt16, true),
+        Field::new("monotonic_ms", DataType::UInt64, true),
+        Field::new("co2_state", DataType::Utf8, true),
+        Field::new("co2_deviation", DataType::Float32, true),
+        Field::new("sequence", DataType::UInt32, true),
+    ]))
+}
+
+/// Turns rows into parquet bytes, without owning the output. Shared by [`ParquetWriter`] and
+/// [`ExportWriter`](crate::export::ExportWriter).
+pub(crate) struct Encoder {
+    arrow: ArrowWriter<Vec<u8>>,
+    rows: Vec<Row>,
+}
+
+impl Encoder {
+    pub(crate) fn new() -> Result<Self> {
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(ROWS_PER_GROUP)
+            .build();
+        Ok(Self {
+            arrow: ArrowWriter::try_new(Vec::new(), schema(), Some(properties))?,
+            rows: Vec::with_capacity(ROWS_PER_BATCH),
+        })
+    }
+
+    /// Add `row`, and write whatever is complete to `out`.
+    pub(crate) fn write(&mut self, row: &Row, out: &mut impl Write) -> Result<()> {
+        self.rows.push(row.clone());
+        if self.rows.len() >= ROWS_PER_BATCH {
+            self.write_batch()?;
+            self.drain(out)?;
+        }
+        Ok(())
+    }
+
+    /// Write the remaining rows and the footer to `out`.
+    pub(crate) fn finish(mut self, out: &mut impl Write) -> Result<()> {
+        self.write_batch()?;
+        self.arrow.finish()?;
+        self.drain(out)
+    }
+
+    fn write_batch(&mut self) -> Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let len = self.rows.len();
+        let mut timestamp = TimestampMicrosecondBuilder::with_capacity(len);
+        let mut temperature = Float32Builder::with_capacity(len);
+        let mut co2_ppm = UInt16Builder::with_capacity(len);
+        let mut co2_is_valid = BooleanBuilder::with_capacity(len);
+        let mut provenance = StringBuilder::new();
+        let mut room = StringBuilder::new();
+        let mut humidity = Float32Builder::with_capacity(len);
+        let mut device = StringBuilder::new();
+        let mut quality = StringBuilder::new();
+        let mut sanity_raw = UInt16Builder::with_capacity(len);
+        let mut monotonic_ms = UInt64Builder::with_capacity(len);
+        let mut co2_state = StringBuilder::new();
+        let mut co2_deviation = Float32Builder::with_capacity(len);
+        let mut sequence = UInt32Builder::with_capacity(len);
+        for row in self.rows.drain(..) {
+            timestamp.append_value(row.timestamp.and_utc().timestamp_micros());
+            temperature.append_value(row.temperature);
+            co2_ppm.append_value(u16::try_from(row.co2_ppm).unwrap_or(u16::MAX));
+            co2_is_valid.append_value(row.co2_is_valid);
+            provenance.append_value(row.provenance.to_string());
+            room.append_option(row.room);
+            humidity.append_option(row.humidity);
+            device.append_option(row.device);
+            quality.append_option(row.quality.map(|quality| quality.to_string()));
+            sanity_raw.append_option(row.sanity_raw);
+            monotonic_ms.append_option(row.monotonic_ms);
+            co2_state.append_option(row.co2_state.map(|state| state.to_string()));
+            co2_deviation.append_option(row.co2_deviation);
+            sequence.append_option(row.sequence);
+        }
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(timestamp.finish()),
+            Arc::new(temperature.finish()),
+            Arc::new(co2_ppm.finish()),
+            Arc::new(co2_is_valid.finish()),
+            Arc::new(provenance.finish()),
+            Arc::new(room.finish()),
+            Arc::new(humidity.finish()),
+            Arc::new(device.finish()),
+            Arc::new(quality.finish()),
+            Arc::new(sanity_raw.finish()),
+            Arc::new(monotonic_ms.finish()),
+            Arc::new(co2_state.finish()),
+            Arc::new(co2_deviation.finish()),
+            Arc::new(sequence.finish()),
+        ];
+        let batch = RecordBatch::try_new(schema(), columns)
+            .map_err(|e| ParquetError::ArrowError(e.to_string()))?;
+        self.arrow.write(&batch)
+    }
+
+    /// Move the bytes of finished row groups to `out`, so at most one row group is kept in memory.
+    fn drain(&mut self, out: &mut impl Write) -> Result<()> {
+        let bytes = core::mem::take(self.arrow.inner_mut());
+        out.write_all(&bytes)?;
+        Ok(())
+    }
+}
+
+impl core::fmt::Debug for Encoder {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Encoder")
+            .field("buffered_rows", &self.rows.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Writes [`Row`]s to a parquet file. Don't forget to [`finish`](Self::finish), without the footer the file can't be
+/// read.
+#[derive(Debug)]
+pub struct ParquetWriter<W: Write> {
+    writer: W,
+    encoder: Encoder,
+}
+
+impl<W: Write> ParquetWriter<W> {
+    /// Write a parquet file to `writer`.
+    pub fn new(writer: W) -> Result<Self> {
+        Ok(Self {
+            writer,
+            encoder: Encoder::new()?,
+        })
+    }
+
+    /// Write a single row. Rows are buffered, and written a row group at a time.
+    pub fn write(&mut self, row: &Row) -> Result<()> {
+        self.encoder.write(row, &mut self.writer)
+    }
+
+    /// Write the remaining rows and the footer, and get the writer back.
+    pub fn finish(mut self) -> Result<W> {
+        self.encoder.finish(&mut self.writer)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
@@ -1,30 +1,414 @@
 //! Implement the Co2 monitor communication for a PC using the `hidapi` crate.
-use crate::device::{Co2MonitorCommunication, MonitorError};
+use crate::{
+    MonitorReading,
+    device::{Co2MonitorCommunication, Co2MonitorExt, MonitorError},
+    profiles::{self, DeviceProfile, EncryptionDetector, ProfileRegistry},
+};
 use hidapi::{HidApi, HidDevice};
+use serde::Serialize;
+use std::{
+    string::{String, ToString},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+    vec::Vec,
+};
+
+/// How often a blocking read checks its [`CancellationToken`]. hidapi has no way to interrupt a read, so reads are
+/// split into slices of this length.
+const CANCEL_CHECK_MS: u32 = 100;
+
+/// Cancels the reads of a [`PcCo2Monitor`] from another thread, see [`PcCo2Monitor::with_cancellation`].
+///
+/// Clones share the same state. Once cancelled, every read fails with [`MonitorError::Cancelled`] within
+/// about 100 ms, until the token is [`reset`](Self::reset).
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// A token that isn't cancelled yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make reads fail with [`MonitorError::Cancelled`], including the one that is currently blocking.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether `cancel` was called (since the last `reset`).
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Allow reading again, e.g. after reconfiguring.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
 
 /// This struct holds the `HidDevice` from hidapi crate, that is needed for communication.
+#[derive(Debug)]
 pub struct PcCo2Monitor {
     device: HidDevice,
+    cancel: CancellationToken,
+    profile: DeviceProfile,
+    encryption: EncryptionDetector,
 }
 
-impl Co2MonitorCommunication for PcCo2Monitor {
-    fn init_and_connect() -> Self {
-        let api = HidApi::new().expect("Could not initialize Hid Api.");
-        let device = api
-            .open(Self::get_vid(), Self::get_pid())
-            .expect("Unable to open HID device. Is it connected to this computer? Do you have sufficient permissions?");
+/// USB descriptor information of a connected monitor.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DeviceInfo {
+    /// USB vendor ID.
+    pub vendor_id: u16,
+    /// USB product ID.
+    pub product_id: u16,
+    /// Manufacturer string, if the device reports one.
+    pub manufacturer: Option<String>,
+    /// Product string, if the device reports one.
+    pub product: Option<String>,
+    /// Serial number, if the device reports one. Most units report something, but it's not guaranteed to be unique.
+    pub serial_number: Option<String>,
+    /// The device release number (bcdDevice), the closest thing to a firmware version there is.
+    pub release_number: u16,
+    /// Platform specific path of the HID device.
+    pub path: String,
+}
+
+impl DeviceInfo {
+    /// The serial number if the device reports one, otherwise the path. Tells two identical units apart, as long as
+    /// they have serial numbers.
+    pub fn identifier(&self) -> &str {
+        self.serial_number
+            .as_deref()
+            .filter(|serial| !serial.is_empty())
+            .unwrap_or(&self.path)
+    }
+
+    /// The release number as a version, e.g. `1.00` for `0x0100`. The monitors have no other firmware version that
+    /// could be read.
+    pub fn firmware_version(&self) -> String {
+        std::format!(
+            "{:x}.{:02x}",
+            self.release_number >> 8,
+            self.release_number & 0xff
+        )
+    }
+}
+
+impl From<&hidapi::DeviceInfo> for DeviceInfo {
+    fn from(info: &hidapi::DeviceInfo) -> Self {
+        Self {
+            vendor_id: info.vendor_id(),
+            product_id: info.product_id(),
+            manufacturer: info.manufacturer_string().map(ToString::to_string),
+            product: info.product_string().map(ToString::to_string),
+            serial_number: info.serial_number().map(ToString::to_string),
+            release_number: info.release_number(),
+            path: info.path().to_string_lossy().into_owned(),
+        }
+    }
+}
+
+impl PcCo2Monitor {
+    /// Open a specific monitor, e.g. one reported by [`DeviceWatcher`], when there are several. The profile is the
+    /// built-in one for its VID/PID, see [`profiles::find`].
+    pub fn open(device: &DeviceInfo) -> Result<Self, MonitorError> {
+        let profile = profiles::find(device.vendor_id, device.product_id)
+            .unwrap_or(DeviceProfile::new(device.vendor_id, device.product_id));
+        Self::open_with_profile(device, profile)
+    }
 
-        // This tells the monitor to actually start sending data over HID.
+    /// Like `open`, but with the given profile, e.g. for an encrypting device or a model this crate doesn't know.
+    pub fn open_with_profile(
+        device: &DeviceInfo,
+        profile: DeviceProfile,
+    ) -> Result<Self, MonitorError> {
+        let path =
+            std::ffi::CString::new(device.path.as_str()).map_err(|_| MonitorError::ReadFailed)?;
+        let api = HidApi::new().map_err(|_| MonitorError::ReadFailed)?;
+        let device = api.open_path(&path).map_err(|e| {
+            event!(warn, "could not open {:?}: {}", path, e);
+            MonitorError::ReadFailed
+        })?;
         device
-            .send_feature_report(Self::get_feature_report())
-            .expect("Could not send feature report.");
+            .send_feature_report(&profile.feature_report())
+            .map_err(|_| MonitorError::ReadFailed)?;
+        event!(info, "connected to {:?}", path);
+        Ok(Self {
+            device,
+            cancel: CancellationToken::new(),
+            encryption: EncryptionDetector::new(profile),
+            profile,
+        })
+    }
 
-        Self { device }
+    /// Open the first monitor with the ids of `profile`.
+    pub fn connect_with_profile(profile: DeviceProfile) -> Result<Self, MonitorError> {
+        let api = HidApi::new().map_err(|_| MonitorError::ReadFailed)?;
+        let device = api
+            .open(profile.vendor_id, profile.product_id)
+            .map_err(|e| {
+                event!(
+                    warn,
+                    "could not open {:04x}:{:04x}: {}",
+                    profile.vendor_id,
+                    profile.product_id,
+                    e
+                );
+                MonitorError::ReadFailed
+            })?;
+        device
+            .send_feature_report(&profile.feature_report())
+            .map_err(|_| MonitorError::ReadFailed)?;
+        event!(
+            info,
+            "connected to {:04x}:{:04x}",
+            profile.vendor_id,
+            profile.product_id
+        );
+        Ok(Self {
+            device,
+            cancel: CancellationToken::new(),
+            encryption: EncryptionDetector::new(profile),
+            profile,
+        })
     }
 
-    fn read(&self, read_buffer: &mut [u8; 8]) -> Result<usize, MonitorError> {
+    /// Open the first connected monitor of any of the built-in profiles, e.g. a clone with other ids than the ZGm053U.
+    pub fn open_any() -> Result<Self, MonitorError> {
+        Self::open_any_with(&ProfileRegistry::new())
+    }
+
+    /// Open the first connected monitor of any profile of `registry`, with that profile.
+    pub fn open_any_with(registry: &ProfileRegistry) -> Result<Self, MonitorError> {
+        let device = enumerate(registry)?
+            .into_iter()
+            .next()
+            .ok_or(MonitorError::ReadFailed)?;
+        let (name, profile) = registry
+            .find(device.vendor_id, device.product_id)
+            .ok_or(MonitorError::ReadFailed)?;
+        event!(debug, "found a {} at {}", name, device.path);
+        Self::open_with_profile(&device, profile)
+    }
+
+    /// The profile the monitor was opened with.
+    pub fn profile(&self) -> &DeviceProfile {
+        &self.profile
+    }
+
+    /// Whether the reports are decrypted, because the profile says so or because they turned out to be encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption.is_encrypted()
+    }
+
+    /// Use `token` to cancel reads, e.g. to shut down a service without waiting for the read timeout. Keep a clone of
+    /// it and call [`CancellationToken::cancel`] from any thread.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// The token that cancels reads of this monitor. Every monitor has one, even without `with_cancellation`.
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancel
+    }
+
+    /// Cancel the reads of this monitor, same as `cancellation_token().cancel()`.
+    pub fn stop(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Read the USB descriptor information of the connected device.
+    pub fn device_info(&self) -> Result<DeviceInfo, MonitorError> {
         self.device
-            .read_timeout(read_buffer, 1000)
+            .get_device_info()
+            .map(|info| DeviceInfo::from(&info))
             .map_err(|_| MonitorError::ReadFailed)
     }
+
+    /// Read until a complete reading is assembled, or give up with [`MonitorError::Timeout`] after `timeout`. A
+    /// complete reading takes about 5 seconds, so scripts should allow for at least 10.
+    pub fn read_one(&self, timeout: Duration) -> Result<MonitorReading, MonitorError> {
+        let start = std::time::Instant::now();
+        let clock = || start.elapsed().as_millis() as u64;
+        self.read_one_with_clock(&clock, timeout.as_millis() as u64)
+    }
+}
+
+impl Co2MonitorCommunication for PcCo2Monitor {
+    /// Opens the first monitor of any built-in profile, so both the ZGm053U (PID `0xa052`) and the newer CO2Mini
+    /// (PID `0xa058`) work. See [`PcCo2Monitor::open_any`].
+    fn init_and_connect() -> Self {
+        // Sending the feature report tells the monitor to actually start sending data over HID.
+        Self::open_any()
+            .expect("Unable to open HID device. Is it connected to this computer? Do you have sufficient permissions?")
+    }
+
+    fn read(&self, read_buffer: &mut [u8; 8]) -> Result<usize, MonitorError> {
+        Co2MonitorCommunication::read_timeout(self, read_buffer, 1000)
+    }
+
+    fn read_timeout(
+        &self,
+        read_buffer: &mut [u8; 8],
+        timeout_ms: u32,
+    ) -> Result<usize, MonitorError> {
+        let mut remaining_ms = timeout_ms;
+        loop {
+            if self.cancel.is_cancelled() {
+                return Err(MonitorError::Cancelled);
+            }
+            let slice_ms = remaining_ms.min(CANCEL_CHECK_MS);
+            let read_len = self
+                .device
+                .read_timeout(read_buffer, slice_ms as i32)
+                .map_err(|e| {
+                    event!(debug, "hidapi read failed: {}", e);
+                    MonitorError::ReadFailed
+                })?;
+            remaining_ms -= slice_ms;
+            if read_len == read_buffer.len() {
+                self.encryption.decrypt(read_buffer);
+            }
+            if read_len > 0 || remaining_ms == 0 {
+                return Ok(read_len);
+            }
+        }
+    }
+}
+
+/// The connected devices with the ids of any profile of `registry`.
+pub fn enumerate(registry: &ProfileRegistry) -> Result<Vec<DeviceInfo>, MonitorError> {
+    let mut api = HidApi::new().map_err(|_| MonitorError::ReadFailed)?;
+    api.reset_devices().map_err(|_| MonitorError::ReadFailed)?;
+    for (vendor_id, product_id) in registry.ids() {
+        api.add_devices(vendor_id, product_id)
+            .map_err(|_| MonitorError::ReadFailed)?;
+    }
+    Ok(api.device_list().map(DeviceInfo::from).collect())
+}
+
+/// Whether two hidapi paths point to the same device. On windows, the same device interface path doesn't always come
+/// back in the same case from one enumeration to the next, and the case doesn't matter there anyway.
+fn same_path(a: &str, b: &str) -> bool {
+    if cfg!(windows) {
+        a.eq_ignore_ascii_case(b)
+    } else {
+        a == b
+    }
+}
+
+/// A monitor was plugged in or removed, see [`DeviceWatcher`].
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum DeviceEvent {
+    /// The device appeared.
+    Connected(DeviceInfo),
+    /// The device is gone.
+    Disconnected(DeviceInfo),
+}
+
+/// Notices when monitors are plugged in or removed, so a service can start logging as soon as one is there.
+///
+/// This simply enumerates the HID devices whenever it's polled and compares against the previous enumeration. On
+/// linux hidapi enumerates through udev anyway, so a udev monitor wouldn't be much cheaper at a poll interval of a
+/// few seconds, and this works the same on every OS.
+pub struct DeviceWatcher {
+    api: HidApi,
+    ids: Vec<(u16, u16)>,
+    present: Vec<DeviceInfo>,
+}
+
+impl core::fmt::Debug for DeviceWatcher {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DeviceWatcher")
+            .field("ids", &self.ids)
+            .field("present", &self.present)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DeviceWatcher {
+    /// Watch for devices with the VID/PID of any built-in profile, see [`crate::profiles`]. Nothing is reported until
+    /// the first `poll`, which reports every device that is already connected.
+    pub fn new() -> Result<Self, MonitorError> {
+        Self::with_registry(&ProfileRegistry::new())
+    }
+
+    /// Watch for devices with the VID/PID of any profile of `registry`.
+    pub fn with_registry(registry: &ProfileRegistry) -> Result<Self, MonitorError> {
+        Self::with_id_list(registry.ids())
+    }
+
+    /// Watch for devices with a custom VID/PID.
+    pub fn with_ids(vendor_id: u16, product_id: u16) -> Result<Self, MonitorError> {
+        Self::with_id_list(std::vec![(vendor_id, product_id)])
+    }
+
+    fn with_id_list(ids: Vec<(u16, u16)>) -> Result<Self, MonitorError> {
+        Ok(Self {
+            api: HidApi::new().map_err(|_| MonitorError::ReadFailed)?,
+            ids,
+            present: Vec::new(),
+        })
+    }
+
+    /// The matching devices as of the last `poll`.
+    pub fn present(&self) -> &[DeviceInfo] {
+        &self.present
+    }
+
+    /// Enumerate the devices and pass what changed since the last poll to `emit`.
+    pub fn poll(&mut self, emit: &mut impl FnMut(DeviceEvent)) -> Result<(), MonitorError> {
+        self.api
+            .reset_devices()
+            .map_err(|_| MonitorError::ReadFailed)?;
+        for &(vendor_id, product_id) in &self.ids {
+            self.api
+                .add_devices(vendor_id, product_id)
+                .map_err(|_| MonitorError::ReadFailed)?;
+        }
+        let now: Vec<DeviceInfo> = self.api.device_list().map(DeviceInfo::from).collect();
+
+        for gone in self
+            .present
+            .iter()
+            .filter(|old| !now.iter().any(|new| same_path(&new.path, &old.path)))
+        {
+            event!(info, "device at {} disconnected", gone.path);
+            emit(DeviceEvent::Disconnected(gone.clone()));
+        }
+        for new in now.iter().filter(|new| {
+            !self
+                .present
+                .iter()
+                .any(|old| same_path(&old.path, &new.path))
+        }) {
+            event!(info, "device at {} connected", new.path);
+            emit(DeviceEvent::Connected(new.clone()));
+        }
+        self.present = now;
+        Ok(())
+    }
+
+    /// Poll every `interval` until at least one device is present, and return it. Returns immediately if one already
+    /// is. Enumeration errors are treated like no device being there.
+    pub fn wait_until_present(
+        &mut self,
+        interval: Duration,
+        emit: &mut impl FnMut(DeviceEvent),
+    ) -> DeviceInfo {
+        loop {
+            if self.poll(emit).is_ok()
+                && let Some(device) = self.present.first()
+            {
+                return device.clone();
+            }
+            std::thread::sleep(interval);
+        }
+    }
 }
@@ -0,0 +1,308 @@
+//! Chain the processing of readings (smoothing, dedup, ...) into a pipeline, instead of wiring every step by hand.
+//!
+//! A [`Stage`] takes readings and passes on whatever comes out of it: the same reading, nothing, or a combined one
+//! later. The aggregators of [`crate::aggregate`] and [`ChangeFilter`] are stages, [`Inspect`] runs a closure on every
+//! reading passing by (e.g. to evaluate alerts), and [`Then`] chains two stages without allocating. The last stage
+//! emits into a closure, which fans out to the sinks, e.g. with a [`FanOut`](crate::fanout::FanOut).
+//!
+//! On a PC, [`Pipeline`] chains boxed stages at runtime, built from [`StageConfig`]s like `sample:60` in the
+//! `[pipeline]` section of the config file.
+use crate::{
+    TimestampedReading,
+    abc::AutoBaseline,
+    aggregate::{Aggregator, IntervalAggregator},
+    dedup::ChangeFilter,
+    warmup::WarmUp,
+};
+#[cfg(feature = "pc")]
+use std::{boxed::Box, vec::Vec};
+
+/// A step in a pipeline, see the [module documentation](self).
+pub trait Stage {
+    /// Take `reading`, and pass whatever comes out of it on to `emit`.
+    fn process(&mut self, reading: TimestampedReading, emit: &mut dyn FnMut(TimestampedReading));
+
+    /// The end of the readings, e.g. on shutdown. Pass on what is still buffered.
+    fn finish(&mut self, emit: &mut dyn FnMut(TimestampedReading)) {
+        let _ = emit;
+    }
+
+    /// Pass the output of this stage to `next`.
+    fn then<S: Stage>(self, next: S) -> Then<Self, S>
+    where
+        Self: Sized,
+    {
+        Then { first: self, next }
+    }
+}
+
+impl<S: Stage + ?Sized> Stage for &mut S {
+    fn process(&mut self, reading: TimestampedReading, emit: &mut dyn FnMut(TimestampedReading)) {
+        (**self).process(reading, emit)
+    }
+
+    fn finish(&mut self, emit: &mut dyn FnMut(TimestampedReading)) {
+        (**self).finish(emit)
+    }
+}
+
+#[cfg(feature = "pc")]
+impl<S: Stage + ?Sized> Stage for Box<S> {
+    fn process(&mut self, reading: TimestampedReading, emit: &mut dyn FnMut(TimestampedReading)) {
+        (**self).process(reading, emit)
+    }
+
+    fn finish(&mut self, emit: &mut dyn FnMut(TimestampedReading)) {
+        (**self).finish(emit)
+    }
+}
+
+/// Smoothing, every `n` readings are combined into one, with the timestamp and sequence number of the last of them.
+impl Stage for Aggregator {
+    fn process(&mut self, reading: TimestampedReading, emit: &mut dyn FnMut(TimestampedReading)) {
+        if let Some(combined) = self.push(&reading.reading) {
+            emit(TimestampedReading {
+                reading: combined,
+                ..reading
+            });
+        }
+    }
+}
+
+/// Smoothing, the readings of every interval are combined into one. Finishing passes on the incomplete interval.
+impl Stage for IntervalAggregator {
+    fn process(&mut self, reading: TimestampedReading, emit: &mut dyn FnMut(TimestampedReading)) {
+        if let Some(combined) = self.push(&reading) {
+            emit(combined);
+        }
+    }
+
+    fn finish(&mut self, emit: &mut dyn FnMut(TimestampedReading)) {
+        if let Some(combined) = self.take() {
+            emit(combined);
+        }
+    }
+}
+
+/// Dedup, only changes (and heartbeats) are passed on.
+impl Stage for ChangeFilter {
+    fn process(&mut self, reading: TimestampedReading, emit: &mut dyn FnMut(TimestampedReading)) {
+        if let Some(reading) = self.push(&reading) {
+            emit(reading);
+        }
+    }
+}
+
+/// Marks the co2 of readings as warming up until the sensor is warm.
+impl Stage for WarmUp {
+    fn process(&mut self, reading: TimestampedReading, emit: &mut dyn FnMut(TimestampedReading)) {
+        emit(self.observe(&reading));
+    }
+}
+
+/// Calibration, co2 is corrected with the offset of the software ABC.
+impl Stage for AutoBaseline {
+    fn process(&mut self, reading: TimestampedReading, emit: &mut dyn FnMut(TimestampedReading)) {
+        emit(self.correct(&reading));
+    }
+}
+
+/// Two stages in a row, see [`Stage::then`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Then<A, B> {
+    first: A,
+    next: B,
+}
+
+impl<A: Stage, B: Stage> Stage for Then<A, B> {
+    fn process(&mut self, reading: TimestampedReading, emit: &mut dyn FnMut(TimestampedReading)) {
+        let next = &mut self.next;
+        self.first
+            .process(reading, &mut |reading| next.process(reading, emit));
+    }
+
+    fn finish(&mut self, emit: &mut dyn FnMut(TimestampedReading)) {
+        let next = &mut self.next;
+        self.first
+            .finish(&mut |reading| next.process(reading, emit));
+        self.next.finish(emit);
+    }
+}
+
+/// Calls a closure with every reading and passes it on unchanged, e.g. to evaluate alerts at some point of the
+/// pipeline.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Inspect<F>(pub F);
+
+impl<F: FnMut(&TimestampedReading)> Stage for Inspect<F> {
+    fn process(&mut self, reading: TimestampedReading, emit: &mut dyn FnMut(TimestampedReading)) {
+        (self.0)(&reading);
+        emit(reading);
+    }
+}
+
+/// A stage, as it is written in the config: `every:<n>`, `sample:<seconds>`, `on-change`,
+/// `on-change:<heartbeat seconds>`, `warm-up` or `warm-up:<grace seconds>`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StageConfig {
+    /// Combine every `n` readings, see [`Aggregator`].
+    Every(u32),
+    /// Combine the readings of every interval of this many seconds, see [`IntervalAggregator`].
+    Sample(u64),
+    /// Only pass on changes, and an unchanged reading after this many seconds, see [`ChangeFilter`].
+    OnChange(Option<u64>),
+    /// Mark readings as warming up, with this grace period in seconds, see [`WarmUp`].
+    WarmUp(Option<u64>),
+}
+
+impl core::fmt::Display for StageConfig {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            StageConfig::Every(n) => write!(f, "every:{n}"),
+            StageConfig::Sample(seconds) => write!(f, "sample:{seconds}"),
+            StageConfig::OnChange(None) => f.write_str("on-change"),
+            StageConfig::OnChange(Some(seconds)) => write!(f, "on-change:{seconds}"),
+            StageConfig::WarmUp(None) => f.write_str("warm-up"),
+            StageConfig::WarmUp(Some(seconds)) => write!(f, "warm-up:{seconds}"),
+        }
+    }
+}
+
+/// Returned when parsing [`StageConfig`] from an unknown string.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseStageConfigError;
+
+impl core::fmt::Display for ParseStageConfigError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(
+            "expected every:<n>, sample:<seconds>, on-change[:<seconds>] or warm-up[:<seconds>]",
+        )
+    }
+}
+
+impl core::error::Error for ParseStageConfigError {}
+
+impl core::str::FromStr for StageConfig {
+    type Err = ParseStageConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, argument) = match s.split_once(':') {
+            Some((name, argument)) => (name, Some(argument)),
+            None => (s, None),
+        };
+        match (name, argument) {
+            ("every", Some(n)) => n
+                .parse()
+                .map(Self::Every)
+                .map_err(|_| ParseStageConfigError),
+            ("sample", Some(seconds)) => seconds
+                .parse()
+                .map(Self::Sample)
+                .map_err(|_| ParseStageConfigError),
+            ("on-change", None) => Ok(Self::OnChange(None)),
+            ("on-change", Some(seconds)) => seconds
+                .parse()
+                .map(|seconds| Self::OnChange(Some(seconds)))
+                .map_err(|_| ParseStageConfigError),
+            ("warm-up", None) => Ok(Self::WarmUp(None)),
+            ("warm-up", Some(seconds)) => seconds
+                .parse()
+                .map(|seconds| Self::WarmUp(Some(seconds)))
+                .map_err(|_| ParseStageConfigError),
+            _ => Err(ParseStageConfigError),
+        }
+    }
+}
+
+#[cfg(feature = "pc")]
+impl StageConfig {
+    /// The configured stage.
+    pub fn build(&self) -> Box<dyn Stage + Send> {
+        match *self {
+            StageConfig::Every(n) => Box::new(Aggregator::new(n)),
+            StageConfig::Sample(seconds) => Box::new(IntervalAggregator::new(seconds * 1000)),
+            StageConfig::OnChange(heartbeat_seconds) => Box::new(ChangeFilter::new(
+                heartbeat_seconds.map(|seconds| seconds * 1000),
+            )),
+            StageConfig::WarmUp(None) => Box::new(WarmUp::default()),
+            StageConfig::WarmUp(Some(seconds)) => Box::new(WarmUp::new(seconds * 1000)),
+        }
+    }
+}
+
+/// Stages chained at runtime. Without stages, every reading is passed on as it is.
+#[cfg(feature = "pc")]
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn Stage + Send>>,
+}
+
+#[cfg(feature = "pc")]
+impl core::fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("stages", &self.stages.len())
+            .finish()
+    }
+}
+
+#[cfg(feature = "pc")]
+impl Pipeline {
+    /// No stages yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The configured stages, in order.
+    pub fn from_config(stages: &[StageConfig]) -> Self {
+        Self {
+            stages: stages.iter().map(StageConfig::build).collect(),
+        }
+    }
+
+    /// Add a stage at the end.
+    pub fn with(mut self, stage: impl Stage + Send + 'static) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// Number of stages.
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// Whether there are no stages, i.e. every reading is passed on as it is.
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+}
+
+#[cfg(feature = "pc")]
+impl Stage for Pipeline {
+    fn process(&mut self, reading: TimestampedReading, emit: &mut dyn FnMut(TimestampedReading)) {
+        run(&mut self.stages, reading, emit);
+    }
+
+    /// Finishes the stages in order, so what the first stage still had goes through (and is buffered by) the rest
+    /// before they finish.
+    fn finish(&mut self, emit: &mut dyn FnMut(TimestampedReading)) {
+        for i in 0..self.stages.len() {
+            let (done, rest) = self.stages.split_at_mut(i + 1);
+            done[i].finish(&mut |reading| run(rest, reading, emit));
+        }
+    }
+}
+
+#[cfg(feature = "pc")]
+fn run(
+    stages: &mut [Box<dyn Stage + Send>],
+    reading: TimestampedReading,
+    emit: &mut dyn FnMut(TimestampedReading),
+) {
+    match stages.split_first_mut() {
+        Some((stage, rest)) => stage.process(reading, &mut |reading| run(rest, reading, emit)),
+        None => emit(reading),
+    }
+}
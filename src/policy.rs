@@ -0,0 +1,155 @@
+//! What to do when reads keep failing.
+//!
+//! [`Co2MonitorExt::read_to_part`](crate::Co2MonitorExt::read_to_part) swallows read errors, so on a dead device it
+//! just returns `Ok(None)` forever. [`Co2MonitorExt::read_with_policy`](crate::Co2MonitorExt::read_with_policy)
+//! instead tells apart "nothing complete yet", "this read failed, try again in a bit" and "give up on this device",
+//! based on a [`ReadPolicy`].
+//!
+//! The backoff is returned as a duration and the caller does the waiting, so the policy works with any executor or
+//! none at all, and needs no [`Clock`](crate::Clock) either.
+use crate::{MonitorError, MonitorHealth, MonitorReading};
+
+/// Result of [`Co2MonitorExt::read_with_policy`](crate::Co2MonitorExt::read_with_policy).
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ReadOutcome {
+    /// A reading was completed.
+    Reading(MonitorReading),
+    /// The read worked, but the reading isn't complete yet.
+    Pending,
+    /// The read failed. Wait `retry_after_ms` before reading again.
+    TransientError {
+        /// What went wrong.
+        error: MonitorError,
+        /// Backoff before the next read. 0 after timeouts, which already waited.
+        retry_after_ms: u32,
+    },
+    /// Too many reads in a row failed, the device is probably gone. Reconnect, or give up.
+    Unhealthy {
+        /// How many reads in a row failed.
+        consecutive_failures: u32,
+    },
+    /// The read was cancelled, stop reading. Doesn't count as a failure.
+    Cancelled,
+}
+
+/// Limits for reading, and the failures counted against them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ReadPolicy {
+    max_consecutive_failures: u32,
+    read_timeout_ms: u32,
+    initial_backoff_ms: u32,
+    max_backoff_ms: u32,
+    consecutive_failures: u32,
+    health: MonitorHealth,
+}
+
+impl ReadPolicy {
+    /// The device sends a report at least every 2 seconds, so 30 failed reads in a row (with a 1 second timeout) means
+    /// it's been quiet or broken for at least half a minute.
+    pub const DEFAULT_MAX_CONSECUTIVE_FAILURES: u32 = 30;
+    /// Timeout of a single read.
+    pub const DEFAULT_READ_TIMEOUT_MS: u32 = 1000;
+
+    /// Default limits, with a backoff from 100 ms doubling up to 5 s.
+    pub const fn new() -> Self {
+        Self {
+            max_consecutive_failures: Self::DEFAULT_MAX_CONSECUTIVE_FAILURES,
+            read_timeout_ms: Self::DEFAULT_READ_TIMEOUT_MS,
+            initial_backoff_ms: 100,
+            max_backoff_ms: 5000,
+            consecutive_failures: 0,
+            health: MonitorHealth::new(),
+        }
+    }
+
+    /// Report [`ReadOutcome::Unhealthy`] after this many failed reads in a row.
+    pub const fn with_max_consecutive_failures(mut self, max: u32) -> Self {
+        self.max_consecutive_failures = if max == 0 { 1 } else { max };
+        self
+    }
+
+    /// Give up on a single read after this long, see
+    /// [`Co2MonitorCommunication::read_timeout`](crate::Co2MonitorCommunication::read_timeout).
+    pub const fn with_read_timeout_ms(mut self, timeout_ms: u32) -> Self {
+        self.read_timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Wait `initial_ms` after the first failure, doubling with every further failure up to `max_ms`.
+    pub const fn with_backoff_ms(mut self, initial_ms: u32, max_ms: u32) -> Self {
+        self.initial_backoff_ms = initial_ms;
+        self.max_backoff_ms = max_ms;
+        self
+    }
+
+    /// Timeout of a single read.
+    pub fn read_timeout_ms(&self) -> u32 {
+        self.read_timeout_ms
+    }
+
+    /// How many reads in a row failed so far.
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    /// Counters of all reads done with this policy.
+    pub fn health(&self) -> &MonitorHealth {
+        &self.health
+    }
+
+    pub(crate) fn health_mut(&mut self) -> &mut MonitorHealth {
+        &mut self.health
+    }
+
+    /// Start counting failures from scratch, e.g. after reconnecting. The health counters are kept.
+    pub fn reset(&mut self) {
+        self.consecutive_failures = 0;
+    }
+
+    /// Turn the result of a read into an outcome. `error` is what went wrong with the read (if anything), `result`
+    /// what decoding made of it.
+    pub(crate) fn judge(
+        &mut self,
+        error: Option<MonitorError>,
+        result: Result<Option<MonitorReading>, MonitorError>,
+    ) -> ReadOutcome {
+        let error = error.or(result.err());
+        let Some(error) = error else {
+            self.consecutive_failures = 0;
+            return match result {
+                Ok(Some(reading)) => ReadOutcome::Reading(reading),
+                _ => ReadOutcome::Pending,
+            };
+        };
+        if error == MonitorError::Cancelled {
+            return ReadOutcome::Cancelled;
+        }
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        if self.consecutive_failures >= self.max_consecutive_failures {
+            return ReadOutcome::Unhealthy {
+                consecutive_failures: self.consecutive_failures,
+            };
+        }
+        let retry_after_ms = if error == MonitorError::Timeout {
+            0
+        } else {
+            let doublings = (self.consecutive_failures - 1).min(31);
+            self.initial_backoff_ms
+                .saturating_mul(1 << doublings)
+                .min(self.max_backoff_ms)
+        };
+        ReadOutcome::TransientError {
+            error,
+            retry_after_ms,
+        }
+    }
+}
+
+impl Default for ReadPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,317 @@
+//! What differs between the models of the ZyAura family: USB ids, the co2 range, humidity and the encryption.
+//!
+//! My ZGm053U is specced up to 3000 ppm, but other variants go up to 5000 ppm, some have a humidity sensor, and older
+//! firmware encrypts the reports with the key sent in the feature report. A [`DeviceProfile`] holds all of that, so it
+//! can be selected when connecting (by VID/PID, see [`find`]) instead of being hard coded. The built-in profiles are in
+//! [`BUILTIN`], others can be made with [`DeviceProfile::new`], e.g. from the `[profiles]` section of the config.
+//!
+//! There are lots of rebadged clones (Holtek is the USB vendor of the chip, TFA Dostmann, co2meter.com, ...), and most
+//! of them use the same VID/PID as the ZGm053U. On a PC, a [`ProfileRegistry`] holds the built-in profiles and the ones
+//! registered at runtime, so enumeration and
+//! [`PcCo2Monitor::open_any`](crate::pc::PcCo2Monitor::open_any) find any of them.
+//!
+//! Firmware of the same model differs in whether it encrypts, so every backend decrypts through an
+//! [`EncryptionDetector`], which notices encrypted reports even if the profile says there are none.
+use crate::{Conversions, parse_report, validity::ValidityModel};
+use core::sync::atomic::{AtomicU8, Ordering};
+#[cfg(feature = "pc")]
+use std::{string::String, vec::Vec};
+
+/// A model of the monitor, see the [module documentation](self).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceProfile {
+    /// USB vendor ID.
+    pub vendor_id: u16,
+    /// USB product ID.
+    pub product_id: u16,
+    /// Specced range of the co2 sensor, above that co2 is [`Co2Value::TooHigh`](crate::Co2Value::TooHigh).
+    pub max_ppm: u16,
+    /// Whether the device reports relative humidity.
+    pub has_humidity: bool,
+    /// Whether the reports are encrypted with `key`, see [`DeviceProfile::decrypt`].
+    pub encrypted: bool,
+    /// Sent in the feature report that starts the reports. Unencrypted devices don't care what it is.
+    pub key: [u8; 8],
+}
+
+impl DeviceProfile {
+    /// The ZGm053U (e.g. TFA Dostmann AirCO2ntrol Mini) with current firmware, the one this crate was written for.
+    pub const ZGM053U: Self = Self::new(0x04d9, 0xa052);
+
+    /// The ZGm053U with older firmware, which encrypts its reports. Same ids, so it has to be selected by name.
+    pub const ZGM053U_ENCRYPTED: Self = Self {
+        encrypted: true,
+        ..Self::ZGM053U
+    };
+
+    /// The newer "CO2Mini" generation (ZGm053UKA), with its own PID. It never encrypts, and sends a few more opcodes,
+    /// which are passed on as [unknown reports](crate::MonitorReportRaw::Unknown).
+    pub const CO2MINI: Self = Self::new(0x04d9, 0xa058);
+
+    /// A device with these ids, 3000 ppm, no humidity and no encryption.
+    pub const fn new(vendor_id: u16, product_id: u16) -> Self {
+        Self {
+            vendor_id,
+            product_id,
+            max_ppm: ValidityModel::DEFAULT.max_ppm,
+            has_humidity: false,
+            encrypted: false,
+            key: [0; 8],
+        }
+    }
+
+    /// The same profile with another key, e.g. one from [`random_key`].
+    pub const fn with_key(mut self, key: [u8; 8]) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// Whether this is the profile of a device with these ids.
+    pub fn matches(&self, vendor_id: u16, product_id: u16) -> bool {
+        self.vendor_id == vendor_id && self.product_id == product_id
+    }
+
+    /// The feature report to send when connecting: report ID 0 and the key.
+    pub fn feature_report(&self) -> [u8; 9] {
+        let mut report = [0u8; 9];
+        report[1..].copy_from_slice(&self.key);
+        report
+    }
+
+    /// The plausible co2 values of this model with `conversions`, i.e. [`ValidityModel::for_conversions`] up to
+    /// `max_ppm`.
+    pub fn validity(&self, conversions: Conversions) -> ValidityModel {
+        ValidityModel::new(
+            ValidityModel::for_conversions(conversions).min_ppm,
+            self.max_ppm,
+        )
+    }
+
+    /// Decrypt a report in place if the device encrypts them, otherwise leave it alone.
+    ///
+    /// The scheme was figured out by Henryk Plötz for the original "CO2 Meter Hacking" write-up: the bytes are
+    /// shuffled, xored with the key, rotated by 3 bits across the report and offset by a constant.
+    pub fn decrypt(&self, report: &mut [u8; 8]) {
+        if !self.encrypted {
+            return;
+        }
+        const SHUFFLE: [usize; 8] = [2, 4, 0, 7, 1, 6, 5, 3];
+        const MAGIC: [u8; 8] = *b"Htemp99e";
+        let mut shuffled = [0u8; 8];
+        for (i, &to) in SHUFFLE.iter().enumerate() {
+            shuffled[to] = report[i] ^ self.key[to];
+        }
+        for i in 0..8 {
+            let rotated = (shuffled[i] >> 3) | (shuffled[(i + 7) % 8] << 5);
+            report[i] = rotated.wrapping_sub(MAGIC[i].rotate_left(4));
+        }
+    }
+}
+
+impl Default for DeviceProfile {
+    fn default() -> Self {
+        Self::ZGM053U
+    }
+}
+
+/// Decrypts the reports of a device, if its profile says it encrypts them or they turn out to be encrypted.
+///
+/// A report that is invalid as it is, but valid after decrypting it with the key of the profile, could also be a
+/// corrupted one that happens to pass the checksum. So decryption is only switched on for good after
+/// [`DETECT_REPORTS`](Self::DETECT_REPORTS) of them in a row, the ones before are passed on as they are (and dropped as
+/// invalid). It works through `&self`, like reading does.
+#[derive(Debug)]
+pub struct EncryptionDetector {
+    /// The profile of the device, with encryption on.
+    encrypted: DeviceProfile,
+    /// Reports in a row that were only valid decrypted, `DETECT_REPORTS` once decryption is on.
+    streak: AtomicU8,
+}
+
+impl EncryptionDetector {
+    /// Reports in a row that have to be valid decrypted only before decryption is switched on.
+    pub const DETECT_REPORTS: u8 = 4;
+
+    /// Decrypt from the start if `profile` encrypts, otherwise once the reports turn out to be encrypted.
+    pub const fn new(profile: DeviceProfile) -> Self {
+        Self {
+            encrypted: DeviceProfile {
+                encrypted: true,
+                ..profile
+            },
+            streak: AtomicU8::new(if profile.encrypted {
+                Self::DETECT_REPORTS
+            } else {
+                0
+            }),
+        }
+    }
+
+    /// Whether the reports are decrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.streak.load(Ordering::Relaxed) >= Self::DETECT_REPORTS
+    }
+
+    /// Decrypt `report` in place if the device encrypts, see the [type documentation](Self).
+    pub fn decrypt(&self, report: &mut [u8; 8]) {
+        if self.is_encrypted() {
+            self.encrypted.decrypt(report);
+            return;
+        }
+        let mut decrypted = *report;
+        self.encrypted.decrypt(&mut decrypted);
+        if parse_report(report).is_ok() || parse_report(&decrypted).is_err() {
+            self.streak.store(0, Ordering::Relaxed);
+            return;
+        }
+        let streak = self.streak.load(Ordering::Relaxed) + 1;
+        self.streak.store(streak, Ordering::Relaxed);
+        if streak >= Self::DETECT_REPORTS {
+            event!(
+                info,
+                "the device encrypts its reports, decrypting from now on"
+            );
+            *report = decrypted;
+        }
+    }
+}
+
+/// The built-in profiles by name, devices with the same ids come first in the order they should be assumed.
+pub const BUILTIN: &[(&str, DeviceProfile)] = &[
+    ("zgm053u", DeviceProfile::ZGM053U),
+    ("zgm053u-encrypted", DeviceProfile::ZGM053U_ENCRYPTED),
+    ("co2mini", DeviceProfile::CO2MINI),
+    // The same device with a TFA Dostmann label.
+    ("tfa-airco2ntrol-mini", DeviceProfile::ZGM053U),
+    // The bigger TFA Dostmann model, it also reports humidity.
+    (
+        "tfa-airco2ntrol-coach",
+        DeviceProfile {
+            has_humidity: true,
+            ..DeviceProfile::ZGM053U
+        },
+    ),
+];
+
+/// The built-in profile called `name`.
+pub fn builtin(name: &str) -> Option<DeviceProfile> {
+    BUILTIN
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, profile)| *profile)
+}
+
+/// The first built-in profile for a device with these ids.
+pub fn find(vendor_id: u16, product_id: u16) -> Option<DeviceProfile> {
+    BUILTIN
+        .iter()
+        .find(|(_, profile)| profile.matches(vendor_id, product_id))
+        .map(|(_, profile)| *profile)
+}
+
+/// The name of the first built-in profile for a device with these ids, e.g. to show the model.
+pub fn name_of(vendor_id: u16, product_id: u16) -> Option<&'static str> {
+    BUILTIN
+        .iter()
+        .find(|(_, profile)| profile.matches(vendor_id, product_id))
+        .map(|(name, _)| *name)
+}
+
+/// A fresh key for the feature report, for firmware that doesn't like the all zero one. It only has to differ between
+/// connects, so the random seed of std's hash maps is good enough and saves a dependency.
+#[cfg(feature = "pc")]
+pub fn random_key() -> [u8; 8] {
+    use core::hash::{BuildHasher, Hasher};
+    let mut hasher = std::hash::RandomState::new().build_hasher();
+    hasher.write_u8(0);
+    hasher.finish().to_le_bytes()
+}
+
+/// The known profiles on a PC, built-in and registered at runtime, see the [module documentation](self).
+///
+/// Registered profiles take precedence over the built-in ones (and later ones over earlier ones) when looking up a
+/// device by its ids.
+#[cfg(feature = "pc")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProfileRegistry {
+    profiles: Vec<(String, DeviceProfile)>,
+}
+
+#[cfg(feature = "pc")]
+impl Default for ProfileRegistry {
+    /// The [built-in](BUILTIN) profiles.
+    fn default() -> Self {
+        Self {
+            profiles: BUILTIN
+                .iter()
+                .rev()
+                .map(|(name, profile)| (String::from(*name), *profile))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(feature = "pc")]
+impl ProfileRegistry {
+    /// The [built-in](BUILTIN) profiles.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// No profiles at all, not even the built-in ones.
+    pub fn empty() -> Self {
+        Self {
+            profiles: Vec::new(),
+        }
+    }
+
+    /// Add a profile, or replace the one with the same name.
+    pub fn register(&mut self, name: impl Into<String>, profile: DeviceProfile) {
+        let name = name.into();
+        self.profiles.retain(|(known, _)| *known != name);
+        self.profiles.push((name, profile));
+    }
+
+    /// Same as `register`, for chaining.
+    pub fn with(mut self, name: impl Into<String>, profile: DeviceProfile) -> Self {
+        self.register(name, profile);
+        self
+    }
+
+    /// The profile called `name`.
+    pub fn get(&self, name: &str) -> Option<DeviceProfile> {
+        self.profiles
+            .iter()
+            .find(|(known, _)| known == name)
+            .map(|(_, profile)| *profile)
+    }
+
+    /// The profile for a device with these ids, and its name.
+    pub fn find(&self, vendor_id: u16, product_id: u16) -> Option<(&str, DeviceProfile)> {
+        self.profiles
+            .iter()
+            .rev()
+            .find(|(_, profile)| profile.matches(vendor_id, product_id))
+            .map(|(name, profile)| (name.as_str(), *profile))
+    }
+
+    /// Every VID/PID pair of the profiles, once, to enumerate devices by.
+    pub fn ids(&self) -> Vec<(u16, u16)> {
+        let mut ids: Vec<(u16, u16)> = self
+            .profiles
+            .iter()
+            .map(|(_, profile)| (profile.vendor_id, profile.product_id))
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    /// The profiles and their names, the ones that take precedence last.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &DeviceProfile)> {
+        self.profiles
+            .iter()
+            .map(|(name, profile)| (name.as_str(), profile))
+    }
+}
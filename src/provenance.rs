@@ -0,0 +1,157 @@
+//! Which processing produced a reading.
+//!
+//! Every reading carries a small set of flags. Whatever smooths, calibrates, compensates or fuses readings sets its
+//! flag, and the flags end up in the csv log. That way it's clear later which numbers came straight from the device,
+//! and which can be reprocessed differently from the raw data.
+use core::str::FromStr;
+
+/// Set of processing steps that were applied to a reading. Empty for readings as they come from the device.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Provenance(u8);
+
+impl Provenance {
+    /// Nothing applied, straight from the device.
+    pub const RAW: Self = Self(0);
+    /// Averaged or filtered over time.
+    pub const SMOOTHED: Self = Self(1 << 0);
+    /// Interpreted with a calibration that differs from the defaults, e.g. a fitted [`crate::SanityCheckModel`].
+    pub const CALIBRATED: Self = Self(1 << 1);
+    /// Corrected for environmental effects, e.g. pressure or temperature.
+    pub const COMPENSATED: Self = Self(1 << 2);
+    /// Combined from several sensors.
+    pub const FUSED: Self = Self(1 << 3);
+
+    const LABELS: [(Self, &'static str); 4] = [
+        (Self::SMOOTHED, "smoothed"),
+        (Self::CALIBRATED, "calibrated"),
+        (Self::COMPENSATED, "compensated"),
+        (Self::FUSED, "fused"),
+    ];
+
+    /// The flags as a bitfield.
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Flags from a bitfield, unknown bits are dropped.
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits & 0b1111)
+    }
+
+    /// Whether no processing was applied.
+    pub const fn is_raw(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether all flags of `other` are set.
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Both sets of flags.
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Set the flags of `other`.
+    pub fn insert(&mut self, other: Self) {
+        *self = self.union(other);
+    }
+}
+
+impl core::ops::BitOr for Provenance {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        self.union(rhs)
+    }
+}
+
+impl core::ops::BitOrAssign for Provenance {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.insert(rhs);
+    }
+}
+
+/// `raw`, or the labels of the set flags joined by `+`, e.g. `smoothed+calibrated`.
+impl core::fmt::Display for Provenance {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.is_raw() {
+            return f.write_str("raw");
+        }
+        let mut first = true;
+        for (flag, label) in Self::LABELS {
+            if self.contains(flag) {
+                if !first {
+                    f.write_str("+")?;
+                }
+                f.write_str(label)?;
+                first = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A label in a provenance string wasn't recognized.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseProvenanceError;
+
+impl core::fmt::Display for ParseProvenanceError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(
+            "unknown provenance label, expected raw, smoothed, calibrated, compensated or fused",
+        )
+    }
+}
+
+impl core::error::Error for ParseProvenanceError {}
+
+/// Parses the `Display` format. An empty string is `raw` as well, that's what logs from before provenance contain.
+impl FromStr for Provenance {
+    type Err = ParseProvenanceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() || s.eq_ignore_ascii_case("raw") {
+            return Ok(Self::RAW);
+        }
+        s.split('+').try_fold(Self::RAW, |provenance, label| {
+            let label = label.trim();
+            Self::LABELS
+                .iter()
+                .find(|(_, l)| l.eq_ignore_ascii_case(label))
+                .map(|&(flag, _)| provenance | flag)
+                .ok_or(ParseProvenanceError)
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Provenance {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Provenance {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = Provenance;
+
+            fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.write_str("provenance labels joined by '+'")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Provenance, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(Visitor)
+    }
+}
@@ -0,0 +1,79 @@
+//! Air quality categories derived from co2, so every frontend colors and announces readings the same way.
+//!
+//! The default boundaries roughly follow the indoor air categories of EN 13779 (IDA 1-4), which in turn go back to
+//! Pettenkofer's 1000 ppm rule of thumb. They can be changed with [`QualityBoundaries`].
+use crate::Co2Value;
+
+/// How good the air is, judging by co2 alone.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Co2Quality {
+    /// Below 600 ppm by default.
+    Excellent,
+    /// Below 1000 ppm by default.
+    Good,
+    /// Below 1400 ppm by default.
+    Moderate,
+    /// Everything above, including out of spec readings.
+    Poor,
+}
+
+impl core::fmt::Display for Co2Quality {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            Co2Quality::Excellent => "excellent",
+            Co2Quality::Good => "good",
+            Co2Quality::Moderate => "moderate",
+            Co2Quality::Poor => "poor",
+        })
+    }
+}
+
+/// Upper (exclusive) co2 limits in ppm of the categories. Anything at or above `moderate_below` is
+/// [`Co2Quality::Poor`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QualityBoundaries {
+    /// Limit of [`Co2Quality::Excellent`].
+    pub excellent_below: u16,
+    /// Limit of [`Co2Quality::Good`].
+    pub good_below: u16,
+    /// Limit of [`Co2Quality::Moderate`].
+    pub moderate_below: u16,
+}
+
+impl QualityBoundaries {
+    /// 600, 1000 and 1400 ppm.
+    pub const DEFAULT: Self = Self {
+        excellent_below: 600,
+        good_below: 1000,
+        moderate_below: 1400,
+    };
+
+    /// Categorize a co2 value. Out of spec values are always [`Co2Quality::Poor`].
+    pub fn classify(&self, co2: Co2Value) -> Co2Quality {
+        let ppm = match co2 {
+            Co2Value::Valid(ppm) => ppm,
+            _ => return Co2Quality::Poor,
+        };
+        if ppm < self.excellent_below {
+            Co2Quality::Excellent
+        } else if ppm < self.good_below {
+            Co2Quality::Good
+        } else if ppm < self.moderate_below {
+            Co2Quality::Moderate
+        } else {
+            Co2Quality::Poor
+        }
+    }
+}
+
+impl Default for QualityBoundaries {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
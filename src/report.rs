@@ -0,0 +1,474 @@
+//! Summaries of a period, day by day, as text, Markdown or HTML for sharing.
+//!
+//! [`ReportBuilder`] takes the readings of a log in chronological order and collects for every day: the mean, 95th
+//! percentile and peak co2 (from [`RollingStats`] over the day), how long co2 was at or above the limits of
+//! [`Co2Quality::Good`](crate::Co2Quality::Good) and [`Co2Quality::Moderate`](crate::Co2Quality::Moderate) (1000 and
+//! 1400 ppm by default), the mean co2 during working hours and the ventilations found by a [`VentilationDetector`].
+//! Readings more than 10 minutes apart don't count towards the time above the limits, the device was probably offline.
+//! The statistics cover up to [`READINGS_PER_DAY`] readings, a day with more (like all rooms of a fleet log together)
+//! only has the last of them in the mean and the percentile.
+use crate::{
+    Co2Value, MonitorReading, QualityBoundaries, TimestampedReading,
+    stats::{READINGS_PER_DAY, RollingStats},
+    ventilation::{VentilationDetector, VentilationEvent},
+};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike, Weekday};
+use core::fmt::Write;
+use std::{
+    boxed::Box,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Time between two readings counts for at most this long.
+const MAX_STEP_MS: i64 = 10 * 60 * 1000;
+
+/// How to lay out a [`Report`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReportFormat {
+    /// Aligned columns, for a terminal or an email.
+    #[default]
+    Text,
+    /// A Markdown document with tables, for chats, issues and wikis.
+    Markdown,
+    /// A standalone HTML page.
+    Html,
+}
+
+impl core::fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            ReportFormat::Text => "text",
+            ReportFormat::Markdown => "markdown",
+            ReportFormat::Html => "html",
+        })
+    }
+}
+
+/// Returned when parsing [`ReportFormat`] from an invalid string.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseReportFormatError;
+
+impl core::fmt::Display for ParseReportFormatError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("expected text, markdown or html")
+    }
+}
+
+impl core::error::Error for ParseReportFormatError {}
+
+impl core::str::FromStr for ReportFormat {
+    type Err = ParseReportFormatError;
+
+    /// Accepts `text`, `markdown` (or `md`) and `html`, in any case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("text") {
+            Ok(Self::Text)
+        } else if s.eq_ignore_ascii_case("markdown") || s.eq_ignore_ascii_case("md") {
+            Ok(Self::Markdown)
+        } else if s.eq_ignore_ascii_case("html") {
+            Ok(Self::Html)
+        } else {
+            Err(ParseReportFormatError)
+        }
+    }
+}
+
+/// The summary of one day.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DaySummary {
+    /// The (local) date.
+    pub date: NaiveDate,
+    /// Readings on that day.
+    pub readings: u32,
+    /// Mean co2 in ppm of the readings within spec, `None` if there are none.
+    pub mean_co2: Option<f32>,
+    /// 95th percentile of co2 in ppm of the readings within spec.
+    pub p95_co2: Option<f32>,
+    /// Highest co2 in ppm within spec, and when it was read.
+    pub peak: Option<(u16, NaiveDateTime)>,
+    /// Minutes co2 was at or above [`QualityBoundaries::good_below`], including readings too high to measure.
+    pub minutes_above_good: f32,
+    /// Minutes co2 was at or above [`QualityBoundaries::moderate_below`], including readings too high to measure.
+    pub minutes_above_moderate: f32,
+    /// Mean co2 in ppm during working hours, `None` on weekends or without readings in working hours.
+    pub working_hours_mean_co2: Option<f32>,
+    /// Ventilations that started on that day.
+    pub ventilations: Vec<VentilationEvent>,
+}
+
+impl DaySummary {
+    fn new(date: NaiveDate) -> Self {
+        Self {
+            date,
+            readings: 0,
+            mean_co2: None,
+            p95_co2: None,
+            peak: None,
+            minutes_above_good: 0.0,
+            minutes_above_moderate: 0.0,
+            working_hours_mean_co2: None,
+            ventilations: Vec::new(),
+        }
+    }
+}
+
+/// Statistics of the day the readings are at, of all of its readings and those in working hours.
+#[derive(Clone, Debug)]
+struct DayStats {
+    date: NaiveDate,
+    all: Box<RollingStats<READINGS_PER_DAY>>,
+    working_hours: Box<RollingStats<READINGS_PER_DAY>>,
+}
+
+/// Collects the readings of a period into a [`Report`], see the [module documentation](self).
+#[derive(Clone, Debug)]
+pub struct ReportBuilder {
+    boundaries: QualityBoundaries,
+    working_hours: (u8, u8),
+    detector: VentilationDetector,
+    days: BTreeMap<NaiveDate, DaySummary>,
+    stats: Option<DayStats>,
+    last: Option<(NaiveDateTime, Co2Value)>,
+}
+
+impl Default for ReportBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReportBuilder {
+    /// The default quality boundaries, working hours from 9 to 17 on weekdays and the default ventilation detector.
+    pub fn new() -> Self {
+        Self {
+            boundaries: QualityBoundaries::DEFAULT,
+            working_hours: (9, 17),
+            detector: VentilationDetector::default(),
+            days: BTreeMap::new(),
+            stats: None,
+            last: None,
+        }
+    }
+
+    /// Count the time above other limits.
+    pub fn with_boundaries(mut self, boundaries: QualityBoundaries) -> Self {
+        self.boundaries = boundaries;
+        self
+    }
+
+    /// Working hours from `start` (inclusive) to `end` (exclusive), Monday to Friday.
+    pub fn with_working_hours(mut self, start: u8, end: u8) -> Self {
+        self.working_hours = (start, end);
+        self
+    }
+
+    /// Find ventilations with another detector.
+    pub fn with_detector(mut self, detector: VentilationDetector) -> Self {
+        self.detector = detector;
+        self
+    }
+
+    /// Add a reading, taken at the local time `timestamp`. Readings have to be in chronological order.
+    pub fn push(&mut self, timestamp: NaiveDateTime, reading: &MonitorReading) {
+        // The time since the previous reading counts for the previous reading.
+        if let Some((last_timestamp, last_co2)) = self.last {
+            let step_ms = (timestamp - last_timestamp).num_milliseconds();
+            if (0..=MAX_STEP_MS).contains(&step_ms) {
+                let minutes = step_ms as f32 / 60_000.0;
+                let ppm = match last_co2 {
+                    Co2Value::Valid(ppm) => Some(ppm),
+                    Co2Value::TooHigh(_) => Some(u16::MAX),
+                    _ => None,
+                };
+                let boundaries = self.boundaries;
+                let day = self.day(last_timestamp.date());
+                if ppm.is_some_and(|ppm| ppm >= boundaries.good_below) {
+                    day.minutes_above_good += minutes;
+                }
+                if ppm.is_some_and(|ppm| ppm >= boundaries.moderate_below) {
+                    day.minutes_above_moderate += minutes;
+                }
+            }
+        }
+        self.last = Some((timestamp, reading.co2_value));
+
+        let timestamped = TimestampedReading {
+            reading: *reading,
+            timestamp_ms: timestamp.and_utc().timestamp_millis() as u64,
+            sequence: 0,
+        };
+        if let Some(event) = self.detector.push(&timestamped) {
+            self.add_ventilation(event);
+        }
+
+        let (start, end) = self.working_hours;
+        let working = !matches!(timestamp.weekday(), Weekday::Sat | Weekday::Sun)
+            && (start..end).contains(&(timestamp.hour() as u8));
+        let date = timestamp.date();
+        if self.stats.as_ref().is_none_or(|stats| stats.date != date) {
+            self.finish_day();
+            self.stats = Some(DayStats {
+                date,
+                all: Box::new(RollingStats::new()),
+                working_hours: Box::new(RollingStats::new()),
+            });
+        }
+        if let Some(stats) = &mut self.stats {
+            stats.all.push(reading);
+            if working {
+                stats.working_hours.push(reading);
+            }
+        }
+        let day = self.day(date);
+        day.readings += 1;
+        // The summary has the peak, but not when it was.
+        if let Co2Value::Valid(ppm) = reading.co2_value
+            && day.peak.is_none_or(|(peak, _)| ppm > peak)
+        {
+            day.peak = Some((ppm, timestamp));
+        }
+    }
+
+    /// The report of all readings so far.
+    pub fn finish(mut self) -> Report {
+        if let Some(event) = self.detector.finish() {
+            self.add_ventilation(event);
+        }
+        self.finish_day();
+        Report {
+            boundaries: self.boundaries,
+            working_hours: self.working_hours,
+            days: self.days.into_values().collect(),
+        }
+    }
+
+    /// Put the statistics of the day the readings were at into its summary.
+    fn finish_day(&mut self) {
+        let Some(stats) = self.stats.take() else {
+            return;
+        };
+        let co2 = stats.all.co2();
+        let day = self.day(stats.date);
+        day.mean_co2 = co2.map(|co2| co2.mean);
+        day.p95_co2 = co2.map(|co2| co2.p95);
+        day.working_hours_mean_co2 = stats.working_hours.co2().map(|co2| co2.mean);
+    }
+
+    fn day(&mut self, date: NaiveDate) -> &mut DaySummary {
+        self.days
+            .entry(date)
+            .or_insert_with(|| DaySummary::new(date))
+    }
+
+    fn add_ventilation(&mut self, event: VentilationEvent) {
+        let date = local(event.start_ms).date();
+        self.day(date).ventilations.push(event);
+    }
+}
+
+/// The timestamps of the events are local times as milliseconds, like those given to the detector.
+fn local(ms: u64) -> NaiveDateTime {
+    chrono::DateTime::from_timestamp_millis(ms as i64)
+        .unwrap_or_default()
+        .naive_utc()
+}
+
+/// The summary of a period, day by day.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    /// The limits the time above is counted for.
+    pub boundaries: QualityBoundaries,
+    /// Working hours, Monday to Friday, from the first (inclusive) to the second (exclusive).
+    pub working_hours: (u8, u8),
+    /// Every day with readings, in order.
+    pub days: Vec<DaySummary>,
+}
+
+impl Report {
+    /// Lay the report out in `format`.
+    pub fn render(&self, format: ReportFormat) -> String {
+        let title = match (self.days.first(), self.days.last()) {
+            (Some(first), Some(last)) if first.date != last.date => {
+                format!("Co2 report {} to {}", first.date, last.date)
+            }
+            (Some(first), _) => format!("Co2 report {}", first.date),
+            _ => String::from("Co2 report"),
+        };
+        let header = [
+            String::from("Date"),
+            String::from("Mean"),
+            String::from("P95"),
+            String::from("Peak"),
+            format!("≥ {} ppm", self.boundaries.good_below),
+            format!("≥ {} ppm", self.boundaries.moderate_below),
+            format!(
+                "Working hours ({}-{})",
+                self.working_hours.0, self.working_hours.1
+            ),
+            String::from("Ventilations"),
+        ];
+        let mut rows: Vec<[String; 8]> = self.days.iter().map(day_row).collect();
+        rows.push(self.total_row());
+        let ventilations: Vec<String> = self
+            .days
+            .iter()
+            .flat_map(|day| &day.ventilations)
+            .map(|event| {
+                format!(
+                    "{} to {}: {} → {} ppm in {:.0} min",
+                    local(event.start_ms).format("%Y-%m-%d %H:%M"),
+                    local(event.end_ms).format("%H:%M"),
+                    event.start_ppm,
+                    event.end_ppm,
+                    event.minutes()
+                )
+            })
+            .collect();
+
+        let mut out = String::new();
+        match format {
+            ReportFormat::Text => {
+                let mut widths = header.clone().map(|cell| cell.chars().count());
+                for row in &rows {
+                    for (width, cell) in widths.iter_mut().zip(row) {
+                        *width = (*width).max(cell.chars().count());
+                    }
+                }
+                let mut line = |cells: &[String; 8]| {
+                    let mut line = String::new();
+                    for (cell, width) in cells.iter().zip(widths) {
+                        let _ = write!(line, "{cell:<width$}  ");
+                    }
+                    let _ = writeln!(out, "{}", line.trim_end());
+                };
+                line(&header);
+                for row in &rows {
+                    line(row);
+                }
+                out.insert_str(0, &format!("{title}\n\n"));
+                if !ventilations.is_empty() {
+                    out.push_str("\nVentilations:\n");
+                    for ventilation in &ventilations {
+                        let _ = writeln!(out, "  {ventilation}");
+                    }
+                }
+            }
+            ReportFormat::Markdown => {
+                let _ = writeln!(out, "# {title}\n");
+                let _ = writeln!(out, "| {} |", header.join(" | "));
+                let _ = writeln!(out, "|{}", "---|".repeat(header.len()));
+                for row in &rows {
+                    let _ = writeln!(out, "| {} |", row.join(" | "));
+                }
+                if !ventilations.is_empty() {
+                    let _ = writeln!(out, "\n## Ventilations\n");
+                    for ventilation in &ventilations {
+                        let _ = writeln!(out, "- {ventilation}");
+                    }
+                }
+            }
+            ReportFormat::Html => {
+                let cells = |tag: &str, row: &[String; 8]| {
+                    row.iter()
+                        .map(|cell| format!("<{tag}>{}</{tag}>", escape_html(cell)))
+                        .collect::<String>()
+                };
+                let _ = writeln!(
+                    out,
+                    "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+                     <style>body {{ font-family: sans-serif; }} table {{ border-collapse: collapse; }} \
+                     th, td {{ border: 1px solid #ccc; padding: 4px 8px; text-align: right; }}</style>\n\
+                     </head>\n<body>\n<h1>{title}</h1>\n<table>\n<tr>{}</tr>",
+                    cells("th", &header)
+                );
+                for row in &rows {
+                    let _ = writeln!(out, "<tr>{}</tr>", cells("td", row));
+                }
+                out.push_str("</table>\n");
+                if !ventilations.is_empty() {
+                    out.push_str("<h2>Ventilations</h2>\n<ul>\n");
+                    for ventilation in &ventilations {
+                        let _ = writeln!(out, "<li>{}</li>", escape_html(ventilation));
+                    }
+                    out.push_str("</ul>\n");
+                }
+                out.push_str("</body>\n</html>\n");
+            }
+        }
+        out
+    }
+
+    /// The whole period in the columns of the days: means weighted by the number of readings, the highest peak and
+    /// the sums of the rest. Percentiles of the days don't add up to one of the period, that column stays empty.
+    fn total_row(&self) -> [String; 8] {
+        let weighted = |value: fn(&DaySummary) -> Option<f32>| {
+            let (sum, count) = self
+                .days
+                .iter()
+                .filter_map(|day| value(day).map(|mean| (mean * day.readings as f32, day.readings)))
+                .fold((0.0, 0), |(sum, count), (s, c)| (sum + s, count + c));
+            (count > 0).then(|| sum / count as f32)
+        };
+        let total = DaySummary {
+            mean_co2: weighted(|day| day.mean_co2),
+            peak: self
+                .days
+                .iter()
+                .filter_map(|day| day.peak)
+                .max_by_key(|(ppm, _)| *ppm),
+            minutes_above_good: self.days.iter().map(|day| day.minutes_above_good).sum(),
+            minutes_above_moderate: self.days.iter().map(|day| day.minutes_above_moderate).sum(),
+            working_hours_mean_co2: weighted(|day| day.working_hours_mean_co2),
+            ventilations: self
+                .days
+                .iter()
+                .flat_map(|day| day.ventilations.iter().copied())
+                .collect(),
+            ..DaySummary::new(NaiveDate::default())
+        };
+        let mut row = day_row(&total);
+        row[0] = String::from("Total");
+        if let Some((ppm, timestamp)) = total.peak {
+            row[3] = format!("{ppm} ({})", timestamp.format("%Y-%m-%d %H:%M"));
+        }
+        row
+    }
+}
+
+fn day_row(day: &DaySummary) -> [String; 8] {
+    let ppm =
+        |value: Option<f32>| value.map_or_else(|| String::from("-"), |ppm| format!("{ppm:.0}"));
+    [
+        day.date.to_string(),
+        ppm(day.mean_co2),
+        ppm(day.p95_co2),
+        day.peak.map_or_else(
+            || String::from("-"),
+            |(ppm, timestamp)| format!("{ppm} ({})", timestamp.format("%H:%M")),
+        ),
+        duration(day.minutes_above_good),
+        duration(day.minutes_above_moderate),
+        ppm(day.working_hours_mean_co2),
+        day.ventilations.len().to_string(),
+    ]
+}
+
+/// e.g. `2 h 05 min`.
+fn duration(minutes: f32) -> String {
+    let minutes = minutes.round() as u32;
+    if minutes >= 60 {
+        format!("{} h {:02} min", minutes / 60, minutes % 60)
+    } else {
+        format!("{minutes} min")
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
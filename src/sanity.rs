@@ -0,0 +1,200 @@
+//! Conversion of the raw sanity check reports (opcode `0x6e`) into an approximate co2 value.
+//!
+//! The raw value decreases with increasing co2. On my unit `12811 - raw` is roughly the co2 in ppm, but other units
+//! seem to need a different offset, and the relation isn't exactly 1:1 either. Hence this is a configurable linear
+//! model, which can also be fitted from collected data.
+
+/// Linear model that estimates the co2 ppm from a raw sanity check value as `offset - scale * raw`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SanityCheckModel {
+    /// The raw value at which the estimate reaches 0 ppm, multiplied by `scale`.
+    pub offset: f32,
+    /// How many ppm one step of the raw value corresponds to.
+    pub scale: f32,
+}
+
+impl SanityCheckModel {
+    /// The offset that works for my ZGm053U.
+    pub const DEFAULT_OFFSET: f32 = 12811.0;
+
+    /// Create a model with the given offset and scale.
+    pub const fn new(offset: f32, scale: f32) -> Self {
+        Self { offset, scale }
+    }
+
+    fn estimate(&self, raw: u16) -> f32 {
+        self.offset - self.scale * f32::from(raw)
+    }
+
+    /// Estimate the co2 ppm for `raw`. Returns `None` if the estimate doesn't fit into a `u16`, i.e. the raw value is
+    /// outside of what this model can explain.
+    pub fn checked_estimate(&self, raw: u16) -> Option<u16> {
+        let estimate = self.estimate(raw);
+        if !(0.0..=f32::from(u16::MAX)).contains(&estimate) {
+            return None;
+        }
+        Some((estimate + 0.5) as u16)
+    }
+
+    /// Estimate the co2 ppm for `raw`, keeping track of whether the model could explain the raw value at all.
+    pub fn classify(&self, raw: u16) -> SanityCheck {
+        match self.checked_estimate(raw) {
+            Some(estimate) => SanityCheck::InRange(estimate),
+            None => SanityCheck::OutOfModel(raw),
+        }
+    }
+
+    /// Estimate the co2 ppm for `raw`, clamped to the range of a `u16`.
+    pub fn saturating_estimate(&self, raw: u16) -> u16 {
+        // Float to int casts saturate (and map NaN to 0).
+        (self.estimate(raw) + 0.5) as u16
+    }
+
+    /// Fit a model from `(co2_ppm, raw_sanity_check)` pairs with least squares. See [`SanityCheckFit`].
+    pub fn fit(pairs: impl IntoIterator<Item = (u16, u16)>) -> Option<Self> {
+        let mut fit = SanityCheckFit::new();
+        for (co2_ppm, raw) in pairs {
+            fit.add(co2_ppm, raw);
+        }
+        fit.model()
+    }
+}
+
+impl Default for SanityCheckModel {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_OFFSET, 1.0)
+    }
+}
+
+/// A sanity check report interpreted by a [`SanityCheckModel`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[non_exhaustive]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SanityCheck {
+    /// The estimated co2 ppm.
+    InRange(u16),
+    /// The model can't explain this raw value, e.g. because the estimate would be negative. Contains the raw value.
+    OutOfModel(u16),
+}
+
+/// Accumulates `(co2_ppm, raw_sanity_check)` pairs to fit a [`SanityCheckModel`], without having to store them.
+///
+/// Only feed pairs where the co2 value is trustworthy, i.e. well below the 3000 ppm the device is specced for. The
+/// sanity check is only interesting above that, but the model is linear so it should extrapolate okay-ish.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SanityCheckFit {
+    n: u32,
+    sum_raw: f64,
+    sum_co2: f64,
+    sum_raw_sq: f64,
+    sum_raw_co2: f64,
+}
+
+impl SanityCheckFit {
+    /// Create an empty fit.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a pair of a co2 report and the sanity check report that belongs to the same cycle.
+    pub fn add(&mut self, co2_ppm: u16, raw: u16) {
+        let (x, y) = (f64::from(raw), f64::from(co2_ppm));
+        self.n += 1;
+        self.sum_raw += x;
+        self.sum_co2 += y;
+        self.sum_raw_sq += x * x;
+        self.sum_raw_co2 += x * y;
+    }
+
+    /// Number of pairs added so far.
+    pub fn len(&self) -> u32 {
+        self.n
+    }
+
+    /// Whether no pairs have been added yet.
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    /// The least squares model of the pairs seen so far. `None` if there are fewer than two distinct raw values, or
+    /// if the raw value doesn't decrease with increasing co2, which is not what the sanity check is supposed to do.
+    pub fn model(&self) -> Option<SanityCheckModel> {
+        let n = f64::from(self.n);
+        let variance = n * self.sum_raw_sq - self.sum_raw * self.sum_raw;
+        if self.n < 2 || variance <= 0.0 {
+            return None;
+        }
+        let slope = (n * self.sum_raw_co2 - self.sum_raw * self.sum_co2) / variance;
+        if slope >= 0.0 {
+            return None;
+        }
+        let intercept = (self.sum_co2 - slope * self.sum_raw) / n;
+        Some(SanityCheckModel::new(intercept as f32, -slope as f32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Co2Value, Conversions, MonitorReadingParts, OPCODE_CO2_SANITY_CHECK, OPCODE_CO2_VALUE,
+        OPCODE_TEMPERATURE, ValidityModel,
+    };
+
+    /// The co2 of a reading of 800 ppm with the sanity check `raw`, with the default model.
+    fn co2_with_sanity(conversions: Conversions, raw: u16) -> Co2Value {
+        let mut parts = MonitorReadingParts::new();
+        parts.conversions = conversions;
+        parts.validity = ValidityModel::for_conversions(conversions);
+        parts.set_op_val(OPCODE_TEMPERATURE, 4700);
+        parts.set_op_val(OPCODE_CO2_VALUE, 800);
+        parts.set_op_val(OPCODE_CO2_SANITY_CHECK, raw);
+        parts.to_reading().expect("all parts were set").co2_value
+    }
+
+    #[test]
+    fn raw_zero_is_too_high() {
+        // 12811 ppm estimated, far beyond the sensor range.
+        assert_eq!(
+            co2_with_sanity(Conversions::Legacy, 0),
+            Co2Value::TooHigh(800)
+        );
+        assert_eq!(co2_with_sanity(Conversions::V2, 0), Co2Value::TooHigh(800));
+    }
+
+    #[test]
+    fn raw_at_zero_point_is_valid() {
+        assert_eq!(
+            co2_with_sanity(Conversions::Legacy, 12811),
+            Co2Value::Valid(800)
+        );
+        assert_eq!(
+            co2_with_sanity(Conversions::V2, 12811),
+            Co2Value::Valid(800)
+        );
+    }
+
+    #[test]
+    fn raw_beyond_zero_point_is_only_valid_with_v2() {
+        assert_eq!(
+            co2_with_sanity(Conversions::Legacy, 12812),
+            Co2Value::TooHigh(800)
+        );
+        assert_eq!(
+            co2_with_sanity(Conversions::V2, 12812),
+            Co2Value::Valid(800)
+        );
+    }
+
+    #[test]
+    fn raw_max_is_only_valid_with_v2() {
+        assert_eq!(
+            co2_with_sanity(Conversions::Legacy, u16::MAX),
+            Co2Value::TooHigh(800)
+        );
+        assert_eq!(
+            co2_with_sanity(Conversions::V2, u16::MAX),
+            Co2Value::Valid(800)
+        );
+    }
+}
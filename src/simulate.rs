@@ -0,0 +1,155 @@
+//! A fake monitor that produces realistic HID reports, for demos and for testing the whole pipeline without hardware.
+//!
+//! Co2 rises while the simulated room is occupied and decays exponentially towards outdoor levels when it's
+//! ventilated. Peaks sometimes go beyond 3000 ppm, where the co2 opcode reports 1065 like the real device does and only
+//! the sanity check tells that the value is too high. Every now and then a report has a broken checksum.
+//!
+//! The values of the undocumented opcodes (`0x43`, `0x44`, `0x4f`) are made up, they are only there so the reports
+//! come in a realistic mix.
+use crate::{
+    OPCODE_CO2_SANITY_CHECK, OPCODE_CO2_UNFILTERED, OPCODE_CO2_VALUE, OPCODE_DIAGNOSTIC_C,
+    OPCODE_DIAGNOSTIC_D, OPCODE_DIAGNOSTIC_O, OPCODE_TEMPERATURE, SanityCheckModel,
+    device::{Co2MonitorCommunication, MonitorError},
+};
+use core::cell::Cell;
+
+/// Order in which the reports of one measurement cycle are sent.
+const CYCLE: [u8; 7] = [
+    OPCODE_TEMPERATURE,
+    OPCODE_CO2_VALUE,
+    OPCODE_CO2_UNFILTERED,
+    OPCODE_CO2_SANITY_CHECK,
+    OPCODE_DIAGNOSTIC_O,
+    OPCODE_DIAGNOSTIC_C,
+    OPCODE_DIAGNOSTIC_D,
+];
+
+/// What the real device reports on the co2 opcode once it's beyond its range.
+const OUT_OF_RANGE_CO2: u16 = 1065;
+const OUTDOOR_CO2: f32 = 420.0;
+
+#[derive(Copy, Clone, Debug)]
+struct State {
+    rng: u64,
+    slot: usize,
+    co2: f32,
+    peak: f32,
+    occupied: bool,
+    temperature: f32,
+}
+
+impl State {
+    fn next_random(&mut self) -> u64 {
+        // xorshift64*
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in `-1.0..1.0`.
+    fn noise(&mut self) -> f32 {
+        (self.next_random() >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+
+    /// Advance the room by one measurement cycle, about 5 seconds.
+    fn advance(&mut self) {
+        if self.occupied {
+            self.co2 += 4.0 + 2.0 * self.noise();
+            if self.co2 >= self.peak {
+                self.occupied = false;
+            }
+        } else {
+            self.co2 += (OUTDOOR_CO2 - self.co2) * 0.02;
+            if self.co2 < OUTDOOR_CO2 + 60.0 {
+                self.occupied = true;
+                // Mostly normal rooms, but sometimes a peak beyond the range of the sensor.
+                self.peak = 800.0 + (self.next_random() % 2800) as f32;
+            }
+        }
+        self.temperature = (self.temperature + 0.02 * self.noise()).clamp(19.0, 26.0);
+    }
+}
+
+/// Simulates a ZGm053U. Every `read` returns the next report of the current measurement cycle, immediately.
+#[derive(Debug)]
+pub struct SimulatedCo2Monitor {
+    state: Cell<State>,
+    checksum_error_one_in: u64,
+}
+
+impl SimulatedCo2Monitor {
+    /// The seed used by `init_and_connect`.
+    pub const DEFAULT_SEED: u64 = 0x5eed_c020;
+
+    /// A simulation that is reproducible for the same `seed`.
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            state: Cell::new(State {
+                // xorshift gets stuck at 0.
+                rng: seed | 1,
+                slot: 0,
+                co2: OUTDOOR_CO2 + 100.0,
+                peak: 1200.0,
+                occupied: true,
+                temperature: 22.0,
+            }),
+            checksum_error_one_in: 500,
+        }
+    }
+
+    /// Break the checksum of about one in `n` reports, 0 to never break it. The default is 500.
+    pub fn with_checksum_errors_one_in(mut self, n: u64) -> Self {
+        self.checksum_error_one_in = n;
+        self
+    }
+
+    /// The actual co2 of the simulated room in ppm, which the device can't report beyond 3000 ppm.
+    pub fn true_co2(&self) -> f32 {
+        self.state.get().co2
+    }
+
+    /// The actual temperature of the simulated room in degrees Celsius.
+    pub fn true_temperature(&self) -> f32 {
+        self.state.get().temperature
+    }
+}
+
+impl Co2MonitorCommunication for SimulatedCo2Monitor {
+    fn init_and_connect() -> Self {
+        Self::with_seed(Self::DEFAULT_SEED)
+    }
+
+    fn read(&self, read_buffer: &mut [u8; 8]) -> Result<usize, MonitorError> {
+        let mut state = self.state.get();
+        if state.slot == 0 {
+            state.advance();
+        }
+        let op = CYCLE[state.slot];
+        state.slot = (state.slot + 1) % CYCLE.len();
+
+        let co2 = state.co2 + 3.0 * state.noise();
+        let value = match op {
+            OPCODE_TEMPERATURE => ((state.temperature + 273.15) * 16.0 + 0.5) as u16,
+            OPCODE_CO2_VALUE if co2 > 3000.0 => OUT_OF_RANGE_CO2,
+            OPCODE_CO2_VALUE | OPCODE_CO2_UNFILTERED => (co2 + 0.5) as u16,
+            OPCODE_CO2_SANITY_CHECK => (SanityCheckModel::DEFAULT_OFFSET - co2 + 0.5) as u16,
+            OPCODE_DIAGNOSTIC_O => 9050,
+            OPCODE_DIAGNOSTIC_C => 3320,
+            _ => 1820,
+        };
+        let [high, low] = value.to_be_bytes();
+        let mut checksum = op.wrapping_add(high).wrapping_add(low);
+        if self.checksum_error_one_in != 0
+            && state
+                .next_random()
+                .is_multiple_of(self.checksum_error_one_in)
+        {
+            checksum ^= 0x5a;
+        }
+        *read_buffer = [op, high, low, checksum, 0x0d, 0, 0, 0];
+
+        self.state.set(state);
+        Ok(8)
+    }
+}
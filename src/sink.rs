@@ -0,0 +1,194 @@
+//! Send readings to network sinks from a background thread, in batches, so a slow endpoint never stalls reading the
+//! device.
+//!
+//! A [`BatchSink`] only knows how to send a batch of items. [`SinkQueue`] hands it the items of every reading through
+//! a bounded queue: when the sink falls behind by more than [`Batching::queue_limit`] readings, new readings are dropped
+//! (and counted) instead of piling up in memory. Batches are sent every [`Batching::readings`] readings or after
+//! [`Batching::max_delay`], whatever comes first. With a [`Spool`], what can't be sent is kept on disk until the sink is
+//! back.
+use crate::spool::Spool;
+use serde::{Serialize, de::DeserializeOwned};
+use std::{
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError, SyncSender, TrySendError},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+    vec::Vec,
+};
+
+/// Something readings can be sent to over the network.
+pub trait BatchSink: Send + 'static {
+    /// What is sent, a reading can consist of several items.
+    type Item: Serialize + DeserializeOwned + Send + 'static;
+
+    /// Name for log messages, e.g. `zabbix`.
+    fn name(&self) -> &str;
+
+    /// Send `items` in as few requests as possible. An error means nothing was sent and it's worth trying again later,
+    /// items the other side rejects should be logged and count as sent.
+    fn send(&mut self, items: &[Self::Item]) -> io::Result<()>;
+}
+
+/// When [`SinkQueue`] sends.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Batching {
+    /// Send once this many readings are collected (at least 1).
+    pub readings: usize,
+    /// Send at the latest this long after the first reading of the batch.
+    pub max_delay: Duration,
+    /// Readings that may wait for the sink thread (at least 1), more are dropped.
+    pub queue_limit: usize,
+}
+
+impl Default for Batching {
+    /// Every reading on its own, as soon as possible, and a queue of up to 1000 readings.
+    fn default() -> Self {
+        Self {
+            readings: 1,
+            max_delay: Duration::ZERO,
+            queue_limit: 1000,
+        }
+    }
+}
+
+/// Feeds a [`BatchSink`] in a background thread, see the [module documentation](self).
+///
+/// Dropping the queue sends what is left and waits for the thread.
+#[derive(Debug)]
+pub struct SinkQueue<T> {
+    sender: Option<SyncSender<Vec<T>>>,
+    dropped: Arc<AtomicU64>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<T: Serialize + DeserializeOwned + Send + 'static> SinkQueue<T> {
+    /// Start sending to `sink`, and spool to `spool` whatever can't be sent.
+    pub fn spawn<S: BatchSink<Item = T>>(
+        mut sink: S,
+        batching: Batching,
+        mut spool: Option<Spool<T>>,
+    ) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<Vec<T>>(batching.queue_limit.max(1));
+        let thread = std::thread::spawn(move || {
+            let mut batch = Vec::new();
+            let mut readings = 0;
+            let mut started: Option<Instant> = None;
+            loop {
+                let received = match started {
+                    Some(started) => {
+                        receiver.recv_timeout(batching.max_delay.saturating_sub(started.elapsed()))
+                    }
+                    None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                let disconnected = match received {
+                    Ok(items) => {
+                        batch.extend(items);
+                        readings += 1;
+                        started.get_or_insert_with(Instant::now);
+                        false
+                    }
+                    Err(RecvTimeoutError::Timeout) => false,
+                    Err(RecvTimeoutError::Disconnected) => true,
+                };
+                if disconnected
+                    || readings >= batching.readings
+                    || started.is_some_and(|started| started.elapsed() >= batching.max_delay)
+                {
+                    if !batch.is_empty() {
+                        send(&mut sink, spool.as_mut(), &batch);
+                    }
+                    batch.clear();
+                    readings = 0;
+                    started = None;
+                }
+                if disconnected {
+                    return;
+                }
+            }
+        });
+        Self {
+            sender: Some(sender),
+            dropped: Arc::default(),
+            thread: Some(thread),
+        }
+    }
+
+    /// Queue the items of a reading. Returns `false` if the queue is full, then the reading is dropped.
+    pub fn push(&self, items: Vec<T>) -> bool {
+        let Some(sender) = &self.sender else {
+            return false;
+        };
+        match sender.try_send(items) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+
+    /// Readings that were dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Drop for SinkQueue<T> {
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Send `batch`, or spool it if the sink can't be reached. Whatever is spooled goes first, to keep the order.
+fn send<S: BatchSink>(sink: &mut S, spool: Option<&mut Spool<S::Item>>, batch: &[S::Item]) {
+    let Some(spool) = spool else {
+        if let Err(e) = sink.send(batch) {
+            event!(warn, "could not send readings to {}: {}", sink.name(), e);
+        }
+        return;
+    };
+    let was_empty = spool.is_empty();
+    if was_empty && sink.send(batch).is_ok() {
+        return;
+    }
+    let dropped = spool.dropped();
+    if let Err(e) = spool.push(batch) {
+        event!(error, "could not spool readings for {}: {}", sink.name(), e);
+    }
+    if spool.dropped() > dropped {
+        event!(
+            warn,
+            "the {} spool is full, dropped {} items",
+            sink.name(),
+            spool.dropped() - dropped
+        );
+    }
+    if was_empty {
+        event!(
+            warn,
+            "{} is unreachable, spooling readings to {}",
+            sink.name(),
+            spool.path().display()
+        );
+        return;
+    }
+    // Catching up in requests of 100 batches.
+    match spool.drain(batch.len() * 100, |items| sink.send(items)) {
+        Ok(sent) if sent > 0 => event!(info, "sent {} spooled items to {}", sent, sink.name()),
+        Ok(_) => (),
+        Err(e) => event!(
+            debug,
+            "{} is still unreachable, {} items spooled: {}",
+            sink.name(),
+            spool.len(),
+            e
+        ),
+    }
+}
@@ -0,0 +1,171 @@
+//! Spoken announcements through the text-to-speech engine of the OS, for people who can't read the display.
+//!
+//! There is no speech synthesis in here, the text is handed to `say` on macOS, to `System.Speech` via PowerShell on
+//! Windows and to `spd-say` (speech-dispatcher, which screen readers like Orca use as well) everywhere else.
+use crate::{
+    Co2Value, MonitorReading, TemperatureUnit,
+    alert::{AlertEvent, AlertKind, Transition},
+    notification::Notification,
+};
+use std::{
+    format, io,
+    process::{Child, Command, Stdio},
+    string::String,
+};
+
+/// Speaks texts, one at a time.
+#[derive(Debug)]
+pub struct Announcer {
+    program: Option<String>,
+    speaking: Option<Child>,
+}
+
+impl Announcer {
+    /// Use the default speech engine of the OS.
+    pub fn new() -> Self {
+        Self {
+            program: None,
+            speaking: None,
+        }
+    }
+
+    /// Use `program` instead, e.g. `espeak-ng`. It is called with the text as its only argument.
+    pub fn with_program(program: impl Into<String>) -> Self {
+        Self {
+            program: Some(program.into()),
+            speaking: None,
+        }
+    }
+
+    fn command(&self, text: &str) -> Command {
+        if let Some(program) = &self.program {
+            let mut command = Command::new(program);
+            command.arg(text);
+            return command;
+        }
+        if cfg!(target_os = "macos") {
+            let mut command = Command::new("say");
+            command.arg(text);
+            command
+        } else if cfg!(target_os = "windows") {
+            let mut command = Command::new("powershell");
+            command.args([
+                "-NoProfile",
+                "-Command",
+                &format!(
+                    "Add-Type -AssemblyName System.Speech; \
+                     (New-Object System.Speech.Synthesis.SpeechSynthesizer).Speak('{}')",
+                    text.replace('\'', "''")
+                ),
+            ]);
+            command
+        } else {
+            let mut command = Command::new("spd-say");
+            command.arg(text);
+            command
+        }
+    }
+
+    /// Start speaking `text`. Doesn't wait for it to be spoken, but an announcement that is still running is finished
+    /// first, so announcements don't talk over each other.
+    pub fn say(&mut self, text: &str) -> io::Result<()> {
+        if let Some(mut previous) = self.speaking.take() {
+            previous.wait()?;
+        }
+        let child = self
+            .command(text)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .spawn()?;
+        self.speaking = Some(child);
+        Ok(())
+    }
+}
+
+impl Default for Announcer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn unit_name(unit: TemperatureUnit) -> &'static str {
+    match unit {
+        TemperatureUnit::Fahrenheit => "degrees Fahrenheit",
+        TemperatureUnit::Kelvin => "Kelvin",
+        _ => "degrees Celsius",
+    }
+}
+
+/// Spoken form of an alert, with temperatures in `unit`.
+pub fn alert_text(event: &AlertEvent, unit: TemperatureUnit) -> String {
+    match event.kind {
+        AlertKind::Co2High => match event.transition {
+            Transition::Raised => format!(
+                "C O 2 is above {:.0} parts per million, now {:.0}. Please ventilate.",
+                event.limit, event.value
+            ),
+            Transition::Cleared => format!(
+                "C O 2 is back to normal, {:.0} parts per million.",
+                event.value
+            ),
+        },
+        AlertKind::TemperatureLow | AlertKind::TemperatureHigh => {
+            let value = unit.from_celsius(event.value);
+            let limit = unit.from_celsius(event.limit);
+            let name = unit_name(unit);
+            match (event.kind, event.transition) {
+                (_, Transition::Cleared) => {
+                    format!("Temperature is back to normal, {value:.0} {name}.")
+                }
+                (AlertKind::TemperatureLow, _) => {
+                    format!("Temperature is below {limit:.0} {name}, now {value:.0}.")
+                }
+                _ => format!("Temperature is above {limit:.0} {name}, now {value:.0}."),
+            }
+        }
+        AlertKind::SensorStuck => match event.transition {
+            Transition::Raised => format!(
+                "The C O 2 sensor is stuck at {:.0} parts per million. Please replug it.",
+                event.value
+            ),
+            Transition::Cleared => String::from("The C O 2 sensor is no longer stuck."),
+        },
+        AlertKind::SensorDrift => match event.transition {
+            Transition::Raised => {
+                String::from("The C O 2 sensor has drifted. Please recalibrate it.")
+            }
+            Transition::Cleared => String::from("The C O 2 sensor no longer seems drifted."),
+        },
+    }
+}
+
+/// Spoken text for a notification, with temperatures in `unit`.
+pub fn notification_text(notification: &Notification, unit: TemperatureUnit) -> String {
+    match notification {
+        Notification::Escalation {
+            co2_ppm, minutes, ..
+        } => format!(
+            "C O 2 has been critical for {minutes:.0} minutes, now {co2_ppm:.0} parts per million. Ventilate now."
+        ),
+        Notification::Alert { event, .. } => alert_text(event, unit),
+    }
+}
+
+/// Spoken summary of a reading, with the temperature in `unit`.
+pub fn summary_text(reading: &MonitorReading, unit: TemperatureUnit) -> String {
+    let co2 = match reading.co2_value {
+        Co2Value::Valid(ppm) => format!("C O 2 {ppm} parts per million"),
+        Co2Value::WarmingUp(_) => String::from("C O 2 sensor warming up"),
+        Co2Value::TooHigh(_) => String::from("C O 2 above the sensor range"),
+        _ => String::from("C O 2 sensor fault"),
+    };
+    let mut text = format!(
+        "{co2}, temperature {:.0} {}",
+        reading.temperature_in(unit),
+        unit_name(unit)
+    );
+    if let Some(humidity) = reading.humidity {
+        text += &format!(", humidity {humidity:.0} percent");
+    }
+    text + "."
+}
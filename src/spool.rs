@@ -0,0 +1,220 @@
+//! Keep what a network sink couldn't send on disk, and send it once the other side is back.
+//!
+//! A [`Spool`] is an append-only file with one JSON line per item, so nothing that was pushed is lost when the logger
+//! crashes or the machine loses power: a line is written in one go, and a line that was cut off anyway is skipped when
+//! the spool is opened again. Draining sends the items oldest first and rewrites the file with whatever is left, via a
+//! temporary file that replaces the spool, so a crash while draining at worst sends a batch twice.
+//!
+//! The spool is bounded by a number of items, and the [`DropPolicy`] decides what goes when it is full. Every network
+//! sink gets its own spool file, e.g. `zabbix.jsonl` in the spool directory of the config.
+use serde::{Serialize, de::DeserializeOwned};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    vec::Vec,
+};
+
+/// Items a spool holds when nothing else is configured, a day of readings every 30 seconds for a sink that sends 3
+/// items per reading.
+pub const DEFAULT_MAX_ITEMS: usize = 3 * 24 * 60 * 2;
+
+/// What to drop when the spool is full.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DropPolicy {
+    /// Drop the oldest items, to keep the most recent data. A tenth of the spool is dropped at once, so the file isn't
+    /// rewritten for every new item.
+    #[default]
+    Oldest,
+    /// Don't take new items, to keep the beginning of the outage.
+    Newest,
+}
+
+impl core::fmt::Display for DropPolicy {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            DropPolicy::Oldest => "oldest",
+            DropPolicy::Newest => "newest",
+        })
+    }
+}
+
+/// Returned when parsing [`DropPolicy`] from an unknown string.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseDropPolicyError;
+
+impl core::fmt::Display for ParseDropPolicyError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("expected oldest or newest")
+    }
+}
+
+impl core::error::Error for ParseDropPolicyError {}
+
+impl core::str::FromStr for DropPolicy {
+    type Err = ParseDropPolicyError;
+
+    /// Accepts `oldest` and `newest`, in any case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("oldest") {
+            Ok(Self::Oldest)
+        } else if s.eq_ignore_ascii_case("newest") {
+            Ok(Self::Newest)
+        } else {
+            Err(ParseDropPolicyError)
+        }
+    }
+}
+
+/// A disk-backed queue of items that couldn't be sent yet, see the [module documentation](self).
+#[derive(Debug)]
+pub struct Spool<T> {
+    path: PathBuf,
+    file: File,
+    len: usize,
+    max_items: usize,
+    policy: DropPolicy,
+    dropped: u64,
+    items: PhantomData<fn(T) -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> Spool<T> {
+    /// Open the spool at `path`, or create it (but not its directory). Items that are already there are kept, even
+    /// beyond `max_items` (at least 1).
+    pub fn open(
+        path: impl Into<PathBuf>,
+        max_items: usize,
+        policy: DropPolicy,
+    ) -> io::Result<Self> {
+        let path = path.into();
+        let mut spool = Self {
+            file: OpenOptions::new().create(true).append(true).open(&path)?,
+            path,
+            len: 0,
+            max_items: max_items.max(1),
+            policy,
+            dropped: 0,
+            items: PhantomData,
+        };
+        // Rewriting gets rid of a line that was cut off, which would otherwise swallow the next item.
+        let items = spool.read_all()?;
+        spool.rewrite(&items)?;
+        Ok(spool)
+    }
+
+    /// The spool file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Items waiting to be sent.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether nothing is waiting to be sent, i.e. the sink can send directly.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Items that were dropped because the spool was full, since it was opened.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Add `items` to the end of the spool, dropping items according to the [`DropPolicy`] when it's full.
+    pub fn push(&mut self, items: &[T]) -> io::Result<()> {
+        let mut items = items;
+        if self.len + items.len() > self.max_items {
+            match self.policy {
+                DropPolicy::Newest => {
+                    let room = self.max_items.saturating_sub(self.len);
+                    self.dropped += (items.len() - room) as u64;
+                    items = &items[..room];
+                }
+                DropPolicy::Oldest => {
+                    let mut kept = self.read_all()?;
+                    let excess = (kept.len() + items.len()).saturating_sub(self.max_items);
+                    let drop = excess.max(self.max_items / 10).min(kept.len());
+                    kept.drain(..drop);
+                    self.dropped += drop as u64;
+                    // More new items than fit at all, only the most recent of them are kept.
+                    let skip = (kept.len() + items.len()).saturating_sub(self.max_items);
+                    self.dropped += skip as u64;
+                    items = &items[skip..];
+                    self.rewrite(&kept)?;
+                }
+            }
+        }
+        if items.is_empty() {
+            return Ok(());
+        }
+        let mut lines = Vec::new();
+        for item in items {
+            serde_json::to_writer(&mut lines, item)?;
+            lines.push(b'\n');
+        }
+        self.file.write_all(&lines)?;
+        self.file.sync_data()?;
+        self.len += items.len();
+        Ok(())
+    }
+
+    /// Send the spooled items oldest first, at most `batch` (at least 1) at a time, until `send` fails or the spool is
+    /// empty. Whatever wasn't sent stays in the spool. Returns how many items were sent, or the error of `send`.
+    pub fn drain(
+        &mut self,
+        batch: usize,
+        mut send: impl FnMut(&[T]) -> io::Result<()>,
+    ) -> io::Result<usize> {
+        let items = self.read_all()?;
+        let mut sent = 0;
+        let mut result = Ok(());
+        for chunk in items.chunks(batch.max(1)) {
+            if let Err(e) = send(chunk) {
+                result = Err(e);
+                break;
+            }
+            sent += chunk.len();
+        }
+        if sent > 0 {
+            self.rewrite(&items[sent..])?;
+        }
+        result.map(|()| sent)
+    }
+
+    fn read_all(&self) -> io::Result<Vec<T>> {
+        let mut items = Vec::new();
+        for line in BufReader::new(File::open(&self.path)?).lines() {
+            match serde_json::from_str(&line?) {
+                Ok(item) => items.push(item),
+                Err(e) => event!(
+                    warn,
+                    "skipping a broken line in {}: {}",
+                    self.path.display(),
+                    e
+                ),
+            }
+        }
+        Ok(items)
+    }
+
+    /// Replace the spool with `items`, atomically.
+    fn rewrite(&mut self, items: &[T]) -> io::Result<()> {
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        for item in items {
+            serde_json::to_writer(&mut writer, item)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&temporary, &self.path)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.len = items.len();
+        Ok(())
+    }
+}
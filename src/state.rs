@@ -0,0 +1,131 @@
+//! Keep what the logger learned across restarts: the software ABC, the anomaly detector, the hourly co2 baseline and
+//! which alerts are raised.
+//!
+//! Some of it takes days to learn, and without it every restart raises the alerts that were already raised. A
+//! [`StateFile`] is a small JSON file with a [`DeviceState`] per device, keyed by serial number (or path, for devices
+//! without one), so a logger that's moved to another monitor doesn't carry over a calibration that doesn't fit.
+//!
+//! Saving writes a temporary file next to it and renames that over the old one, so a crash while saving leaves the
+//! previous state, never half of one. There's no hook for Ctrl-C or a kill, so save every few minutes and whenever
+//! something changed that's worth keeping.
+use crate::{
+    abc::AutoBaseline,
+    alert::{AlertKind, AlertRule},
+    anomaly::AnomalyDetector,
+    baseline::HourlyBaseline,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// What is kept of a single device.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceState {
+    /// The software ABC, if it was on.
+    pub abc: Option<AutoBaseline>,
+    /// The anomaly detector.
+    pub anomaly: Option<AnomalyDetector>,
+    /// The typical co2 of every hour of the day.
+    pub baseline: Option<HourlyBaseline>,
+    /// The threshold alerts that were raised, see [`AlertRule::set_raised`](crate::alert::AlertRule::set_raised).
+    pub raised: Vec<AlertKind>,
+}
+
+impl DeviceState {
+    /// What `abc`, `anomaly`, `baseline` and `rules` learned so far.
+    pub fn new<'a>(
+        abc: Option<&AutoBaseline>,
+        anomaly: &AnomalyDetector,
+        baseline: &HourlyBaseline,
+        rules: impl IntoIterator<Item = &'a AlertRule>,
+    ) -> Self {
+        Self {
+            abc: abc.copied(),
+            anomaly: Some(*anomaly),
+            baseline: Some(*baseline),
+            raised: rules
+                .into_iter()
+                .filter(|rule| rule.is_raised())
+                .map(|rule| rule.kind())
+                .collect(),
+        }
+    }
+
+    /// Hand what was saved back to `abc`, `anomaly`, `baseline` and `rules`, which keep their own settings.
+    pub fn restore<'a>(
+        &self,
+        abc: Option<&mut AutoBaseline>,
+        anomaly: &mut AnomalyDetector,
+        baseline: &mut HourlyBaseline,
+        rules: impl IntoIterator<Item = &'a mut AlertRule>,
+    ) {
+        if let (Some(abc), Some(saved)) = (abc, &self.abc) {
+            abc.restore(saved);
+        }
+        if let Some(saved) = &self.anomaly {
+            anomaly.restore(saved);
+        }
+        if let Some(saved) = &self.baseline {
+            baseline.restore(saved);
+        }
+        for rule in rules {
+            if self.raised.contains(&rule.kind()) {
+                rule.set_raised();
+            }
+        }
+    }
+}
+
+/// The state of every device, in a file, see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateFile {
+    path: PathBuf,
+    devices: BTreeMap<String, DeviceState>,
+}
+
+impl StateFile {
+    /// Load the state from `path`. A file that doesn't exist yet is an empty state, one that can't be parsed an error.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let devices = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self { path, devices })
+    }
+
+    /// Where the state is saved.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The state of `device`, if there is one.
+    pub fn get(&self, device: &str) -> Option<&DeviceState> {
+        self.devices.get(device)
+    }
+
+    /// Replace the state of `device`. It's only written by [`save`](Self::save).
+    pub fn set(&mut self, device: &str, state: DeviceState) {
+        self.devices.insert(device.to_string(), state);
+    }
+
+    /// Write the state of all devices, replacing the file in one go.
+    pub fn save(&self) -> io::Result<()> {
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        serde_json::to_writer_pretty(&mut writer, &self.devices).map_err(io::Error::other)?;
+        writer.write_all(b"\n")?;
+        writer.into_inner()?.sync_all()?;
+        fs::rename(&temporary, &self.path)
+    }
+}
@@ -0,0 +1,205 @@
+//! Rolling statistics over readings, without allocating.
+//!
+//! [`RollingStats`] keeps the last `N` values and computes exact statistics over them, the summaries of `analyze` and
+//! of the [reports](crate::report) are built on it. [`DailyMinima`] keeps the lowest value of each of the last days,
+//! which is what baseline checks look at.
+use crate::{Co2Value, MonitorReading};
+
+/// A day of readings, one every ~5 seconds like the device sends them. A `RollingStats<READINGS_PER_DAY>` needs about
+/// 140 kB.
+pub const READINGS_PER_DAY: usize = 24 * 60 * 12;
+
+/// Statistics of a single quantity.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Summary {
+    /// Smallest value.
+    pub min: f32,
+    /// Largest value.
+    pub max: f32,
+    /// Arithmetic mean.
+    pub mean: f32,
+    /// 95th percentile.
+    pub p95: f32,
+}
+
+/// Ring buffer of the last `N` values of a single quantity.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Window<const N: usize> {
+    values: [f32; N],
+    len: usize,
+    next: usize,
+}
+
+impl<const N: usize> Window<N> {
+    /// Create an empty window.
+    pub const fn new() -> Self {
+        Self {
+            values: [0.0; N],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    /// Add a value, replacing the oldest one once the window is full.
+    pub fn push(&mut self, value: f32) {
+        if N == 0 {
+            return;
+        }
+        self.values[self.next] = value;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+    }
+
+    /// Number of values in the window.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether there are no values in the window.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Statistics of the values in the window, `None` if it's empty.
+    pub fn summary(&self) -> Option<Summary> {
+        if self.is_empty() {
+            return None;
+        }
+        // The order in the ring buffer doesn't matter for any of these, so the first `len` values are enough.
+        let mut sorted = self.values;
+        let sorted = &mut sorted[..self.len];
+        sorted.sort_unstable_by(f32::total_cmp);
+        let sum: f32 = sorted.iter().sum();
+        // Nearest rank percentile.
+        let rank = (self.len * 95).div_ceil(100).max(1);
+        Some(Summary {
+            min: sorted[0],
+            max: sorted[self.len - 1],
+            mean: sum / self.len as f32,
+            p95: sorted[rank - 1],
+        })
+    }
+}
+
+impl<const N: usize> Default for Window<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Exact statistics over the last `N` readings.
+///
+/// Co2 values that are out of spec are left out of the co2 statistics, since the device can't tell how high they
+/// actually were.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RollingStats<const N: usize> {
+    co2: Window<N>,
+    temperature: Window<N>,
+}
+
+impl<const N: usize> RollingStats<N> {
+    /// Create empty statistics.
+    pub const fn new() -> Self {
+        Self {
+            co2: Window::new(),
+            temperature: Window::new(),
+        }
+    }
+
+    /// Add a reading.
+    pub fn push(&mut self, reading: &MonitorReading) {
+        if let Co2Value::Valid(ppm) = reading.co2_value {
+            self.co2.push(f32::from(ppm));
+        }
+        self.temperature.push(reading.temperature);
+    }
+
+    /// Co2 statistics in ppm.
+    pub fn co2(&self) -> Option<Summary> {
+        self.co2.summary()
+    }
+
+    /// Temperature statistics in degrees Celsius.
+    pub fn temperature(&self) -> Option<Summary> {
+        self.temperature.summary()
+    }
+}
+
+impl<const N: usize> Default for RollingStats<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The lowest value of each of the last [`DailyMinima::MAX_DAYS`] days. A day is 24 hours since the first value, not a
+/// calendar day, so this works with any [`Clock`](crate::Clock). Days without values (e.g. the logger wasn't running)
+/// are skipped, not counted as empty.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DailyMinima {
+    first_ms: Option<u64>,
+    day: u64,
+    day_min: Option<f32>,
+    minima: [f32; Self::MAX_DAYS],
+    len: usize,
+    next: usize,
+}
+
+impl DailyMinima {
+    /// How many complete days are kept.
+    pub const MAX_DAYS: usize = 31;
+
+    const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+    /// No values yet.
+    pub const fn new() -> Self {
+        Self {
+            first_ms: None,
+            day: 0,
+            day_min: None,
+            minima: [0.0; Self::MAX_DAYS],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    /// Add `value` at `timestamp_ms`. Returns whether it started a new day, i.e. another day is complete.
+    pub fn push(&mut self, timestamp_ms: u64, value: f32) -> bool {
+        let first_ms = *self.first_ms.get_or_insert(timestamp_ms);
+        let day = timestamp_ms.saturating_sub(first_ms) / Self::DAY_MS;
+        let mut completed = false;
+        if day != self.day {
+            if let Some(day_min) = self.day_min.take() {
+                self.minima[self.next] = day_min;
+                self.next = (self.next + 1) % Self::MAX_DAYS;
+                self.len = (self.len + 1).min(Self::MAX_DAYS);
+                completed = true;
+            }
+            self.day = day;
+        }
+        self.day_min = Some(self.day_min.map_or(value, |day_min| day_min.min(value)));
+        completed
+    }
+
+    /// Number of complete days kept.
+    pub fn days(&self) -> usize {
+        self.len
+    }
+
+    /// The lowest value of the last `days` complete days, `None` until there are that many (or `days` is 0).
+    pub fn minimum(&self, days: usize) -> Option<f32> {
+        if days == 0 || self.len < days {
+            return None;
+        }
+        (1..=days)
+            .map(|back| self.minima[(self.next + Self::MAX_DAYS - back) % Self::MAX_DAYS])
+            .reduce(f32::min)
+    }
+}
+
+impl Default for DailyMinima {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,163 @@
+//! One line snapshots of a reading for status bars (waybar, i3blocks, polybar) and scripts.
+//!
+//! Every format carries a color hint from the air quality of the reading, see [`quality_color`]. Waybar gets the
+//! quality as CSS class instead, so the colors can be set in its stylesheet, e.g. `#custom-co2.poor { color: red; }`.
+use crate::{Co2Quality, Co2Value, MonitorReading, TemperatureUnit};
+use serde::Serialize;
+use std::{
+    format,
+    string::{String, ToString},
+};
+
+/// Output formats of [`format_status`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StatusFormat {
+    /// Plain text, e.g. `812 ppm 21.3°C`.
+    #[default]
+    Text,
+    /// A JSON object with all values, for scripts.
+    Json,
+    /// A JSON object for a waybar `custom` module with `"return-type": "json"`.
+    Waybar,
+    /// Full text, short text and color on separate lines, as i3blocks expects them.
+    I3blocks,
+    /// Text with polybar color tags.
+    Polybar,
+}
+
+impl core::fmt::Display for StatusFormat {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            StatusFormat::Text => "text",
+            StatusFormat::Json => "json",
+            StatusFormat::Waybar => "waybar",
+            StatusFormat::I3blocks => "i3blocks",
+            StatusFormat::Polybar => "polybar",
+        })
+    }
+}
+
+/// Returned when parsing [`StatusFormat`] from an unknown string.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseStatusFormatError;
+
+impl core::fmt::Display for ParseStatusFormatError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("expected text, json, waybar, i3blocks or polybar")
+    }
+}
+
+impl core::error::Error for ParseStatusFormatError {}
+
+impl core::str::FromStr for StatusFormat {
+    type Err = ParseStatusFormatError;
+
+    /// Accepts the names printed by `Display`, in any case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            StatusFormat::Text,
+            StatusFormat::Json,
+            StatusFormat::Waybar,
+            StatusFormat::I3blocks,
+            StatusFormat::Polybar,
+        ]
+        .into_iter()
+        .find(|format| s.eq_ignore_ascii_case(&format.to_string()))
+        .ok_or(ParseStatusFormatError)
+    }
+}
+
+/// Color hint for a quality level as `#rrggbb`: green, yellow green, amber and red.
+pub fn quality_color(quality: Co2Quality) -> &'static str {
+    match quality {
+        Co2Quality::Excellent => "#4caf50",
+        Co2Quality::Good => "#8bc34a",
+        Co2Quality::Moderate => "#ffc107",
+        Co2Quality::Poor => "#f44336",
+    }
+}
+
+#[derive(Serialize)]
+struct JsonStatus<'a> {
+    co2_ppm: u16,
+    co2_is_valid: bool,
+    temperature: f32,
+    unit: &'a str,
+    humidity: Option<f32>,
+    quality: Co2Quality,
+    color: &'a str,
+}
+
+#[derive(Serialize)]
+struct WaybarStatus {
+    text: String,
+    tooltip: String,
+    class: String,
+    alt: String,
+    percentage: u8,
+}
+
+/// Format `reading` as a single snapshot in `format`, with temperatures in `unit`. The result has no trailing newline.
+pub fn format_status(
+    reading: &MonitorReading,
+    format: StatusFormat,
+    unit: TemperatureUnit,
+) -> String {
+    let quality = reading.quality();
+    let color = quality_color(quality);
+    let co2 = co2_text(reading.co2_value);
+    let temperature = format!("{:.1}{}", reading.temperature_in(unit), unit.symbol());
+    match format {
+        StatusFormat::Text => format!("{co2} {temperature}"),
+        StatusFormat::Json => {
+            let (co2_ppm, co2_is_valid) = reading.co2_value.as_num_and_bool();
+            serde_json::to_string(&JsonStatus {
+                co2_ppm,
+                co2_is_valid,
+                temperature: reading.temperature_in(unit),
+                unit: unit.symbol(),
+                humidity: reading.humidity,
+                quality,
+                color,
+            })
+            .expect("status is always serializable")
+        }
+        StatusFormat::Waybar => {
+            let mut tooltip = format!("Co2: {co2}\nTemperature: {temperature}");
+            if let Some(humidity) = reading.humidity {
+                tooltip += &format!("\nHumidity: {humidity:.0}%");
+            }
+            tooltip += &format!("\nAir quality: {quality}");
+            serde_json::to_string(&WaybarStatus {
+                text: co2,
+                tooltip,
+                class: quality.to_string(),
+                alt: quality.to_string(),
+                percentage: co2_percentage(reading.co2_value),
+            })
+            .expect("status is always serializable")
+        }
+        StatusFormat::I3blocks => format!("{co2} {temperature}\n{co2}\n{color}"),
+        StatusFormat::Polybar => format!("%{{F{color}}}{co2}%{{F-}} {temperature}"),
+    }
+}
+
+fn co2_text(co2: Co2Value) -> String {
+    match co2 {
+        Co2Value::Valid(ppm) => format!("{ppm} ppm"),
+        _ => format!("co2 {co2}"),
+    }
+}
+
+/// Co2 from outdoor level (400 ppm) to twice the limit of [`Co2Quality::Moderate`] as 0 to 100, for waybar's
+/// `format-icons`.
+fn co2_percentage(co2: Co2Value) -> u8 {
+    let ppm = match co2 {
+        Co2Value::Valid(ppm) => ppm,
+        Co2Value::TooHigh(_) => return 100,
+        _ => return 0,
+    };
+    let full = 2 * crate::QualityBoundaries::DEFAULT.moderate_below;
+    (u32::from(ppm.saturating_sub(400)) * 100 / u32::from(full - 400)).min(100) as u8
+}
@@ -0,0 +1,375 @@
+//! Newline delimited JSON on stdin and stdout, so other programs can drive the logger without a server in between, e.g.
+//! an `exec` node in Node-RED (in spawn mode, which passes on every line of stdout as a message).
+//!
+//! Every line on stdin is a [`Request`]: `{"cmd":"subscribe"}` starts streaming readings, `{"cmd":"unsubscribe"}`
+//! stops it, `{"cmd":"get"}` sends the latest reading once and `{"cmd":"set_thresholds","max_co2":1200}` changes the
+//! limits of the alerts. Every line on stdout is a [`Message`] with a `type`: a `reading`, an `alert`, a `ventilation`
+//! (co2 fell like it does when a window is opened), and `ok` or `error` for every request. Alerts and ventilations are
+//! sent whether subscribed or not, setting thresholds is asking for them. With the `occupancy` feature and a room
+//! volume, readings also carry an `occupancy` estimate (see [`crate::occupancy`]). With a `max_co2` threshold, they
+//! carry the minutes until co2 reaches it (`minutes_to_limit`) or is back below it (`minutes_to_below`), see
+//! [`crate::forecast`]. Once a few days showed what's typical for the hour, they also carry how far co2 is above (or
+//! below) that as `co2_deviation`, see [`crate::baseline`]. Every reading tells how many readings were missed so far
+//! (`missed_cycles`, and `missed_percent` of all that were expected), see [`crate::cadence`]. Readings are numbered
+//! (`sequence`), starting from 0 for every session.
+//!
+//! [`Session`] keeps track of all of this, so the caller only has to move lines and readings in and messages out.
+#[cfg(feature = "occupancy")]
+use crate::occupancy::OccupancyEstimator;
+use crate::{
+    Co2Quality, Co2Value, MonitorReading, TemperatureUnit, TimestampedReading,
+    alert::{AlertEvent, AlertRule, TemperatureAlert},
+    anomaly::AnomalyDetector,
+    baseline::HourlyBaseline,
+    cadence::CadenceTracker,
+    config::ThresholdConfig,
+    forecast::Forecast,
+    log_format::Co2State,
+    trend::Trend,
+    ventilation::VentilationDetector,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    format,
+    string::{String, ToString},
+};
+
+/// A line on stdin, see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case", deny_unknown_fields)]
+#[non_exhaustive]
+pub enum Request {
+    /// Send every complete reading from now on.
+    Subscribe,
+    /// Stop sending readings.
+    Unsubscribe,
+    /// Send the latest reading once, if there was one yet.
+    Get,
+    /// Replace the limits of the alerts, in the temperature unit of the config. Limits that are left out are off.
+    /// Hysteresis and quiet hours stay as configured.
+    SetThresholds {
+        /// Alert when co2 rises above this many ppm.
+        max_co2: Option<u16>,
+        /// Alert when the temperature drops below this.
+        min_temperature: Option<f32>,
+        /// Alert when the temperature rises above this.
+        max_temperature: Option<f32>,
+    },
+}
+
+impl Request {
+    /// The `cmd` of the request, to acknowledge it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Request::Subscribe => "subscribe",
+            Request::Unsubscribe => "unsubscribe",
+            Request::Get => "get",
+            Request::SetThresholds { .. } => "set_thresholds",
+        }
+    }
+}
+
+/// A line on stdout, see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Message {
+    /// A complete reading.
+    Reading {
+        /// When it was read, in seconds since the Unix epoch.
+        timestamp: i64,
+        /// Number of the reading, see [`TimestampedReading::sequence`].
+        sequence: u32,
+        /// Co2 in ppm, only meaningful if `co2_state` is `valid`.
+        co2_ppm: u16,
+        /// `valid`, `too_high`, `too_low`, `warming_up` or `sensor_fault`, see [`Co2Value`].
+        co2_state: Co2State,
+        /// Temperature in `unit`.
+        temperature: f32,
+        /// The temperature unit of the config.
+        unit: &'static str,
+        /// Relative humidity in percent, if the device has a humidity sensor.
+        humidity: Option<f32>,
+        /// Air quality from the co2 value.
+        quality: Co2Quality,
+        /// Minutes until co2 reaches `max_co2`, if it's rising towards it.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        minutes_to_limit: Option<f32>,
+        /// Minutes until co2 is back below `max_co2`, if it's falling towards it.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        minutes_to_below: Option<f32>,
+        /// Ppm above (negative: below) the typical co2 of the hour, once that was learned.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        co2_deviation: Option<f32>,
+        /// Readings that should have been there so far, but weren't.
+        missed_cycles: u64,
+        /// `missed_cycles` in percent of the readings that were expected.
+        missed_percent: f32,
+        /// Estimated number of people in the room, once there's a trend. Only with an occupancy estimator.
+        #[cfg(feature = "occupancy")]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        occupancy: Option<f32>,
+    },
+    /// An alert was raised or cleared.
+    Alert {
+        /// `co2_high`, `temperature_low`, `temperature_high`, or `sensor_stuck` and `sensor_drift` about the sensor
+        /// itself (see [`crate::anomaly`]).
+        kind: &'static str,
+        /// `raised` or `cleared`.
+        transition: &'static str,
+        /// The value that caused it, in ppm or the temperature unit.
+        value: f32,
+        /// The limit of the alert, in ppm or the temperature unit. Minutes for `sensor_stuck`.
+        limit: f32,
+        /// The alert as text, e.g. for a notification.
+        text: String,
+    },
+    /// Co2 fell like it does when a window is opened, see [`crate::ventilation`].
+    Ventilation {
+        /// When the drop started, in seconds since the Unix epoch.
+        start: i64,
+        /// When co2 was lowest.
+        end: i64,
+        /// Co2 in ppm at the start.
+        start_ppm: u16,
+        /// Co2 in ppm at the end.
+        end_ppm: u16,
+    },
+    /// A request was carried out.
+    Ok {
+        /// The `cmd` of the request.
+        cmd: &'static str,
+    },
+    /// A request couldn't be parsed, or something went wrong with the device.
+    Error {
+        /// What went wrong.
+        message: String,
+    },
+}
+
+impl Message {
+    /// The message as a single line of JSON, without the newline.
+    pub fn to_line(&self) -> String {
+        serde_json::to_string(self).expect("messages are always serializable")
+    }
+}
+
+/// The state of a client on the other end of stdin and stdout, see the [module documentation](self).
+#[derive(Clone, Debug)]
+pub struct Session {
+    unit: TemperatureUnit,
+    thresholds: ThresholdConfig,
+    temperature_alert: TemperatureAlert,
+    co2_alert: Option<AlertRule>,
+    anomaly: AnomalyDetector,
+    ventilation: VentilationDetector,
+    // 10 minutes of readings every 5 seconds.
+    trend: Trend<120>,
+    baseline: HourlyBaseline,
+    deviation: Option<f32>,
+    cadence: CadenceTracker,
+    #[cfg(feature = "occupancy")]
+    occupancy: Option<OccupancyEstimator>,
+    #[cfg(feature = "occupancy")]
+    people: Option<f32>,
+    subscribed: bool,
+    latest: Option<(MonitorReading, i64, u32)>,
+}
+
+impl Session {
+    /// Not subscribed yet, with the configured thresholds, temperatures in `unit` and the default ventilation and
+    /// anomaly detectors.
+    pub fn new(thresholds: &ThresholdConfig, unit: TemperatureUnit) -> Self {
+        let (temperature_alert, co2_alert) = thresholds.alerts(unit);
+        Self {
+            unit,
+            thresholds: thresholds.clone(),
+            temperature_alert,
+            co2_alert,
+            anomaly: AnomalyDetector::default(),
+            ventilation: VentilationDetector::default(),
+            trend: Trend::new(),
+            baseline: HourlyBaseline::new(),
+            deviation: None,
+            cadence: CadenceTracker::new(),
+            #[cfg(feature = "occupancy")]
+            occupancy: None,
+            #[cfg(feature = "occupancy")]
+            people: None,
+            subscribed: false,
+            latest: None,
+        }
+    }
+
+    /// Watch the sensor with `detector`.
+    pub fn with_anomaly(self, detector: AnomalyDetector) -> Self {
+        Self {
+            anomaly: detector,
+            ..self
+        }
+    }
+
+    /// Detect ventilations with `detector`.
+    pub fn with_ventilation(self, detector: VentilationDetector) -> Self {
+        Self {
+            ventilation: detector,
+            ..self
+        }
+    }
+
+    /// Add an occupancy estimate to the readings.
+    #[cfg(feature = "occupancy")]
+    pub fn with_occupancy(self, estimator: OccupancyEstimator) -> Self {
+        Self {
+            occupancy: Some(estimator),
+            ..self
+        }
+    }
+
+    /// Whether readings are sent.
+    pub fn is_subscribed(&self) -> bool {
+        self.subscribed
+    }
+
+    /// Handle a line from stdin, passing the answers to `emit`. Empty lines are ignored.
+    pub fn request(&mut self, line: &str, emit: &mut impl FnMut(Message)) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        let request: Request = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(e) => {
+                emit(Message::Error {
+                    message: format!("invalid request: {e}"),
+                });
+                return;
+            }
+        };
+        match request {
+            Request::Subscribe => self.subscribed = true,
+            Request::Unsubscribe => self.subscribed = false,
+            Request::Get => match self.latest {
+                Some((reading, timestamp, sequence)) => {
+                    emit(self.reading_message(&reading, timestamp, sequence))
+                }
+                None => {
+                    emit(Message::Error {
+                        message: "no reading yet".to_string(),
+                    });
+                    return;
+                }
+            },
+            Request::SetThresholds {
+                max_co2,
+                min_temperature,
+                max_temperature,
+            } => {
+                self.thresholds = ThresholdConfig {
+                    max_co2,
+                    min_temperature,
+                    max_temperature,
+                    ..self.thresholds.clone()
+                };
+                (self.temperature_alert, self.co2_alert) = self.thresholds.alerts(self.unit);
+            }
+        }
+        emit(Message::Ok {
+            cmd: request.name(),
+        });
+    }
+
+    /// Handle a complete reading, taken at `timestamp` (seconds since the Unix epoch) in the local `hour`, passing the
+    /// alerts, ventilations and (if subscribed) the reading to `emit`.
+    pub fn reading(
+        &mut self,
+        reading: &MonitorReading,
+        timestamp: i64,
+        hour: u8,
+        emit: &mut impl FnMut(Message),
+    ) {
+        let sequence = self
+            .latest
+            .map_or(0, |(_, _, sequence)| sequence.wrapping_add(1));
+        self.latest = Some((*reading, timestamp, sequence));
+        let timestamped = TimestampedReading {
+            reading: *reading,
+            timestamp_ms: timestamp.max(0) as u64 * 1000,
+            sequence,
+        };
+        self.trend.observe_reading(&timestamped);
+        self.cadence.observe(timestamped.timestamp_ms);
+        self.deviation = match reading.co2_value {
+            Co2Value::Valid(ppm) => self.baseline.deviation(hour, ppm).map(|d| d.delta),
+            _ => None,
+        };
+        self.baseline.observe_reading(hour, reading);
+        #[cfg(feature = "occupancy")]
+        if let Some(estimator) = &mut self.occupancy {
+            self.people = estimator.push(&timestamped).or(self.people);
+        }
+        if self.subscribed {
+            emit(self.reading_message(reading, timestamp, sequence));
+        }
+        if let Some(event) = self.ventilation.push(&timestamped) {
+            emit(Message::Ventilation {
+                start: (event.start_ms / 1000) as i64,
+                end: (event.end_ms / 1000) as i64,
+                start_ppm: event.start_ppm,
+                end_ppm: event.end_ppm,
+            });
+        }
+        let unit = self.unit;
+        let mut alert = |event: AlertEvent| emit(alert_message(&event, unit));
+        self.temperature_alert.evaluate(hour, reading, &mut alert);
+        if let Some(event) = self
+            .co2_alert
+            .as_mut()
+            .and_then(|rule| rule.evaluate(hour, reading))
+        {
+            alert(event);
+        }
+        self.anomaly.observe(&timestamped, &mut alert);
+    }
+
+    fn reading_message(&self, reading: &MonitorReading, timestamp: i64, sequence: u32) -> Message {
+        let (co2_ppm, _) = reading.co2_value.as_num_and_bool();
+        let forecast = self
+            .thresholds
+            .max_co2
+            .map_or(Forecast::Steady, |limit| Forecast::new(&self.trend, limit));
+        Message::Reading {
+            timestamp,
+            sequence,
+            co2_ppm,
+            co2_state: Co2State::from(reading.co2_value),
+            temperature: reading.temperature_in(self.unit),
+            unit: self.unit.symbol(),
+            humidity: reading.humidity,
+            quality: reading.quality(),
+            minutes_to_limit: forecast.minutes_to_limit().map(round),
+            minutes_to_below: forecast.minutes_to_below().map(round),
+            co2_deviation: self.deviation.map(round),
+            missed_cycles: self.cadence.missed(),
+            missed_percent: round(100.0 * self.cadence.missed_ratio()),
+            #[cfg(feature = "occupancy")]
+            occupancy: self.people.map(round),
+        }
+    }
+}
+
+/// To a tenth, the estimates aren't any more precise than that.
+fn round(value: f32) -> f32 {
+    (value * 10.0).round() / 10.0
+}
+
+fn alert_message(event: &AlertEvent, unit: TemperatureUnit) -> Message {
+    let (value, limit) = event.value_and_limit_in(unit);
+    Message::Alert {
+        kind: event.kind.name(),
+        transition: event.transition.name(),
+        value,
+        limit,
+        text: event.to_string(),
+    }
+}
@@ -0,0 +1,141 @@
+//! [`Actuator`]s on the network: smart plugs over HTTP, and anything that takes MQTT commands.
+//!
+//! [`HttpSwitch`] sends a GET request to one URL to switch on and to another to switch off, which is all Tasmota and
+//! Shelly plugs need (both have shortcuts here) and covers most other HTTP relays. Only `http` is supported, these
+//! plugs don't do TLS anyway. Requests block for up to the timeout, but switching is rare.
+//!
+//! There's no MQTT client in this crate, so [`MqttSwitch`] only collects the command messages, publish them with your
+//! own client like those of [`crate::homeassistant`].
+use crate::{actuator::Actuator, homeassistant::MqttMessage, http};
+use std::{
+    format, io, mem,
+    string::{String, ToString},
+    time::Duration,
+    vec::Vec,
+};
+
+/// Switches with HTTP requests, see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HttpSwitch {
+    on_url: String,
+    off_url: String,
+    timeout: Duration,
+}
+
+impl HttpSwitch {
+    /// GET `on_url` to switch on and `off_url` to switch off, both `http://address[:port]/path`.
+    pub fn new(on_url: &str, off_url: &str) -> Self {
+        Self {
+            on_url: on_url.to_string(),
+            off_url: off_url.to_string(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// A Tasmota device at `host` (`address[:port]`), switching its first relay.
+    pub fn tasmota(host: &str) -> Self {
+        Self::new(
+            &format!("http://{host}/cm?cmnd=Power%20On"),
+            &format!("http://{host}/cm?cmnd=Power%20Off"),
+        )
+    }
+
+    /// A Shelly (Plus, Pro or later, with the RPC API) at `host` (`address[:port]`), switching its first relay. Gen 1
+    /// devices want `/relay/0?turn=on` and `off` with [`new`](Self::new) instead.
+    pub fn shelly(host: &str) -> Self {
+        Self::new(
+            &format!("http://{host}/rpc/Switch.Set?id=0&on=true"),
+            &format!("http://{host}/rpc/Switch.Set?id=0&on=false"),
+        )
+    }
+
+    /// Connect, write and read timeout. The default is 5 seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// GET `url`, an error for anything but a 2xx response.
+    fn get(&self, url: &str) -> io::Result<()> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("not an http URL: {url}"),
+            )
+        };
+        let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+        let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        let path = if path.is_empty() { "/" } else { path };
+        let server = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{host}:80")
+        };
+        let request = format!("GET {path} HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\n\r\n");
+        match http::request(&server, &request, self.timeout)? {
+            (200..=299, _) => Ok(()),
+            (_, status) => Err(io::Error::other(format!("{host} answered {status}"))),
+        }
+    }
+}
+
+impl Actuator for HttpSwitch {
+    type Error = io::Error;
+
+    fn set(&mut self, on: bool) -> io::Result<()> {
+        self.get(if on { &self.on_url } else { &self.off_url })
+    }
+}
+
+/// Switches with MQTT messages, see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MqttSwitch {
+    topic: String,
+    on_payload: String,
+    off_payload: String,
+    pending: Vec<MqttMessage>,
+}
+
+impl MqttSwitch {
+    /// Publish `on_payload` or `off_payload` to `topic`.
+    pub fn new(topic: &str, on_payload: &str, off_payload: &str) -> Self {
+        Self {
+            topic: topic.to_string(),
+            on_payload: on_payload.to_string(),
+            off_payload: off_payload.to_string(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// A Tasmota device with the MQTT topic `device_topic`, i.e. `ON` and `OFF` to `cmnd/<device_topic>/POWER`.
+    pub fn tasmota(device_topic: &str) -> Self {
+        Self::new(&format!("cmnd/{device_topic}/POWER"), "ON", "OFF")
+    }
+
+    /// The messages to publish since the last call, oldest first. They aren't retained, a retained command would
+    /// switch again whenever the device reconnects.
+    pub fn take_messages(&mut self) -> Vec<MqttMessage> {
+        mem::take(&mut self.pending)
+    }
+}
+
+impl Actuator for MqttSwitch {
+    type Error = core::convert::Infallible;
+
+    fn set(&mut self, on: bool) -> Result<(), Self::Error> {
+        self.pending.push(MqttMessage {
+            topic: self.topic.clone(),
+            payload: if on {
+                &self.on_payload
+            } else {
+                &self.off_payload
+            }
+            .clone(),
+            ..MqttMessage::default()
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+//! How fast co2 is rising or falling, and when it will cross a threshold.
+//!
+//! The slope is a least-squares line through the co2 values of the last few minutes, so single noisy readings don't
+//! flip the direction.
+use crate::{Co2Value, TimestampedReading};
+
+/// Keeps up to `N` co2 samples of the last `window_ms` milliseconds and fits a line through them.
+///
+/// `N` has to be large enough for the window, e.g. 120 for 10 minutes of readings every 5 seconds. If it's too small,
+/// the oldest samples are dropped early and the effective window is shorter.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Trend<const N: usize> {
+    window_ms: u64,
+    samples: [(u64, f32); N],
+    len: usize,
+    next: usize,
+}
+
+impl<const N: usize> Trend<N> {
+    /// Ten minutes, long enough to smooth over noise but still reacting to a window being opened.
+    pub const DEFAULT_WINDOW_MS: u64 = 10 * 60 * 1000;
+
+    /// Fit over the default window.
+    pub const fn new() -> Self {
+        Self::with_window(Self::DEFAULT_WINDOW_MS)
+    }
+
+    /// Fit over the last `window_ms` milliseconds.
+    pub const fn with_window(window_ms: u64) -> Self {
+        Self {
+            window_ms,
+            samples: [(0, 0.0); N],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    /// Add a co2 value in ppm at `timestamp_ms` (of any [`crate::Clock`], as long as it's always the same).
+    pub fn observe(&mut self, timestamp_ms: u64, ppm: f32) {
+        if N == 0 {
+            return;
+        }
+        self.samples[self.next] = (timestamp_ms, ppm);
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+    }
+
+    /// Add the co2 value of a reading. Out of spec values are skipped, since their actual value isn't known.
+    pub fn observe_reading(&mut self, reading: &TimestampedReading) {
+        if let Co2Value::Valid(ppm) = reading.reading.co2_value {
+            self.observe(reading.timestamp_ms, f32::from(ppm));
+        }
+    }
+
+    fn newest(&self) -> Option<u64> {
+        (self.len > 0).then(|| self.samples[(self.next + N - 1) % N].0)
+    }
+
+    /// Fitted line as (value at the newest sample, slope in ppm per minute). Times are relative to the newest sample
+    /// so they stay small enough for `f32`.
+    fn fit(&self) -> Option<(f32, f32)> {
+        let newest = self.newest()?;
+        let start = newest.saturating_sub(self.window_ms);
+        let (mut n, mut sum_t, mut sum_v, mut sum_tt, mut sum_tv) = (0.0f32, 0.0, 0.0, 0.0, 0.0);
+        for &(timestamp_ms, ppm) in &self.samples[..self.len] {
+            if timestamp_ms < start || timestamp_ms > newest {
+                continue;
+            }
+            let t = -((newest - timestamp_ms) as f32) / 60_000.0;
+            n += 1.0;
+            sum_t += t;
+            sum_v += ppm;
+            sum_tt += t * t;
+            sum_tv += t * ppm;
+        }
+        let denominator = n * sum_tt - sum_t * sum_t;
+        if n < 2.0 || denominator <= f32::EPSILON {
+            return None;
+        }
+        let slope = (n * sum_tv - sum_t * sum_v) / denominator;
+        let intercept = (sum_v - slope * sum_t) / n;
+        Some((intercept, slope))
+    }
+
+    /// Co2 change in ppm per minute, `None` until there are at least two samples at different times in the window.
+    pub fn ppm_per_minute(&self) -> Option<f32> {
+        self.fit().map(|(_, slope)| slope)
+    }
+
+    /// Minutes from the newest sample until co2 reaches `threshold_ppm`, if it keeps changing at the current rate.
+    /// `None` if it's moving away from the threshold (e.g. it's already above and still rising), or there is no trend
+    /// yet.
+    pub fn minutes_until(&self, threshold_ppm: f32) -> Option<f32> {
+        let (now, slope) = self.fit()?;
+        let remaining = threshold_ppm - now;
+        if remaining == 0.0 {
+            return Some(0.0);
+        }
+        let minutes = remaining / slope;
+        (minutes.is_finite() && minutes > 0.0).then_some(minutes)
+    }
+
+    /// Forget all samples, e.g. after reconnecting to the device.
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.next = 0;
+    }
+}
+
+impl<const N: usize> Default for Trend<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
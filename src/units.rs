@@ -0,0 +1,73 @@
+//! Temperature units for displaying readings.
+
+/// Unit to show temperatures in. Readings always store degrees Celsius.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TemperatureUnit {
+    /// Degrees Celsius.
+    #[default]
+    Celsius,
+    /// Degrees Fahrenheit.
+    Fahrenheit,
+    /// Kelvin.
+    Kelvin,
+}
+
+impl TemperatureUnit {
+    /// Convert a temperature in degrees Celsius to this unit.
+    pub fn from_celsius(self, celsius: f32) -> f32 {
+        match self {
+            Self::Celsius => celsius,
+            Self::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+            Self::Kelvin => celsius + 273.15,
+        }
+    }
+
+    /// Convert a temperature in this unit to degrees Celsius.
+    pub fn to_celsius(self, value: f32) -> f32 {
+        match self {
+            Self::Celsius => value,
+            Self::Fahrenheit => (value - 32.0) * 5.0 / 9.0,
+            Self::Kelvin => value - 273.15,
+        }
+    }
+
+    /// Short symbol of the unit, e.g. "°F".
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Celsius => "°C",
+            Self::Fahrenheit => "°F",
+            Self::Kelvin => "K",
+        }
+    }
+}
+
+/// Returned when parsing a [`TemperatureUnit`] from an unknown string.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseTemperatureUnitError;
+
+impl core::fmt::Display for ParseTemperatureUnitError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("expected one of C, F, K (or celsius, fahrenheit, kelvin)")
+    }
+}
+
+impl core::error::Error for ParseTemperatureUnitError {}
+
+impl core::str::FromStr for TemperatureUnit {
+    type Err = ParseTemperatureUnitError;
+
+    /// Accepts the first letter or the full name, in any case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let is = |name: &str| s.eq_ignore_ascii_case(name) || s.eq_ignore_ascii_case(&name[..1]);
+        if is("celsius") {
+            Ok(Self::Celsius)
+        } else if is("fahrenheit") {
+            Ok(Self::Fahrenheit)
+        } else if is("kelvin") {
+            Ok(Self::Kelvin)
+        } else {
+            Err(ParseTemperatureUnitError)
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Decide whether a co2 value is plausible, instead of a hard coded 3000 ppm limit.
+//!
+//! The sensor is specified up to 3000 ppm, above that the co2 channel saturates and only the sanity check still tells
+//! (see [`crate::sanity`]). At the other end, fresh outdoor air has about 420 ppm, so a value far below that is the
+//! sensor being off, and a plain 0 (outside of the [warm-up](crate::warmup)) is the sensor not measuring at all.
+//! [`ValidityModel`] turns these rules into a [`Co2Value`], with limits that can be configured for other sensors.
+//!
+//! The lower limit came later, and would mark readings of existing logs invalid that always were valid. So
+//! [`Conversions::Legacy`] keeps [`ValidityModel::LEGACY`], without one, and only [`Conversions::V2`] (or a configured
+//! `min_ppm`) gets [`ValidityModel::DEFAULT`].
+use crate::{Co2Value, Conversions};
+
+/// Limits of plausible co2 values, see the [module documentation](self).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ValidityModel {
+    /// Below this (but above 0), co2 is [`Co2Value::TooLow`]. 0 turns the lower limit off, including
+    /// [`Co2Value::SensorFault`].
+    pub min_ppm: u16,
+    /// Above this, by the co2 channel or the sanity check, co2 is [`Co2Value::TooHigh`].
+    pub max_ppm: u16,
+}
+
+impl ValidityModel {
+    /// 250 to 3000 ppm, the range of the sensor with some room below outdoor air.
+    pub const DEFAULT: Self = Self {
+        min_ppm: 250,
+        max_ppm: 3000,
+    };
+
+    /// Only the 3000 ppm at the top, how readings were always judged.
+    pub const LEGACY: Self = Self {
+        min_ppm: 0,
+        max_ppm: 3000,
+    };
+
+    /// [`LEGACY`](Self::LEGACY) for [`Conversions::Legacy`], [`DEFAULT`](Self::DEFAULT) for [`Conversions::V2`].
+    pub const fn for_conversions(conversions: Conversions) -> Self {
+        match conversions {
+            Conversions::Legacy => Self::LEGACY,
+            Conversions::V2 => Self::DEFAULT,
+        }
+    }
+
+    /// Plausible from `min_ppm` to `max_ppm`.
+    pub const fn new(min_ppm: u16, max_ppm: u16) -> Self {
+        Self { min_ppm, max_ppm }
+    }
+
+    /// Judge `ppm` from the co2 channel, together with the co2 estimated from the sanity check. An estimate of `None`
+    /// means the sanity check couldn't be explained, which is taken as too high.
+    pub fn classify(&self, ppm: u16, sanity_estimate: Option<u16>) -> Co2Value {
+        if sanity_estimate.is_none_or(|estimate| estimate > self.max_ppm) || ppm > self.max_ppm {
+            Co2Value::TooHigh(ppm)
+        } else if self.min_ppm == 0 {
+            Co2Value::Valid(ppm)
+        } else if ppm == 0 {
+            Co2Value::SensorFault(ppm)
+        } else if ppm < self.min_ppm {
+            Co2Value::TooLow(ppm)
+        } else {
+            Co2Value::Valid(ppm)
+        }
+    }
+}
+
+impl Default for ValidityModel {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
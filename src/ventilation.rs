@@ -0,0 +1,149 @@
+//! Tell when a window was opened, from how fast co2 falls.
+//!
+//! People breathing raise co2 by a few ppm per minute at most, and a door left open lowers it about as slowly. An
+//! opened window (or a purge with the ventilation) drops it by tens to hundreds of ppm per minute.
+//! [`VentilationDetector`] fits a line through the last few minutes (see [`Trend`]) and starts an event once it falls
+//! faster than a threshold. The event starts at the highest value before the drop and ends at the lowest one, once co2
+//! falls at less than half the threshold. Events with a total drop below a minimum are ignored, so a short dip of a
+//! noisy sensor doesn't count.
+use crate::{Co2Value, TimestampedReading, trend::Trend};
+
+/// Co2 falling at least this fast (ppm per minute) is taken as ventilation, unless configured otherwise.
+pub const DEFAULT_MIN_RATE: f32 = 30.0;
+
+/// Drops smaller than this many ppm are ignored, unless configured otherwise.
+pub const DEFAULT_MIN_DROP: u16 = 150;
+
+/// The slope is fitted over this long.
+const WINDOW_MS: u64 = 3 * 60 * 1000;
+
+/// A detected ventilation, e.g. an opened window.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct VentilationEvent {
+    /// Timestamp of the highest co2 value before the drop.
+    pub start_ms: u64,
+    /// Timestamp of the lowest co2 value of the drop.
+    pub end_ms: u64,
+    /// Co2 in ppm at the start.
+    pub start_ppm: u16,
+    /// Co2 in ppm at the end.
+    pub end_ppm: u16,
+}
+
+impl VentilationEvent {
+    /// How far co2 fell, in ppm.
+    pub fn drop_ppm(&self) -> u16 {
+        self.start_ppm.saturating_sub(self.end_ppm)
+    }
+
+    /// How long it took, in minutes.
+    pub fn minutes(&self) -> f32 {
+        self.end_ms.saturating_sub(self.start_ms) as f32 / 60_000.0
+    }
+}
+
+/// An ongoing drop.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Falling {
+    start_ms: u64,
+    start_ppm: f32,
+    low_ms: u64,
+    low_ppm: f32,
+}
+
+/// Finds [`VentilationEvent`]s in a series of readings, see the [module documentation](self).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VentilationDetector {
+    min_rate: f32,
+    min_drop: f32,
+    trend: Trend<64>,
+    last_ms: Option<u64>,
+    peak: Option<(u64, f32)>,
+    falling: Option<Falling>,
+}
+
+impl VentilationDetector {
+    /// Detect drops of at least `min_rate` ppm per minute and `min_drop` ppm in total.
+    pub const fn new(min_rate: f32, min_drop: u16) -> Self {
+        Self {
+            min_rate,
+            min_drop: min_drop as f32,
+            trend: Trend::with_window(WINDOW_MS),
+            last_ms: None,
+            peak: None,
+            falling: None,
+        }
+    }
+
+    /// Add a reading, and return the event it completes, if any. The readings have to be in chronological order, a
+    /// gap of more than a few minutes ends a drop. Out of spec values are skipped.
+    pub fn push(&mut self, reading: &TimestampedReading) -> Option<VentilationEvent> {
+        let Co2Value::Valid(ppm) = reading.reading.co2_value else {
+            return None;
+        };
+        let (timestamp_ms, ppm) = (reading.timestamp_ms, f32::from(ppm));
+        let mut event = None;
+        if self
+            .last_ms
+            .is_some_and(|last_ms| timestamp_ms < last_ms || timestamp_ms - last_ms > WINDOW_MS)
+        {
+            event = self.finish();
+            self.trend.clear();
+            self.peak = None;
+        }
+        self.last_ms = Some(timestamp_ms);
+        self.trend.observe(timestamp_ms, ppm);
+        let slope = self.trend.ppm_per_minute();
+
+        if let Some(falling) = &mut self.falling {
+            if ppm < falling.low_ppm {
+                falling.low_ms = timestamp_ms;
+                falling.low_ppm = ppm;
+            }
+            if slope.is_none_or(|slope| slope > -self.min_rate / 2.0) {
+                event = event.or(self.finish());
+                self.peak = Some((timestamp_ms, ppm));
+            }
+            return event;
+        }
+        // Roughly the highest value of the last few minutes.
+        if self
+            .peak
+            .is_none_or(|(peak_ms, peak_ppm)| ppm >= peak_ppm || timestamp_ms - peak_ms > WINDOW_MS)
+        {
+            self.peak = Some((timestamp_ms, ppm));
+        }
+        if slope.is_some_and(|slope| slope <= -self.min_rate)
+            && let Some((start_ms, start_ppm)) = self.peak
+        {
+            self.falling = Some(Falling {
+                start_ms,
+                start_ppm,
+                low_ms: timestamp_ms,
+                low_ppm: ppm,
+            });
+        }
+        event
+    }
+
+    /// End an ongoing drop, e.g. at the end of the series, and return it if it was large enough.
+    pub fn finish(&mut self) -> Option<VentilationEvent> {
+        let falling = self.falling.take()?;
+        (falling.start_ppm - falling.low_ppm >= self.min_drop).then_some(VentilationEvent {
+            start_ms: falling.start_ms,
+            end_ms: falling.low_ms,
+            start_ppm: falling.start_ppm as u16,
+            end_ppm: falling.low_ppm as u16,
+        })
+    }
+}
+
+impl Default for VentilationDetector {
+    /// [`DEFAULT_MIN_RATE`] and [`DEFAULT_MIN_DROP`].
+    fn default() -> Self {
+        Self::new(DEFAULT_MIN_RATE, DEFAULT_MIN_DROP)
+    }
+}
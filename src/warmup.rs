@@ -0,0 +1,94 @@
+//! Tell the garbage of a freshly plugged in sensor from real readings.
+//!
+//! The NDIR sensor needs a while to warm up, and for the first 30 to 60 seconds it reports 0 ppm or values all over the
+//! place. [`WarmUp`] marks the co2 of these readings as [`Co2Value::WarmingUp`], so frontends show "warming up" instead
+//! of an alarming 0 ppm, and alerts and statistics ignore them. The sensor counts as warm once a few readings in a row
+//! are non-zero and close to each other, or after the grace period at the latest. A monitor that was already warm (e.g.
+//! when the logger is restarted) is done after those few readings.
+use crate::{Co2Value, TimestampedReading};
+
+/// Grace period when nothing else is configured.
+pub const DEFAULT_GRACE_MS: u64 = 60_000;
+
+/// Marks readings as warming up until the sensor is warm, see the [module documentation](self).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WarmUp {
+    grace_ms: u64,
+    stable_readings: u8,
+    stable_delta_ppm: u16,
+    started_ms: Option<u64>,
+    last_ppm: Option<u16>,
+    stable: u8,
+    warm: bool,
+}
+
+impl Default for WarmUp {
+    fn default() -> Self {
+        Self::new(DEFAULT_GRACE_MS)
+    }
+}
+
+impl WarmUp {
+    /// Warm after `grace_ms` since the first reading at the latest (0 means right away), or once 3 readings in a row
+    /// are within 50 ppm of each other.
+    pub const fn new(grace_ms: u64) -> Self {
+        Self {
+            grace_ms,
+            stable_readings: 3,
+            stable_delta_ppm: 50,
+            started_ms: None,
+            last_ppm: None,
+            stable: 1,
+            warm: grace_ms == 0,
+        }
+    }
+
+    /// Warm once `readings` (at least 1) readings in a row are non-zero and within `delta_ppm` of the one before.
+    pub const fn with_stability(mut self, readings: u8, delta_ppm: u16) -> Self {
+        self.stable_readings = if readings == 0 { 1 } else { readings };
+        self.stable_delta_ppm = delta_ppm;
+        self
+    }
+
+    /// Whether the sensor is warm, i.e. readings pass unchanged.
+    pub fn is_warm(&self) -> bool {
+        self.warm
+    }
+
+    /// Mark the co2 of `reading` as warming up, unless the sensor is warm (which this reading may decide).
+    pub fn observe(&mut self, reading: &TimestampedReading) -> TimestampedReading {
+        if self.warm {
+            return *reading;
+        }
+        let (ppm, _) = reading.reading.co2_value.as_num_and_bool();
+        let started_ms = *self.started_ms.get_or_insert(reading.timestamp_ms);
+        self.stable = match self.last_ppm {
+            Some(last) if ppm > 0 && last > 0 && ppm.abs_diff(last) <= self.stable_delta_ppm => {
+                self.stable.saturating_add(1)
+            }
+            _ => 1,
+        };
+        self.last_ppm = Some(ppm);
+        self.warm = (ppm > 0 && self.stable >= self.stable_readings)
+            || reading.timestamp_ms.saturating_sub(started_ms) >= self.grace_ms;
+        if self.warm {
+            event!(debug, "the co2 sensor is warm");
+            return *reading;
+        }
+        let mut marked = *reading;
+        marked.reading.co2_value = Co2Value::WarmingUp(ppm);
+        marked
+    }
+
+    /// Start over, e.g. after the device was plugged in again.
+    pub fn reset(&mut self) {
+        *self = Self {
+            started_ms: None,
+            last_ppm: None,
+            stable: 1,
+            warm: self.grace_ms == 0,
+            ..*self
+        };
+    }
+}
@@ -0,0 +1,288 @@
+//! Talk to the monitor from a browser with WebHID, for a dashboard that needs nothing installed.
+//!
+//! Browsers only hand out a HID device after the user picked it in `navigator.hid.requestDevice()`, which needs a click,
+//! so choosing the device is left to the page ([`request_filters`] are the filters for the known models). Pass the
+//! `HIDDevice` it got to [`WebHidCo2Monitor::open`]. Everything in the browser is async: input reports arrive as
+//! events, which are queued until [`WebHidCo2Monitor::read_report`] or [`WebHidCo2Monitor::read_complete`] ask for
+//! them. With the `embassy` feature, the monitor is also an
+//! [`AsyncReportSource`](crate::embassy::AsyncReportSource).
+//!
+//! I only bind the bits of WebHID this needs with `wasm-bindgen` directly, instead of pulling in `web-sys`. Outside of
+//! wasm32 this compiles, but calling into JavaScript panics.
+use crate::{
+    MonitorError, MonitorHealth, MonitorReading, MonitorReadingParts,
+    device::decode_to_part,
+    profiles::{BUILTIN, DeviceProfile},
+};
+use core::{
+    cell::RefCell,
+    future::{Future, poll_fn},
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+use js_sys::{Array, DataView, Function, Object, Promise, Reflect, Uint8Array};
+use std::{collections::VecDeque, rc::Rc};
+use wasm_bindgen::{JsCast, JsValue, prelude::*};
+
+/// Input reports that may wait for a read, older ones are dropped. The device sends a few per second.
+const MAX_QUEUED_REPORTS: usize = 64;
+
+#[wasm_bindgen]
+extern "C" {
+    /// A WebHID `HIDDevice`, as returned by `navigator.hid.requestDevice()` or `navigator.hid.getDevices()`.
+    #[derive(Debug, Clone)]
+    #[wasm_bindgen(js_name = HIDDevice)]
+    pub type HidDevice;
+
+    #[wasm_bindgen(method)]
+    fn open(this: &HidDevice) -> Promise;
+
+    #[wasm_bindgen(method)]
+    fn close(this: &HidDevice) -> Promise;
+
+    #[wasm_bindgen(method, getter)]
+    fn opened(this: &HidDevice) -> bool;
+
+    #[wasm_bindgen(method, getter, js_name = vendorId)]
+    fn vendor_id(this: &HidDevice) -> u16;
+
+    #[wasm_bindgen(method, getter, js_name = productId)]
+    fn product_id(this: &HidDevice) -> u16;
+
+    #[wasm_bindgen(method, js_name = sendFeatureReport)]
+    fn send_feature_report(this: &HidDevice, report_id: u8, data: &Uint8Array) -> Promise;
+
+    #[wasm_bindgen(method, js_name = addEventListener)]
+    fn add_event_listener(this: &HidDevice, kind: &str, listener: &Function);
+
+    #[wasm_bindgen(method, js_name = removeEventListener)]
+    fn remove_event_listener(this: &HidDevice, kind: &str, listener: &Function);
+
+    #[derive(Debug)]
+    type HidInputReportEvent;
+
+    #[wasm_bindgen(method, getter)]
+    fn data(this: &HidInputReportEvent) -> DataView;
+}
+
+/// The filters for `navigator.hid.requestDevice({ filters })`, one `{ vendorId, productId }` per known model.
+pub fn request_filters() -> Array {
+    let filters = Array::new();
+    let mut seen: std::vec::Vec<(u16, u16)> = std::vec::Vec::new();
+    for (_, profile) in BUILTIN {
+        let ids = (profile.vendor_id, profile.product_id);
+        if seen.contains(&ids) {
+            continue;
+        }
+        seen.push(ids);
+        let filter = Object::new();
+        let _ = Reflect::set(&filter, &"vendorId".into(), &ids.0.into());
+        let _ = Reflect::set(&filter, &"productId".into(), &ids.1.into());
+        filters.push(&filter);
+    }
+    filters
+}
+
+/// Reports received but not read yet, and who is waiting for them.
+#[derive(Debug, Default)]
+struct Inbox {
+    reports: VecDeque<[u8; 8]>,
+    waker: Option<Waker>,
+}
+
+/// A monitor in the browser, see the [module documentation](self).
+#[derive(Debug)]
+pub struct WebHidCo2Monitor {
+    device: HidDevice,
+    profile: DeviceProfile,
+    inbox: Rc<RefCell<Inbox>>,
+    listener: Closure<dyn FnMut(HidInputReportEvent)>,
+}
+
+impl WebHidCo2Monitor {
+    /// Open `device` (unless the page already did) and send the feature report that starts the reports. The profile
+    /// is the built-in one for its ids, see [`crate::profiles::find`].
+    pub async fn open(device: HidDevice) -> Result<Self, MonitorError> {
+        let (vendor_id, product_id) = (device.vendor_id(), device.product_id());
+        let profile = crate::profiles::find(vendor_id, product_id)
+            .unwrap_or(DeviceProfile::new(vendor_id, product_id));
+        Self::open_with_profile(device, profile).await
+    }
+
+    /// Like `open`, with the given profile.
+    pub async fn open_with_profile(
+        device: HidDevice,
+        profile: DeviceProfile,
+    ) -> Result<Self, MonitorError> {
+        if !device.opened() {
+            PromiseFuture::new(&device.open()).await?;
+        }
+        // WebHID takes the report ID separately.
+        let key = Uint8Array::from(&profile.feature_report()[1..]);
+        PromiseFuture::new(&device.send_feature_report(0, &key)).await?;
+
+        let inbox = Rc::new(RefCell::new(Inbox::default()));
+        let listener = {
+            let inbox = Rc::clone(&inbox);
+            Closure::<dyn FnMut(HidInputReportEvent)>::new(move |event: HidInputReportEvent| {
+                let data = event.data();
+                if data.byte_length() < 8 {
+                    return;
+                }
+                let mut report = [0u8; 8];
+                for (i, byte) in report.iter_mut().enumerate() {
+                    *byte = data.get_uint8(i);
+                }
+                let mut inbox = inbox.borrow_mut();
+                if inbox.reports.len() >= MAX_QUEUED_REPORTS {
+                    inbox.reports.pop_front();
+                }
+                inbox.reports.push_back(report);
+                if let Some(waker) = inbox.waker.take() {
+                    waker.wake();
+                }
+            })
+        };
+        device.add_event_listener("inputreport", listener.as_ref().unchecked_ref());
+        event!(
+            info,
+            "connected to {:04x}:{:04x} with WebHID",
+            profile.vendor_id,
+            profile.product_id
+        );
+        Ok(Self {
+            device,
+            profile,
+            inbox,
+            listener,
+        })
+    }
+
+    /// The profile the monitor was opened with.
+    pub fn profile(&self) -> &DeviceProfile {
+        &self.profile
+    }
+
+    /// The `HIDDevice`, e.g. to show its `productName`.
+    pub fn device(&self) -> &HidDevice {
+        &self.device
+    }
+
+    /// Wait for the next input report and write it (decrypted, if the profile says so) to `read_buffer`. Returns the
+    /// number of bytes read, which is always 8.
+    pub async fn read_report(&self, read_buffer: &mut [u8; 8]) -> Result<usize, MonitorError> {
+        let report = poll_fn(|cx| {
+            let mut inbox = self.inbox.borrow_mut();
+            match inbox.reports.pop_front() {
+                Some(report) => Poll::Ready(report),
+                None => {
+                    inbox.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await;
+        *read_buffer = report;
+        self.profile.decrypt(read_buffer);
+        Ok(read_buffer.len())
+    }
+
+    /// Read until `part` is complete, skipping invalid reports.
+    pub async fn read_complete(
+        &self,
+        part: &mut MonitorReadingParts,
+    ) -> Result<MonitorReading, MonitorError> {
+        let mut health = MonitorHealth::new();
+        loop {
+            let mut read_buffer = [0u8; 8];
+            let read_len = self.read_report(&mut read_buffer).await;
+            if let Ok(Some(reading)) =
+                decode_to_part(read_len, &read_buffer, part, &mut health, &mut |_| ())
+            {
+                return Ok(reading);
+            }
+        }
+    }
+
+    /// Stop listening and close the device.
+    pub async fn close(self) -> Result<(), MonitorError> {
+        let device = self.device.clone();
+        drop(self);
+        PromiseFuture::new(&device.close()).await.map(|_| ())
+    }
+}
+
+impl Drop for WebHidCo2Monitor {
+    fn drop(&mut self) {
+        self.device
+            .remove_event_listener("inputreport", self.listener.as_ref().unchecked_ref());
+    }
+}
+
+#[cfg(feature = "embassy")]
+impl crate::embassy::AsyncReportSource for WebHidCo2Monitor {
+    fn read_report(
+        &mut self,
+        read_buffer: &mut [u8; 8],
+    ) -> impl Future<Output = Result<usize, MonitorError>> {
+        WebHidCo2Monitor::read_report(self, read_buffer)
+    }
+}
+
+/// `resolve` or `reject` of a promise.
+type Callback = Closure<dyn FnMut(JsValue)>;
+
+/// Where a promise is at.
+#[derive(Debug, Default)]
+struct PromiseState {
+    result: Option<Result<JsValue, JsValue>>,
+    waker: Option<Waker>,
+}
+
+/// Awaits a JavaScript promise, a minimal `wasm-bindgen-futures::JsFuture`. A rejection is
+/// [`MonitorError::ReadFailed`].
+struct PromiseFuture {
+    state: Rc<RefCell<PromiseState>>,
+    _callbacks: [Callback; 2],
+}
+
+impl PromiseFuture {
+    fn new(promise: &Promise) -> Self {
+        let state = Rc::new(RefCell::new(PromiseState::default()));
+        let settle = |ok: bool| {
+            let state = Rc::clone(&state);
+            Callback::new(move |value: JsValue| {
+                let mut state = state.borrow_mut();
+                state.result = Some(if ok { Ok(value) } else { Err(value) });
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            })
+        };
+        let (resolve, reject) = (settle(true), settle(false));
+        let _ = promise.then2(&resolve, &reject);
+        Self {
+            state,
+            _callbacks: [resolve, reject],
+        }
+    }
+}
+
+impl Future for PromiseFuture {
+    type Output = Result<JsValue, MonitorError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.borrow_mut();
+        match state.result.take() {
+            Some(Ok(value)) => Poll::Ready(Ok(value)),
+            Some(Err(e)) => {
+                event!(warn, "WebHID call failed: {:?}", e);
+                Poll::Ready(Err(MonitorError::ReadFailed))
+            }
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
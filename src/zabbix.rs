@@ -0,0 +1,240 @@
+//! Feed readings into Zabbix, either directly with the sender protocol or through `zabbix_sender` input files.
+//!
+//! The items have to exist as "Zabbix trapper" items on the host, with the keys from [`ZabbixKeys`]. Temperature is
+//! sent in degrees Celsius, co2 validity as 0 or 1. Values the logger derives from the readings, like
+//! `co2_deviation`, go to keys with the [prefix](ZabbixKeys::prefix) in front of their name.
+use crate::{MonitorReading, sink::BatchSink};
+use serde::{Deserialize, Serialize};
+use std::{
+    format,
+    io::{self, Read, Write},
+    net::TcpStream,
+    string::{String, ToString},
+    time::Duration,
+    vec,
+    vec::Vec,
+};
+
+/// Port of the Zabbix server (or proxy) trapper, when none is given.
+pub const DEFAULT_PORT: u16 = 10051;
+
+/// Item keys the values are sent as.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZabbixKeys {
+    /// Co2 in ppm.
+    pub co2: String,
+    /// 1 if co2 was within spec, 0 otherwise.
+    pub co2_is_valid: String,
+    /// Temperature in degrees Celsius.
+    pub temperature: String,
+    /// Put in front of the name of a derived value, see [`ZabbixSender::items_with`].
+    pub prefix: String,
+}
+
+impl Default for ZabbixKeys {
+    fn default() -> Self {
+        Self {
+            co2: String::from("co2_monitor.co2"),
+            co2_is_valid: String::from("co2_monitor.co2_is_valid"),
+            temperature: String::from("co2_monitor.temperature"),
+            prefix: String::from("co2_monitor."),
+        }
+    }
+}
+
+/// A single value for Zabbix.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ZabbixItem {
+    /// Host name as configured in Zabbix.
+    pub host: String,
+    /// Item key.
+    pub key: String,
+    /// The value, Zabbix converts it to the item's type.
+    pub value: String,
+    /// Unix timestamp in seconds.
+    pub clock: i64,
+}
+
+/// What the server answered.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ZabbixResponse {
+    /// `success` or `failed`.
+    pub response: String,
+    /// e.g. `processed: 3; failed: 0; total: 3; seconds spent: 0.000055`.
+    #[serde(default)]
+    pub info: String,
+}
+
+impl ZabbixResponse {
+    /// Whether the server accepted the request. Single items can still fail, e.g. for unknown keys, see `info`.
+    pub fn is_success(&self) -> bool {
+        self.response == "success"
+    }
+}
+
+/// Sends readings to a Zabbix server or proxy with the sender protocol, one connection per reading.
+#[derive(Clone, Debug)]
+pub struct ZabbixSender {
+    server: String,
+    host: String,
+    keys: ZabbixKeys,
+    timeout: Duration,
+}
+
+#[derive(Serialize)]
+struct SenderRequest<'a> {
+    request: &'static str,
+    data: &'a [ZabbixItem],
+}
+
+impl ZabbixSender {
+    /// Send to `server` (`address[:port]`, the port defaults to [`DEFAULT_PORT`]) for the Zabbix host `host`.
+    pub fn new(server: &str, host: &str) -> Self {
+        let server = if server.contains(':') {
+            server.to_string()
+        } else {
+            format!("{server}:{DEFAULT_PORT}")
+        };
+        Self {
+            server,
+            host: host.to_string(),
+            keys: ZabbixKeys::default(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Use other item keys.
+    pub fn with_keys(mut self, keys: ZabbixKeys) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    /// Connect, write and read timeout. The default is 5 seconds.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The items for `reading`, taken at the unix timestamp `clock`.
+    pub fn items(&self, reading: &MonitorReading, clock: i64) -> Vec<ZabbixItem> {
+        let (co2, valid) = reading.co2_value.as_num_and_bool();
+        let item = |key: &str, value: String| ZabbixItem {
+            host: self.host.clone(),
+            key: key.to_string(),
+            value,
+            clock,
+        };
+        vec![
+            item(&self.keys.co2, co2.to_string()),
+            item(&self.keys.co2_is_valid, u8::from(valid).to_string()),
+            item(
+                &self.keys.temperature,
+                format!("{:.2}", reading.temperature),
+            ),
+        ]
+    }
+
+    /// Like [`items`](Self::items), with more values, e.g. the deviation from the typical co2 as `co2_deviation`. Their
+    /// keys are the name with the [prefix](ZabbixKeys::prefix) in front.
+    pub fn items_with(
+        &self,
+        reading: &MonitorReading,
+        fields: &[(&str, f32)],
+        clock: i64,
+    ) -> Vec<ZabbixItem> {
+        let mut items = self.items(reading, clock);
+        items.extend(fields.iter().map(|(name, value)| ZabbixItem {
+            host: self.host.clone(),
+            key: format!("{}{name}", self.keys.prefix),
+            value: format!("{value:.1}"),
+            clock,
+        }));
+        items
+    }
+
+    /// Send `reading`, taken at the unix timestamp `clock`.
+    pub fn send(&self, reading: &MonitorReading, clock: i64) -> io::Result<ZabbixResponse> {
+        self.send_items(&self.items(reading, clock))
+    }
+
+    /// Send arbitrary items in a single request.
+    pub fn send_items(&self, items: &[ZabbixItem]) -> io::Result<ZabbixResponse> {
+        let address = std::net::ToSocketAddrs::to_socket_addrs(&self.server)?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "server has no address"))?;
+        let mut stream = TcpStream::connect_timeout(&address, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        let request = serde_json::to_vec(&SenderRequest {
+            request: "sender data",
+            data: items,
+        })?;
+        stream.write_all(&frame_header(request.len()))?;
+        stream.write_all(&request)?;
+
+        let mut header = [0u8; 13];
+        stream.read_exact(&mut header)?;
+        if &header[..5] != b"ZBXD\x01" {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a Zabbix response",
+            ));
+        }
+        let len = u64::from_le_bytes(header[5..].try_into().unwrap());
+        let mut body = Vec::new();
+        stream.take(len).read_to_end(&mut body)?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+impl BatchSink for ZabbixSender {
+    type Item = ZabbixItem;
+
+    fn name(&self) -> &str {
+        "zabbix"
+    }
+
+    fn send(&mut self, items: &[ZabbixItem]) -> io::Result<()> {
+        let response = self.send_items(items)?;
+        if response.is_success() {
+            event!(debug, "zabbix: {}", response.info);
+        } else {
+            // Sending the same items again wouldn't change that, so they're done.
+            event!(warn, "zabbix rejected readings: {}", response.info);
+        }
+        Ok(())
+    }
+}
+
+/// `ZBXD`, protocol version 1 (no compression), and the little endian data length.
+fn frame_header(len: usize) -> [u8; 13] {
+    let mut header = [0u8; 13];
+    header[..5].copy_from_slice(b"ZBXD\x01");
+    header[5..].copy_from_slice(&(len as u64).to_le_bytes());
+    header
+}
+
+/// Write `items` as input for `zabbix_sender --with-timestamps --input-file`, one item per line.
+pub fn write_sender_lines(writer: &mut impl Write, items: &[ZabbixItem]) -> io::Result<()> {
+    for item in items {
+        writeln!(
+            writer,
+            "{} {} {} {}",
+            quote(&item.host),
+            quote(&item.key),
+            item.clock,
+            quote(&item.value)
+        )?;
+    }
+    Ok(())
+}
+
+/// `zabbix_sender` splits at whitespace, unless the field is quoted.
+fn quote(field: &str) -> String {
+    if field.is_empty() || field.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+        format!("\"{}\"", field.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -48,7 +48,7 @@ def update_graph(n, options, interval):
     use_lttb = not use_naive
     if not auto_update:
         return no_update, no_update
-    df = pd.read_csv(CSV_PATH, parse_dates=['timestamp'])
+    df = pd.read_csv(CSV_PATH, parse_dates=['timestamp'], comment='#')
 
     df["co2_for_plot"] = df["co2_ppm"]#.where(df["co2_is_valid"], None)
 